@@ -0,0 +1,61 @@
+//! Micro-benchmark of `DynBox::coerce`, comparing the identity coercion of a
+//! `DynBox<Sheep>` to `Sheep` with a coercion to a trait object. Run with
+//! `cargo bench --bench coerce`.
+
+use std::any::Any;
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ocaml_rs_smartptr::ptr::DynBox;
+use ocaml_rs_smartptr::registry;
+
+const ITERATIONS: u32 = 1_000_000;
+
+struct Sheep {
+    name: String,
+}
+
+trait Named {
+    fn name(&self) -> &str;
+}
+
+impl Named for Sheep {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Runs `f` `ITERATIONS` times and returns the average time per iteration.
+fn bench(f: impl Fn() -> usize) -> Duration {
+    // Warm up caches and the registry lock
+    for _ in 0..ITERATIONS / 10 {
+        black_box(f());
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    registry::register_type::<Sheep>();
+    registry::register_type::<dyn Named>();
+    registry::register::<Sheep, Sheep>(|x| x, |x| x);
+    registry::register::<Sheep, dyn Named>(|x| x, |x| x);
+
+    let sheep = DynBox::<Sheep>::new_exclusive(Sheep {
+        name: "Dolly".to_owned(),
+    });
+    // `DynBox<Sheep>` only coerces to `Sheep`, so go through the registry for
+    // the same kind of container
+    let any: Arc<dyn Any + Sync + Send> = Arc::new(Mutex::new(Sheep {
+        name: "Dolly".to_owned(),
+    }));
+
+    let identity = bench(|| sheep.coerce().name.len());
+    let to_trait = bench(|| registry::coerce::<dyn Named>(any.clone()).name().len());
+    println!("coerce DynBox<Sheep> to Sheep:      {:?}/iter", identity);
+    println!("coerce DynBox<Sheep> to dyn Named:  {:?}/iter", to_trait);
+}
@@ -0,0 +1,36 @@
+//! Micro-benchmark of `DynBox::new_exclusive`, which registers the type of the
+//! value on every call. Run with `cargo bench --bench construct`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use ocaml_rs_smartptr::ptr::DynBox;
+
+const BOXES: u32 = 100_000;
+
+struct Sheep {
+    name: String,
+}
+
+/// Constructs `BOXES` boxes and returns the average time per box.
+fn bench() -> Duration {
+    let start = Instant::now();
+    let boxes: Vec<_> = (0..BOXES)
+        .map(|i| {
+            DynBox::<Sheep>::new_exclusive(Sheep {
+                name: format!("sheep #{}", i),
+            })
+        })
+        .collect();
+    let elapsed = start.elapsed();
+    let names: usize = boxes.iter().map(|b| b.coerce().name.len()).sum();
+    black_box(names);
+    elapsed / BOXES
+}
+
+fn main() {
+    // The first box registers `Sheep`, the rest should only check that it's
+    // already registered
+    bench();
+    println!("construct DynBox<Sheep>:  {:?}/box", bench());
+}
@@ -0,0 +1,49 @@
+//! Micro-benchmark of the reference count traffic of a stub returning a fresh
+//! `DynBox<Sheep>`, like `sheep_create` does. `ToValue` clones the box into the
+//! custom block, and the stub drops the original right after, while
+//! `Returned` moves the box into the block. The custom block itself needs the
+//! OCaml runtime, so only the reference count operations are measured. Run
+//! with `cargo bench --bench returned`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use ocaml_rs_smartptr::ptr::DynBox;
+use ocaml_rs_smartptr::registry;
+
+const ITERATIONS: u32 = 1_000_000;
+
+struct Sheep {
+    name: String,
+}
+
+/// Runs `f` `ITERATIONS` times on the same box and returns the average time
+/// per iteration.
+fn bench(sheep: &DynBox<Sheep>, f: impl Fn(DynBox<Sheep>) -> DynBox<Sheep>) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        // The box held by OCaml, dropped by the finalizer later on
+        black_box(f(black_box(sheep.clone())));
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    registry::register::<Sheep, Sheep>(|x| x, |x| x);
+
+    let sheep = DynBox::new_exclusive(Sheep {
+        name: "Dolly".to_owned(),
+    });
+    black_box(sheep.coerce().name.len());
+
+    // `to_value` clones the box, and the stub drops the original afterwards
+    let cloned = bench(&sheep, |sheep| {
+        let held = sheep.clone();
+        drop(sheep);
+        held
+    });
+    // `Returned` moves the box into the custom block
+    let moved = bench(&sheep, |sheep| sheep);
+    println!("return DynBox<Sheep> with ToValue:   {:?}/iter", cloned);
+    println!("return DynBox<Sheep> with Returned:  {:?}/iter", moved);
+}
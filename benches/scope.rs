@@ -0,0 +1,72 @@
+//! Micro-benchmark of `CoercionScope`, comparing a stub which coerces the same
+//! `DynBox<Sheep>` three times with plain `DynBox::coerce` calls to the same
+//! stub memoizing the coercions in a scope. Run with
+//! `cargo bench --bench scope`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use ocaml_rs_smartptr::ptr::DynBox;
+use ocaml_rs_smartptr::registry;
+use ocaml_rs_smartptr::scope::CoercionScope;
+
+const ITERATIONS: u32 = 1_000_000;
+
+struct Sheep {
+    name: String,
+    age: usize,
+    wool: usize,
+}
+
+/// Runs `f` `ITERATIONS` times and returns the average time per iteration.
+fn bench(f: impl Fn() -> usize) -> Duration {
+    // Warm up caches and the registry lock
+    for _ in 0..ITERATIONS / 10 {
+        black_box(f());
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// A stub coercing its argument once per field it reads. Each handle is
+/// dropped before the next coercion, as a `Mutex` can't be locked twice.
+fn stub(sheep: &DynBox<Sheep>) -> usize {
+    let name = sheep.coerce().name.len();
+    let age = sheep.coerce().age;
+    let wool = sheep.coerce().wool;
+    name + age + wool
+}
+
+/// Same as `stub`, with the coercions memoized in a scope.
+fn scoped_stub(sheep: &DynBox<Sheep>) -> usize {
+    let scope = CoercionScope::new();
+    let name = scope.coerce(sheep).name.len();
+    let age = scope.coerce(sheep).age;
+    let wool = scope.coerce(sheep).wool;
+    name + age + wool
+}
+
+fn main() {
+    registry::register::<Sheep, Sheep>(|x| x, |x| x);
+
+    let exclusive = DynBox::new_exclusive(Sheep {
+        name: "Dolly".to_owned(),
+        age: 6,
+        wool: 3,
+    });
+    let shared = DynBox::new_shared(Sheep {
+        name: "Dolly".to_owned(),
+        age: 6,
+        wool: 3,
+    });
+
+    for (kind, sheep) in [("Mutex", &exclusive), ("RwLock", &shared)] {
+        let plain = bench(|| stub(sheep));
+        let scoped = bench(|| scoped_stub(sheep));
+        println!("coerce {:6} 3 times:           {:?}/iter", kind, plain);
+        println!("coerce {:6} 3 times in scope:  {:?}/iter", kind, scoped);
+    }
+}
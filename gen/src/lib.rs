@@ -0,0 +1,350 @@
+//! This crate bootstraps type registrations for `ocaml-rs-smartptr` out of
+//! rustdoc JSON. Given the JSON of a crate, it finds public types along with
+//! the traits they implement, and produces a `register_rtti!` block with
+//! `register_trait!` and `register_type!` calls, which can be pasted into the
+//! bindings and adjusted by hand:
+//!
+//! ```rust,ignore
+//! let mut generator = Generator::new();
+//! // Index crates defining the implemented traits, so that their object
+//! // safety can be checked
+//! generator.index_rustdoc_json("core.json")?;
+//! generator.index_rustdoc_json("std.json")?;
+//! let krate = read_rustdoc_json("my_crate.json")?;
+//! println!("{}", generator.registrations(&krate));
+//! ```
+//!
+//! Only traits which can be used as `dyn Trait` make it into
+//! `object_safe_traits`: object-safe, non-generic traits without associated
+//! types. `Send` and `Sync` implementations (including auto-derived ones)
+//! become `marker_traits`. Blanket implementations (e.g. `ToString` for any
+//! `Display`) are left out, as they would register every type against a
+//! bunch of unrelated traits. Everything else which was left out is listed
+//! in comments at the top of the block, along with the reason.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::rc::Rc;
+
+use rustdoc_types::{Crate, GenericArgs, Id, Item, ItemEnum, Path, Trait};
+
+/// Marker traits picked up from trait implementations, in the order they are
+/// listed in registrations.
+const MARKER_TRAITS: [&str; 2] = ["core::marker::Sync", "core::marker::Send"];
+
+/// Maps fully qualified paths of traits to the crate defining them.
+type PathIndex = HashMap<Vec<String>, (Rc<Crate>, Id)>;
+
+/// Reads rustdoc JSON of a crate, as produced by
+/// `cargo +nightly rustdoc -- -Z unstable-options --output-format json`.
+pub fn read_rustdoc_json(
+    json_path: impl AsRef<std::path::Path>,
+) -> Result<Crate, Box<dyn std::error::Error>> {
+    let file = File::open(json_path)?;
+    let reader = BufReader::new(file);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// A `register_type!` call for a single type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeRegistration {
+    pub ty: String,
+    pub marker_traits: Vec<String>,
+    pub object_safe_traits: Vec<String>,
+}
+
+/// Contents of a `register_rtti!` block, its `Display` implementation
+/// renders the block itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registrations {
+    /// Object-safe traits implemented by registered types, each one gets a
+    /// `register_trait!` call.
+    pub traits: BTreeSet<String>,
+    pub types: Vec<TypeRegistration>,
+    /// Items which were left out, along with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+impl fmt::Display for Registrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let markers = MARKER_TRAITS.join(", ");
+        writeln!(f, "register_rtti! {{")?;
+        for (item, reason) in &self.skipped {
+            writeln!(f, "    // skipped {}: {}", item, reason)?;
+        }
+        for trait_ in &self.traits {
+            writeln!(f, "    register_trait!(")?;
+            writeln!(f, "        {{")?;
+            writeln!(f, "            ty: {},", trait_)?;
+            writeln!(f, "            marker_traits: [{}],", markers)?;
+            writeln!(f, "        }}")?;
+            writeln!(f, "    );")?;
+        }
+        for ty in &self.types {
+            writeln!(f, "    register_type!(")?;
+            writeln!(f, "        {{")?;
+            writeln!(f, "            ty: {},", ty.ty)?;
+            writeln!(
+                f,
+                "            marker_traits: [{}],",
+                ty.marker_traits.join(", ")
+            )?;
+            writeln!(
+                f,
+                "            object_safe_traits: [{}],",
+                ty.object_safe_traits.join(", ")
+            )?;
+            writeln!(f, "        }}")?;
+            writeln!(f, "    );")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Generates registrations out of rustdoc JSON, see crate level docs.
+#[derive(Default)]
+pub struct Generator {
+    path_index: PathIndex,
+}
+
+impl Generator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes public traits defined by `krate`, so that implementations of
+    /// those traits can be checked for object safety. Toolchain crates can be
+    /// indexed with their JSON from `rust-docs-json` rustup component.
+    pub fn index_crate(&mut self, krate: Crate) {
+        let krate = Rc::new(krate);
+        for (id, summary) in krate.paths.iter().filter(|(_, s)| s.crate_id == 0) {
+            let is_trait = matches!(
+                krate.index.get(id).map(|item| &item.inner),
+                Some(ItemEnum::Trait(_))
+            );
+            if is_trait {
+                // Keep the first definition if several crates export the same
+                // path
+                self.path_index
+                    .entry(summary.path.clone())
+                    .or_insert_with(|| (krate.clone(), id.clone()));
+            }
+        }
+    }
+
+    /// Reads rustdoc JSON at `json_path` and indexes it, see `index_crate`.
+    pub fn index_rustdoc_json(
+        &mut self,
+        json_path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.index_crate(read_rustdoc_json(json_path)?);
+        Ok(())
+    }
+
+    /// Collects registrations for all public, non-generic structs and enums
+    /// defined by `krate`.
+    pub fn registrations(&self, krate: &Crate) -> Registrations {
+        let mut registrations = Registrations::default();
+        let mut types: Vec<_> = krate
+            .paths
+            .iter()
+            .filter(|(_, summary)| summary.crate_id == 0)
+            .filter_map(|(id, summary)| {
+                Some((local_path(&summary.path), krate.index.get(id)?))
+            })
+            .filter(|(_, item)| item.visibility == rustdoc_types::Visibility::Public)
+            .filter_map(|(path, item)| match &item.inner {
+                ItemEnum::Struct(s) => Some((path, &s.generics, &s.impls)),
+                ItemEnum::Enum(e) => Some((path, &e.generics, &e.impls)),
+                _ => None,
+            })
+            .collect();
+        types.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (ty, generics, impls) in types {
+            if !generics.params.is_empty() {
+                registrations
+                    .skipped
+                    .push((ty, "generic types can't be registered as is".to_owned()));
+                continue;
+            }
+            let mut markers = BTreeSet::new();
+            let mut object_safe_traits = BTreeSet::new();
+            let trait_impls = impls
+                .iter()
+                .filter_map(|id| krate.index.get(id))
+                .filter_map(|item| match &item.inner {
+                    ItemEnum::Impl(impl_) => Some(impl_),
+                    _ => None,
+                })
+                .filter(|impl_| !impl_.negative && impl_.blanket_impl.is_none());
+            for impl_ in trait_impls {
+                let Some(trait_ref) = &impl_.trait_ else {
+                    continue;
+                };
+                let Some(trait_path) = self.trait_path(krate, trait_ref) else {
+                    continue;
+                };
+                if MARKER_TRAITS.contains(&trait_path.as_str()) {
+                    markers.insert(trait_path);
+                    continue;
+                }
+                if impl_.synthetic {
+                    // Other auto traits, like `Unpin`, are of no interest
+                    continue;
+                }
+                match self.check_dyn_compatible(krate, trait_ref) {
+                    Ok(()) => {
+                        object_safe_traits.insert(trait_path);
+                    }
+                    Err(reason) => registrations
+                        .skipped
+                        .push((format!("{} for {}", trait_path, ty), reason.to_owned())),
+                }
+            }
+            registrations
+                .traits
+                .extend(object_safe_traits.iter().cloned());
+            registrations.types.push(TypeRegistration {
+                ty,
+                marker_traits: MARKER_TRAITS
+                    .iter()
+                    .filter(|m| markers.contains(**m))
+                    .map(|m| m.to_string())
+                    .collect(),
+                object_safe_traits: object_safe_traits.into_iter().collect(),
+            });
+        }
+        registrations
+    }
+
+    /// Resolves the path of a trait referenced from `krate`, with paths of
+    /// local traits starting with `crate::`.
+    fn trait_path(&self, krate: &Crate, trait_ref: &Path) -> Option<String> {
+        let summary = krate.paths.get(&trait_ref.id)?;
+        if summary.crate_id == 0 {
+            Some(local_path(&summary.path))
+        } else {
+            Some(summary.path.join("::"))
+        }
+    }
+
+    /// Looks up the definition of a trait referenced from `krate`, either in
+    /// `krate` itself or in indexed crates.
+    fn find_trait<'a>(
+        &'a self,
+        krate: &'a Crate,
+        trait_ref: &Path,
+    ) -> Option<(&'a Crate, &'a Trait)> {
+        let summary = krate.paths.get(&trait_ref.id)?;
+        let (krate, item) = if summary.crate_id == 0 {
+            (krate, krate.index.get(&trait_ref.id)?)
+        } else {
+            let (other, id) = self.path_index.get(&summary.path)?;
+            (other.as_ref(), other.index.get(id)?)
+        };
+        match &item.inner {
+            ItemEnum::Trait(trait_) => Some((krate, trait_)),
+            _ => None,
+        }
+    }
+
+    /// Checks that `dyn Trait` can be spelled out for the referenced trait
+    /// without any further arguments.
+    fn check_dyn_compatible(
+        &self,
+        krate: &Crate,
+        trait_ref: &Path,
+    ) -> Result<(), &'static str> {
+        if has_generic_args(trait_ref) {
+            return Err("generic traits are not supported");
+        }
+        let (trait_krate, trait_) = self
+            .find_trait(krate, trait_ref)
+            .ok_or("trait definition is not found, index rustdoc JSON of its crate")?;
+        if trait_.is_auto {
+            return Err("auto traits other than Send and Sync are not supported");
+        }
+        if !trait_.is_object_safe {
+            return Err("trait is not object safe");
+        }
+        if !trait_.generics.params.is_empty() {
+            return Err("generic traits are not supported");
+        }
+        let has_assoc_types = trait_
+            .items
+            .iter()
+            .filter_map(|id| trait_krate.index.get(id))
+            .any(|item: &Item| matches!(item.inner, ItemEnum::AssocType { .. }));
+        if has_assoc_types {
+            return Err("traits with associated types are not supported");
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the crate name at the start of a local item path with `crate`.
+fn local_path(path: &[String]) -> String {
+    std::iter::once("crate")
+        .chain(path.iter().skip(1).map(String::as_str))
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn has_generic_args(path: &Path) -> bool {
+    match path.args.as_deref() {
+        None => false,
+        Some(GenericArgs::AngleBracketed { args, bindings }) => {
+            !args.is_empty() || !bindings.is_empty()
+        }
+        Some(GenericArgs::Parenthesized { .. }) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_path() {
+        let path: Vec<String> = vec!["my_crate".into(), "stubs".into(), "Sheep".into()];
+        assert_eq!(local_path(&path), "crate::stubs::Sheep");
+    }
+
+    #[test]
+    fn test_display() {
+        let registrations = Registrations {
+            traits: ["crate::stubs::AnimalProxy".to_owned()].into(),
+            types: vec![TypeRegistration {
+                ty: "crate::stubs::Sheep".to_owned(),
+                marker_traits: vec!["core::marker::Send".to_owned()],
+                object_safe_traits: vec!["crate::stubs::AnimalProxy".to_owned()],
+            }],
+            skipped: vec![(
+                "core::clone::Clone for crate::stubs::Sheep".to_owned(),
+                "trait is not object safe".to_owned(),
+            )],
+        };
+        assert_eq!(
+            registrations.to_string(),
+            r#"register_rtti! {
+    // skipped core::clone::Clone for crate::stubs::Sheep: trait is not object safe
+    register_trait!(
+        {
+            ty: crate::stubs::AnimalProxy,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    register_type!(
+        {
+            ty: crate::stubs::Sheep,
+            marker_traits: [core::marker::Send],
+            object_safe_traits: [crate::stubs::AnimalProxy],
+        }
+    );
+}"#
+        );
+    }
+}
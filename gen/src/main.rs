@@ -0,0 +1,76 @@
+//! Prints a `register_rtti!` block for a crate, out of its rustdoc JSON:
+//!
+//! ```text
+//! cargo +nightly rustdoc -p my-crate -- -Z unstable-options --output-format json
+//! ocaml_rs_smartptr_gen target/doc/my_crate.json [dependency.json ...]
+//! ```
+//!
+//! Traits from `core`, `alloc` and `std` are resolved with the JSON shipped by
+//! `rust-docs-json` component of nightly toolchain, if it's installed:
+//!
+//! ```text
+//! rustup component add rust-docs-json --toolchain nightly
+//! ```
+//!
+//! Traits from other crates are resolved with JSON of those crates, passed as
+//! extra arguments.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use ocaml_rs_smartptr_gen::{read_rustdoc_json, Generator};
+
+fn get_nightly_toolchain_path() -> Option<PathBuf> {
+    // Get the path to the nightly rustc binary
+    let nightly_rustc_output = Command::new("rustup")
+        .arg("which")
+        .arg("rustc")
+        .arg("--toolchain")
+        .arg("nightly")
+        .output()
+        .ok()?;
+
+    let nightly_rustc = String::from_utf8(nightly_rustc_output.stdout)
+        .ok()?
+        .trim()
+        .to_string();
+
+    // Infer the base path to the nightly toolchain
+    let nightly_toolchain_path = std::path::Path::new(&nightly_rustc)
+        .parent()
+        .and_then(|p| p.parent())?;
+
+    Some(nightly_toolchain_path.to_path_buf())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let Some(crate_json) = args.next() else {
+        eprintln!("usage: ocaml_rs_smartptr_gen <crate.json> [dependency.json ...]");
+        std::process::exit(2);
+    };
+
+    let mut generator = Generator::new();
+    match get_nightly_toolchain_path() {
+        Some(toolchain_path) => {
+            for component in ["core", "alloc", "std"] {
+                let json_path = toolchain_path
+                    .join("share/doc/rust/json")
+                    .join(format!("{}.json", component));
+                if let Err(error) = generator.index_rustdoc_json(&json_path) {
+                    eprintln!("Failed to index {}: {}", json_path.display(), error);
+                }
+            }
+        }
+        None => {
+            eprintln!("Nightly toolchain is not found, toolchain traits are not indexed")
+        }
+    }
+    for json_path in args {
+        generator.index_rustdoc_json(&json_path)?;
+    }
+
+    let krate = read_rustdoc_json(&crate_json)?;
+    println!("{}", generator.registrations(&krate));
+    Ok(())
+}
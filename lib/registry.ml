@@ -1,7 +1,20 @@
+exception Coercion_error of string
+
+let () =
+  Callback.register_exception "Ocaml_rs_smartptr.Coercion_error" (Coercion_error "")
+;;
+
 external ocaml_rs_smartptr_init_registry
   :  unit
   -> unit
   = "ocaml_rs_smartptr_init_registry"
 
+external set_raise_on_coercion_failure
+  :  bool
+  -> unit
+  = "ocaml_rs_smartptr_set_raise_on_coercion_failure"
+
+external shutdown : unit -> unit = "ocaml_rs_smartptr_shutdown"
+
 let init () = ocaml_rs_smartptr_init_registry ()
 let () = init ()
@@ -1 +1,24 @@
+(* Raised by stubs when a coercion fails, e.g. when the object has no registered
+   coercion to the requested type, once enabled with
+   [set_raise_on_coercion_failure]. Carries the message of the failure *)
+exception Coercion_error of string
+
 val init : unit -> unit
+
+(* Makes failed coercions raise [Coercion_error] instead of panicking (which aborts
+   the program), or back, for all stubs which catch them, e.g. the ones defined with
+   [#[smartptr_func]] on Rust side *)
+external set_raise_on_coercion_failure
+  :  bool
+  -> unit
+  = "ocaml_rs_smartptr_set_raise_on_coercion_failure"
+
+(* Runs the hooks registered on Rust side with [register_at_exit], and makes the
+   finalizers of Rust objects leak them from now on, so that they don't crash the
+   program if the runtime finalizes them at exit. Meant to be registered with
+   [at_exit] at the start of the program, so that it runs after all other [at_exit]
+   functions:
+   {[
+     let () = at_exit Ocaml_rs_smartptr.Registry.shutdown
+   ]} *)
+external shutdown : unit -> unit = "ocaml_rs_smartptr_shutdown"
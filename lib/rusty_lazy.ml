@@ -0,0 +1,8 @@
+type thunk
+
+external run : thunk -> 'a = "ocaml_rs_smartptr_lazy_run"
+
+let () =
+  Callback.register "ocaml_rs_smartptr_lazy_of_thunk" (fun thunk -> lazy (run thunk));
+  Callback.register "ocaml_rs_smartptr_lazy_force" Lazy.force
+;;
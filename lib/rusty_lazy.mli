@@ -0,0 +1 @@
+(* Registers callbacks used by [Lazy<T>] interop type on Rust side *)
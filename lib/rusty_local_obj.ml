@@ -0,0 +1,2 @@
+type -'tags intf
+type 'a t = ([> ] as 'a) intf
@@ -0,0 +1,5 @@
+(* Objects of [LocalDynBox] smart pointer, which are only used on a single
+   thread. Tagged the same way as [Rusty_obj.t], but not interchangeable with
+   them *)
+type -'tags intf
+type 'a t = ([> ] as 'a) intf
@@ -1,2 +1,8 @@
 type -'tags intf
 type 'a t = ([> ] as 'a) intf
+
+external type_name : _ t -> string = "ocaml_rs_smartptr_rusty_obj_type_name"
+external to_string : _ t -> string = "ocaml_rs_smartptr_rusty_obj_to_string"
+external equal : _ t -> _ t -> bool = "ocaml_rs_smartptr_rusty_obj_equal"
+
+let pp fmt obj = Format.pp_print_string fmt (to_string obj)
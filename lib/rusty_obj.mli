@@ -1,2 +1,17 @@
 type -'tags intf
 type 'a t = ([> ] as 'a) intf
+
+(* Returns the registered Rust type name of the value held by the object, e.g.
+   for debugging coercion failures *)
+external type_name : _ t -> string = "ocaml_rs_smartptr_rusty_obj_type_name"
+
+(* Renders the object with the function registered with [register_show!] for
+   its Rust type, or returns the type name if there is none *)
+external to_string : _ t -> string = "ocaml_rs_smartptr_rusty_obj_to_string"
+
+(* Prints the object as [to_string] renders it, e.g. for [%a] *)
+val pp : Format.formatter -> _ t -> unit
+
+(* Checks if two objects are equal: physically by default, or structurally if
+   the Rust type is registered with [register_eq] *)
+external equal : _ t -> _ t -> bool = "ocaml_rs_smartptr_rusty_obj_equal"
@@ -0,0 +1,5 @@
+type thunk
+
+external next : thunk -> 'a option = "ocaml_rs_smartptr_stream_next"
+
+let () = Callback.register "ocaml_rs_smartptr_stream_of_thunk" (fun thunk () -> next thunk)
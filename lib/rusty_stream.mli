@@ -0,0 +1 @@
+(* Registers callbacks used by [OCamlStream<T>] interop type on Rust side *)
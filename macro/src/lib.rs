@@ -1,25 +1,54 @@
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
+use quote::{format_ident, quote, quote_spanned};
 use syn::{
     parse::{Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
+    spanned::Spanned,
     Path, PathSegment, Token, TypePath,
 };
 
+mod snake_case;
+
+use snake_case::to_snake_case;
+
 fn resolve_path(input_path: &Path, current_crate_name: &str) -> Path {
+    let mut new_path = resolve_crate_root(input_path, current_crate_name);
+    if !matches!(input_path.segments.first(), Some(segment) if segment.ident == "crate") {
+        new_path.leading_colon = Some(syn::Token![::](proc_macro2::Span::call_site()));
+    }
+    new_path
+}
+
+// Replaces `crate` with the crate name in the path and in the paths nested in
+// its generic arguments, e.g. `core::iter::Iterator<Item = crate::Row>`, so
+// that the same trait instantiated in different crates gets different names.
+// Leading colons are not added to nested paths to keep the names as written.
+fn resolve_crate_root(input_path: &Path, current_crate_name: &str) -> Path {
+    let mut new_path = input_path.clone();
     if let Some(PathSegment { ref ident, .. }) = input_path.segments.first() {
         if ident == "crate" {
-            let mut new_path = Path::from(format_ident!("{}", current_crate_name));
+            new_path = Path::from(format_ident!("{}", current_crate_name));
             new_path
                 .segments
                 .extend(input_path.segments.iter().skip(1).cloned());
-            return new_path;
         }
     }
 
-    let mut new_path = input_path.clone();
-    new_path.leading_colon = Some(syn::Token![::](proc_macro2::Span::call_site()));
+    for segment in new_path.segments.iter_mut() {
+        if let syn::PathArguments::AngleBracketed(ref mut args) = segment.arguments {
+            for arg in args.args.iter_mut() {
+                let ty = match arg {
+                    syn::GenericArgument::Type(ty) => ty,
+                    syn::GenericArgument::AssocType(assoc) => &mut assoc.ty,
+                    _ => continue,
+                };
+                if let syn::Type::Path(TypePath { qself: None, path }) = ty {
+                    *path = resolve_crate_root(path, current_crate_name);
+                }
+            }
+        }
+    }
     new_path
 }
 
@@ -85,23 +114,36 @@ fn generate_type_registration(
         vec![#(#implementations),*]
     };
     output.extend(quote! {
-        ocaml_rs_smartptr::registry::register_type_info::<#ty>(#fq_name, #implementations);
+        ocaml_rs_smartptr::registry::register_type_info::<#ty>(#current_crate_name, #fq_name, #implementations);
     });
 
-    output.extend(quote! {
-        ocaml_rs_smartptr::registry::register::<#ty, #ty>(
+    // All coercions go through a single `register_multi` call, which takes
+    // the registry lock once and generates less code than separate
+    // `register` calls
+    let mut coercions = vec![quote! {
+        &ocaml_rs_smartptr::registry::Coercion::<#ty, #ty>::new(
             |x: &#ty| x as &#ty,
             |x: &mut #ty| x as &mut #ty
-        );
-    });
+        )
+    }];
 
     for obj_trait in object_safe_traits {
+        // Check that the trait is object safe before it's used in `dyn`
+        // position, with the span of the trait path, so that the compiler
+        // error points at the trait listed by the user rather than deep into
+        // the expansion
+        output.extend(quote_spanned! {obj_trait.span()=>
+            const _: fn() = || {
+                fn assert_object_safe(_: &dyn #obj_trait) {}
+            };
+        });
+
         // Generate code for type -> obj_trait
-        output.extend(quote! {
-            ocaml_rs_smartptr::registry::register::<#ty, dyn #obj_trait>(
+        coercions.push(quote! {
+            &ocaml_rs_smartptr::registry::Coercion::<#ty, dyn #obj_trait>::new(
                 |x: &#ty| x as &dyn #obj_trait,
                 |x: &mut #ty| x as &mut dyn #obj_trait
-            );
+            )
         });
 
         let combinations = marker_trait_combinations(&marker_traits);
@@ -109,15 +151,19 @@ fn generate_type_registration(
         for (_, combination) in combinations {
             let full_trait = quote! { #obj_trait + #combination };
 
-            output.extend(quote! {
-                ocaml_rs_smartptr::registry::register::<#ty, dyn #full_trait>(
+            coercions.push(quote! {
+                &ocaml_rs_smartptr::registry::Coercion::<#ty, dyn #full_trait>::new(
                     |x: &#ty| x as &(dyn #full_trait),
                     |x: &mut #ty| x as &mut (dyn #full_trait)
-                );
+                )
             });
         }
     }
 
+    output.extend(quote! {
+        ocaml_rs_smartptr::registry::register_multi::<#ty>(&[#(#coercions),*]);
+    });
+
     output
 }
 
@@ -126,15 +172,61 @@ fn generate_type_registration(
 pub fn register_type(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as TypeRegisterInput);
 
-    let output = generate_type_registration(
+    let mut output = generate_type_registration(
         &input.ty,
         &input.marker_traits,
         &input.object_safe_traits,
         &std::env::var("CARGO_CRATE_NAME").unwrap(),
     );
+    if let Some(containers) = &input.containers {
+        output.extend(generate_containers_registration(&input.ty, containers));
+    }
+    if let Some(variant_kind) = &input.variant_kind {
+        output.extend(generate_variant_kind_registration(&input.ty, variant_kind));
+    }
     output.into()
 }
 
+// Records the containers a type is meant to be held in, when the type is
+// registered with the `containers` field, all of them are allowed otherwise
+fn generate_containers_registration(
+    ty: &TypePath,
+    containers: &[syn::Ident],
+) -> proc_macro2::TokenStream {
+    let mut ty = ty.clone();
+    ty.path = globalize_path(&ty.path);
+    quote! {
+        ocaml_rs_smartptr::registry::register_type_containers::<#ty>(
+            ocaml_rs_smartptr::registry::ContainerSet::of(&[
+                #(ocaml_rs_smartptr::registry::ContainerKind::#containers),*
+            ])
+        );
+    }
+}
+
+// Records the bound of the polymorphic variant in the OCaml type, when the type
+// is registered with the `variant_kind` field, it's open otherwise
+fn generate_variant_kind_registration(
+    ty: &TypePath,
+    variant_kind: &syn::Ident,
+) -> proc_macro2::TokenStream {
+    let mut ty = ty.clone();
+    ty.path = globalize_path(&ty.path);
+    let variant_kind = syn::Ident::new(
+        if variant_kind == "closed" {
+            "Closed"
+        } else {
+            "Open"
+        },
+        variant_kind.span(),
+    );
+    quote! {
+        ocaml_rs_smartptr::registry::register_type_variant_kind::<#ty>(
+            ocaml_rs_smartptr::registry::VariantKind::#variant_kind
+        );
+    }
+}
+
 // Helper function to generate combinations of marker traits
 fn marker_trait_combinations(
     marker_traits: &[Path],
@@ -169,6 +261,8 @@ struct TypeRegisterInput {
     ty: TypePath,
     marker_traits: Vec<Path>,
     object_safe_traits: Vec<Path>,
+    containers: Option<Vec<syn::Ident>>,
+    variant_kind: Option<syn::Ident>,
     #[allow(dead_code)]
     conversions: Vec<Conversion>,
 }
@@ -229,27 +323,55 @@ impl Parse for TypeRegisterInput {
 
         let ty = parse_named_field(&content, "ty")?;
         let marker_traits = parse_named_list(&content, "marker_traits")?;
-        let object_safe_traits = if content.peek(syn::Ident) && content.peek2(Token![:]) {
+        let object_safe_traits = if peek_named_field(&content, "object_safe_traits") {
             parse_named_list(&content, "object_safe_traits")?
         } else {
             vec![]
         };
+        let containers = if peek_named_field(&content, "containers") {
+            Some(parse_named_list_with(
+                &content,
+                "containers",
+                parse_container_kind,
+            )?)
+        } else {
+            None
+        };
+        let variant_kind = if peek_named_field(&content, "variant_kind") {
+            Some(parse_named_field_with(
+                &content,
+                "variant_kind",
+                parse_variant_kind,
+            )?)
+        } else {
+            None
+        };
         let conversions = vec![];
 
         Ok(TypeRegisterInput {
             ty,
             marker_traits,
             object_safe_traits,
+            containers,
+            variant_kind,
             conversions,
         })
     }
 }
 
 fn parse_named_field<T: Parse>(input: ParseStream, name: &str) -> syn::Result<T> {
+    parse_named_field_with(input, name, T::parse)
+}
+
+fn parse_named_field_with<T>(
+    input: ParseStream,
+    name: &str,
+    parser: fn(ParseStream) -> syn::Result<T>,
+) -> syn::Result<T> {
     let ident: syn::Ident = input.parse()?;
     if ident == name {
         input.parse::<Token![:]>()?;
-        let value: T = input.parse()?;
+        let value: T = parser(input)?;
         input.parse::<Token![,]>().ok(); // Optional trailing comma
         Ok(value)
     } else {
@@ -260,13 +382,54 @@ fn parse_named_field<T: Parse>(input: ParseStream, name: &str) -> syn::Result<T>
     }
 }
 
+// Checks if the next field is named `name`, without consuming it
+fn peek_named_field(input: ParseStream, name: &str) -> bool {
+    input.peek2(Token![:])
+        && input
+            .fork()
+            .parse::<syn::Ident>()
+            .is_ok_and(|ident| ident == name)
+}
+
+// Parses a variant name of `registry::ContainerKind`
+fn parse_container_kind(input: ParseStream) -> syn::Result<syn::Ident> {
+    let ident: syn::Ident = input.parse()?;
+    match ident.to_string().as_str() {
+        "Mutex" | "RwLock" | "Immutable" => Ok(ident),
+        _ => Err(syn::Error::new(
+            ident.span(),
+            "Expected one of: Mutex, RwLock, Immutable",
+        )),
+    }
+}
+
+// Parses the bound of the polymorphic variant, see `registry::VariantKind`
+fn parse_variant_kind(input: ParseStream) -> syn::Result<syn::Ident> {
+    let ident: syn::Ident = input.parse()?;
+    match ident.to_string().as_str() {
+        "open" | "closed" => Ok(ident),
+        _ => Err(syn::Error::new(
+            ident.span(),
+            "Expected one of: open, closed",
+        )),
+    }
+}
+
 fn parse_named_list<T: Parse>(input: ParseStream, name: &str) -> syn::Result<Vec<T>> {
+    parse_named_list_with(input, name, T::parse)
+}
+
+fn parse_named_list_with<T>(
+    input: ParseStream,
+    name: &str,
+    parser: fn(ParseStream) -> syn::Result<T>,
+) -> syn::Result<Vec<T>> {
     let ident: syn::Ident = input.parse()?;
     if ident == name {
         input.parse::<Token![:]>()?;
         let content;
         let _ = syn::bracketed!(content in input);
-        let values = Punctuated::<T, Token![,]>::parse_terminated(&content)?;
+        let values = Punctuated::<T, Token![,]>::parse_terminated_with(&content, parser)?;
         input.parse::<Token![,]>().ok(); // Optional trailing comma
         Ok(values.into_iter().collect())
     } else {
@@ -277,6 +440,19 @@ fn parse_named_list<T: Parse>(input: ParseStream, name: &str) -> syn::Result<Vec
     }
 }
 
+// Parses a trait path, allowing `Fn`-style parenthesized arguments on the last
+// segment, e.g. `core::ops::FnMut(i32) -> i32`, which `TypePath` does not accept
+fn parse_trait_path(input: ParseStream) -> syn::Result<TypePath> {
+    let mut ty: TypePath = input.parse()?;
+    if input.peek(syn::token::Paren) {
+        let args: syn::ParenthesizedGenericArguments = input.parse()?;
+        if let Some(last) = ty.path.segments.last_mut() {
+            last.arguments = syn::PathArguments::Parenthesized(args);
+        }
+    }
+    Ok(ty)
+}
+
 struct TraitRegisterInput {
     ty: TypePath,
     marker_traits: Vec<Path>,
@@ -288,7 +464,7 @@ impl Parse for TraitRegisterInput {
         let content;
         let _ = syn::braced!(content in input);
 
-        let ty = parse_named_field(&content, "ty")?;
+        let ty = parse_named_field_with(&content, "ty", parse_trait_path)?;
         let marker_traits = parse_named_list(&content, "marker_traits")?;
         let super_traits = if content.peek(syn::Ident) && content.peek2(Token![:]) {
             parse_named_list(&content, "super_traits")?
@@ -353,7 +529,7 @@ fn generate_trait_registration(
             vec![#(#implementations),*]
         };
         output.extend(quote! {
-            ocaml_rs_smartptr::registry::register_type_info::<dyn #full_trait>(#fq_name, #implementations);
+            ocaml_rs_smartptr::registry::register_type_info::<dyn #full_trait>(#current_crate_name, #fq_name, #implementations);
         });
 
         output.extend(quote! {
@@ -362,6 +538,22 @@ fn generate_trait_registration(
                 |x: &mut Box<dyn #full_trait>| x.as_mut()
             );
         });
+        // A boxed trait object also coerces to the trait with fewer markers,
+        // same as a type registered by `register_type` with these markers
+        // does, so that both coerce to the same set of trait objects
+        let markers: Vec<Path> = combination_paths.into_iter().cloned().collect();
+        for (subset_paths, subset_tokens) in marker_trait_combinations(&markers) {
+            if subset_paths.len() == markers.len() {
+                continue;
+            }
+            let sub_trait = quote! { #ty + #subset_tokens };
+            output.extend(quote! {
+                ocaml_rs_smartptr::registry::register::<Box<dyn #full_trait>, dyn #sub_trait>(
+                    |x: &Box<dyn #full_trait>| x.as_ref(),
+                    |x: &mut Box<dyn #full_trait>| x.as_mut()
+                );
+            });
+        }
         for super_trait in super_traits {
             // Generate code for trait -> super_trait
             output.extend(quote! {
@@ -390,275 +582,976 @@ pub fn register_trait(input: TokenStream) -> TokenStream {
     output.into()
 }
 
-#[cfg(test)]
-mod generation_tests {
-    use super::*;
+struct AllRegisterInput {
+    ty: TypePath,
+    traits: Vec<Path>,
+    markers: Vec<Path>,
+}
 
-    use quote::quote;
-    use syn::{parse_quote, Path, TypePath};
+impl Parse for AllRegisterInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        let _ = syn::braced!(content in input);
 
-    fn pretty_print_item(ts: proc_macro2::TokenStream) -> String {
-        let code = "fn main() {\n".to_owned() + &ts.to_string() + "\n}";
-        let file = syn::parse_file(&code).unwrap();
-        prettyplease::unparse(&file)
+        let ty = parse_named_field(&content, "ty")?;
+        let traits = parse_named_list(&content, "traits")?;
+        let markers = if content.peek(syn::Ident) && content.peek2(Token![:]) {
+            parse_named_list(&content, "markers")?
+        } else {
+            vec![]
+        };
+
+        Ok(AllRegisterInput {
+            ty,
+            traits,
+            markers,
+        })
     }
+}
 
-    #[test]
-    fn test_register_traits_macro_crate() {
-        // Define the input to the core function
-        let ty: TypePath = parse_quote! { crate::test_types::MyType };
-        let marker_traits: Vec<Path> = vec![
-            parse_quote! { crate::test_types::MyMarkerTrait1 },
-            parse_quote! { crate::test_types::MyMarkerTrait2 },
-        ];
-        let object_safe_traits: Vec<Path> = vec![
-            parse_quote! { crate::test_types::MyObjectSafeTrait1 },
-            parse_quote! { crate::test_types::MyObjectSafeTrait2 },
-        ];
+// This function contains the core logic and can be reused in tests
+fn generate_all_registration(
+    ty: &TypePath,
+    traits: &[Path],
+    markers: &[Path],
+    current_crate_name: &str,
+) -> proc_macro2::TokenStream {
+    // Coercions from the type itself to every trait, "multiplied" by markers
+    let mut output = generate_type_registration(ty, markers, traits, current_crate_name);
+    // Each trait object on its own, so that values coerced to any of the traits
+    // can be bound and passed around as `DynBox<dyn Trait>`
+    for obj_trait in traits {
+        let trait_ty = TypePath {
+            qself: None,
+            path: obj_trait.clone(),
+        };
+        output.extend(generate_trait_registration(
+            &trait_ty,
+            markers,
+            &[],
+            current_crate_name,
+        ));
+    }
 
-        // Generate the actual output using the core logic function
-        let output_tokens = generate_type_registration(
-            &ty,
-            &marker_traits,
-            &object_safe_traits,
-            "this_crate",
-        );
+    output
+}
 
-        let expected_output = quote! {
-            ocaml_rs_smartptr::registry::register_type::<crate::test_types::MyType>();
-            ocaml_rs_smartptr::registry::register_type_info::<
-                crate::test_types::MyType,
-            >(
-                "this_crate::test_types::MyType",
-                vec![
-                    "this_crate::test_types::MyType",
-                    "this_crate::test_types::MyMarkerTrait1",
-                    "this_crate::test_types::MyMarkerTrait2",
-                    "this_crate::test_types::MyObjectSafeTrait1",
-                    "this_crate::test_types::MyObjectSafeTrait2"
-                ],
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                crate::test_types::MyType,
-            >(
-                |x: &crate::test_types::MyType| x as &crate::test_types::MyType,
-                |x: &mut crate::test_types::MyType| x as &mut crate::test_types::MyType,
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait1,
-            >(
-                |x: &crate::test_types::MyType| x as &dyn crate::test_types::MyObjectSafeTrait1,
-                |x: &mut crate::test_types::MyType| {
-                    x as &mut dyn crate::test_types::MyObjectSafeTrait1
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait1,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x as &(dyn crate::test_types::MyObjectSafeTrait1)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x as &mut (dyn crate::test_types::MyObjectSafeTrait1)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x
-                        as &(dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait2,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x
-                        as &(dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait2)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait2)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x
-                        as &(dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait2,
-            >(
-                |x: &crate::test_types::MyType| x as &dyn crate::test_types::MyObjectSafeTrait2,
-                |x: &mut crate::test_types::MyType| {
-                    x as &mut dyn crate::test_types::MyObjectSafeTrait2
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait2,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x as &(dyn crate::test_types::MyObjectSafeTrait2)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x as &mut (dyn crate::test_types::MyObjectSafeTrait2)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x
-                        as &(dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait2,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x
-                        as &(dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait2)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait2)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x
-                        as &(dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
-                },
-            );
-        };
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro]
+pub fn register_all(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as AllRegisterInput);
 
-        // Use prettyplease to format the output and expected output
-        let output = pretty_print_item(output_tokens);
-        let expected_output = pretty_print_item(expected_output);
+    let output = generate_all_registration(
+        &input.ty,
+        &input.traits,
+        &input.markers,
+        &std::env::var("CARGO_CRATE_NAME").unwrap(),
+    );
+    output.into()
+}
 
-        // Assert that the output matches the expected output
-        assert_eq!(output, expected_output);
+// This function contains the core logic and can be reused in tests
+fn generate_smartptr_func(item_fn: &syn::ItemFn) -> proc_macro2::TokenStream {
+    let name = &item_fn.sig.ident;
+    let name_str = name.to_string();
+    // `#[ocaml_gen::func]` generates a `<name>_to_ocaml` function next to the
+    // annotated one, which is what `decl_func!` calls under the hood
+    let to_ocaml = format_ident!("{}_to_ocaml", name);
+    // Stubs run with the domain lock held, which makes `MlBox` recoverable.
+    // The body is moved into a closure owning the arguments, so that a failed
+    // coercion unwinds them before `Coercion_error` is raised. Its return type
+    // is inferred from the one of the stub, which may be `impl Trait`
+    let syn::ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item_fn;
+
+    quote! {
+        #[ocaml_gen::func]
+        #[ocaml::func]
+        #(#attrs)*
+        #vis #sig {
+            ocaml_rs_smartptr::ml_box::mark_ocaml_thread();
+            ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || #block)
+        }
+
+        ocaml_rs_smartptr::inventory::submit! {
+            ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                std::env!("CARGO_PKG_NAME"),
+                std::module_path!(),
+                #name_str,
+                #to_ocaml,
+            )
+        }
     }
+}
 
-    #[test]
-    fn test_register_traits_macro_global() {
-        // Define the input to the core function
-        let ty: TypePath = parse_quote! { crate::test_types::MyType };
-        let marker_traits: Vec<Path> = vec![
-            parse_quote! { core::marker::Send },
-            parse_quote! { core::marker::Sync },
-        ];
-        let object_safe_traits: Vec<Path> = vec![parse_quote! { std::error::Error }];
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro_attribute]
+pub fn smartptr_func(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item_fn = parse_macro_input!(item as syn::ItemFn);
 
-        // Generate the actual output using the core logic function
-        let output_tokens = generate_type_registration(
-            &ty,
-            &marker_traits,
-            &object_safe_traits,
-            "this_crate",
-        );
+    let output = generate_smartptr_func(&item_fn);
+    output.into()
+}
 
-        let expected_output = quote! {
-            ocaml_rs_smartptr::registry::register_type::<crate::test_types::MyType>();
-            ocaml_rs_smartptr::registry::register_type_info::<
-                crate::test_types::MyType,
-            >(
-                "this_crate::test_types::MyType",
-                vec![
-                    "this_crate::test_types::MyType",
-                    "core::marker::Send",
-                    "core::marker::Sync",
-                    "std::error::Error"
-                ],
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                crate::test_types::MyType,
-            >(
-                |x: &crate::test_types::MyType| x as &crate::test_types::MyType,
-                |x: &mut crate::test_types::MyType| x as &mut crate::test_types::MyType,
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn ::std::error::Error,
-            >(
-                |x: &crate::test_types::MyType| x as &dyn ::std::error::Error,
-                |x: &mut crate::test_types::MyType| x as &mut dyn ::std::error::Error,
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn ::std::error::Error,
-            >(
-                |x: &crate::test_types::MyType| x as &(dyn ::std::error::Error),
-                |x: &mut crate::test_types::MyType| x as &mut (dyn ::std::error::Error),
-            );
-            ocaml_rs_smartptr::registry::register::<
-                crate::test_types::MyType,
-                dyn ::std::error::Error + ::core::marker::Send,
-            >(
-                |x: &crate::test_types::MyType| {
-                    x as &(dyn ::std::error::Error + ::core::marker::Send)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x as &mut (dyn ::std::error::Error + ::core::marker::Send)
-                },
-            );
-            ocaml_rs_smartptr::registry::register::<
+// Checks for `#[smartptr(set)]`, which requests a setter for a field
+fn has_setter_attr(attrs: &[syn::Attribute]) -> syn::Result<bool> {
+    let mut set = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("smartptr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("set") {
+                set = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported smartptr attribute"))
+            }
+        })?;
+    }
+    Ok(set)
+}
+
+// This function contains the core logic and can be reused in tests
+fn generate_accessors(input: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "OcamlAccessors can't be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "OcamlAccessors can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let ty = &input.ident;
+    let vis = &input.vis;
+    let prefix = to_snake_case(&ty.to_string());
+    let mut output = proc_macro2::TokenStream::new();
+    let mut to_ocaml_calls = Vec::new();
+    for field in fields {
+        let set = has_setter_attr(&field.attrs)?;
+        if !matches!(field.vis, syn::Visibility::Public(_)) {
+            if set {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "setters can only be generated for public fields",
+                ));
+            }
+            continue;
+        }
+        let field_name = field.ident.as_ref().expect("named field has no name");
+        let field_ty = &field.ty;
+        let ocaml_name = field_name.to_string();
+        let stub = format_ident!("{}_{}", prefix, field_name);
+        let stub_str = stub.to_string();
+        let to_ocaml = format_ident!("{}_to_ocaml", stub);
+        // `Clone::clone` is a plain copy for `Copy` fields
+        output.extend(quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            #vis fn #stub(this: ocaml_rs_smartptr::ptr::DynBox<#ty>) -> #field_ty {
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let this = this.coerce();
+                    ::std::clone::Clone::clone(&this.#field_name)
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    #stub_str,
+                    #to_ocaml,
+                )
+            }
+        });
+        to_ocaml_calls.push(quote! { #to_ocaml(env, Some(#ocaml_name)) });
+        if !set {
+            continue;
+        }
+        let ocaml_name = format!("set_{}", field_name);
+        let stub = format_ident!("{}_set_{}", prefix, field_name);
+        let stub_str = stub.to_string();
+        let to_ocaml = format_ident!("{}_to_ocaml", stub);
+        // Immutable boxes fail the coercion, as usual for write coercions
+        output.extend(quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            #vis fn #stub(this: ocaml_rs_smartptr::ptr::DynBox<#ty>, value: #field_ty) {
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let mut this = this.coerce_mut();
+                    this.#field_name = value;
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    #stub_str,
+                    #to_ocaml,
+                )
+            }
+        });
+        to_ocaml_calls.push(quote! { #to_ocaml(env, Some(#ocaml_name)) });
+    }
+
+    output.extend(quote! {
+        impl ocaml_rs_smartptr::ocaml_gen_extras::OcamlAccessors for #ty {
+            fn accessors_to_ocaml(env: &ocaml_gen::Env) -> Vec<String> {
+                vec![#(#to_ocaml_calls),*]
+            }
+        }
+    });
+    Ok(output)
+}
+
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro_derive(OcamlAccessors, attributes(smartptr))]
+pub fn derive_ocaml_accessors(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+
+    generate_accessors(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct ConstructorsInput {
+    ty: syn::Type,
+    name: syn::LitStr,
+    args: Vec<syn::Type>,
+    constructor: Option<Path>,
+}
+
+impl Parse for ConstructorsInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ty = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let content;
+        let _ = syn::bracketed!(content in input);
+        let args = Punctuated::<syn::Type, Token![,]>::parse_terminated(&content)?;
+        let constructor = if input.peek(Token![=>]) {
+            input.parse::<Token![=>]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        input.parse::<Token![,]>().ok(); // Optional trailing comma
+
+        Ok(ConstructorsInput {
+            ty,
+            name,
+            args: args.into_iter().collect(),
+            constructor,
+        })
+    }
+}
+
+// This function contains the core logic and can be reused in tests
+fn generate_constructors_impl(input: &ConstructorsInput) -> proc_macro2::TokenStream {
+    let ty = &input.ty;
+    let prefix = to_snake_case(&input.name.value());
+    let arg_names: Vec<_> = (0..input.args.len())
+        .map(|i| format_ident!("arg{}", i))
+        .collect();
+    let arg_tys = &input.args;
+    let constructor = match &input.constructor {
+        Some(constructor) => quote! { #constructor },
+        None => quote! { <#ty>::new },
+    };
+
+    let mut output = proc_macro2::TokenStream::new();
+    let mut to_ocaml_calls = Vec::new();
+    for (ocaml_name, dynbox_new) in [
+        ("create_exclusive", quote! { new_exclusive }),
+        ("create_shared", quote! { new_shared }),
+    ] {
+        let stub = format_ident!("{}_{}", prefix, ocaml_name);
+        let stub_str = stub.to_string();
+        let to_ocaml = format_ident!("{}_to_ocaml", stub);
+        output.extend(quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn #stub(#(#arg_names: #arg_tys),*) -> ocaml_rs_smartptr::ptr::DynBox<#ty> {
+                ocaml_rs_smartptr::ptr::DynBox::#dynbox_new(#constructor(#(#arg_names),*))
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    #stub_str,
+                    #to_ocaml,
+                )
+            }
+        });
+        to_ocaml_calls.push(quote! { #to_ocaml(env, Some(#ocaml_name)) });
+    }
+
+    // Registered under the name rather than implemented for the type, so
+    // that types of other crates can be given constructors too
+    let name = &input.name;
+    let constructors_to_ocaml = format_ident!("{}_constructors_to_ocaml", prefix);
+    output.extend(quote! {
+        fn #constructors_to_ocaml(env: &ocaml_gen::Env) -> Vec<String> {
+            vec![#(#to_ocaml_calls),*]
+        }
+
+        ocaml_rs_smartptr::inventory::submit! {
+            ocaml_rs_smartptr::ocaml_gen_extras::OcamlConstructors::new(
+                std::env!("CARGO_PKG_NAME"),
+                #name,
+                #constructors_to_ocaml,
+            )
+        }
+    });
+    output
+}
+
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro]
+pub fn generate_constructors(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ConstructorsInput);
+
+    generate_constructors_impl(&input).into()
+}
+
+// Checks if the type is literally `f64` or `f32`
+fn is_float_type(ty: &syn::Type) -> bool {
+    matches!(ty, syn::Type::Path(path) if path.qself.is_none()
+        && (path.path.is_ident("f64") || path.path.is_ident("f32")))
+}
+
+// This function contains the core logic and can be reused in tests
+fn generate_ocaml_record(
+    input: &syn::DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "OcamlRecord can't be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) if !fields.named.is_empty() => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "OcamlRecord can only be derived for structs with named fields",
+            ))
+        }
+    };
+    if fields.iter().all(|field| is_float_type(&field.ty)) {
+        // OCaml stores such records as unboxed float arrays
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "OcamlRecord can't be derived for structs with float fields only, \
+             as OCaml unboxes such records",
+        ));
+    }
+
+    let ty = &input.ident;
+    let type_name = to_snake_case(&ty.to_string());
+    let size = fields.len();
+    let mut field_decls = Vec::new();
+    let mut stores = Vec::new();
+    let mut loads = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let field_name = field.ident.as_ref().expect("named field has no name");
+        let field_ty = &field.ty;
+        let ocaml_name = field_name.to_string();
+        field_decls.push(quote! {
+            ocaml_rs_smartptr::record::field_decl::<#field_ty>(env, #ocaml_name)
+        });
+        stores.push(quote! {
+            ocaml_rs_smartptr::record::store(gc, &mut record, #index, &self.#field_name);
+        });
+        loads.push(quote! {
+            #field_name: ocaml_rs_smartptr::record::field(&v, #index)
+        });
+    }
+
+    Ok(quote! {
+        unsafe impl ocaml::ToValue for #ty {
+            fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+                let mut record = ocaml_rs_smartptr::record::alloc(#size);
+                #(#stores)*
+                record
+            }
+        }
+
+        unsafe impl ocaml::FromValue for #ty {
+            fn from_value(v: ocaml::Value) -> Self {
+                #ty {
+                    #(#loads),*
+                }
+            }
+        }
+
+        impl ocaml_gen::OCamlDesc for #ty {
+            fn ocaml_desc(env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+                env.get_type(<Self as ocaml_gen::OCamlDesc>::unique_id(), #type_name).0
+            }
+
+            fn unique_id() -> u128 {
+                ocaml_rs_smartptr::ocaml_gen_extras::stable_unique_id(
+                    "OcamlRecord",
+                    &::std::any::TypeId::of::<Self>(),
+                )
+            }
+        }
+
+        impl ocaml_gen::OCamlBinding for #ty {
+            fn ocaml_binding(
+                env: &mut ocaml_gen::Env,
+                rename: Option<&'static str>,
+                new_type: bool,
+            ) -> String {
+                ocaml_rs_smartptr::record::record_binding(
+                    env,
+                    rename,
+                    new_type,
+                    <Self as ocaml_gen::OCamlDesc>::unique_id(),
+                    #type_name,
+                    |env| vec![#(#field_decls),*],
+                )
+            }
+        }
+    })
+}
+
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro_derive(OcamlRecord)]
+pub fn derive_ocaml_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+
+    generate_ocaml_record(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// This function contains the core logic and can be reused in tests
+fn generate_ocaml_poly_variant(
+    input: &syn::DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "OcamlPolyVariant can't be derived for generic types",
+        ));
+    }
+    let variants = match &input.data {
+        syn::Data::Enum(data) if !data.variants.is_empty() => &data.variants,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "OcamlPolyVariant can only be derived for non-empty enums",
+            ))
+        }
+    };
+
+    let ty = &input.ident;
+    let type_name = to_snake_case(&ty.to_string());
+    let mut variant_decls = Vec::new();
+    let mut to_arms = Vec::new();
+    let mut from_arms = Vec::new();
+    for variant in variants {
+        let fields = match &variant.fields {
+            syn::Fields::Unit => None,
+            syn::Fields::Unnamed(fields) => Some(&fields.unnamed),
+            syn::Fields::Named(_) => {
+                return Err(syn::Error::new(
+                    variant.ident.span(),
+                    "OcamlPolyVariant doesn't support variants with named fields, \
+                     wrap the fields into an OcamlRecord struct instead",
+                ))
+            }
+        };
+        let variant_name = &variant.ident;
+        let tag = variant_name.to_string();
+        let Some(fields) = fields.filter(|fields| !fields.is_empty()) else {
+            let pattern = match &variant.fields {
+                syn::Fields::Unit => quote! { #ty::#variant_name },
+                _ => quote! { #ty::#variant_name() },
+            };
+            variant_decls.push(quote! {
+                ocaml_rs_smartptr::poly_variant::variant_decl(#tag, &[])
+            });
+            to_arms.push(quote! {
+                #pattern => ocaml_rs_smartptr::poly_variant::constant(gc, #tag)
+            });
+            from_arms.push(quote! {
+                hash if hash == ocaml_rs_smartptr::poly_variant::hash(#tag) => #pattern
+            });
+            continue;
+        };
+
+        let args: Vec<_> = (0..fields.len())
+            .map(|i| format_ident!("arg{}", i))
+            .collect();
+        let arg_decls = fields.iter().map(|field| {
+            let field_ty = &field.ty;
+            quote! { ocaml_rs_smartptr::poly_variant::arg_decl::<#field_ty>(env) }
+        });
+        variant_decls.push(quote! {
+            ocaml_rs_smartptr::poly_variant::variant_decl(#tag, &[#(#arg_decls),*])
+        });
+        if fields.len() == 1 {
+            to_arms.push(quote! {
+                #ty::#variant_name(arg0) => ocaml_rs_smartptr::poly_variant::with_arg(
+                    gc,
+                    #tag,
+                    ocaml::ToValue::to_value(arg0, gc),
+                )
+            });
+            from_arms.push(quote! {
+                hash if hash == ocaml_rs_smartptr::poly_variant::hash(#tag) => {
+                    let arg = ocaml_rs_smartptr::poly_variant::arg(#tag, arg);
+                    #ty::#variant_name(ocaml::FromValue::from_value(arg))
+                }
+            });
+        } else {
+            let size = fields.len();
+            let indices = 0..size;
+            let stores = args.iter().enumerate().map(|(index, arg)| {
+                quote! {
+                    ocaml_rs_smartptr::record::store(gc, &mut args, #index, #arg);
+                }
+            });
+            to_arms.push(quote! {
+                #ty::#variant_name(#(#args),*) => {
+                    let mut args = ocaml_rs_smartptr::poly_variant::alloc_args(#size);
+                    #(#stores)*
+                    ocaml_rs_smartptr::poly_variant::with_arg(gc, #tag, args)
+                }
+            });
+            from_arms.push(quote! {
+                hash if hash == ocaml_rs_smartptr::poly_variant::hash(#tag) => {
+                    let args = ocaml_rs_smartptr::poly_variant::arg(#tag, arg);
+                    #ty::#variant_name(#(ocaml_rs_smartptr::record::field(&args, #indices)),*)
+                }
+            });
+        }
+    }
+
+    let ty_name = ty.to_string();
+    Ok(quote! {
+        unsafe impl ocaml::ToValue for #ty {
+            fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+                match self {
+                    #(#to_arms),*
+                }
+            }
+        }
+
+        unsafe impl ocaml::FromValue for #ty {
+            fn from_value(v: ocaml::Value) -> Self {
+                #[allow(unused_variables)]
+                let (hash, arg) = ocaml_rs_smartptr::poly_variant::destructure(v);
+                match hash {
+                    #(#from_arms,)*
+                    hash => ocaml_rs_smartptr::poly_variant::unknown_tag(#ty_name, hash),
+                }
+            }
+        }
+
+        impl ocaml_gen::OCamlDesc for #ty {
+            fn ocaml_desc(env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+                env.get_type(<Self as ocaml_gen::OCamlDesc>::unique_id(), #type_name).0
+            }
+
+            fn unique_id() -> u128 {
+                ocaml_rs_smartptr::ocaml_gen_extras::stable_unique_id(
+                    "OcamlPolyVariant",
+                    &::std::any::TypeId::of::<Self>(),
+                )
+            }
+        }
+
+        impl ocaml_gen::OCamlBinding for #ty {
+            fn ocaml_binding(
+                env: &mut ocaml_gen::Env,
+                rename: Option<&'static str>,
+                new_type: bool,
+            ) -> String {
+                ocaml_rs_smartptr::poly_variant::variant_binding(
+                    env,
+                    rename,
+                    new_type,
+                    <Self as ocaml_gen::OCamlDesc>::unique_id(),
+                    #type_name,
+                    |env| vec![#(#variant_decls),*],
+                )
+            }
+        }
+    })
+}
+
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro_derive(OcamlPolyVariant)]
+pub fn derive_ocaml_poly_variant(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+
+    generate_ocaml_poly_variant(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// Looks up `key = "value"` in `#[module(...)]` attributes
+fn module_attr(attrs: &[syn::Attribute], key: &str) -> syn::Result<Option<syn::LitStr>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("module")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                value = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported module attribute"))
+            }
+        })?;
+    }
+    Ok(value)
+}
+
+// This function contains the core logic and can be reused in tests
+fn generate_from_module(
+    input: &syn::DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "FromModule can't be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "FromModule can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let ty = &input.ident;
+    let module_type = match module_attr(&input.attrs, "type_name")? {
+        Some(name) => name.value(),
+        None => ty.to_string(),
+    };
+    let mut value_decls = Vec::new();
+    let mut projections = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let field_name = field.ident.as_ref().expect("named field has no name");
+        let field_ty = &field.ty;
+        let ocaml_name = match module_attr(&field.attrs, "name")? {
+            Some(name) => name.value(),
+            None => field_name.to_string(),
+        };
+        value_decls.push(quote! {
+            ocaml_rs_smartptr::module::value_decl::<#field_ty>(env, #ocaml_name)
+        });
+        projections.push(quote! {
+            #field_name: ocaml_rs_smartptr::module::project(&v, #index)
+        });
+    }
+
+    Ok(quote! {
+        impl ocaml_rs_smartptr::module::FromModule for #ty {
+            const MODULE_TYPE: &'static str = #module_type;
+
+            fn module_type_to_ocaml(env: &ocaml_gen::Env) -> String {
+                ocaml_rs_smartptr::module::module_type_decl(
+                    Self::MODULE_TYPE,
+                    &[#(#value_decls),*],
+                )
+            }
+        }
+
+        unsafe impl ocaml::FromValue for #ty {
+            fn from_value(v: ocaml::Value) -> Self {
+                #ty {
+                    #(#projections),*
+                }
+            }
+        }
+
+        impl ocaml_gen::OCamlDesc for #ty {
+            fn ocaml_desc(_env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+                format!("(module {})", #module_type)
+            }
+
+            fn unique_id() -> u128 {
+                ocaml_rs_smartptr::ocaml_gen_extras::stable_unique_id(
+                    "FromModule",
+                    &::std::any::TypeId::of::<Self>(),
+                )
+            }
+        }
+    })
+}
+
+// The procedural macro itself just handles parsing and calling the core logic
+#[proc_macro_derive(FromModule, attributes(module))]
+pub fn derive_from_module(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+
+    generate_from_module(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[cfg(test)]
+mod generation_tests {
+    use super::*;
+
+    use quote::quote;
+    use syn::{parse_quote, Path, TypePath};
+
+    fn pretty_print_item(ts: proc_macro2::TokenStream) -> String {
+        let code = "fn main() {\n".to_owned() + &ts.to_string() + "\n}";
+        let file = syn::parse_file(&code).unwrap();
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn test_register_traits_macro_crate() {
+        // Define the input to the core function
+        let ty: TypePath = parse_quote! { crate::test_types::MyType };
+        let marker_traits: Vec<Path> = vec![
+            parse_quote! { crate::test_types::MyMarkerTrait1 },
+            parse_quote! { crate::test_types::MyMarkerTrait2 },
+        ];
+        let object_safe_traits: Vec<Path> = vec![
+            parse_quote! { crate::test_types::MyObjectSafeTrait1 },
+            parse_quote! { crate::test_types::MyObjectSafeTrait2 },
+        ];
+
+        // Generate the actual output using the core logic function
+        let output_tokens = generate_type_registration(
+            &ty,
+            &marker_traits,
+            &object_safe_traits,
+            "this_crate",
+        );
+
+        let expected_output = quote! {
+            ocaml_rs_smartptr::registry::register_type::<crate::test_types::MyType>();
+            ocaml_rs_smartptr::registry::register_type_info::<
                 crate::test_types::MyType,
-                dyn ::std::error::Error + ::core::marker::Sync,
             >(
-                |x: &crate::test_types::MyType| {
-                    x as &(dyn ::std::error::Error + ::core::marker::Sync)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x as &mut (dyn ::std::error::Error + ::core::marker::Sync)
-                },
+                "this_crate",
+                "this_crate::test_types::MyType",
+                vec![
+                    "this_crate::test_types::MyType",
+                    "this_crate::test_types::MyMarkerTrait1",
+                    "this_crate::test_types::MyMarkerTrait2",
+                    "this_crate::test_types::MyObjectSafeTrait1",
+                    "this_crate::test_types::MyObjectSafeTrait2"
+                ],
             );
-            ocaml_rs_smartptr::registry::register::<
+            const _: fn() = || {
+                fn assert_object_safe(_: &dyn crate::test_types::MyObjectSafeTrait1) {}
+            };
+            const _: fn() = || {
+                fn assert_object_safe(_: &dyn crate::test_types::MyObjectSafeTrait2) {}
+            };
+            ocaml_rs_smartptr::registry::register_multi::<crate::test_types::MyType>(&[
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, crate::test_types::MyType>::new(
+                    |x: &crate::test_types::MyType| x as &crate::test_types::MyType,
+                    |x: &mut crate::test_types::MyType| x as &mut crate::test_types::MyType,
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait1>::new(
+                    |x: &crate::test_types::MyType| x as &dyn crate::test_types::MyObjectSafeTrait1,
+                    |x: &mut crate::test_types::MyType| {
+                        x as &mut dyn crate::test_types::MyObjectSafeTrait1
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait1>::new(
+                    |x: &crate::test_types::MyType| {
+                        x as &(dyn crate::test_types::MyObjectSafeTrait1)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x as &mut (dyn crate::test_types::MyObjectSafeTrait1)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1>::new(
+                    |x: &crate::test_types::MyType| {
+                        x
+                            as &(dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait2>::new(
+                    |x: &crate::test_types::MyType| {
+                        x
+                            as &(dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait2)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait2)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2>::new(
+                    |x: &crate::test_types::MyType| {
+                        x
+                            as &(dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn crate::test_types::MyObjectSafeTrait1 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait2>::new(
+                    |x: &crate::test_types::MyType| x as &dyn crate::test_types::MyObjectSafeTrait2,
+                    |x: &mut crate::test_types::MyType| {
+                        x as &mut dyn crate::test_types::MyObjectSafeTrait2
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait2>::new(
+                    |x: &crate::test_types::MyType| {
+                        x as &(dyn crate::test_types::MyObjectSafeTrait2)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x as &mut (dyn crate::test_types::MyObjectSafeTrait2)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1>::new(
+                    |x: &crate::test_types::MyType| {
+                        x
+                            as &(dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait2>::new(
+                    |x: &crate::test_types::MyType| {
+                        x
+                            as &(dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait2)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait2)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2>::new(
+                    |x: &crate::test_types::MyType| {
+                        x
+                            as &(dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn crate::test_types::MyObjectSafeTrait2 + crate::test_types::MyMarkerTrait1 + crate::test_types::MyMarkerTrait2)
+                    },
+                ),
+            ]);
+        };
+
+        // Use prettyplease to format the output and expected output
+        let output = pretty_print_item(output_tokens);
+        let expected_output = pretty_print_item(expected_output);
+
+        // Assert that the output matches the expected output
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_register_traits_macro_global() {
+        // Define the input to the core function
+        let ty: TypePath = parse_quote! { crate::test_types::MyType };
+        let marker_traits: Vec<Path> = vec![
+            parse_quote! { core::marker::Send },
+            parse_quote! { core::marker::Sync },
+        ];
+        let object_safe_traits: Vec<Path> = vec![parse_quote! { std::error::Error }];
+
+        // Generate the actual output using the core logic function
+        let output_tokens = generate_type_registration(
+            &ty,
+            &marker_traits,
+            &object_safe_traits,
+            "this_crate",
+        );
+
+        let expected_output = quote! {
+            ocaml_rs_smartptr::registry::register_type::<crate::test_types::MyType>();
+            ocaml_rs_smartptr::registry::register_type_info::<
                 crate::test_types::MyType,
-                dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync,
             >(
-                |x: &crate::test_types::MyType| {
-                    x as &(dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync)
-                },
-                |x: &mut crate::test_types::MyType| {
-                    x
-                        as &mut (dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync)
-                },
+                "this_crate",
+                "this_crate::test_types::MyType",
+                vec![
+                    "this_crate::test_types::MyType",
+                    "core::marker::Send",
+                    "core::marker::Sync",
+                    "std::error::Error"
+                ],
             );
+            const _: fn() = || {
+                fn assert_object_safe(_: &dyn ::std::error::Error) {}
+            };
+            ocaml_rs_smartptr::registry::register_multi::<crate::test_types::MyType>(&[
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, crate::test_types::MyType>::new(
+                    |x: &crate::test_types::MyType| x as &crate::test_types::MyType,
+                    |x: &mut crate::test_types::MyType| x as &mut crate::test_types::MyType,
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn ::std::error::Error>::new(
+                    |x: &crate::test_types::MyType| x as &dyn ::std::error::Error,
+                    |x: &mut crate::test_types::MyType| x as &mut dyn ::std::error::Error,
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn ::std::error::Error>::new(
+                    |x: &crate::test_types::MyType| x as &(dyn ::std::error::Error),
+                    |x: &mut crate::test_types::MyType| x as &mut (dyn ::std::error::Error),
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn ::std::error::Error + ::core::marker::Send>::new(
+                    |x: &crate::test_types::MyType| {
+                        x as &(dyn ::std::error::Error + ::core::marker::Send)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x as &mut (dyn ::std::error::Error + ::core::marker::Send)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn ::std::error::Error + ::core::marker::Sync>::new(
+                    |x: &crate::test_types::MyType| {
+                        x as &(dyn ::std::error::Error + ::core::marker::Sync)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x as &mut (dyn ::std::error::Error + ::core::marker::Sync)
+                    },
+                ),
+                &ocaml_rs_smartptr::registry::Coercion::<crate::test_types::MyType, dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>::new(
+                    |x: &crate::test_types::MyType| {
+                        x as &(dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync)
+                    },
+                    |x: &mut crate::test_types::MyType| {
+                        x
+                            as &mut (dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync)
+                    },
+                ),
+            ]);
         };
 
         // Use prettyplease to format the output and expected output
@@ -692,6 +1585,7 @@ mod generation_tests {
             ocaml_rs_smartptr::registry::register_type_info::<
                 dyn ::std::error::Error,
             >(
+                "this_crate",
                 "std::error::Error",
                 vec!["std::error::Error", "std::fmt::Display", "core::fmt::Debug"],
             );
@@ -722,6 +1616,7 @@ mod generation_tests {
             ocaml_rs_smartptr::registry::register_type_info::<
                 dyn ::std::error::Error + ::core::marker::Send,
             >(
+                "this_crate",
                 "std::error::Error",
                 vec![
                     "std::error::Error", "core::marker::Send", "std::fmt::Display",
@@ -735,6 +1630,13 @@ mod generation_tests {
                 |x: &Box<dyn ::std::error::Error + ::core::marker::Send>| x.as_ref(),
                 |x: &mut Box<dyn ::std::error::Error + ::core::marker::Send>| x.as_mut(),
             );
+            ocaml_rs_smartptr::registry::register::<
+                Box<dyn ::std::error::Error + ::core::marker::Send>,
+                dyn ::std::error::Error,
+            >(
+                |x: &Box<dyn ::std::error::Error + ::core::marker::Send>| x.as_ref(),
+                |x: &mut Box<dyn ::std::error::Error + ::core::marker::Send>| x.as_mut(),
+            );
             ocaml_rs_smartptr::registry::register::<
                 Box<dyn ::std::error::Error + ::core::marker::Send>,
                 dyn std::fmt::Display,
@@ -755,6 +1657,7 @@ mod generation_tests {
             ocaml_rs_smartptr::registry::register_type_info::<
                 dyn ::std::error::Error + ::core::marker::Sync,
             >(
+                "this_crate",
                 "std::error::Error",
                 vec![
                     "std::error::Error", "core::marker::Sync", "std::fmt::Display",
@@ -768,6 +1671,13 @@ mod generation_tests {
                 |x: &Box<dyn ::std::error::Error + ::core::marker::Sync>| x.as_ref(),
                 |x: &mut Box<dyn ::std::error::Error + ::core::marker::Sync>| x.as_mut(),
             );
+            ocaml_rs_smartptr::registry::register::<
+                Box<dyn ::std::error::Error + ::core::marker::Sync>,
+                dyn ::std::error::Error,
+            >(
+                |x: &Box<dyn ::std::error::Error + ::core::marker::Sync>| x.as_ref(),
+                |x: &mut Box<dyn ::std::error::Error + ::core::marker::Sync>| x.as_mut(),
+            );
             ocaml_rs_smartptr::registry::register::<
                 Box<dyn ::std::error::Error + ::core::marker::Sync>,
                 dyn std::fmt::Display,
@@ -788,6 +1698,7 @@ mod generation_tests {
             ocaml_rs_smartptr::registry::register_type_info::<
                 dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync,
             >(
+                "this_crate",
                 "std::error::Error",
                 vec![
                     "std::error::Error", "core::marker::Send", "core::marker::Sync",
@@ -798,14 +1709,35 @@ mod generation_tests {
                 Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>,
                 dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync,
             >(
-                |x: &Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| {
-                    x.as_ref()
-                },
-                |
-                    x: &mut Box<
-                        dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync,
-                    >|
-                x.as_mut(),
+                |x: &Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| {
+                    x.as_ref()
+                },
+                |
+                    x: &mut Box<
+                        dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync,
+                    >|
+                x.as_mut(),
+            );
+            ocaml_rs_smartptr::registry::register::<
+                Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>,
+                dyn ::std::error::Error,
+            >(
+                |x: &Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| x.as_ref(),
+                |x: &mut Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| x.as_mut(),
+            );
+            ocaml_rs_smartptr::registry::register::<
+                Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>,
+                dyn ::std::error::Error + ::core::marker::Send,
+            >(
+                |x: &Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| x.as_ref(),
+                |x: &mut Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| x.as_mut(),
+            );
+            ocaml_rs_smartptr::registry::register::<
+                Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>,
+                dyn ::std::error::Error + ::core::marker::Sync,
+            >(
+                |x: &Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| x.as_ref(),
+                |x: &mut Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>| x.as_mut(),
             );
             ocaml_rs_smartptr::registry::register::<
                 Box<dyn ::std::error::Error + ::core::marker::Send + ::core::marker::Sync>,
@@ -842,6 +1774,615 @@ mod generation_tests {
         // Assert that the output matches the expected output
         assert_eq!(output, expected_output);
     }
+
+    #[test]
+    fn test_register_type_assoc_type_trait() {
+        let ty: TypePath = parse_quote! { crate::RowIter };
+        let marker_traits: Vec<Path> = vec![];
+        let object_safe_traits: Vec<Path> =
+            vec![parse_quote! { core::iter::Iterator<Item = crate::Row> }];
+
+        let output_tokens = generate_type_registration(
+            &ty,
+            &marker_traits,
+            &object_safe_traits,
+            "this_crate",
+        );
+        let output = output_tokens.to_string();
+
+        // Paths in generic arguments are resolved as well, so the tag doesn't
+        // collide with the same trait instantiated in another crate
+        let expected_info = quote! {
+            ocaml_rs_smartptr::registry::register_type_info::<crate::RowIter>(
+                "this_crate",
+                "this_crate::RowIter",
+                vec![
+                    "this_crate::RowIter",
+                    "core::iter::Iterator < Item = this_crate::Row >"
+                ]
+            );
+        };
+        assert!(output.contains(&expected_info.to_string()));
+
+        let expected_assert = quote! {
+            fn assert_object_safe(_: &dyn ::core::iter::Iterator<Item = crate::Row>) {}
+        };
+        assert!(output.contains(&expected_assert.to_string()));
+
+        let expected_coercion = quote! {
+            &ocaml_rs_smartptr::registry::Coercion::<
+                crate::RowIter,
+                dyn ::core::iter::Iterator<Item = crate::Row>
+            >::new(
+                |x: &crate::RowIter| x as &dyn ::core::iter::Iterator<Item = crate::Row>,
+                |x: &mut crate::RowIter| x as &mut dyn ::core::iter::Iterator<Item = crate::Row>
+            )
+        };
+        assert!(output.contains(&expected_coercion.to_string()));
+    }
+
+    #[test]
+    fn test_smartptr_func() {
+        let item_fn: syn::ItemFn = parse_quote! {
+            pub fn animal_name(animal: DynBox<Animal>) -> String {
+                let animal = animal.coerce();
+                animal.name()
+            }
+        };
+
+        let output = generate_smartptr_func(&item_fn);
+
+        let expected_output = quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn animal_name(animal: DynBox<Animal>) -> String {
+                ocaml_rs_smartptr::ml_box::mark_ocaml_thread();
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let animal = animal.coerce();
+                    animal.name()
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "animal_name",
+                    animal_name_to_ocaml,
+                )
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+    }
+
+    #[test]
+    fn test_ocaml_accessors() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Pen {
+                pub name: String,
+                pub capacity: i64,
+                secret: u8,
+            }
+        };
+
+        let output = generate_accessors(&input).unwrap();
+
+        let expected_output = quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn pen_name(this: ocaml_rs_smartptr::ptr::DynBox<Pen>) -> String {
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let this = this.coerce();
+                    ::std::clone::Clone::clone(&this.name)
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "pen_name",
+                    pen_name_to_ocaml,
+                )
+            }
+
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn pen_capacity(this: ocaml_rs_smartptr::ptr::DynBox<Pen>) -> i64 {
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let this = this.coerce();
+                    ::std::clone::Clone::clone(&this.capacity)
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "pen_capacity",
+                    pen_capacity_to_ocaml,
+                )
+            }
+
+            impl ocaml_rs_smartptr::ocaml_gen_extras::OcamlAccessors for Pen {
+                fn accessors_to_ocaml(env: &ocaml_gen::Env) -> Vec<String> {
+                    vec![
+                        pen_name_to_ocaml(env, Some("name")),
+                        pen_capacity_to_ocaml(env, Some("capacity"))
+                    ]
+                }
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+    }
+
+    #[test]
+    fn test_ocaml_accessors_setters() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Pen {
+                #[smartptr(set)]
+                pub capacity: i64,
+            }
+        };
+
+        let output = generate_accessors(&input).unwrap();
+
+        let expected_output = quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn pen_capacity(this: ocaml_rs_smartptr::ptr::DynBox<Pen>) -> i64 {
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let this = this.coerce();
+                    ::std::clone::Clone::clone(&this.capacity)
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "pen_capacity",
+                    pen_capacity_to_ocaml,
+                )
+            }
+
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn pen_set_capacity(this: ocaml_rs_smartptr::ptr::DynBox<Pen>, value: i64) {
+                ocaml_rs_smartptr::registry::raise_coercion_errors(gc, move || {
+                    let mut this = this.coerce_mut();
+                    this.capacity = value;
+                })
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "pen_set_capacity",
+                    pen_set_capacity_to_ocaml,
+                )
+            }
+
+            impl ocaml_rs_smartptr::ocaml_gen_extras::OcamlAccessors for Pen {
+                fn accessors_to_ocaml(env: &ocaml_gen::Env) -> Vec<String> {
+                    vec![
+                        pen_capacity_to_ocaml(env, Some("capacity")),
+                        pen_set_capacity_to_ocaml(env, Some("set_capacity"))
+                    ]
+                }
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Pen {
+                #[smartptr(set)]
+                capacity: i64,
+            }
+        };
+        let err = generate_accessors(&input).unwrap_err();
+        assert!(err.to_string().contains("public fields"), "{}", err);
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Pen {
+                #[smartptr(get)]
+                pub capacity: i64,
+            }
+        };
+        assert!(generate_accessors(&input).is_err());
+    }
+
+    #[test]
+    fn test_generate_constructors() {
+        let input: ConstructorsInput = parse_quote! {
+            Pen, "Pen", [String, i64]
+        };
+
+        let output = generate_constructors_impl(&input);
+
+        let expected_output = quote! {
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn pen_create_exclusive(arg0: String, arg1: i64) -> ocaml_rs_smartptr::ptr::DynBox<Pen> {
+                ocaml_rs_smartptr::ptr::DynBox::new_exclusive(<Pen>::new(arg0, arg1))
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "pen_create_exclusive",
+                    pen_create_exclusive_to_ocaml,
+                )
+            }
+
+            #[ocaml_gen::func]
+            #[ocaml::func]
+            pub fn pen_create_shared(arg0: String, arg1: i64) -> ocaml_rs_smartptr::ptr::DynBox<Pen> {
+                ocaml_rs_smartptr::ptr::DynBox::new_shared(<Pen>::new(arg0, arg1))
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlGenFunc::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    std::module_path!(),
+                    "pen_create_shared",
+                    pen_create_shared_to_ocaml,
+                )
+            }
+
+            fn pen_constructors_to_ocaml(env: &ocaml_gen::Env) -> Vec<String> {
+                vec![
+                    pen_create_exclusive_to_ocaml(env, Some("create_exclusive")),
+                    pen_create_shared_to_ocaml(env, Some("create_shared"))
+                ]
+            }
+
+            ocaml_rs_smartptr::inventory::submit! {
+                ocaml_rs_smartptr::ocaml_gen_extras::OcamlConstructors::new(
+                    std::env!("CARGO_PKG_NAME"),
+                    "Pen",
+                    pen_constructors_to_ocaml,
+                )
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+    }
+
+    #[test]
+    fn test_generate_constructors_with_constructor() {
+        let input: ConstructorsInput = parse_quote! {
+            Sheep, "SheepWrapper", [] => animals::Sheep::default,
+        };
+
+        let output = generate_constructors_impl(&input).to_string();
+
+        let expected_body = quote! {
+            pub fn sheep_wrapper_create_shared() -> ocaml_rs_smartptr::ptr::DynBox<Sheep> {
+                ocaml_rs_smartptr::ptr::DynBox::new_shared(animals::Sheep::default())
+            }
+        };
+        assert!(output.contains(&expected_body.to_string()), "{}", output);
+    }
+
+    #[test]
+    fn test_ocaml_accessors_tuple_struct() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Pen(pub String);
+        };
+
+        let err = generate_accessors(&input).unwrap_err();
+        assert!(err.to_string().contains("structs with named fields"));
+    }
+
+    #[test]
+    fn test_from_module() {
+        let input: syn::DeriveInput = parse_quote! {
+            #[module(type_name = "S")]
+            pub struct Greeter {
+                pub greet: OCamlFunc<(String,), String>,
+                #[module(name = "bye")]
+                pub farewell: OCamlFunc<(String,), String>,
+            }
+        };
+
+        let output = generate_from_module(&input).unwrap();
+
+        let expected_output = quote! {
+            impl ocaml_rs_smartptr::module::FromModule for Greeter {
+                const MODULE_TYPE: &'static str = "S";
+
+                fn module_type_to_ocaml(env: &ocaml_gen::Env) -> String {
+                    ocaml_rs_smartptr::module::module_type_decl(
+                        Self::MODULE_TYPE,
+                        &[
+                            ocaml_rs_smartptr::module::value_decl::<OCamlFunc<(String,), String> >(env, "greet"),
+                            ocaml_rs_smartptr::module::value_decl::<OCamlFunc<(String,), String> >(env, "bye")
+                        ],
+                    )
+                }
+            }
+
+            unsafe impl ocaml::FromValue for Greeter {
+                fn from_value(v: ocaml::Value) -> Self {
+                    Greeter {
+                        greet: ocaml_rs_smartptr::module::project(&v, 0usize),
+                        farewell: ocaml_rs_smartptr::module::project(&v, 1usize)
+                    }
+                }
+            }
+
+            impl ocaml_gen::OCamlDesc for Greeter {
+                fn ocaml_desc(_env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+                    format!("(module {})", "S")
+                }
+
+                fn unique_id() -> u128 {
+                    ocaml_rs_smartptr::ocaml_gen_extras::stable_unique_id(
+                        "FromModule",
+                        &::std::any::TypeId::of::<Self>(),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+    }
+
+    #[test]
+    fn test_from_module_bad_attribute() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Greeter {
+                #[module(rename = "bye")]
+                pub farewell: OCamlFunc<(String,), String>,
+            }
+        };
+
+        let err = generate_from_module(&input).unwrap_err();
+        assert!(err.to_string().contains("unsupported module attribute"));
+    }
+
+    #[test]
+    fn test_ocaml_record() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct FlockStats {
+                pub count: i32,
+                pub names: Vec<String>,
+            }
+        };
+
+        let output = generate_ocaml_record(&input).unwrap();
+
+        let expected_output = quote! {
+            unsafe impl ocaml::ToValue for FlockStats {
+                fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+                    let mut record = ocaml_rs_smartptr::record::alloc(2usize);
+                    ocaml_rs_smartptr::record::store(gc, &mut record, 0usize, &self.count);
+                    ocaml_rs_smartptr::record::store(gc, &mut record, 1usize, &self.names);
+                    record
+                }
+            }
+
+            unsafe impl ocaml::FromValue for FlockStats {
+                fn from_value(v: ocaml::Value) -> Self {
+                    FlockStats {
+                        count: ocaml_rs_smartptr::record::field(&v, 0usize),
+                        names: ocaml_rs_smartptr::record::field(&v, 1usize)
+                    }
+                }
+            }
+
+            impl ocaml_gen::OCamlDesc for FlockStats {
+                fn ocaml_desc(env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+                    env.get_type(<Self as ocaml_gen::OCamlDesc>::unique_id(), "flock_stats").0
+                }
+
+                fn unique_id() -> u128 {
+                    ocaml_rs_smartptr::ocaml_gen_extras::stable_unique_id(
+                        "OcamlRecord",
+                        &::std::any::TypeId::of::<Self>(),
+                    )
+                }
+            }
+
+            impl ocaml_gen::OCamlBinding for FlockStats {
+                fn ocaml_binding(
+                    env: &mut ocaml_gen::Env,
+                    rename: Option<&'static str>,
+                    new_type: bool,
+                ) -> String {
+                    ocaml_rs_smartptr::record::record_binding(
+                        env,
+                        rename,
+                        new_type,
+                        <Self as ocaml_gen::OCamlDesc>::unique_id(),
+                        "flock_stats",
+                        |env| vec![
+                            ocaml_rs_smartptr::record::field_decl::<i32>(env, "count"),
+                            ocaml_rs_smartptr::record::field_decl::<Vec<String> >(env, "names")
+                        ],
+                    )
+                }
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+    }
+
+    #[test]
+    fn test_ocaml_record_of_floats() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub struct Point {
+                pub x: f64,
+                pub y: f64,
+            }
+        };
+
+        let err = generate_ocaml_record(&input).unwrap_err();
+        assert!(err.to_string().contains("float fields only"));
+    }
+
+    #[test]
+    fn test_ocaml_poly_variant() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub enum Shape {
+                Origin,
+                Circle(f64),
+                Point(i32, i32),
+            }
+        };
+
+        let output = generate_ocaml_poly_variant(&input).unwrap();
+
+        let expected_output = quote! {
+            unsafe impl ocaml::ToValue for Shape {
+                fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+                    match self {
+                        Shape::Origin => ocaml_rs_smartptr::poly_variant::constant(gc, "Origin"),
+                        Shape::Circle(arg0) => ocaml_rs_smartptr::poly_variant::with_arg(
+                            gc,
+                            "Circle",
+                            ocaml::ToValue::to_value(arg0, gc),
+                        ),
+                        Shape::Point(arg0, arg1) => {
+                            let mut args = ocaml_rs_smartptr::poly_variant::alloc_args(2usize);
+                            ocaml_rs_smartptr::record::store(gc, &mut args, 0usize, arg0);
+                            ocaml_rs_smartptr::record::store(gc, &mut args, 1usize, arg1);
+                            ocaml_rs_smartptr::poly_variant::with_arg(gc, "Point", args)
+                        }
+                    }
+                }
+            }
+
+            unsafe impl ocaml::FromValue for Shape {
+                fn from_value(v: ocaml::Value) -> Self {
+                    #[allow(unused_variables)]
+                    let (hash, arg) = ocaml_rs_smartptr::poly_variant::destructure(v);
+                    match hash {
+                        hash if hash == ocaml_rs_smartptr::poly_variant::hash("Origin") => Shape::Origin,
+                        hash if hash == ocaml_rs_smartptr::poly_variant::hash("Circle") => {
+                            let arg = ocaml_rs_smartptr::poly_variant::arg("Circle", arg);
+                            Shape::Circle(ocaml::FromValue::from_value(arg))
+                        },
+                        hash if hash == ocaml_rs_smartptr::poly_variant::hash("Point") => {
+                            let args = ocaml_rs_smartptr::poly_variant::arg("Point", arg);
+                            Shape::Point(
+                                ocaml_rs_smartptr::record::field(&args, 0usize),
+                                ocaml_rs_smartptr::record::field(&args, 1usize)
+                            )
+                        },
+                        hash => ocaml_rs_smartptr::poly_variant::unknown_tag("Shape", hash),
+                    }
+                }
+            }
+
+            impl ocaml_gen::OCamlDesc for Shape {
+                fn ocaml_desc(env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+                    env.get_type(<Self as ocaml_gen::OCamlDesc>::unique_id(), "shape").0
+                }
+
+                fn unique_id() -> u128 {
+                    ocaml_rs_smartptr::ocaml_gen_extras::stable_unique_id(
+                        "OcamlPolyVariant",
+                        &::std::any::TypeId::of::<Self>(),
+                    )
+                }
+            }
+
+            impl ocaml_gen::OCamlBinding for Shape {
+                fn ocaml_binding(
+                    env: &mut ocaml_gen::Env,
+                    rename: Option<&'static str>,
+                    new_type: bool,
+                ) -> String {
+                    ocaml_rs_smartptr::poly_variant::variant_binding(
+                        env,
+                        rename,
+                        new_type,
+                        <Self as ocaml_gen::OCamlDesc>::unique_id(),
+                        "shape",
+                        |env| vec![
+                            ocaml_rs_smartptr::poly_variant::variant_decl("Origin", &[]),
+                            ocaml_rs_smartptr::poly_variant::variant_decl(
+                                "Circle",
+                                &[ocaml_rs_smartptr::poly_variant::arg_decl::<f64>(env)]
+                            ),
+                            ocaml_rs_smartptr::poly_variant::variant_decl(
+                                "Point",
+                                &[
+                                    ocaml_rs_smartptr::poly_variant::arg_decl::<i32>(env),
+                                    ocaml_rs_smartptr::poly_variant::arg_decl::<i32>(env)
+                                ]
+                            )
+                        ],
+                    )
+                }
+            }
+        };
+
+        assert_eq!(output.to_string(), expected_output.to_string());
+    }
+
+    #[test]
+    fn test_ocaml_poly_variant_named_fields() {
+        let input: syn::DeriveInput = parse_quote! {
+            pub enum Shape {
+                Point { x: i32, y: i32 },
+            }
+        };
+
+        let err = generate_ocaml_poly_variant(&input).unwrap_err();
+        assert!(err.to_string().contains("named fields"));
+    }
+
+    #[test]
+    fn test_register_all_macro() {
+        let ty: TypePath = parse_quote! { crate::test_types::MyType };
+        let traits: Vec<Path> = vec![
+            parse_quote! { std::fmt::Display },
+            parse_quote! { crate::test_types::Foo },
+        ];
+        let markers: Vec<Path> = vec![parse_quote! { core::marker::Send }];
+
+        let output_tokens =
+            generate_all_registration(&ty, &traits, &markers, "this_crate");
+
+        let mut expected_tokens =
+            generate_type_registration(&ty, &markers, &traits, "this_crate");
+        expected_tokens.extend(generate_trait_registration(
+            &parse_quote! { std::fmt::Display },
+            &markers,
+            &[],
+            "this_crate",
+        ));
+        expected_tokens.extend(generate_trait_registration(
+            &parse_quote! { crate::test_types::Foo },
+            &markers,
+            &[],
+            "this_crate",
+        ));
+        assert_eq!(output_tokens.to_string(), expected_tokens.to_string());
+
+        // Spot check the trait object registrations for the crate-local trait
+        let output = pretty_print_item(output_tokens);
+        let expected_fragment = pretty_print_item(quote! {
+            ocaml_rs_smartptr::registry::register_type_info::<
+                dyn crate::test_types::Foo + ::core::marker::Send,
+            >(
+                "this_crate",
+                "this_crate::test_types::Foo",
+                vec!["this_crate::test_types::Foo", "core::marker::Send"],
+            );
+        });
+        let expected_fragment = expected_fragment
+            .trim_start_matches("fn main() {\n")
+            .trim_end_matches("}\n");
+        assert!(output.contains(expected_fragment), "{}", output);
+    }
 }
 
 #[cfg(test)]
@@ -910,6 +2451,78 @@ mod parsing_tests {
         assert!(input.conversions.is_empty());
     }
 
+    #[test]
+    fn test_containers_section() {
+        let input: TypeRegisterInput = syn::parse_quote! {
+            {
+                ty: crate::MyType,
+                marker_traits: [crate::MyMarkerTrait1],
+                containers: [Immutable, RwLock],
+            }
+        };
+
+        assert!(input.object_safe_traits.is_empty());
+        let containers = input.containers.expect("containers should be parsed");
+        assert_eq!(containers, ["Immutable", "RwLock"]);
+
+        let output_tokens = generate_containers_registration(&input.ty, &containers);
+        let expected_tokens = quote! {
+            ocaml_rs_smartptr::registry::register_type_containers::<crate::MyType>(
+                ocaml_rs_smartptr::registry::ContainerSet::of(&[
+                    ocaml_rs_smartptr::registry::ContainerKind::Immutable,
+                    ocaml_rs_smartptr::registry::ContainerKind::RwLock
+                ])
+            );
+        };
+        assert_eq!(output_tokens.to_string(), expected_tokens.to_string());
+
+        let result: syn::Result<TypeRegisterInput> = syn::parse2(quote! {
+            {
+                ty: crate::MyType,
+                marker_traits: [],
+                containers: [Arc],
+            }
+        });
+        let err = result.err().expect("unknown container should be rejected");
+        assert!(err.to_string().contains("Expected one of"));
+    }
+
+    #[test]
+    fn test_variant_kind_section() {
+        let input: TypeRegisterInput = syn::parse_quote! {
+            {
+                ty: crate::MyType,
+                marker_traits: [crate::MyMarkerTrait1],
+                containers: [Mutex],
+                variant_kind: closed,
+            }
+        };
+
+        assert!(input.containers.is_some());
+        let variant_kind = input.variant_kind.expect("variant kind should be parsed");
+        assert_eq!(variant_kind, "closed");
+
+        let output_tokens = generate_variant_kind_registration(&input.ty, &variant_kind);
+        let expected_tokens = quote! {
+            ocaml_rs_smartptr::registry::register_type_variant_kind::<crate::MyType>(
+                ocaml_rs_smartptr::registry::VariantKind::Closed
+            );
+        };
+        assert_eq!(output_tokens.to_string(), expected_tokens.to_string());
+
+        let result: syn::Result<TypeRegisterInput> = syn::parse2(quote! {
+            {
+                ty: crate::MyType,
+                marker_traits: [],
+                variant_kind: exact,
+            }
+        });
+        let err = result
+            .err()
+            .expect("unknown variant kind should be rejected");
+        assert!(err.to_string().contains("Expected one of"));
+    }
+
     #[test]
     fn test_empty_sections() {
         let input: TypeRegisterInput = syn::parse_quote! {
@@ -930,6 +2543,42 @@ mod parsing_tests {
         assert!(input.conversions.is_empty());
     }
 
+    #[test]
+    fn test_fn_trait_parsing() {
+        let input: TraitRegisterInput = syn::parse_quote! {
+            {
+                ty: core::ops::FnMut(i32) -> i32,
+                marker_traits: [core::marker::Send],
+            }
+        };
+
+        assert_eq!(
+            input.ty.to_token_stream().to_string(),
+            quote::quote!(core::ops::FnMut(i32) -> i32).to_string()
+        );
+        assert_eq!(input.marker_traits.len(), 1);
+        assert!(input.super_traits.is_empty());
+    }
+
+    #[test]
+    fn test_register_all_parsing() {
+        let input: AllRegisterInput = syn::parse_quote! {
+            {
+                ty: crate::MyType,
+                traits: [std::fmt::Display, std::fmt::Debug, crate::Foo],
+                markers: [core::marker::Send, core::marker::Sync],
+            }
+        };
+
+        let expected_ty: TypePath = parse_quote!(crate::MyType);
+        assert_eq!(
+            input.ty.to_token_stream().to_string(),
+            expected_ty.to_token_stream().to_string()
+        );
+        assert_eq!(input.traits.len(), 3);
+        assert_eq!(input.markers.len(), 2);
+    }
+
     #[test]
     fn test_invalid_input_missing_type() {
         let result: syn::Result<TypeRegisterInput> = syn::parse_str(
@@ -0,0 +1,55 @@
+//! Conversion of Rust names to snake_case OCaml identifiers, shared by the
+//! macros, which name generated stubs and OCaml types after Rust types, and by
+//! `ocaml-rs-smartptr`, which includes this file to build type tags, so that
+//! both name the same type the same way.
+
+/// Converts a name to snake_case. Words start at an uppercase letter following
+/// a lowercase letter or a digit, and at the last letter of a run of uppercase
+/// ones followed by a lowercase letter, so that acronyms stay together
+/// (`HTTPServer` becomes `http_server`) and digits stick to the preceding word
+/// (`Utf8Decoder` becomes `utf8_decoder`). Characters which can't appear in an
+/// OCaml identifier (e.g. in `FnMut(i32) -> i32`, `Iterator<Item = T>` or
+/// non-ASCII names) are collapsed into a single `_`.
+pub(crate) fn to_snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut snake_cased = String::with_capacity(name.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next = chars.get(i + 1).copied().unwrap_or_default();
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next.is_ascii_lowercase())
+            {
+                snake_cased.push('_');
+            }
+        }
+        snake_cased.push(c.to_ascii_lowercase());
+    }
+    snake_cased
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("Sheep"), "sheep");
+        assert_eq!(to_snake_case("SheepWrapper"), "sheep_wrapper");
+        assert_eq!(to_snake_case("HTTPServer"), "http_server");
+        assert_eq!(to_snake_case("Utf8Decoder"), "utf8_decoder");
+        assert_eq!(to_snake_case("IOError"), "io_error");
+        assert_eq!(to_snake_case("ParseURL"), "parse_url");
+        assert_eq!(to_snake_case("Point3D"), "point3_d");
+        assert_eq!(to_snake_case("Sha256Hasher"), "sha256_hasher");
+        assert_eq!(to_snake_case("DynBox"), "dyn_box");
+        assert_eq!(to_snake_case("X"), "x");
+        assert_eq!(to_snake_case("already_snake"), "already_snake");
+        assert_eq!(to_snake_case("Größe"), "gr_e");
+    }
+}
@@ -0,0 +1,214 @@
+//! This module provides zero-copy views of Rust byte buffers as OCaml
+//! Bigarrays. Instead of copying a `Vec<u8>` into an OCaml string, the OCaml
+//! side gets a `(char, int8_unsigned_elt, c_layout) Bigarray.Array1.t` which
+//! points directly at the memory owned by a `DynBox<Vec<u8>>`.
+//!
+//! The Bigarray is allocated as a custom block with the regular Bigarray
+//! layout, followed by a raw pointer to a clone of the `DynBox`. Its finalizer
+//! drops that clone, so the backing memory outlives OCaml's view of it. All
+//! other custom operations (comparison, hashing, serialization) are delegated
+//! to the OCaml runtime Bigarray implementation. The element kind is
+//! `Bigarray.char`, so views are interchangeable with char Bigarrays created
+//! by OCaml code, e.g. for `Bigarray.Array1.blit`.
+//!
+//! Stubs return views wrapped into `Bigarray`, which describes the OCaml type
+//! in bindings:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn buffer_view(buffer: DynBox<Vec<u8>>) -> Bigarray {
+//!     // Safety: the buffer is never resized once it's created
+//!     unsafe { Bigarray::new(buffer) }
+//! }
+//! ```
+//!
+//! which is bound as:
+//!
+//! ```ocaml
+//! external view
+//!   :  _ t'
+//!   -> (char, Bigarray.int8_unsigned_elt, Bigarray.c_layout) Bigarray.Array1.t
+//!   = "buffer_view"
+//! ```
+//!
+//! OCaml code may write to the view, so only `Mutex` and `RwLock` backed
+//! boxes can be viewed, i.e. the ones created with `new_exclusive` or
+//! `new_shared`.
+
+use std::any::{Any, TypeId};
+use std::ffi::c_void;
+use std::sync::{Mutex, PoisonError, RwLock};
+
+use ocaml_gen::OCamlDesc;
+
+use crate::logging::trace;
+use crate::ocaml_gen_extras;
+use crate::ptr::DynBox;
+use crate::shutdown;
+
+/// `CAML_BA_CHAR` element kind from `caml/bigarray.h`.
+const CAML_BA_CHAR: isize = 12;
+/// `CAML_BA_C_LAYOUT` layout from `caml/bigarray.h`.
+const CAML_BA_C_LAYOUT: isize = 0;
+/// `CAML_BA_EXTERNAL` management flag from `caml/bigarray.h`, OCaml will
+/// never try to free the data on its own.
+const CAML_BA_EXTERNAL: isize = 0;
+
+extern "C" {
+    fn caml_ba_compare(v1: ocaml::Raw, v2: ocaml::Raw) -> i32;
+    fn caml_ba_hash(v: ocaml::Raw) -> isize;
+    fn caml_ba_serialize(v: ocaml::Raw, wsize_32: *mut usize, wsize_64: *mut usize);
+}
+
+/// Mirrors `struct caml_ba_array` for a single dimension, followed by the
+/// "leaked" `DynBox` pointer which keeps the buffer alive.
+#[repr(C)]
+struct BigarrayView {
+    data: *mut c_void,
+    num_dims: isize,
+    flags: isize,
+    proxy: *mut c_void,
+    dim: [isize; 1],
+    owner: *const (dyn Any + Send + Sync),
+}
+
+/// Finalizer is registered with OCaml GC, and ensures that our "leaked"
+/// `DynBox` pointer is released whenever OCaml drops the Bigarray
+unsafe extern "C" fn bigarray_view_finalizer(v: ocaml::Raw) {
+    if shutdown::is_shut_down() {
+        return;
+    }
+    let ptr = v.as_pointer::<BigarrayView>();
+    trace!("finalize Bigarray view of {} bytes", ptr.as_ref().dim[0]);
+    let dynbox: DynBox<Vec<u8>> = DynBox::from_raw(ptr.as_ref().owner);
+    drop(dynbox);
+    ptr.drop_in_place();
+}
+
+impl ocaml::Custom for BigarrayView {
+    // Use the identifier of OCaml Bigarrays, so that the view is deserialized
+    // as a regular Bigarray
+    const NAME: &'static str = "_bigarr02\0";
+
+    const OPS: ocaml::custom::CustomOps = ocaml::custom::CustomOps {
+        identifier: Self::NAME.as_ptr() as *mut ocaml::sys::Char,
+        finalize: Some(bigarray_view_finalizer),
+        compare: Some(caml_ba_compare),
+        hash: Some(caml_ba_hash),
+        serialize: Some(caml_ba_serialize),
+        ..ocaml::custom::DEFAULT_CUSTOM_OPS
+    };
+}
+
+impl DynBox<Vec<u8>> {
+    /// Creates an OCaml Bigarray which points directly at the buffer inside
+    /// this `DynBox`, without copying it. The Bigarray holds a clone of the
+    /// `DynBox`, so the buffer is kept alive until OCaml GC collects it.
+    ///
+    /// # Safety
+    ///
+    /// The Bigarray bypasses the lock protecting the buffer. The caller must
+    /// ensure that the `Vec` is not resized or reallocated while the view is
+    /// alive, and that Rust does not access the buffer concurrently with OCaml
+    /// writes to it. Sub-arrays created on OCaml side (e.g. with
+    /// `Bigarray.Array1.sub`) do not keep the `DynBox` alive and must not
+    /// outlive the original view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not held in a `Mutex` or a `RwLock`, e.g. if
+    /// the `DynBox` is immutable, weak or a projection, as OCaml code could
+    /// write to the view of such a buffer.
+    pub unsafe fn as_ocaml_bigarray(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        let (data, len) = with_buffer(self.inner.as_ref(), |buf| {
+            (buf.as_mut_ptr() as *mut c_void, buf.len())
+        });
+        let view = BigarrayView {
+            data,
+            num_dims: 1,
+            flags: CAML_BA_CHAR | CAML_BA_C_LAYOUT | CAML_BA_EXTERNAL,
+            proxy: std::ptr::null_mut(),
+            dim: [len as isize],
+            owner: self.clone().into_raw(),
+        };
+        ocaml::Pointer::from(view).to_value(gc)
+    }
+}
+
+/// Locks the container holding the buffer and passes the buffer to `f`.
+fn with_buffer<R>(any: &(dyn Any + Send + Sync), f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<Vec<u8>>>() {
+        return f(&mut rwlock.write());
+    }
+    if let Some(mutex) = any.downcast_ref::<Mutex<Vec<u8>>>() {
+        f(&mut mutex.lock().unwrap_or_else(PoisonError::into_inner))
+    } else if let Some(rwlock) = any.downcast_ref::<RwLock<Vec<u8>>>() {
+        f(&mut rwlock.write().unwrap_or_else(PoisonError::into_inner))
+    } else {
+        panic!(
+            "unsupported container provided for bigarray view, only boxes \
+             created with new_exclusive or new_shared can be viewed"
+        );
+    }
+}
+
+/// A `DynBox<Vec<u8>>` passed to OCaml as a Bigarray view of the buffer,
+/// instead of a reference to the box, see module level docs.
+pub struct Bigarray(DynBox<Vec<u8>>);
+
+impl Bigarray {
+    /// Wraps the buffer to be passed to OCaml as a Bigarray view.
+    ///
+    /// # Safety
+    ///
+    /// Same as for `DynBox::as_ocaml_bigarray`, which creates the view once
+    /// the `Bigarray` is converted to OCaml.
+    pub unsafe fn new(buffer: DynBox<Vec<u8>>) -> Self {
+        Bigarray(buffer)
+    }
+}
+
+unsafe impl ocaml::ToValue for Bigarray {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        // Safety: guaranteed by the caller of `Bigarray::new`
+        unsafe { self.0.as_ocaml_bigarray(gc) }
+    }
+}
+
+impl OCamlDesc for Bigarray {
+    fn ocaml_desc(_env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
+        "(char, Bigarray.int8_unsigned_elt, Bigarray.c_layout) Bigarray.Array1.t"
+            .to_string()
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id("Bigarray", &TypeId::of::<Vec<u8>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_buffer() {
+        let exclusive = DynBox::new_exclusive(b"dolly".to_vec());
+        with_buffer(exclusive.inner.as_ref(), |buf| buf[0] = b'D');
+        assert_eq!(exclusive.read(|buf| buf.clone()), b"Dolly");
+        let shared = DynBox::new_shared(b"shaun".to_vec());
+        assert_eq!(with_buffer(shared.inner.as_ref(), |buf| buf.len()), 5);
+        assert_eq!(
+            Bigarray::ocaml_desc(&ocaml_gen::Env::new(), &[]),
+            "(char, Bigarray.int8_unsigned_elt, Bigarray.c_layout) Bigarray.Array1.t"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported container provided for bigarray view")]
+    fn test_with_buffer_immutable() {
+        let immutable = DynBox::new_immutable(b"timmy".to_vec());
+        with_buffer(immutable.inner.as_ref(), |_| ());
+    }
+}
@@ -0,0 +1,94 @@
+//! This module lets stubs fill OCaml `bytes` in place, for output-buffer
+//! patterns where OCaml allocates a buffer (e.g. with `Bytes.create`) and Rust
+//! writes into it, without copying the data through a Rust `Vec` and back:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn sheep_write_name(sheep: DynBox<Sheep>, mut buf: OcamlBytesMut) -> i32 {
+//!     let name = sheep.coerce().name();
+//!     let len = name.len().min(buf.len());
+//!     buf.as_mut_slice()[..len].copy_from_slice(&name.as_bytes()[..len]);
+//!     len as i32
+//! }
+//! ```
+//!
+//! `OcamlBytesMut` maps to OCaml `bytes`, so OCaml strings can't be passed to
+//! such stubs, which keeps the mutation in line with OCaml semantics: strings
+//! are immutable, and all references to the bytes see the changes.
+//!
+//! The slice points right into the OCaml heap, so the same rules as for
+//! `DynBoxRef` apply. OCaml bytes are not moved by the GC as long as the GC
+//! doesn't run, but a minor collection promotes young blocks to the major
+//! heap, and compaction moves old ones. So the slice is only valid until the
+//! stub allocates on the OCaml heap (e.g. converts a value to OCaml), calls
+//! back into OCaml or releases the domain lock. `as_mut_slice` borrows the
+//! wrapper mutably, and can be called again to get a fresh slice, as long as
+//! the bytes are still referenced from OCaml (e.g. by the caller).
+//!
+//! Nothing prevents OCaml from passing the same bytes twice to a stub, so a
+//! stub taking several `OcamlBytesMut` arguments must not hold slices of more
+//! than one of them at a time.
+
+use ocaml_gen::OCamlDesc;
+use std::marker::PhantomData;
+
+use crate::ocaml_gen_extras;
+
+/// OCaml `bytes` received by a stub, which can be mutated in place, see
+/// module level docs.
+pub struct OcamlBytesMut<'a> {
+    value: ocaml::Value,
+    // Not `Send` nor `Sync`, the bytes can only be accessed on the OCaml
+    // thread for the duration of the call
+    _borrow: PhantomData<&'a mut *mut u8>,
+}
+
+impl OcamlBytesMut<'_> {
+    /// Returns the length of the bytes.
+    pub fn len(&self) -> usize {
+        unsafe { ocaml::sys::caml_string_length(self.value.raw().0) }
+    }
+
+    /// Checks if the bytes are empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the contents of the bytes, see module level docs for how long
+    /// the slice stays valid.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe {
+            let data = ocaml::sys::string_val(self.value.raw().0);
+            std::slice::from_raw_parts(data, self.len())
+        }
+    }
+
+    /// Returns the contents of the bytes for writing, see module level docs
+    /// for how long the slice stays valid.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe {
+            let data = ocaml::sys::string_val(self.value.raw().0);
+            std::slice::from_raw_parts_mut(data, self.len())
+        }
+    }
+}
+
+unsafe impl ocaml::FromValue for OcamlBytesMut<'_> {
+    fn from_value(v: ocaml::Value) -> Self {
+        OcamlBytesMut {
+            value: v,
+            _borrow: PhantomData,
+        }
+    }
+}
+
+impl OCamlDesc for OcamlBytesMut<'_> {
+    fn ocaml_desc(_env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
+        "bytes".to_string()
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id("OcamlBytesMut", &())
+    }
+}
@@ -1,9 +1,10 @@
-use std::hash::Hash;
+use std::marker::PhantomData;
 
-use highway::{HighwayHash, HighwayHasher}; // For hashing unique IDs
-use ocaml_gen::{const_random, OCamlDesc};
+use ocaml_gen::OCamlDesc;
 use paste::paste; // For generating repetitive code
 
+use crate::ocaml_gen_extras::stable_unique_id; // For hashing unique IDs
+
 /// The `Callable` trait represents a function or closure that can be called
 /// with a set of arguments to produce a return value. This trait is designed to
 /// be used with OCaml values and provides methods for calling the function,
@@ -17,7 +18,28 @@ pub trait Callable<Ret>
 where
     Ret: ocaml::FromValue + OCamlDesc,
 {
-    fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret;
+    /// Calls `func` with the arguments, returning the raw OCaml result or the
+    /// exception raised by `func`. This method should be provided by
+    /// downstream trait implementations.
+    fn call_raw(
+        &self,
+        gc: &ocaml::Runtime,
+        func: ocaml::Value,
+    ) -> Result<ocaml::Value, ocaml::Error>;
+    /// Calls `func` with the arguments and converts the result, panicking if
+    /// `func` raises an exception.
+    fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret {
+        self.process_result(self.call_raw(gc, func))
+    }
+    /// Calls `func` with the arguments and converts the result, returning the
+    /// exception raised by `func` as an error instead of panicking.
+    fn try_call_with(
+        &self,
+        gc: &ocaml::Runtime,
+        func: ocaml::Value,
+    ) -> Result<Ret, ocaml::Error> {
+        self.call_raw(gc, func).map(Ret::from_value)
+    }
     /// Describes the arguments (i.e. calls OCamlDesc::ocaml_desc) of the
     /// function. This method should be provided by downstream trait
     /// implementations.
@@ -29,30 +51,15 @@ where
     fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
         let args = Self::describe_args(env, generics)
             .into_iter()
-            .map(|desc| format!("({})", desc))
+            .map(|desc| wrap_arg_desc(&desc))
             .collect::<Vec<_>>()
             .join(" -> ");
         format!("({} -> ({}))", args, Ret::ocaml_desc(env, generics))
     }
 
     fn unique_id() -> u128 {
-        // Static randomized key for Callable
-        let key = highway::Key([
-            const_random!(u64),
-            const_random!(u64),
-            const_random!(u64),
-            const_random!(u64),
-        ]);
-        // Hasher seeded with our key
-        let mut hasher = HighwayHasher::new(key);
-        // Hash all Callable arguments
-        Self::unique_id_args()
-            .iter()
-            .for_each(|id| id.hash(&mut hasher));
-        /* Hash return value */
-        Ret::unique_id().hash(&mut hasher);
-        let result = hasher.finalize128();
-        (result[0] as u128) | ((result[1] as u128) << 64)
+        // Hash all Callable arguments along with the return value
+        stable_unique_id("Callable", &(Self::unique_id_args(), Ret::unique_id()))
     }
     fn process_result(&self, res: Result<ocaml::Value, ocaml::Error>) -> Ret {
         let res = res.unwrap();
@@ -60,11 +67,200 @@ where
     }
 }
 
+/// The `CallableOnce` trait is implemented by argument tuples which can be
+/// moved into the call, see `OCamlFunc::call_consuming`. Arguments are
+/// converted with `IntoValue` instead of `ocaml::ToValue`, so that e.g.
+/// `DynBox` arguments hand their references over to OCaml instead of cloning
+/// them.
+pub trait CallableOnce<Ret>: Callable<Ret>
+where
+    Ret: ocaml::FromValue + OCamlDesc,
+{
+    /// Calls `func` with the arguments moved into it, returning the raw OCaml
+    /// result or the exception raised by `func`.
+    fn call_raw_once(
+        self,
+        gc: &ocaml::Runtime,
+        func: ocaml::Value,
+    ) -> Result<ocaml::Value, ocaml::Error>;
+    /// Calls `func` with the arguments moved into it and converts the result,
+    /// panicking if `func` raises an exception.
+    fn call_once_with(self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret
+    where
+        Self: Sized,
+    {
+        let res = self.call_raw_once(gc, func).unwrap();
+        Ret::from_value(res)
+    }
+}
+
+/// The `IntoValue` trait converts a value to OCaml by moving it, as opposed to
+/// `ocaml::ToValue`, which only gets a reference and has to copy or clone
+/// whatever the OCaml value holds. It's implemented by `DynBox` and
+/// `LocalDynBox`, which move their references into the OCaml custom blocks, by
+/// `Option` and `Vec` of such values, and by plain values, which are converted
+/// with `ocaml::ToValue` as usual.
+pub trait IntoValue {
+    /// Converts the value to OCaml, consuming it.
+    fn into_value(self, gc: &ocaml::Runtime) -> ocaml::Value;
+}
+
+/// Implements `IntoValue` for types with nothing to move, by converting them
+/// with `ocaml::ToValue`.
+macro_rules! impl_into_value_by_ref {
+    ($($ty:ty),+) => {
+        $(
+            impl IntoValue for $ty {
+                fn into_value(self, gc: &ocaml::Runtime) -> ocaml::Value {
+                    ocaml::ToValue::to_value(&self, gc)
+                }
+            }
+        )+
+    };
+}
+
+impl_into_value_by_ref!((), bool, i32, i64, f32, f64, String, ocaml::Value);
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self, gc: &ocaml::Runtime) -> ocaml::Value {
+        match self {
+            // `Some` is the first non-constant constructor of `option`
+            Some(value) => {
+                let value = value.into_value(gc);
+                let mut block = unsafe { ocaml::Value::alloc(1, 0) };
+                unsafe { block.store_field(gc, 0, value) };
+                block
+            }
+            None => ocaml::Value::none(),
+        }
+    }
+}
+
+// Same layout as `ocaml::ToValue` for `Vec` gives, i.e. an array of the values
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self, gc: &ocaml::Runtime) -> ocaml::Value {
+        let mut array = unsafe { ocaml::Value::alloc(self.len(), 0) };
+        for (i, value) in self.into_iter().enumerate() {
+            let value = value.into_value(gc);
+            unsafe { array.store_field(gc, i, value) };
+        }
+        array
+    }
+}
+
+impl<L: Label, T: IntoValue> IntoValue for Labeled<L, T> {
+    fn into_value(self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.into_value(gc)
+    }
+}
+
+/// Wraps an argument description in parentheses, keeping the label of labeled
+/// (`name:ty`) and optional (`?name:ty`) arguments outside of them.
+fn wrap_arg_desc(desc: &str) -> String {
+    match desc.split_once(':') {
+        Some((label, ty)) if is_label(label) => format!("{}:({})", label, ty),
+        _ => format!("({})", desc),
+    }
+}
+
+/// Checks if `s` is an OCaml label, optionally prefixed with `?`.
+fn is_label(s: &str) -> bool {
+    let mut chars = s.strip_prefix('?').unwrap_or(s).chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'')
+}
+
+/// The `Label` trait is implemented by marker types which name OCaml argument
+/// labels for `Labeled` and `Optional` wrappers:
+///
+/// ```
+/// struct Verbose;
+///
+/// impl ocaml_rs_smartptr::callable::Label for Verbose {
+///     const NAME: &'static str = "verbose";
+/// }
+/// ```
+pub trait Label {
+    /// Label name as it appears in OCaml, without `~` or `?`.
+    const NAME: &'static str;
+}
+
+/// Wraps an argument of a `Callable`, so that it's described as a labeled
+/// argument (`name:ty`) in OCaml. Labels are erased at runtime, so the value is
+/// passed as is, in the position of the argument within the tuple.
+pub struct Labeled<L: Label, T>(pub T, PhantomData<L>);
+
+impl<L: Label, T> Labeled<L, T> {
+    /// Creates a new labeled argument.
+    pub fn new(v: T) -> Self {
+        Labeled(v, PhantomData)
+    }
+}
+
+unsafe impl<L: Label, T: ocaml::ToValue> ocaml::ToValue for Labeled<L, T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.to_value(gc)
+    }
+}
+
+impl<L: Label, T: OCamlDesc> OCamlDesc for Labeled<L, T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        format!("{}:{}", L::NAME, T::ocaml_desc(env, generics))
+    }
+
+    fn unique_id() -> u128 {
+        labeled_unique_id(L::NAME, T::unique_id())
+    }
+}
+
+/// Wraps an argument of a `Callable`, so that it's described as an optional
+/// argument (`?name:ty`) in OCaml. At runtime an optional argument is just an
+/// `option`, so `None` is passed to omit it.
+///
+/// By OCaml convention, optional arguments must be followed by a positional
+/// one, so put `()` as the last tuple element if there are no other positional
+/// arguments, e.g. `OCamlFunc<(Optional<Verbose, bool>, ()), Ret>` corresponds
+/// to `?verbose:bool -> unit -> ret`.
+pub struct Optional<L: Label, T>(pub Option<T>, PhantomData<L>);
+
+impl<L: Label, T> Optional<L, T> {
+    /// Creates a new optional argument.
+    pub fn new(v: Option<T>) -> Self {
+        Optional(v, PhantomData)
+    }
+}
+
+unsafe impl<L: Label, T: ocaml::ToValue> ocaml::ToValue for Optional<L, T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.to_value(gc)
+    }
+}
+
+impl<L: Label, T: OCamlDesc> OCamlDesc for Optional<L, T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        format!("?{}:{}", L::NAME, T::ocaml_desc(env, generics))
+    }
+
+    fn unique_id() -> u128 {
+        labeled_unique_id(&format!("?{}", L::NAME), T::unique_id())
+    }
+}
+
+/// Generates a unique ID for a labeled argument out of its label and the
+/// unique ID of its type.
+fn labeled_unique_id(label: &str, type_id: u128) -> u128 {
+    stable_unique_id("LabeledArg", &(label, type_id))
+}
+
 impl<Ret: ocaml::FromValue + OCamlDesc> Callable<Ret> for () {
-    fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret {
+    fn call_raw(
+        &self,
+        gc: &ocaml::Runtime,
+        func: ocaml::Value,
+    ) -> Result<ocaml::Value, ocaml::Error> {
         // We use .call1 with a single `()' argument as OCaml does not have a
         // notion of a function without arguments
-        self.process_result(unsafe { func.call1(gc, ()) })
+        unsafe { func.call1(gc, ()) }
     }
     fn describe_args(env: &ocaml_gen::Env, generics: &[&str]) -> Vec<String> {
         // Just call OCamlDesc::ocaml_desc on `()' type
@@ -76,30 +272,57 @@ impl<Ret: ocaml::FromValue + OCamlDesc> Callable<Ret> for () {
     }
 }
 
-/// Macro to generate the `call_with` function for tuples of different sizes.
+impl<Ret: ocaml::FromValue + OCamlDesc> CallableOnce<Ret> for () {
+    fn call_raw_once(
+        self,
+        gc: &ocaml::Runtime,
+        func: ocaml::Value,
+    ) -> Result<ocaml::Value, ocaml::Error> {
+        // There's nothing to move
+        Callable::<Ret>::call_raw(&self, gc, func)
+    }
+}
+
+/// Macro to generate the `call_raw` function for tuples of different sizes.
 /// This macro handles special cases for tuples with 1, 2, and 3 elements by
 /// generating the appropriate `func.call1`, `func.call2`, and `func.call3` calls.
 /// For tuples with more than 3 elements, it generates a generic `func.call`
 /// with the elements converted to OCaml values.
 macro_rules! generate_call_with {
     ($idx:tt) => {
-        fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret {
-            self.process_result(unsafe { func.call1(gc, &self.0) })
+        fn call_raw(
+            &self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe { func.call1(gc, &self.0) }
         }
     };
     ($idx1:tt, $idx2:tt) => {
-        fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret {
-            self.process_result(unsafe { func.call2(gc, &self.0, &self.1) })
+        fn call_raw(
+            &self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe { func.call2(gc, &self.0, &self.1) }
         }
     };
     ($idx1:tt, $idx2:tt, $idx3:tt) => {
-        fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret {
-            self.process_result(unsafe { func.call3(gc, &self.0, &self.1, &self.2) })
+        fn call_raw(
+            &self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe { func.call3(gc, &self.0, &self.1, &self.2) }
         }
     };
     ($count:tt, $($idx:tt),*) => {
-        fn call_with(&self, gc: &ocaml::Runtime, func: ocaml::Value) -> Ret {
-            self.process_result(unsafe {
+        fn call_raw(
+            &self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe {
                 func.call(
                     gc,
                     [
@@ -108,13 +331,71 @@ macro_rules! generate_call_with {
                         )*
                     ],
                 )
-            })
+            }
+        }
+    };
+}
+
+/// Macro to generate the `call_raw_once` function for tuples of different
+/// sizes, the same way as `generate_call_with` does, but with the elements
+/// moved into OCaml values with `IntoValue`.
+macro_rules! generate_call_once_with {
+    ($idx:tt) => {
+        fn call_raw_once(
+            self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe { func.call1(gc, self.0.into_value(gc)) }
+        }
+    };
+    ($idx1:tt, $idx2:tt) => {
+        fn call_raw_once(
+            self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe { func.call2(gc, self.0.into_value(gc), self.1.into_value(gc)) }
+        }
+    };
+    ($idx1:tt, $idx2:tt, $idx3:tt) => {
+        fn call_raw_once(
+            self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe {
+                func.call3(
+                    gc,
+                    self.0.into_value(gc),
+                    self.1.into_value(gc),
+                    self.2.into_value(gc),
+                )
+            }
+        }
+    };
+    ($count:tt, $($idx:tt),*) => {
+        fn call_raw_once(
+            self,
+            gc: &ocaml::Runtime,
+            func: ocaml::Value,
+        ) -> Result<ocaml::Value, ocaml::Error> {
+            unsafe {
+                func.call(
+                    gc,
+                    [
+                        $(
+                            self.$idx.into_value(gc),
+                        )*
+                    ],
+                )
+            }
         }
     };
 }
 
 /// Macro to implement the `Callable` trait for tuples of different sizes.
-/// This macro uses the `generate_call_with` macro to generate the `call_with`
+/// This macro uses the `generate_call_with` macro to generate the `call_raw`
 /// function and implements the `describe_args` and `unique_id_args` functions
 /// for tuples of different sizes.
 macro_rules! impl_callable_for_tuple {
@@ -145,6 +426,18 @@ macro_rules! impl_callable_for_tuple {
                     ]
                 }
             }
+
+            impl<
+                $(
+                    [<T $idx>]: ocaml::ToValue + IntoValue + OCamlDesc,
+                )*
+                Ret: ocaml::FromValue + OCamlDesc,
+            > CallableOnce<Ret> for ($(
+                [<T $idx>],
+            )*)
+            {
+                generate_call_once_with! { $($idx),+ }
+            }
         }
     };
 }
@@ -177,3 +470,17 @@ impl_callable_for_tuple!(
 impl_callable_for_tuple!(
     0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_arg_desc() {
+        assert_eq!(wrap_arg_desc("int"), "(int)");
+        assert_eq!(wrap_arg_desc("_ Animal.t'"), "(_ Animal.t')");
+        assert_eq!(wrap_arg_desc("name:string"), "name:(string)");
+        assert_eq!(wrap_arg_desc("?verbose:bool"), "?verbose:(bool)");
+        assert_eq!(wrap_arg_desc("< m : int >"), "(< m : int >)");
+    }
+}
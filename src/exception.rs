@@ -0,0 +1,74 @@
+//! This module allows raising Rust errors as dedicated OCaml exceptions,
+//! instead of wrapping them into an opaque `DynBox<dyn Error>`.
+//!
+//! An error type is bound to an exception with `register_exception!`:
+//!
+//! ```rust,ignore
+//! register_exception!(MyError => "My_error");
+//! ```
+//!
+//! This implements `OCamlException` for `MyError`, and `From<MyError>` for
+//! `ocaml::Error`, so that stubs returning `Result<T, ocaml::Error>` can use `?`
+//! on `Result<T, MyError>`. The exception is then declared in OCaml bindings
+//! with `decl_exception!(MyError)` within `ocaml_gen_bindings!`, which emits:
+//!
+//! ```ocaml
+//! exception My_error of string
+//! let () = Callback.register_exception "My_error" (My_error "")
+//! ```
+//!
+//! The exception carries the `Display` representation of the error, and can be
+//! handled with `try ... with My_error msg -> ...` on OCaml side.
+
+use ocaml::ToValue;
+
+/// A Rust error type which is raised as an OCaml exception. Implemented by
+/// `register_exception!`.
+pub trait OCamlException: std::fmt::Display {
+    /// Name of the OCaml exception constructor, e.g. `My_error`. It's also
+    /// the name under which the exception is registered with `Callback`.
+    const NAME: &'static str;
+
+    /// Generates the OCaml declaration of the exception, along with its
+    /// registration, which makes it available to Rust.
+    fn ocaml_decl() -> String {
+        format!(
+            "exception {} of string let () = Callback.register_exception {:?} ({} \"\")",
+            Self::NAME,
+            Self::NAME,
+            Self::NAME
+        )
+    }
+}
+
+/// Converts `err` into an `ocaml::Error` which raises the registered OCaml
+/// exception, with the `Display` representation of `err` as its argument.
+///
+/// Panics if the exception was not registered on OCaml side, i.e. the module
+/// with `decl_exception!` output is not linked or not initialized yet.
+pub fn to_ocaml_error<E: OCamlException>(err: &E) -> ocaml::Error {
+    let gc = unsafe { ocaml::Runtime::recover_handle() };
+    let exn = unsafe { ocaml::Value::named(E::NAME) }.unwrap_or_else(|| {
+        panic!(
+            "OCaml exception {} is not registered, make sure that its binding is linked",
+            E::NAME
+        )
+    });
+    ocaml::Error::Caml(ocaml::CamlError::WithArg(exn, err.to_string().to_value(gc)))
+}
+
+/// Binds a Rust error type to an OCaml exception, see module level docs.
+#[macro_export]
+macro_rules! register_exception {
+    ($error:ty => $name:expr) => {
+        impl $crate::exception::OCamlException for $error {
+            const NAME: &'static str = $name;
+        }
+
+        impl ::std::convert::From<$error> for ocaml::Error {
+            fn from(err: $error) -> Self {
+                $crate::exception::to_ocaml_error(&err)
+            }
+        }
+    };
+}
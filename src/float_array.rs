@@ -0,0 +1,202 @@
+//! This module converts Rust float vectors to and from OCaml `float array`s,
+//! which is the usual currency of numeric bindings. OCaml stores float arrays
+//! unboxed, as a block with `Double_array_tag` holding the raw doubles, so the
+//! conversion is a single copy of the data, with no per-element allocation.
+//!
+//! `FloatArray<T>` is a thin wrapper around `Vec<T>` for stub arguments and
+//! return values, described as `float array` in bindings. Both `f64` and `f32`
+//! elements are supported, the latter are widened to OCaml floats (which are
+//! doubles) and narrowed back.
+//!
+//! Vectors kept in a `DynBox<Vec<T>>` are exported to OCaml with
+//! `FloatArrayOf<T>`, which copies the vector right into the OCaml array under
+//! the lock of the box, without an intermediate `Vec`:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn samples_to_array(samples: DynBox<Vec<f64>>) -> FloatArrayOf {
+//!     FloatArrayOf(samples)
+//! }
+//! ```
+//!
+//! Arrays received from OCaml may also be empty (`[||]`, which is a shared
+//! atom with tag 0 rather than a `Double_array_tag` block) or hold boxed floats
+//! when the compiler is configured with `-no-flat-float-array`, both are
+//! handled. Arrays created by this module use the flat representation, which is
+//! the default one.
+
+use ocaml_gen::OCamlDesc;
+use std::any::{Any, TypeId};
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError, RwLock};
+
+use crate::ocaml_gen_extras;
+use crate::ptr::DynBox;
+
+/// `Double_array_tag` from `caml/mlvalues.h`.
+const DOUBLE_ARRAY_TAG: ocaml::sys::Tag = 254;
+
+extern "C" {
+    fn caml_alloc_float_array(len: usize) -> ocaml::Raw;
+}
+
+/// Element type of a Rust float vector which can be converted to an OCaml
+/// `float array`, implemented for `f64` and `f32`.
+pub trait FloatElement: Copy + Send + Sync + 'static {
+    /// Converts the element to an OCaml float.
+    fn to_f64(self) -> f64;
+
+    /// Converts an OCaml float to the element, possibly losing precision.
+    fn from_f64(value: f64) -> Self;
+}
+
+impl FloatElement for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+impl FloatElement for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+/// Allocates an OCaml `float array` holding a copy of `values`.
+pub fn to_ocaml<T: FloatElement>(_gc: &ocaml::Runtime, values: &[T]) -> ocaml::Value {
+    unsafe {
+        // Nothing is allocated on OCaml heap while the array is filled, so the
+        // raw block can't move
+        let raw = caml_alloc_float_array(values.len());
+        let data = raw.0 as *mut f64;
+        for (i, value) in values.iter().enumerate() {
+            data.add(i).write(value.to_f64());
+        }
+        ocaml::Value::new(raw.0)
+    }
+}
+
+/// Copies an OCaml `float array` into a new vector, see module level docs for
+/// the representations which are supported.
+pub fn from_ocaml<T: FloatElement>(array: &ocaml::Value) -> Vec<T> {
+    unsafe {
+        let raw = array.raw().0;
+        let wosize = ocaml::sys::wosize_val(raw);
+        if ocaml::sys::tag_val(raw) == DOUBLE_ARRAY_TAG {
+            let len = wosize * std::mem::size_of::<ocaml::sys::Value>()
+                / std::mem::size_of::<f64>();
+            let data = raw as *const f64;
+            (0..len).map(|i| T::from_f64(data.add(i).read())).collect()
+        } else {
+            // Either an empty array, or an array of boxed floats
+            (0..wosize)
+                .map(|i| T::from_f64((*ocaml::sys::field(raw, i) as *const f64).read()))
+                .collect()
+        }
+    }
+}
+
+/// A Rust float vector passed as an OCaml `float array`, see module level
+/// docs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FloatArray<T: FloatElement = f64>(pub Vec<T>);
+
+unsafe impl<T: FloatElement> ocaml::ToValue for FloatArray<T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        to_ocaml(gc, &self.0)
+    }
+}
+
+unsafe impl<T: FloatElement> ocaml::FromValue for FloatArray<T> {
+    fn from_value(v: ocaml::Value) -> Self {
+        FloatArray(from_ocaml(&v))
+    }
+}
+
+impl<T: FloatElement> OCamlDesc for FloatArray<T> {
+    fn ocaml_desc(_env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
+        "float array".to_string()
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id("FloatArray", &TypeId::of::<T>())
+    }
+}
+
+/// A `DynBox<Vec<T>>` passed to OCaml as a `float array` copy of the vector,
+/// instead of a reference to the box, see module level docs.
+pub struct FloatArrayOf<T: FloatElement = f64>(pub DynBox<Vec<T>>);
+
+unsafe impl<T: FloatElement> ocaml::ToValue for FloatArrayOf<T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.to_ocaml_float_array(gc)
+    }
+}
+
+impl<T: FloatElement> OCamlDesc for FloatArrayOf<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        FloatArray::<T>::ocaml_desc(env, generics)
+    }
+
+    fn unique_id() -> u128 {
+        FloatArray::<T>::unique_id()
+    }
+}
+
+impl<T: FloatElement> DynBox<Vec<T>> {
+    /// Allocates an OCaml `float array` holding a copy of the vector inside
+    /// this `DynBox`. The vector is locked for reading while it's copied.
+    pub fn to_ocaml_float_array(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        with_values(self.inner.as_ref(), |values| to_ocaml(gc, values))
+    }
+}
+
+/// Locks the container holding the vector for reading and passes the vector
+/// to `f`.
+fn with_values<T: FloatElement, R>(
+    any: &(dyn Any + Send + Sync),
+    f: impl FnOnce(&[T]) -> R,
+) -> R {
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<Vec<T>>>() {
+        return f(&rwlock.read());
+    }
+    if let Some(mutex) = any.downcast_ref::<Mutex<Vec<T>>>() {
+        f(&mutex.lock().unwrap_or_else(PoisonError::into_inner))
+    } else if let Some(rwlock) = any.downcast_ref::<RwLock<Vec<T>>>() {
+        f(&rwlock.read().unwrap_or_else(PoisonError::into_inner))
+    } else if let Some(mutex) = any.downcast_ref::<Mutex<Pin<Box<Vec<T>>>>>() {
+        f(&mutex.lock().unwrap_or_else(PoisonError::into_inner))
+    } else if let Some(values) = any.downcast_ref::<Vec<T>>() {
+        f(values)
+    } else {
+        panic!("unsupported container provided for float array conversion");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sum(values: &DynBox<Vec<f64>>) -> f64 {
+        with_values(values.inner.as_ref(), |values: &[f64]| values.iter().sum())
+    }
+
+    #[test]
+    fn test_with_values() {
+        let values = vec![1.0, 2.0, 3.5];
+        assert_eq!(sum(&DynBox::new_exclusive(values.clone())), 6.5);
+        assert_eq!(sum(&DynBox::new_shared(values.clone())), 6.5);
+        assert_eq!(sum(&DynBox::new_immutable(values.clone())), 6.5);
+        assert_eq!(sum(&DynBox::new_exclusive_pinned(values)), 6.5);
+    }
+}
@@ -1,7 +1,7 @@
 use ocaml_gen::OCamlDesc; // Importing OCamlDesc trait for describing OCaml types
 
-use crate::callable::Callable;
-use crate::ml_box::MlBox;
+use crate::callable::{Callable, CallableOnce};
+use crate::ml_box::{with_domain_lock, MlBox};
 use std::marker::PhantomData;
 use std::panic::{AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
 
@@ -53,6 +53,74 @@ where
     pub fn call(&self, gc: &ocaml::Runtime, args: Args) -> Ret {
         args.call_with(gc, self.0.as_value(gc))
     }
+
+    /// Calls the OCaml function with the provided arguments, returning the
+    /// exception it raises as an error instead of panicking like `call` does.
+    ///
+    /// Meant for Rust code sandwiched between two OCaml layers: a stub which
+    /// returns `Result<T, ocaml::Error>` can propagate the error with `?`, and
+    /// the exception is re-raised as is when the stub returns, so the outer
+    /// OCaml code can handle it with `try ... with`. Rust code between the
+    /// call and the return runs as usual, e.g. drops its values.
+    ///
+    /// # Returns
+    ///
+    /// The result of the function, or `ocaml::Error::Caml` holding the
+    /// exception.
+    pub fn call_propagate(
+        &self,
+        gc: &ocaml::Runtime,
+        args: Args,
+    ) -> Result<Ret, ocaml::Error> {
+        args.try_call_with(gc, self.0.as_value(gc))
+    }
+
+    /// Calls the OCaml function with the provided arguments moved into it.
+    ///
+    /// `call` converts the arguments with `ocaml::ToValue`, which clones every
+    /// `DynBox` argument, and the original is only dropped after the call
+    /// returns. Here `DynBox` arguments hand their own references over to the
+    /// OCaml values instead, so that a box passed down a chain of callbacks
+    /// isn't referenced once more at every step. Arguments have to implement
+    /// `callable::IntoValue`.
+    pub fn call_consuming(&self, gc: &ocaml::Runtime, args: Args) -> Ret
+    where
+        Args: CallableOnce<Ret>,
+    {
+        args.call_once_with(gc, self.0.as_value(gc))
+    }
+
+    /// Calls the OCaml function with the provided arguments, recovering the
+    /// runtime handle instead of taking it as a parameter. Useful when the
+    /// function is stored in a Rust data structure whose methods don't have
+    /// access to the runtime handle.
+    ///
+    /// # Safety
+    ///
+    /// This function must only be called from a thread which holds the OCaml
+    /// domain lock, e.g. (transitively) from an `#[ocaml::func]` stub invoked by
+    /// OCaml. Calling it from any other thread, or after releasing the domain
+    /// lock, is undefined behavior. This is the same assumption that
+    /// `from_value` makes.
+    pub unsafe fn call_recovered(&self, args: Args) -> Ret {
+        self.call(ocaml::Runtime::recover_handle(), args)
+    }
+
+    /// Calls the OCaml function from a thread which does not hold the OCaml
+    /// domain lock, e.g. a Rust worker thread which needs to notify OCaml. The
+    /// lock is acquired for the duration of the call, see
+    /// `ml_box::with_domain_lock`.
+    ///
+    /// The call blocks until OCaml releases the lock, and calls from several
+    /// threads are serialized. If the thread which called into Rust waits for
+    /// the worker (e.g. joins it), it has to release the lock while waiting,
+    /// e.g. with `ocaml::Runtime::releasing_runtime`. Calling this function on
+    /// a thread which holds the lock deadlocks, use `call` there. The
+    /// `OCamlFunc` itself is better dropped on an OCaml thread too, as
+    /// releasing its root requires the lock.
+    pub fn call_from_any_thread(&self, args: Args) -> Ret {
+        with_domain_lock(|gc| self.call(gc, args))
+    }
 }
 
 /// OCamlDesc impl for OCamlFunc is a thin wrapper on top of corresponding
@@ -0,0 +1,237 @@
+//! This module allows binding generic containers, e.g. `Stack<T>`, as OCaml
+//! functors parameterized over the module of their elements, so that OCaml code
+//! instantiates them for the element type it needs:
+//!
+//! ```ocaml
+//! module Sheep_stack = Stubs.Stack (Sheep)
+//! ```
+//!
+//! Rust generics are resolved at compile time, while functors are applied by
+//! OCaml code, so the container is instantiated once on Rust side, with
+//! `Element`, a rooted OCaml value of the element type, i.e. `Elt.t` where
+//! `Elt` is the parameter of the functor. Elements are not converted when they
+//! are pushed or popped: whatever `to_value`/`from_value` conversions the
+//! element type has were applied by the bindings which produced the value on
+//! OCaml side, and Rust code which needs to look inside an element converts it
+//! explicitly with `Element::get`, e.g. to `DynBox<Sheep>` when it knows that
+//! the container holds sheep.
+//!
+//! ```rust,ignore
+//! pub struct Stack<T>(Vec<T>);
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn stack_create() -> Instance<DynBox<Stack<Element>>> {
+//!     DynBox::new_exclusive(Stack(Vec::new())).into()
+//! }
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn stack_push(stack: Instance<DynBox<Stack<Element>>>, elt: Element) {
+//!     stack.write(|stack| stack.0.push(elt));
+//! }
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn stack_pop(stack: Instance<DynBox<Stack<Element>>>) -> Option<Element> {
+//!     stack.write(|stack| stack.0.pop())
+//! }
+//! ```
+//!
+//! The functor is declared in bindings with `decl_functor!` within
+//! `ocaml_gen_bindings!`, and the type of its instances with
+//! `decl_instance_type!`:
+//!
+//! ```rust,ignore
+//! decl_functor!("Stack", "Elt", {
+//!     decl_type!(DynBox<Stack<Element>> => "stack");
+//!     decl_instance_type!(Instance<DynBox<Stack<Element>>> => "t");
+//!     decl_func!(stack_create => "create");
+//!     decl_func!(stack_push => "push");
+//!     decl_func!(stack_pop => "pop");
+//! });
+//! ```
+//!
+//! which emits:
+//!
+//! ```ocaml
+//! module Stack (Elt : sig type t end) = struct
+//!   type tags = [ `Ocaml_rs_smartptr_test_stubs_stack_..._bf0ca79c | ... ]
+//!   type 'a stack' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+//!   type stack = tags stack'
+//!   type t = [ tags | `Elt of Elt.t ] stack'
+//!
+//!   external create : unit -> [ tags | `Elt of Elt.t ] stack' = "stack_create"
+//!   external push : [ tags | `Elt of Elt.t ] stack' -> Elt.t -> unit = "stack_push"
+//!   external pop : [ tags | `Elt of Elt.t ] stack' -> Elt.t option = "stack_pop"
+//! end
+//! ```
+//!
+//! Types defined by a functor are the same for all of its applications, so
+//! `Instance` carries the element type in a phantom tag, same as `Phantom`
+//! does with states: a `Stack (Sheep).t` can't be passed where a
+//! `Stack (Wolf).t` is expected, and the elements popped from a stack are of
+//! the type that was pushed. Stubs which don't touch the elements, e.g. the
+//! length of the stack, may take `DynBox<Stack<Element>>`, which accepts the
+//! instances of any element type.
+//!
+//! Only functors of a single parameter with an abstract type `t` are
+//! supported. The parameter is named `Elt` unless `Element` and `Instance` are
+//! given another `FunctorParam`, the name passed to `decl_functor!` has to
+//! match it.
+
+use std::marker::PhantomData;
+
+use derive_more::derive::{Deref, DerefMut};
+use ocaml_gen::OCamlDesc;
+
+use crate::ml_box::MlBox;
+use crate::ocaml_gen_extras::dynbox_desc_with_variant;
+use crate::ptr::DynBox;
+
+/// A Rust marker type describing the parameter of a functor, see `Element`.
+pub trait FunctorParam {
+    /// Name of the module parameter of the functor, e.g. `Elt`.
+    const MODULE: &'static str;
+}
+
+/// The default functor parameter, named `Elt`.
+pub struct Elt;
+
+impl FunctorParam for Elt {
+    const MODULE: &'static str = "Elt";
+}
+
+/// A rooted OCaml value of the type `t` of the functor parameter `P`, i.e.
+/// printed by `ocaml_gen` as `Elt.t`. The value is kept as is, see module
+/// level docs.
+pub struct Element<P: FunctorParam = Elt>(MlBox, PhantomData<P>);
+
+impl<P: FunctorParam> Element<P> {
+    /// Roots `value`, which has to be of the element type on OCaml side.
+    pub fn new(gc: &ocaml::Runtime, value: ocaml::Value) -> Self {
+        Self(MlBox::new(gc, value), PhantomData)
+    }
+
+    /// Converts the element to the Rust type `T` the element type is bound
+    /// to, e.g. `DynBox<Sheep>` for the `Sheep` module. The conversion is not
+    /// checked against the functor application, so `T` has to match the
+    /// elements of the container, same as with `ocaml::FromValue` in general.
+    pub fn get<T: ocaml::FromValue>(&self, gc: &ocaml::Runtime) -> T {
+        T::from_value(self.0.as_value(gc))
+    }
+
+    /// Returns the rooted OCaml value of the element.
+    pub fn as_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.as_value(gc)
+    }
+}
+
+impl<P: FunctorParam> Clone for Element<P> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone(), PhantomData)
+    }
+}
+
+impl<P: FunctorParam> OCamlDesc for Element<P> {
+    fn ocaml_desc(_env: &ocaml_gen::Env, _generics: &[&str]) -> String {
+        format!("{}.t", P::MODULE)
+    }
+
+    fn unique_id() -> u128 {
+        panic!("unique_id is not supported for Element")
+    }
+}
+
+unsafe impl<P: FunctorParam> ocaml::ToValue for Element<P> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.as_value(gc)
+    }
+}
+
+unsafe impl<P: FunctorParam> ocaml::FromValue for Element<P> {
+    /// Same as for `OCamlFunc`, assumes that it's not called manually on a
+    /// non-OCaml thread.
+    fn from_value(v: ocaml::Value) -> Self {
+        Self::new(unsafe { ocaml::Runtime::recover_handle() }, v)
+    }
+}
+
+/// Thin wrapper around the `DynBox` of a container of `Element`s, which adds
+/// the element type of the functor application to its OCaml type, i.e.
+/// `Instance<DynBox<Stack<Element>>>` is printed as
+/// ``[ tags | `Elt of Elt.t ] stack'``, see module level docs.
+///
+/// Same as with `Phantom`, the type of `DynBox<T>` must be declared with
+/// `decl_type!` within the functor.
+#[derive(Deref, DerefMut)]
+pub struct Instance<T, P: FunctorParam = Elt>(
+    #[deref]
+    #[deref_mut]
+    T,
+    PhantomData<P>,
+);
+
+impl<T, P: FunctorParam> Instance<T, P> {
+    /// Creates a new `Instance`.
+    pub fn new(v: T) -> Self {
+        Self(v, PhantomData)
+    }
+
+    /// Consumes the `Instance` and returns the inner value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, P: FunctorParam> From<T> for Instance<T, P> {
+    fn from(v: T) -> Self {
+        Self::new(v)
+    }
+}
+
+impl<T: ?Sized + Send + 'static, P: FunctorParam> OCamlDesc for Instance<DynBox<T>, P> {
+    fn ocaml_desc(env: &ocaml_gen::Env, generics: &[&str]) -> String {
+        let variant = format!("`{} of {}.t", P::MODULE, P::MODULE);
+        dynbox_desc_with_variant::<T>(env, generics, &variant)
+    }
+
+    fn unique_id() -> u128 {
+        DynBox::<T>::unique_id()
+    }
+}
+
+unsafe impl<T: ocaml::ToValue, P: FunctorParam> ocaml::ToValue for Instance<T, P> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.to_value(gc)
+    }
+}
+
+unsafe impl<T: ocaml::FromValue, P: FunctorParam> ocaml::FromValue for Instance<T, P> {
+    fn from_value(v: ocaml::Value) -> Self {
+        Self(T::from_value(v), PhantomData)
+    }
+}
+
+/// Generates the opening line of a functor `name` taking a module `param`
+/// with an abstract type `t`, to be closed with `end`.
+pub fn functor_header(name: &str, param: &str) -> String {
+    format!("module {} ({} : sig type t end) = struct", name, param)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_functor_header() {
+        assert_eq!(
+            functor_header("Stack", "Elt"),
+            "module Stack (Elt : sig type t end) = struct"
+        );
+        assert_eq!(
+            Element::<Elt>::ocaml_desc(&ocaml_gen::Env::new(), &[]),
+            "Elt.t"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! This module provides `Lazy<T>`, an interop type which maps to OCaml
+//! `'a lazy_t`. It allows bindings to return expensive-to-compute values, so
+//! that OCaml only pays for the computation when it forces the value:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn sheep_create_lazy(name: String) -> Lazy<DynBox<Sheep>> {
+//!     Lazy::new(move || animals::Sheep::new(name).into())
+//! }
+//! ```
+//!
+//! A `Lazy<T>` created on Rust side is passed to OCaml as a regular lazy value,
+//! whose computation calls back into Rust. A `Lazy<T>` received from OCaml
+//! wraps the OCaml lazy value. In both cases, the computation runs at most once
+//! and its result is cached, no matter whether the value is forced from Rust
+//! or from OCaml.
+//!
+//! The OCaml side of this interop is registered by the `Rusty_lazy` module of
+//! `ocaml-rs-smartptr` library.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use ocaml_gen::OCamlDesc;
+
+use crate::ml_box::MlBox;
+use crate::ocaml_gen_extras::stable_unique_id;
+use crate::shutdown;
+
+/// Name of `Lazy.force` registered with `Callback` on OCaml side.
+const LAZY_FORCE: &str = "ocaml_rs_smartptr_lazy_force";
+/// Name of the function which wraps a `LazyThunk` into `lazy_t`, registered
+/// with `Callback` on OCaml side.
+const LAZY_OF_THUNK: &str = "ocaml_rs_smartptr_lazy_of_thunk";
+
+/// A deferred computation of `T`, which maps to OCaml `T lazy_t`, see module
+/// level docs.
+pub struct Lazy<T>(Repr<T>);
+
+enum Repr<T> {
+    /// Computation defined on Rust side, shared with OCaml lazy values created
+    /// out of it.
+    Rust(Arc<Deferred<T>>),
+    /// Lazy value received from OCaml.
+    OCaml(MlBox),
+}
+
+struct Deferred<T> {
+    value: OnceLock<T>,
+    thunk: Mutex<Option<Box<dyn FnOnce() -> T + Send>>>,
+}
+
+impl<T> Deferred<T> {
+    /// Runs the computation if it was not run yet, and returns the cached
+    /// result.
+    fn force(&self) -> &T {
+        self.value.get_or_init(|| {
+            let thunk = self
+                .thunk
+                .lock()
+                .expect("lazy thunk mutex is poisoned")
+                .take()
+                .expect("lazy value was not computed, but its thunk is gone");
+            thunk()
+        })
+    }
+}
+
+impl<T> Lazy<T> {
+    /// Creates a new `Lazy` which runs `f` when it's forced for the first time.
+    pub fn new(f: impl FnOnce() -> T + Send + 'static) -> Self {
+        Lazy(Repr::Rust(Arc::new(Deferred {
+            value: OnceLock::new(),
+            thunk: Mutex::new(Some(Box::new(f))),
+        })))
+    }
+
+    /// Creates an already forced `Lazy` out of `v`.
+    pub fn from_val(v: T) -> Self {
+        Lazy(Repr::Rust(Arc::new(Deferred {
+            value: OnceLock::from(v),
+            thunk: Mutex::new(None),
+        })))
+    }
+}
+
+impl<T: Clone + ocaml::FromValue> Lazy<T> {
+    /// Forces the value, running the computation if it was not run yet, and
+    /// returns the result. The result is cached, so the computation is run at
+    /// most once. Forcing the value from within its own computation deadlocks
+    /// for values created on Rust side. For values received from OCaml, any
+    /// exception raised by the computation (including `Lazy.Undefined`) panics.
+    pub fn force(&self, gc: &ocaml::Runtime) -> T {
+        match &self.0 {
+            Repr::Rust(deferred) => deferred.force().clone(),
+            Repr::OCaml(lazy) => {
+                let force = named_callback(LAZY_FORCE);
+                let res = unsafe { force.call1(gc, lazy.as_value(gc)) };
+                T::from_value(res.expect("forcing OCaml lazy value failed"))
+            }
+        }
+    }
+}
+
+impl<T> Clone for Lazy<T> {
+    /// Clones the `Lazy`, sharing the underlying computation. Custom Clone
+    /// implementation lifts the requirement for T to be Clone
+    fn clone(&self) -> Self {
+        match &self.0 {
+            Repr::Rust(deferred) => Lazy(Repr::Rust(deferred.clone())),
+            Repr::OCaml(lazy) => Lazy(Repr::OCaml(lazy.clone())),
+        }
+    }
+}
+
+/// Looks up a function registered with `Callback` by `Rusty_lazy` (or other
+/// `Rusty_*`) module.
+pub(crate) fn named_callback(name: &str) -> ocaml::Value {
+    unsafe { ocaml::Value::named(name) }.unwrap_or_else(|| {
+        panic!(
+            "OCaml callback {} is not registered, make sure that ocaml-rs-smartptr \
+             library is linked",
+            name
+        )
+    })
+}
+
+/// Type-erased computation of the OCaml value of a `Lazy<T>` created on Rust
+/// side, which is held by the OCaml heap.
+struct LazyThunk(Box<dyn Fn(&ocaml::Runtime) -> ocaml::Value>);
+
+/// Finalizer is registered with OCaml GC, and drops the thunk (and the
+/// `Deferred` reference it holds) whenever OCaml drops it.
+unsafe extern "C" fn lazy_thunk_finalizer(v: ocaml::Raw) {
+    if shutdown::is_shut_down() {
+        return;
+    }
+    let ptr = v.as_pointer::<LazyThunk>();
+    ptr.drop_in_place();
+}
+
+impl ocaml::Custom for LazyThunk {
+    const NAME: &'static str = "LazyThunk\0";
+
+    const OPS: ocaml::custom::CustomOps = ocaml::custom::CustomOps {
+        identifier: Self::NAME.as_ptr() as *mut ocaml::sys::Char,
+        finalize: Some(lazy_thunk_finalizer),
+        ..ocaml::custom::DEFAULT_CUSTOM_OPS
+    };
+}
+
+/// Runs the thunk of a lazy value on behalf of OCaml, see
+/// `ocaml_rs_smartptr_lazy_run` stub.
+pub(crate) fn run_thunk(gc: &ocaml::Runtime, thunk: ocaml::Value) -> ocaml::Value {
+    let ptr = unsafe { thunk.raw().as_pointer::<LazyThunk>() };
+    (ptr.as_ref().0)(gc)
+}
+
+unsafe impl<T: ocaml::ToValue + 'static> ocaml::ToValue for Lazy<T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        match &self.0 {
+            Repr::Rust(deferred) => {
+                let deferred = deferred.clone();
+                let thunk = LazyThunk(Box::new(move |gc| deferred.force().to_value(gc)));
+                let thunk = ocaml::Pointer::from(thunk).to_value(gc);
+                let of_thunk = named_callback(LAZY_OF_THUNK);
+                unsafe { of_thunk.call1(gc, thunk) }
+                    .expect("wrapping Rust thunk into OCaml lazy value failed")
+            }
+            Repr::OCaml(lazy) => lazy.as_value(gc),
+        }
+    }
+}
+
+unsafe impl<T> ocaml::FromValue for Lazy<T> {
+    /// Wraps an OCaml lazy value. Same as for `OCamlFunc`, this function
+    /// assumes that it is not called manually on a non-OCaml thread.
+    fn from_value(v: ocaml::Value) -> Self {
+        Lazy(Repr::OCaml(MlBox::new(
+            unsafe { ocaml::Runtime::recover_handle() },
+            v,
+        )))
+    }
+}
+
+impl<T: OCamlDesc> OCamlDesc for Lazy<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        format!("{} lazy_t", T::ocaml_desc(env, generics))
+    }
+
+    fn unique_id() -> u128 {
+        stable_unique_id("Lazy", &T::unique_id())
+    }
+}
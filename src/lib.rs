@@ -1,16 +1,44 @@
+pub mod bigarray;
+pub mod bytes;
 pub mod callable;
+pub mod exception;
+pub mod float_array;
 pub mod func;
+pub mod functor;
+pub mod lazy;
+pub mod local_ptr;
+mod logging;
 pub mod ml_box;
+pub mod module;
+pub mod newtype;
 pub mod ocaml_gen_extras;
+pub mod poly_variant;
 pub mod ptr;
+pub mod record;
 pub mod registry;
+pub mod result;
+#[cfg(feature = "tokio")]
+pub mod runtime;
+pub mod scope;
+pub mod shutdown;
+pub mod stream;
 pub mod stubs;
 mod type_name;
 
+pub use ocaml_rs_smartptr_macro::generate_constructors;
+pub use ocaml_rs_smartptr_macro::register_all;
 pub use ocaml_rs_smartptr_macro::register_trait;
 pub use ocaml_rs_smartptr_macro::register_type;
+pub use ocaml_rs_smartptr_macro::smartptr_func;
+pub use ocaml_rs_smartptr_macro::FromModule;
+pub use ocaml_rs_smartptr_macro::OcamlAccessors;
+pub use ocaml_rs_smartptr_macro::OcamlPolyVariant;
+pub use ocaml_rs_smartptr_macro::OcamlRecord;
 
 pub use inventory;
+pub use ocaml_gen_extras::set_unique_id_key;
+pub use registry::{set_panic_policy, PanicPolicy};
+pub use shutdown::register_at_exit;
 
 #[macro_use]
 extern crate static_assertions;
@@ -37,7 +65,27 @@ macro_rules! ocaml_gen_bindings {
                 #[allow(unused_macros)]
                 macro_rules! decl_module {
                     ($name:expr, $content:tt) => {
+                        $crate::ocaml_gen_extras::enter_module(std::env!("CARGO_PKG_NAME"), $name);
                         ocaml_gen::decl_module!(w, ocaml_gen_env, $name, $content);
+                        $crate::ocaml_gen_extras::leave_module();
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_functor {
+                    ($name:expr, $param:expr, $content:tt) => {
+                        writeln!(
+                            w,
+                            "{}",
+                            $crate::functor::functor_header($name, $param)
+                        )
+                        .unwrap();
+                        $crate::ocaml_gen_extras::enter_module(std::env!("CARGO_PKG_NAME"), $name);
+                        ocaml_gen_env.new_module($name);
+                        $content
+                        ocaml_gen_env.parent();
+                        $crate::ocaml_gen_extras::leave_module();
+                        writeln!(w, "end").unwrap();
                     };
                 }
 
@@ -55,6 +103,23 @@ macro_rules! ocaml_gen_bindings {
                     };
                 }
 
+                #[allow(unused_macros)]
+                macro_rules! decl_func_with_defaults {
+                    ($func:ident => $name:expr, [$($param:expr),+ $(,)?]) => {
+                        ocaml_gen::decl_func!(w, ocaml_gen_env, $func => concat!($name, "_raw"));
+                        writeln!(
+                            w,
+                            "{}",
+                            $crate::ocaml_gen_extras::func_with_defaults(
+                                $name,
+                                concat!($name, "_raw"),
+                                &[$($param),+],
+                            )
+                        )
+                        .unwrap();
+                    };
+                }
+
                 #[allow(unused_macros)]
                 macro_rules! decl_type_alias {
                     ($new:expr => $ty:ty) => {
@@ -62,6 +127,82 @@ macro_rules! ocaml_gen_bindings {
                     };
                 }
 
+                #[allow(unused_macros)]
+                macro_rules! decl_instance_type {
+                    ($type:ty => $name:expr) => {
+                        let desc = <$type as ocaml_gen::OCamlDesc>::ocaml_desc(ocaml_gen_env, &[]);
+                        writeln!(w, "type {} = {}", $name, desc).unwrap();
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_exception {
+                    ($error:ty) => {
+                        writeln!(
+                            w,
+                            "{}",
+                            <$error as $crate::exception::OCamlException>::ocaml_decl()
+                        )
+                        .unwrap();
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_accessors {
+                    ($type:ty) => {
+                        for decl in <$type as $crate::ocaml_gen_extras::OcamlAccessors>::accessors_to_ocaml(ocaml_gen_env) {
+                            writeln!(w, "{}", decl).unwrap();
+                        }
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_constructors {
+                    ($name:expr) => {
+                        for decl in $crate::ocaml_gen_extras::constructors_to_ocaml(ocaml_gen_env, std::env!("CARGO_PKG_NAME"), $name) {
+                            writeln!(w, "{}", decl).unwrap();
+                        }
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_equal {
+                    ($type:ty) => {
+                        let desc = <$type as ocaml_gen::OCamlDesc>::ocaml_desc(ocaml_gen_env, &[]);
+                        writeln!(
+                            w,
+                            "external equal : {} -> {} -> bool = \"ocaml_rs_smartptr_rusty_obj_equal\"",
+                            desc, desc
+                        )
+                        .unwrap();
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_compare {
+                    ($type:ty) => {
+                        let desc = <$type as ocaml_gen::OCamlDesc>::ocaml_desc(ocaml_gen_env, &[]);
+                        writeln!(
+                            w,
+                            "external compare : {} -> {} -> int = \"ocaml_rs_smartptr_rusty_obj_compare\"",
+                            desc, desc
+                        )
+                        .unwrap();
+                    };
+                }
+
+                #[allow(unused_macros)]
+                macro_rules! decl_module_type {
+                    ($type:ty) => {
+                        writeln!(
+                            w,
+                            "{}",
+                            <$type as $crate::module::FromModule>::module_type_to_ocaml(ocaml_gen_env)
+                        )
+                        .unwrap();
+                    };
+                }
+
                 #[allow(unused_macros)]
                 macro_rules! decl_fake_generic {
                     ($name:ident, $i:expr) => {
@@ -0,0 +1,280 @@
+//! This module provides the `LocalDynBox` smart pointer, a single-threaded
+//! counterpart of `DynBox` for `!Send` types. Single-threaded Rust object
+//! graphs (GUI widgets, interpreters) are usually built out of
+//! `Rc<RefCell<T>>`, which can't be put into a `DynBox`, as its values may be
+//! sent to other threads. `LocalDynBox` stores an `Rc<RefCell<dyn Any>>`
+//! instead, and coerces by borrowing the `RefCell` rather than locking:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn widget_child(parent: LocalDynBox<Widget>, label: String) -> LocalDynBox<Widget> {
+//!     let parent = parent.to_rc().expect("not a widget");
+//!     let child = Widget::new(label, Some(parent));
+//!     LocalDynBox::new(child)
+//! }
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn widget_label(widget: LocalDynBox<Widget>) -> String {
+//!     widget.coerce().label.clone()
+//! }
+//! ```
+//!
+//! An existing `Rc<RefCell<T>>` is wrapped with `LocalDynBox::from`, sharing
+//! the value with the rest of the graph, and `to_rc` gives it back. Types and
+//! traits are registered the same way as for `DynBox`, with `register_type!`
+//! and `register_trait!` (listing no marker traits, as the types are not
+//! `Send`), and coercions registered for them work for both smart pointers.
+//!
+//! Handles returned by `coerce` and `coerce_mut` are `RefCell` borrows: read
+//! handles may be held concurrently, and a conflicting borrow panics instead of
+//! blocking.
+//!
+//! On OCaml side, values are `Ocaml_rs_smartptr.Rusty_local_obj.t`, tagged
+//! with polymorphic variants the same way as `Rusty_obj.t`, so they can't be
+//! mixed up with `DynBox` values. `decl_type!(LocalDynBox<T> => "t")` declares
+//! the types. The OCaml GC releases its references from finalizers, which run
+//! on the thread holding the OCaml domain lock. As `Rc` is not thread-safe, the
+//! values must stay within a single OCaml domain, and stubs must not release
+//! the domain lock (or send the values to other threads) while using them.
+
+use ocaml_gen::{OCamlBinding, OCamlDesc};
+use static_assertions::assert_not_impl_any;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::callable::IntoValue;
+use crate::logging::trace;
+use crate::{ocaml_gen_extras, ptr, registry, shutdown, type_name};
+
+/// A single-threaded smart pointer around `Rc<RefCell<dyn Any>>` with
+/// `PhantomData` for type safety, see module level docs.
+pub struct LocalDynBox<T: ?Sized> {
+    pub(crate) inner: Rc<dyn Any>,
+    _phantom: PhantomData<fn(T) -> T>,
+}
+
+impl<T: 'static> LocalDynBox<T> {
+    /// Creates a `LocalDynBox` holding `value` in a new `RefCell`.
+    pub fn new(value: T) -> Self {
+        Self::from(Rc::new(RefCell::new(value)))
+    }
+
+    /// Returns the `Rc<RefCell<T>>` held by the `LocalDynBox`, sharing the
+    /// value with it, so that it can be linked into Rust object graphs.
+    ///
+    /// # Returns
+    ///
+    /// The `Rc`, or `None` if the `LocalDynBox` holds a value of another type,
+    /// e.g. when `T` is a trait object.
+    pub fn to_rc(&self) -> Option<Rc<RefCell<T>>> {
+        self.inner.clone().downcast::<RefCell<T>>().ok()
+    }
+}
+
+impl<T: ?Sized + 'static> LocalDynBox<T> {
+    /// Creates a `LocalDynBox` out of a Box'ed T. Useful if T is unsized, e.g.
+    /// a `dyn Trait`.
+    pub fn new_boxed(value: Box<T>) -> Self {
+        registry::register_value_type::<Box<T>>();
+        LocalDynBox {
+            inner: Rc::new(RefCell::new(value)),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Coerces the `LocalDynBox` to a handle of the specified type.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced type, which borrows the `RefCell`. Panics if the
+    /// value is mutably borrowed elsewhere.
+    pub fn coerce(&self) -> registry::Handle<T> {
+        registry::coerce_local::<T>(self.inner.clone())
+    }
+
+    /// Coerces the `LocalDynBox` to a mutable handle of the specified type.
+    ///
+    /// # Returns
+    ///
+    /// A mutable handle to the coerced type, which borrows the `RefCell`
+    /// mutably. Panics if the value is borrowed elsewhere.
+    pub fn coerce_mut(&self) -> registry::HandleMut<T> {
+        registry::coerce_local_mut::<T>(self.inner.clone())
+    }
+
+    /// Checks if both `LocalDynBox`es share the same value.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    fn into_raw(self) -> *const dyn Any {
+        Rc::into_raw(self.inner)
+    }
+
+    fn from_raw(ptr: *const dyn Any) -> Self {
+        LocalDynBox {
+            inner: unsafe { Rc::from_raw(ptr) },
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> From<Rc<RefCell<T>>> for LocalDynBox<T> {
+    /// Wraps an existing `Rc<RefCell<T>>`, sharing the value with it.
+    fn from(value: Rc<RefCell<T>>) -> Self {
+        registry::register_value_type::<T>();
+        LocalDynBox {
+            inner: value,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Clone for LocalDynBox<T> {
+    /// Clones the `LocalDynBox`, sharing the value. Custom Clone
+    /// implementation lifts the requirement for T to be Clone
+    fn clone(&self) -> Self {
+        LocalDynBox {
+            inner: self.inner.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized + 'static> OCamlDesc for LocalDynBox<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
+        let type_id = <Self as OCamlDesc>::unique_id();
+        let typ = env
+            .get_type(type_id, type_name::get_type_name::<T>().as_str())
+            .0;
+        format!("_ {}'", typ)
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id("LocalDynBox", &TypeId::of::<T>())
+    }
+}
+
+impl<T: ?Sized + 'static> OCamlBinding for LocalDynBox<T> {
+    fn ocaml_binding(
+        env: &mut ::ocaml_gen::Env,
+        rename: Option<&'static str>,
+        new_type: bool,
+    ) -> String {
+        ptr::rusty_obj_binding::<T>(
+            env,
+            rename,
+            new_type,
+            Self::unique_id(),
+            "Ocaml_rs_smartptr.Rusty_local_obj.t",
+            |env| Self::ocaml_desc(env, &[]),
+        )
+    }
+}
+
+// Rc is neither Send nor Sync, and so is LocalDynBox
+assert_not_impl_any!(LocalDynBox<i32>: Send, Sync);
+
+/// A thin wrapper around a pointer to `Rc<RefCell<dyn Any>>`, same as
+/// `RustyObj` is for `DynBox`.
+struct RustyLocalObj(*const dyn Any);
+
+/// Finalizer is registered with OCaml GC, and releases the "leaked" `Rc`
+/// pointer whenever OCaml drops corresponding object. It runs on the thread
+/// holding the OCaml domain lock, which is the one the value lives on.
+unsafe extern "C" fn rusty_local_obj_finalizer(v: ocaml::Raw) {
+    if shutdown::is_shut_down() {
+        return;
+    }
+    let ptr = v.as_pointer::<RustyLocalObj>();
+    // Actual type parameter T is irrelevant here, same as for `RustyObj`
+    let local: LocalDynBox<()> = LocalDynBox::from_raw(ptr.as_ref().0);
+    trace!("finalize RustyLocalObj");
+    drop(local);
+    ptr.drop_in_place();
+}
+
+impl ocaml::Custom for RustyLocalObj {
+    const NAME: &'static str = "RustyLocalObj\0";
+
+    const OPS: ocaml::custom::CustomOps = ocaml::custom::CustomOps {
+        identifier: Self::NAME.as_ptr() as *mut ocaml::sys::Char,
+        finalize: Some(rusty_local_obj_finalizer),
+        ..ocaml::custom::DEFAULT_CUSTOM_OPS
+    };
+}
+
+unsafe impl<T: ?Sized + 'static> ocaml::FromValue for LocalDynBox<T> {
+    fn from_value(v: ocaml::Value) -> Self {
+        let ptr = unsafe { v.raw().as_pointer::<RustyLocalObj>() };
+        // The `Rc` held by the custom block is owned by OCaml GC, so we take a
+        // clone of it and leave the original intact
+        let orig =
+            std::mem::ManuallyDrop::new(LocalDynBox::<T>::from_raw(ptr.as_ref().0));
+        (*orig).clone()
+    }
+}
+
+unsafe impl<T: ?Sized + 'static> ocaml::ToValue for LocalDynBox<T> {
+    fn to_value(&self, rt: &ocaml::Runtime) -> ocaml::Value {
+        self.clone().into_value(rt)
+    }
+}
+
+impl<T: ?Sized + 'static> IntoValue for LocalDynBox<T> {
+    fn into_value(self, rt: &ocaml::Runtime) -> ocaml::Value {
+        // Our own reference is moved into the custom block, without cloning
+        let ptr = self.into_raw();
+        ocaml::Pointer::from(RustyLocalObj(ptr)).to_value(rt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    trait Node {
+        fn depth(&self) -> usize;
+    }
+
+    struct Tree {
+        parent: Option<Rc<RefCell<Tree>>>,
+    }
+
+    impl Node for Tree {
+        fn depth(&self) -> usize {
+            match &self.parent {
+                Some(parent) => parent.borrow().depth() + 1,
+                None => 0,
+            }
+        }
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_local_dynbox() {
+        registry::register::<Tree, Tree>(|x| x, |x| x);
+        registry::register::<Tree, dyn Node>(|x| x as &dyn Node, |x| x as &mut dyn Node);
+        let root = LocalDynBox::new(Tree { parent: None });
+        let child = LocalDynBox::new(Tree {
+            parent: Some(root.to_rc().unwrap()),
+        });
+        // The following line mimics the box being sent to OCaml and received
+        // back as a trait object
+        let child: LocalDynBox<dyn Node> = LocalDynBox::from_raw(child.into_raw());
+        assert_eq!(child.coerce().depth(), 1);
+        assert!(child.ptr_eq(&child.clone()));
+
+        // The child shares the root with the original box
+        let root_rc = root.to_rc().unwrap();
+        let grandparent = Rc::new(RefCell::new(Tree { parent: None }));
+        root.coerce_mut().parent = Some(grandparent);
+        assert_eq!(child.coerce().depth(), 2);
+        assert!(root_rc.borrow().parent.is_some());
+    }
+}
@@ -0,0 +1,16 @@
+//! Optional debug logging of registrations, coercions and finalizers, enabled
+//! by the `log` feature. Events are emitted with the `log` crate at trace level
+//! under the `ocaml_rs_smartptr` target prefix, so that e.g. `env_logger` picks
+//! them up with `RUST_LOG=ocaml_rs_smartptr=trace`. Without the feature the
+//! events are compiled out along with their arguments.
+
+/// Emits a trace event when the `log` feature is enabled, takes the same
+/// arguments as `log::trace!`.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        ::log::trace!($($arg)*);
+    };
+}
+
+pub(crate) use trace;
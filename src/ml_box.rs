@@ -3,11 +3,103 @@
 //! that the OCaml runtime handle is available when recovering the value,
 //! maintaining safety.
 
+use std::cell::Cell;
 use std::panic::{AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
 use std::sync::Arc;
 
 use derive_more::derive::Display;
 
+thread_local! {
+    /// Whether the current thread is known to hold the OCaml domain lock, i.e.
+    /// it entered a stub or `with_domain_lock`. Only tracked in debug builds,
+    /// see `assert_ocaml_thread`.
+    static IS_OCAML_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as holding the OCaml domain lock, which makes
+/// `MlBox` recoverable on it in debug builds. Stubs defined with
+/// `#[smartptr_func]` and conversions of `DynBox` arguments call it already,
+/// hand-written stubs which recover an `MlBox` without taking any of those
+/// should call it on entry. Must only be called with the domain lock held.
+/// No-op in release builds.
+pub fn mark_ocaml_thread() {
+    if cfg!(debug_assertions) {
+        IS_OCAML_THREAD.with(|flag| flag.set(true));
+    }
+}
+
+/// Asserts that the current thread is known to hold the OCaml domain lock,
+/// which catches recovering an `MlBox` on a thread that never held it (e.g. a
+/// tokio worker) during development. Doing so corrupts boxroots instead of
+/// failing loudly. It's a heuristic: OCaml threads are marked on stub entry
+/// (see `mark_ocaml_thread`), and we can't tell whether a stub released the
+/// lock since. No-op in release builds.
+fn assert_ocaml_thread(method: &str) {
+    if cfg!(debug_assertions) && !IS_OCAML_THREAD.with(Cell::get) {
+        let thread = std::thread::current();
+        panic!(
+            "MlBox::{} called on thread {:?} ({:?}) which is not known to hold the \
+             OCaml domain lock, MlBox can only be recovered from OCaml threads",
+            method,
+            thread.name().unwrap_or("<unnamed>"),
+            thread.id()
+        );
+    }
+}
+
+extern "C" {
+    // Provided by OCaml systhreads (`threads.posix` library)
+    fn caml_c_thread_register() -> std::os::raw::c_int;
+    fn caml_c_thread_unregister() -> std::os::raw::c_int;
+    fn caml_enter_blocking_section();
+    fn caml_leave_blocking_section();
+}
+
+/// Releases the OCaml domain lock and unregisters the thread (if it was
+/// registered by `with_domain_lock`) when dropped, including on panic.
+struct DomainLockGuard {
+    registered: bool,
+    // Whether the thread was marked before taking the lock, see
+    // `mark_ocaml_thread`
+    was_ocaml_thread: bool,
+}
+
+impl Drop for DomainLockGuard {
+    fn drop(&mut self) {
+        IS_OCAML_THREAD.with(|flag| flag.set(self.was_ocaml_thread));
+        unsafe {
+            caml_enter_blocking_section();
+            if self.registered {
+                caml_c_thread_unregister();
+            }
+        }
+    }
+}
+
+/// Runs `f` with the OCaml domain lock acquired, on a thread which does not
+/// hold it, e.g. a Rust worker thread. The thread is registered with the OCaml
+/// runtime for the duration of the call if it's not registered yet.
+///
+/// This function blocks until the domain lock is available, i.e. until OCaml
+/// code releases it (by returning from a stub, or by releasing the lock, e.g.
+/// via `ocaml::Runtime::releasing_runtime` or blocking IO). As the OCaml
+/// runtime runs a single thread at a time under the lock, calls from several
+/// threads are serialized. Calling it on a thread which already holds the lock
+/// (e.g. from within a stub) deadlocks.
+///
+/// OCaml systhreads have to be linked and initialized, which is the case when
+/// `ocaml-rs-smartptr` library is linked.
+pub fn with_domain_lock<T>(f: impl FnOnce(&ocaml::Runtime) -> T) -> T {
+    let registered = unsafe { caml_c_thread_register() } != 0;
+    unsafe { caml_leave_blocking_section() };
+    let _guard = DomainLockGuard {
+        registered,
+        was_ocaml_thread: IS_OCAML_THREAD.with(Cell::get),
+    };
+    mark_ocaml_thread();
+    f(unsafe { ocaml::Runtime::recover_handle() })
+}
+
 /// This wrapper type around `ocaml::root::Root` allows sending
 /// `ocaml::root::Root` to other threads and provides a safe interface for doing
 /// so - the value can be recovered back only in a context where an OCaml
@@ -37,6 +129,7 @@ impl MlBox {
     /// Creates a new `MlBox` out of `ocaml::Value`, taking an OCaml runtime handle to
     /// ensure this operation is called while the OCaml domain lock is acquired.
     pub fn new(_gc: &ocaml::Runtime, value: ocaml::Value) -> Self {
+        mark_ocaml_thread();
         match value {
             ocaml::Value::Raw(v) => {
                 // `ocaml::Value` was a raw one, need to create a new root for it
@@ -65,6 +158,7 @@ impl MlBox {
     /// can be used when you're sure that you have only one reference to `MlBox`,
     /// in this case using this method can save on new boxroot allocation.
     pub fn into_value(self, _gc: &ocaml::Runtime) -> Option<ocaml::Value> {
+        assert_ocaml_thread("into_value");
         Arc::into_inner(self.inner)
             .map(|x| x.0)
             .map(ocaml::Value::Root)
@@ -73,6 +167,7 @@ impl MlBox {
     /// Creates a new rooted `ocaml::Value`, the root is obtained by recovering the value
     /// from the current root and creating a new root for it.
     pub fn as_value(&self, _gc: &ocaml::Runtime) -> ocaml::Value {
+        assert_ocaml_thread("as_value");
         // Caveat: we call `.clone()` on `ocaml::root::Root`, which will create a
         // new boxroot with the value obtained from the current boxroot. According to
         // `boxroot.h`, both `boxroot_create` and `boxroot_get` require the OCaml
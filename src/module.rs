@@ -0,0 +1,78 @@
+//! This module allows receiving OCaml first-class modules, i.e. bundles of
+//! functions passed as `(module S)`, as Rust structs. Plugin-style OCaml APIs
+//! often take those instead of a record of closures.
+//!
+//! A struct with named fields is bound to an OCaml module type with
+//! `#[derive(FromModule)]`. Each field becomes a value of the module type,
+//! named after the field unless it's renamed with `#[module(name = "...")]`,
+//! and the module type is named after the struct unless it's renamed with
+//! `#[module(type_name = "...")]` on the struct:
+//!
+//! ```rust,ignore
+//! #[derive(FromModule)]
+//! #[module(type_name = "S")]
+//! pub struct Greeter {
+//!     pub greet: OCamlFunc<(String,), String>,
+//!     #[module(name = "bye")]
+//!     pub farewell: OCamlFunc<(String,), String>,
+//! }
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn greeter_run(greeter: Greeter, name: String) -> String {
+//!     greeter.greet.call(gc, (name,))
+//! }
+//! ```
+//!
+//! The module type is declared in bindings with `decl_module_type!(Greeter)`
+//! within `ocaml_gen_bindings!`, which emits:
+//!
+//! ```ocaml
+//! module type S = sig
+//!   val greet : string -> string
+//!   val bye : string -> string
+//! end
+//! ```
+//!
+//! Stubs taking the struct are then declared as taking `(module S)`, so
+//! `decl_module_type!` has to come before them, in the same or an enclosing
+//! module. Any OCaml module which satisfies the module type can be passed, e.g.
+//! `Greeter.run (module My_greeter) "Dolly"`.
+//!
+//! At runtime, a first-class module is a block holding the values of its
+//! module type in the order they are declared, so each field is projected out
+//! of the block by its position. Fields are usually `OCamlFunc`s, which root
+//! the projected functions with `MlBox`, so the struct can be kept around and
+//! sent to other threads. Any other type implementing `ocaml::FromValue` and
+//! `OCamlDesc` works as well.
+
+use ocaml_gen::OCamlDesc;
+
+/// Implemented by `#[derive(FromModule)]`, see module level docs. The derive
+/// also implements `ocaml::FromValue` and `OCamlDesc` for the struct.
+pub trait FromModule {
+    /// Name of the OCaml module type, e.g. `S`.
+    const MODULE_TYPE: &'static str;
+
+    /// Generates the OCaml declaration of the module type.
+    fn module_type_to_ocaml(env: &ocaml_gen::Env) -> String;
+}
+
+/// Projects the value at `index` out of a first-class module.
+///
+/// The index is the position of the value in the module type, which is how
+/// OCaml lays out packed modules.
+pub fn project<T: ocaml::FromValue>(module: &ocaml::Value, index: usize) -> T {
+    unsafe { module.field(index) }
+}
+
+/// Describes a value of a module type, as used by `module_type_decl`.
+pub fn value_decl<T: OCamlDesc>(env: &ocaml_gen::Env, name: &str) -> String {
+    format!("val {} : {}", name, T::ocaml_desc(env, &[]))
+}
+
+/// Generates the declaration of a module type out of its values, see
+/// `value_decl`.
+pub fn module_type_decl(name: &str, values: &[String]) -> String {
+    format!("module type {} = sig {} end", name, values.join(" "))
+}
@@ -0,0 +1,234 @@
+//! This module provides a workaround for the orphan rule, which forbids
+//! implementing a trait for a type when neither of them is defined by the
+//! current crate. Coercions are registered with `x as &dyn Trait`, so a crate
+//! binding a third-party type (e.g. `std::time::Duration`) to a third-party
+//! trait it doesn't implement (e.g. `std::fmt::Display`) has nothing to
+//! register. The usual fix is a local newtype, which `newtype_coercion!`
+//! declares along with conversions from and to the wrapped type:
+//!
+//! ```rust,ignore
+//! newtype_coercion!(pub struct Elapsed(std::time::Duration));
+//!
+//! impl std::fmt::Display for Elapsed {
+//!     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+//!         write!(f, "{}ms", self.as_millis())
+//!     }
+//! }
+//!
+//! register_rtti! {
+//!     register_type!(
+//!         {
+//!             ty: crate::Elapsed,
+//!             marker_traits: [core::marker::Send],
+//!             object_safe_traits: [std::fmt::Display],
+//!         }
+//!     );
+//!     ocaml_rs_smartptr::newtype::register_newtype::<crate::Elapsed>();
+//! }
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn elapsed_since_epoch() -> DynBox<Elapsed> {
+//!     let now = std::time::SystemTime::now();
+//!     Elapsed::from(now.duration_since(std::time::UNIX_EPOCH).unwrap()).into()
+//! }
+//! ```
+//!
+//! The newtype derefs to the wrapped value, so stubs taking `DynBox<Elapsed>`
+//! use it as a `Duration` right away. `register_newtype` additionally registers
+//! a coercion of the newtype to the wrapped type, so that code which only knows
+//! about `Duration` can still coerce values holding an `Elapsed` to it.
+//!
+//! A newtype may also be declared to implement a trait of the current crate
+//! for a foreign type, with the wrapped type keeping all its registered
+//! coercions. `register_transparent!` registers such a newtype as transparent
+//! over the type it wraps, so that values of the newtype coerce to the wrapped
+//! type through `AsRef` and `AsMut`, and to all the traits the wrapped type
+//! coerces to, along with the ones the newtype registers itself:
+//!
+//! ```rust,ignore
+//! #[derive(AsRef, AsMut)]
+//! pub struct SheepWrapper(animals::Sheep);
+//!
+//! register_rtti! {
+//!     register_transparent!(crate::SheepWrapper => animals::Sheep);
+//! }
+//! ```
+
+use crate::registry;
+
+/// A local newtype around a foreign type, see module level docs. Implemented
+/// by `newtype_coercion!`.
+pub trait NewtypeCoercion: Send + 'static {
+    /// The wrapped foreign type.
+    type Inner: Send + 'static;
+
+    /// Wraps a value of the foreign type.
+    fn from_inner(inner: Self::Inner) -> Self;
+
+    /// Returns a reference to the wrapped value.
+    fn inner(&self) -> &Self::Inner;
+
+    /// Returns a mutable reference to the wrapped value.
+    fn inner_mut(&mut self) -> &mut Self::Inner;
+}
+
+/// Registers the coercion of the newtype `W` to the type it wraps, in addition
+/// to whatever `register_type!` registered for `W` itself.
+pub fn register_newtype<W: NewtypeCoercion>() {
+    registry::register_type::<W::Inner>();
+    registry::register::<W, W::Inner>(W::inner, W::inner_mut);
+}
+
+/// Declares a newtype around a foreign type, along with `NewtypeCoercion`,
+/// `From`, `Deref` and `DerefMut` implementations, see module level docs.
+#[macro_export]
+macro_rules! newtype_coercion {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident($inner_vis:vis $inner:ty) $(;)?) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        $vis struct $name($inner_vis $inner);
+
+        impl $crate::newtype::NewtypeCoercion for $name {
+            type Inner = $inner;
+
+            fn from_inner(inner: $inner) -> Self {
+                $name(inner)
+            }
+
+            fn inner(&self) -> &$inner {
+                &self.0
+            }
+
+            fn inner_mut(&mut self) -> &mut $inner {
+                &mut self.0
+            }
+        }
+
+        impl ::std::convert::From<$inner> for $name {
+            fn from(inner: $inner) -> Self {
+                $name(inner)
+            }
+        }
+
+        impl ::std::ops::Deref for $name {
+            type Target = $inner;
+
+            fn deref(&self) -> &$inner {
+                &self.0
+            }
+        }
+
+        impl ::std::ops::DerefMut for $name {
+            fn deref_mut(&mut self) -> &mut $inner {
+                &mut self.0
+            }
+        }
+    };
+}
+
+/// Registers the newtype `$newtype` as transparent over the type `$inner` it
+/// wraps, see module level docs and `registry::register_transparent`.
+#[macro_export]
+macro_rules! register_transparent {
+    ($newtype:ty => $inner:ty) => {
+        $crate::registry::register_transparent::<$newtype, $inner>()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::*;
+
+    crate::newtype_coercion!(struct Elapsed(Duration));
+
+    impl std::fmt::Display for Elapsed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}ms", self.as_millis())
+        }
+    }
+
+    #[test]
+    fn test_newtype_coercion() {
+        registry::with_registry(|| {
+            registry::register_type::<Elapsed>();
+            registry::register_type::<dyn std::fmt::Display>();
+            registry::register::<Elapsed, dyn std::fmt::Display>(|x| x, |x| x);
+            register_newtype::<Elapsed>();
+
+            let value: Arc<dyn Any + Sync + Send> =
+                Arc::new(Mutex::new(Elapsed::from(Duration::from_millis(1500))));
+            let display = registry::coerce::<dyn std::fmt::Display>(value.clone());
+            assert_eq!(display.to_string(), "1500ms");
+            drop(display);
+            *registry::coerce_mut::<Duration>(value.clone()) += Duration::from_secs(1);
+            assert_eq!(
+                *registry::coerce::<Duration>(value),
+                Duration::from_millis(2500)
+            );
+        });
+    }
+
+    trait Millis {
+        fn millis(&self) -> u128;
+    }
+
+    impl Millis for Duration {
+        fn millis(&self) -> u128 {
+            self.as_millis()
+        }
+    }
+
+    struct Wrapper(Duration);
+
+    impl AsRef<Duration> for Wrapper {
+        fn as_ref(&self) -> &Duration {
+            &self.0
+        }
+    }
+
+    impl AsMut<Duration> for Wrapper {
+        fn as_mut(&mut self) -> &mut Duration {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn test_register_transparent() {
+        registry::with_registry(|| {
+            registry::register_type::<Duration>();
+            registry::register_type::<dyn std::fmt::Debug>();
+            registry::register::<Duration, dyn std::fmt::Debug>(|x| x, |x| x);
+            crate::register_transparent!(Wrapper => Duration);
+            // Coercions of the wrapped type registered afterwards are
+            // delegated as well
+            registry::register_type::<dyn Millis>();
+            registry::register::<Duration, dyn Millis>(|x| x, |x| x);
+
+            let value: Arc<dyn Any + Sync + Send> =
+                Arc::new(Mutex::new(Wrapper(Duration::from_millis(1500))));
+            *registry::coerce_mut::<Duration>(value.clone()) += Duration::from_secs(1);
+            assert_eq!(
+                format!(
+                    "{:?}",
+                    &*registry::coerce::<dyn std::fmt::Debug>(value.clone())
+                ),
+                "2.5s"
+            );
+            assert_eq!(
+                format!(
+                    "{:?}",
+                    &*registry::coerce_ref::<dyn std::fmt::Debug>(&value)
+                ),
+                "2.5s"
+            );
+            assert_eq!(registry::coerce::<dyn Millis>(value.clone()).millis(), 2500);
+            // `dyn Any` exposes the wrapper itself
+            assert!(registry::coerce::<dyn Any>(value).is::<Wrapper>());
+        });
+    }
+}
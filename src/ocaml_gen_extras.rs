@@ -1,29 +1,172 @@
 #![doc = "This module provides additional utilities and extensions for generating OCaml bindings."]
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
+use std::hash::Hash;
 use std::io::Write;
 use std::marker::PhantomData;
 use std::path::Path;
+use std::sync::OnceLock;
 
 use derive_more::{
     derive::{AsMut, AsRef, Deref, DerefMut},
     From, Into,
 };
 
+use highway::{HighwayHash, HighwayHasher};
 use ocaml_gen::{OCamlBinding, OCamlDesc};
 
 use crate::ptr::DynBox;
 
+/// Key for hashing unique IDs of OCaml types. It's fixed rather than random,
+/// so that a type gets the same unique ID no matter which crate of the build
+/// computes it, and `ocaml_gen` can deduplicate types declared by several
+/// crates. It can be replaced with `set_unique_id_key`.
+pub const UNIQUE_ID_KEY: highway::Key = highway::Key([
+    0x6f63_616d_6c2d_7273,
+    0x2d73_6d61_7274_7074,
+    0x722d_756e_6971_7565,
+    0x2d69_642d_6b65_7931,
+]);
+
+/// The key in use, fixed by `set_unique_id_key` or by the first unique ID
+/// computed, whichever comes first.
+static CURRENT_UNIQUE_ID_KEY: OnceLock<[u64; 4]> = OnceLock::new();
+
+/// Replaces `UNIQUE_ID_KEY` as the key for hashing unique IDs, e.g. to use a
+/// project-specific key. All crates of a build share the key, so types
+/// declared by several crates are still deduplicated.
+///
+/// Note that the IDs of types such as `DynBox<T>` are hashed out of the
+/// `TypeId` of `T`, which changes with the compiler version and with the
+/// source, so these IDs (as dumped with `--dump-env` by `stubs_gen_main`)
+/// can't be diffed across builds whichever key is used. Only the IDs of types
+/// built out of other parts, e.g. `OcamlBytesMut`, are stable.
+///
+/// The key has to be set before the first unique ID is computed, i.e. at the
+/// very beginning of `main` of the stubs generation binary, before
+/// `stubs_gen_main` is called. Setting it again to the same key is a no-op.
+///
+/// # Panics
+///
+/// Panics if a unique ID has already been computed with another key, or if
+/// another key has already been set.
+pub fn set_unique_id_key(key: [u64; 4]) {
+    let current = CURRENT_UNIQUE_ID_KEY.get_or_init(|| key);
+    if *current != key {
+        panic!(
+            "unique id key must be set before the first unique id is computed \
+             (current key: {:x?})",
+            current
+        );
+    }
+}
+
+/// Computes a unique ID for `OCamlDesc::unique_id` out of `parts`, e.g. the
+/// `TypeId` or the unique IDs of type parameters. `domain` tells apart the
+/// kinds of types built out of the same parts, e.g. `DynBox<T>` and
+/// `Lazy<T>`.
+pub fn stable_unique_id<H: Hash + ?Sized>(domain: &str, parts: &H) -> u128 {
+    let key = *CURRENT_UNIQUE_ID_KEY.get_or_init(|| UNIQUE_ID_KEY.0);
+    let mut hasher = HighwayHasher::new(highway::Key(key));
+    domain.hash(&mut hasher);
+    parts.hash(&mut hasher);
+    let result = hasher.finalize128();
+    (result[0] as u128) | ((result[1] as u128) << 64)
+}
+
+/// A trait describing an optional bound for a `PolymorphicValue`. When a
+/// bound is present, the type variable is printed as an alias of the bound,
+/// i.e. `(<bound> as 'a)`, which constrains the type variable on OCaml side.
+pub trait PolymorphicBound {
+    /// Returns the OCaml type expression used as the bound, or `None` if the
+    /// type variable should stay unconstrained.
+    fn bound(env: &ocaml_gen::Env, generics: &[&str]) -> Option<String>;
+}
+
+/// Default bound for `PolymorphicValue`, leaves the type variable
+/// unconstrained.
+pub struct Unbounded;
+
+impl PolymorphicBound for Unbounded {
+    fn bound(_env: &ocaml_gen::Env, _generics: &[&str]) -> Option<String> {
+        None
+    }
+}
+
+/// Bounds the type variable to any `Ocaml_rs_smartptr.Rusty_obj.t`, whatever
+/// tags it carries.
+pub struct AnyRustyObj;
+
+impl PolymorphicBound for AnyRustyObj {
+    fn bound(_env: &ocaml_gen::Env, _generics: &[&str]) -> Option<String> {
+        Some("[> ] Ocaml_rs_smartptr.Rusty_obj.t".to_owned())
+    }
+}
+
+/// Bounds the type variable to the OCaml type described by `T`, e.g.
+/// `BoundedBy<DynBox<Animal>>` requires the value to be an object carrying at
+/// least the `Animal` tags.
+pub struct BoundedBy<T: OCamlDesc>(PhantomData<T>);
+
+impl<T: OCamlDesc> PolymorphicBound for BoundedBy<T> {
+    fn bound(env: &ocaml_gen::Env, generics: &[&str]) -> Option<String> {
+        Some(T::ocaml_desc(env, generics))
+    }
+}
+
 /// A wrapper around `ocaml::Value` that is printed by `ocaml_gen` as an OCaml
 /// polymorphic type, i.e., `'a` or `'b`, where the `a` or `b` symbol comes from
-/// the const `C: char` of this `PolymorphicValue`.
-#[derive(From, Into, Deref, DerefMut)]
-pub struct PolymorphicValue<const C: char>(ocaml::Value);
+/// the const `C: char` of this `PolymorphicValue`. The optional `B` parameter
+/// allows to constrain the type variable (see `PolymorphicBound`), by default
+/// it's left unconstrained.
+#[derive(Deref, DerefMut)]
+pub struct PolymorphicValue<const C: char, B: PolymorphicBound = Unbounded>(
+    #[deref]
+    #[deref_mut]
+    ocaml::Value,
+    PhantomData<B>,
+);
 
-impl<const C: char> ocaml_gen::OCamlDesc for PolymorphicValue<C> {
-    fn ocaml_desc(_env: &ocaml_gen::Env, _generics: &[&str]) -> String {
-        format!("'{}", C)
+impl<const C: char, B: PolymorphicBound> PolymorphicValue<C, B> {
+    /// Recovers the object wrapped by the value, for stubs which take any OCaml
+    /// value and handle Rust objects specially. The value is checked to be a
+    /// `RustyObj` custom block with `ptr::is_rusty_obj`, so plain values, as
+    /// well as custom blocks of other libraries, are safely told apart.
+    ///
+    /// # Returns
+    ///
+    /// A `DynBox<T>` sharing the object, or `None` if the value is not an
+    /// object, or the object has no registered coercion to `T`.
+    pub fn try_as_dynbox<T: ?Sized + Send + 'static>(&self) -> Option<DynBox<T>> {
+        if !crate::ptr::is_rusty_obj(&self.0) {
+            return None;
+        }
+        let dynbox = <DynBox<T> as ocaml::FromValue>::from_value(self.0.clone());
+        crate::registry::has_coercion::<T>(&dynbox.inner).then_some(dynbox)
+    }
+}
+
+impl<const C: char, B: PolymorphicBound> From<ocaml::Value> for PolymorphicValue<C, B> {
+    fn from(v: ocaml::Value) -> Self {
+        Self(v, PhantomData)
+    }
+}
+
+impl<const C: char, B: PolymorphicBound> From<PolymorphicValue<C, B>> for ocaml::Value {
+    fn from(v: PolymorphicValue<C, B>) -> Self {
+        v.0
+    }
+}
+
+impl<const C: char, B: PolymorphicBound> ocaml_gen::OCamlDesc for PolymorphicValue<C, B> {
+    fn ocaml_desc(env: &ocaml_gen::Env, generics: &[&str]) -> String {
+        match B::bound(env, generics) {
+            Some(bound) => format!("({} as '{})", bound, C),
+            None => format!("'{}", C),
+        }
     }
 
     fn unique_id() -> u128 {
@@ -31,15 +174,19 @@ impl<const C: char> ocaml_gen::OCamlDesc for PolymorphicValue<C> {
     }
 }
 
-unsafe impl<const C: char> ocaml::ToValue for PolymorphicValue<C> {
+unsafe impl<const C: char, B: PolymorphicBound> ocaml::ToValue
+    for PolymorphicValue<C, B>
+{
     fn to_value(&self, _gc: &ocaml::Runtime) -> ocaml::Value {
         self.0.clone()
     }
 }
 
-unsafe impl<const C: char> ocaml::FromValue for PolymorphicValue<C> {
+unsafe impl<const C: char, B: PolymorphicBound> ocaml::FromValue
+    for PolymorphicValue<C, B>
+{
     fn from_value(v: ocaml::Value) -> Self {
-        Self(v)
+        Self(v, PhantomData)
     }
 }
 
@@ -210,9 +357,114 @@ where
     }
 }
 
+/// A Rust marker type describing a state of a state machine type, which is
+/// reflected on OCaml side as a phantom polymorphic variant tag, see `Phantom`.
+pub trait PhantomState {
+    /// Name of the polymorphic variant tag, without the leading backtick.
+    const TAG: &'static str;
+}
+
+/// Thin wrapper around `DynBox<T>` which adds a phantom state `S` to its OCaml
+/// type. The state tag is appended to the tags of the type, i.e.
+/// `Phantom<Open, DynBox<Connection>>` is printed as ``[ tags | `Open ] t'``, so
+/// values in different states share the representation but can't be mixed up
+/// on OCaml side. Functions accepting any state can still take `DynBox<T>`.
+///
+/// The state is not tracked on Rust side, and the type of `DynBox<T>` must be
+/// declared with `decl_type!` (not an alias), as the generated `tags` type is
+/// referenced by the description.
+#[derive(Deref, DerefMut)]
+pub struct Phantom<S: PhantomState, T>(
+    #[deref]
+    #[deref_mut]
+    T,
+    PhantomData<S>,
+);
+
+impl<S: PhantomState, T> Phantom<S, T> {
+    /// Creates a new `Phantom` instance.
+    pub fn new(v: T) -> Self {
+        Self(v, PhantomData)
+    }
+
+    /// Consumes the `Phantom` instance and returns the inner value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<S: PhantomState, T> From<T> for Phantom<S, T> {
+    fn from(v: T) -> Self {
+        Self::new(v)
+    }
+}
+
+impl<S: PhantomState, T: ?Sized + Send + 'static> OCamlDesc for Phantom<S, DynBox<T>> {
+    fn ocaml_desc(env: &ocaml_gen::Env, generics: &[&str]) -> String {
+        dynbox_desc_with_variant::<T>(env, generics, &format!("`{}", S::TAG))
+    }
+
+    fn unique_id() -> u128 {
+        DynBox::<T>::unique_id()
+    }
+}
+
+/// Describes `DynBox<T>` with `variant` appended to its tags, i.e. as
+/// ``[ tags | <variant> ] t'``, see `Phantom`.
+pub(crate) fn dynbox_desc_with_variant<T: ?Sized + Send + 'static>(
+    env: &ocaml_gen::Env,
+    generics: &[&str],
+    variant: &str,
+) -> String {
+    let desc = DynBox::<T>::ocaml_desc(env, generics);
+    let typ = desc
+        .strip_prefix("_ ")
+        .expect("dynbox type description does not start with `_ `!");
+    // `tags` is declared next to the type by `DynBox` binding
+    let tags = match typ.rsplit_once('.') {
+        Some((module, _)) => format!("{}.tags", module),
+        None => "tags".to_owned(),
+    };
+    format!("[ {} | {} ] {}", tags, variant, typ)
+}
+
+unsafe impl<S: PhantomState, T: ocaml::ToValue> ocaml::ToValue for Phantom<S, T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        self.0.to_value(gc)
+    }
+}
+
+unsafe impl<S: PhantomState, T: ocaml::FromValue> ocaml::FromValue for Phantom<S, T> {
+    fn from_value(v: ocaml::Value) -> Self {
+        Self(T::from_value(v), PhantomData)
+    }
+}
+
+/// Declares a newtype wrapper `$new_type` around `$inner_type`, which is
+/// described in OCaml as `$ocaml_path`, i.e. a type declared by bindings of
+/// another library. The wrapper is then used in signatures of functions instead
+/// of `$inner_type`:
+///
+/// ```rust,ignore
+/// ocaml_export!(crate::stubs::Barn, Barn, "Some_other_lib.Barn.t");
+/// ```
+///
+/// If the type is declared with type parameters in the other library (e.g.
+/// with `WithTypeParams`), pass the parameters as the last argument, so that
+/// the type is described as `'a Some_other_lib.Tagged_barn.t`:
+///
+/// ```rust,ignore
+/// ocaml_export!(crate::stubs::Barn, TaggedBarn, "Some_other_lib.Tagged_barn.t", P1<'a'>);
+/// ```
+///
+/// The exported type can't be declared as a new type with `decl_type!`, as it's
+/// already declared by the other library. Use `decl_type_alias!` to give it a
+/// local name instead, which carries the type parameters if any, i.e.
+/// `decl_type_alias!("tagged_barn" => TaggedBarn)` generates
+/// `type nonrec 'a tagged_barn = 'a Some_other_lib.Tagged_barn.t`.
 #[macro_export]
 macro_rules! ocaml_export {
-    ($inner_type:ty, $new_type:ident, $ocaml_path:expr) => {
+    (@newtype $inner_type:ty, $new_type:ident) => {
         #[allow(dead_code)]
         pub struct $new_type($inner_type);
 
@@ -244,31 +496,45 @@ macro_rules! ocaml_export {
 
         unsafe impl ocaml::FromValue for $new_type {
             fn from_value(v: ocaml::Value) -> Self {
-                $new_type::from_value(v)
+                Self(<$inner_type as ocaml::FromValue>::from_value(v))
             }
         }
+    };
+    (@desc $inner_type:ty, $ocaml_path:expr, $env:ident, $generics:ident) => {{
+        // We clone an env
+        let mut env = $env.clone();
+        // Ask our inner type to produce ocaml binding for a new type in
+        // the cloned env under desired name, we ignore the actial
+        // binding code returned by `ocaml_binding` as we don't need it
+        <$inner_type as ::ocaml_gen::OCamlBinding>::ocaml_binding(
+            &mut env,
+            Some($ocaml_path),
+            true,
+        );
+        // Call ocaml_desc for our inner type in this new env with
+        // defined binding
+        let res = <$inner_type as ::ocaml_gen::OCamlDesc>::ocaml_desc(&env, $generics);
+        // Discard the env to avoid panics on drop as we're still nested
+        // in some module etc
+        env.discard();
+        // Return the ocaml_desc produced in fake env
+        res
+    }};
+    (@new_type_panic $new_type:ident, $ocaml_path:expr) => {
+        panic!(
+            "can't declare a new type for {}, as it's exported from other lib as `{}`, \
+             declare an alias for it with `decl_type_alias!(\"<name>\" => {})` instead",
+            stringify!($new_type),
+            $ocaml_path,
+            stringify!($new_type)
+        )
+    };
+    ($inner_type:ty, $new_type:ident, $ocaml_path:expr) => {
+        $crate::ocaml_export!(@newtype $inner_type, $new_type);
 
         impl ::ocaml_gen::OCamlDesc for $new_type {
             fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
-                // We clone an env
-                let mut env = env.clone();
-                // Ask our inner type to produce ocaml binding for a new type in
-                // the cloned env under desired name, we ignore the actial
-                // binding code returned by `ocaml_binding` as we don't need it
-                <$inner_type as ::ocaml_gen::OCamlBinding>::ocaml_binding(
-                    &mut env,
-                    Some($ocaml_path),
-                    true,
-                );
-                // Call ocaml_desc for our inner type in this new env with
-                // defined binding
-                let res =
-                    <$inner_type as ::ocaml_gen::OCamlDesc>::ocaml_desc(&env, generics);
-                // Discard the env to avoid panics on drop as we're still nested
-                // in some module etc
-                env.discard();
-                // Return the ocaml_desc produced in fake env
-                res
+                $crate::ocaml_export!(@desc $inner_type, $ocaml_path, env, generics)
             }
 
             fn unique_id() -> u128 {
@@ -286,8 +552,7 @@ macro_rules! ocaml_export {
                 let name = <Self as ::ocaml_gen::OCamlDesc>::ocaml_desc(env, &[]);
 
                 if new_type {
-                    panic!("can't declare a new type for {}, as it's exported from other lib, \
-                        you can declare an alias for it if you really want to", stringify!($new_type));
+                    $crate::ocaml_export!(@new_type_panic $new_type, $ocaml_path);
                 } else {
                     let ty_name =
                         rename.expect("bug in ocaml-gen: rename should be Some");
@@ -297,6 +562,53 @@ macro_rules! ocaml_export {
             }
         }
     };
+    ($inner_type:ty, $new_type:ident, $ocaml_path:expr, $params:ty) => {
+        $crate::ocaml_export!(@newtype $inner_type, $new_type);
+
+        impl ::ocaml_gen::OCamlDesc for $new_type {
+            fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+                let res =
+                    $crate::ocaml_export!(@desc $inner_type, $ocaml_path, env, generics);
+                // Same as `WithTypeParams`, prefix the type with its parameters
+                format!(
+                    "({} {})",
+                    <$params as $crate::ocaml_gen_extras::TypeParams>::params_string(),
+                    res
+                )
+            }
+
+            fn unique_id() -> u128 {
+                <$inner_type as ::ocaml_gen::OCamlDesc>::unique_id()
+            }
+        }
+
+        impl ::ocaml_gen::OCamlBinding for $new_type {
+            fn ocaml_binding(
+                env: &mut ::ocaml_gen::Env,
+                rename: Option<&'static str>,
+                new_type: bool,
+            ) -> String {
+                let ty_id = <Self as ::ocaml_gen::OCamlDesc>::unique_id();
+                let name = <Self as ::ocaml_gen::OCamlDesc>::ocaml_desc(env, &[]);
+
+                if new_type {
+                    $crate::ocaml_export!(@new_type_panic $new_type, $ocaml_path);
+                } else {
+                    let ty_name =
+                        rename.expect("bug in ocaml-gen: rename should be Some");
+                    env.add_alias(ty_id, ty_name);
+                    // The alias has to be declared with the same parameters, as
+                    // they are free in the aliased type expression
+                    format!(
+                        "type nonrec {} {} = {}",
+                        <$params as $crate::ocaml_gen_extras::TypeParams>::params_string(),
+                        ty_name,
+                        name
+                    )
+                }
+            }
+        }
+    };
 }
 
 /// Represents a plugin for generating OCaml bindings.
@@ -333,42 +645,397 @@ impl OcamlGenPlugin {
 
 inventory::collect!(OcamlGenPlugin);
 
+/// Represents a function declared with `#[smartptr_func]`, recorded so that
+/// OCaml bindings for it can be collected automatically.
+pub struct OcamlGenFunc {
+    /// Name of the crate where this function was declared
+    crate_name: &'static str,
+    /// Module path where this function was declared
+    module_path: &'static str,
+    /// Name of the Rust function
+    name: &'static str,
+    /// The `<name>_to_ocaml` function generated by `#[ocaml_gen::func]`.
+    to_ocaml: fn(&ocaml_gen::Env, Option<&'static str>) -> String,
+}
+
+impl OcamlGenFunc {
+    /// Creates a new `OcamlGenFunc` instance.
+    pub const fn new(
+        crate_name: &'static str,
+        module_path: &'static str,
+        name: &'static str,
+        to_ocaml: fn(&ocaml_gen::Env, Option<&'static str>) -> String,
+    ) -> Self {
+        OcamlGenFunc {
+            crate_name,
+            module_path,
+            name,
+            to_ocaml,
+        }
+    }
+
+    /// Returns the name of the crate associated with this function.
+    pub fn crate_name(&self) -> &'static str {
+        self.crate_name
+    }
+
+    /// Returns the module path where this function was declared.
+    pub fn module_path(&self) -> &'static str {
+        self.module_path
+    }
+
+    /// Returns the name of the Rust function.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Generates the OCaml binding for this function, optionally renamed.
+    pub fn to_ocaml(&self, env: &ocaml_gen::Env, rename: Option<&'static str>) -> String {
+        (self.to_ocaml)(env, rename)
+    }
+}
+
+inventory::collect!(OcamlGenFunc);
+
+/// Returns all functions declared with `#[smartptr_func]` in the given crate.
+pub fn registered_funcs(
+    crate_name: &str,
+) -> impl Iterator<Item = &'static OcamlGenFunc> + '_ {
+    inventory::iter::<OcamlGenFunc>
+        .into_iter()
+        .filter(move |func| func.crate_name() == crate_name)
+}
+
+/// Implemented by `#[derive(OcamlAccessors)]` for structs with named fields.
+/// The derive generates a getter stub `<type>_<field>` for each public field,
+/// which takes `DynBox<Type>` and returns a copy (or a clone) of the field. The
+/// stubs are declared in bindings with `decl_accessors!(Type)`, under the names
+/// of the fields.
+pub trait OcamlAccessors {
+    /// Generates OCaml bindings for all getter stubs of this type.
+    fn accessors_to_ocaml(env: &ocaml_gen::Env) -> Vec<String>;
+}
+
+/// Constructor stubs generated by `generate_constructors!`. The macro generates
+/// `<name>_create_exclusive` and `<name>_create_shared` stubs, which pass their
+/// arguments to the constructor of the type and wrap the value with
+/// `DynBox::new_exclusive` or `DynBox::new_shared` respectively, and registers
+/// them under `name`. The stubs are declared in bindings with
+/// `decl_constructors!(name)`, as `create_exclusive` and `create_shared`.
+pub struct OcamlConstructors {
+    /// Name of the crate where the constructors were generated
+    crate_name: &'static str,
+    /// Name given to `generate_constructors!`
+    name: &'static str,
+    /// Generates OCaml bindings for all constructor stubs
+    to_ocaml: fn(&ocaml_gen::Env) -> Vec<String>,
+}
+
+impl OcamlConstructors {
+    /// Creates a new `OcamlConstructors` instance.
+    pub const fn new(
+        crate_name: &'static str,
+        name: &'static str,
+        to_ocaml: fn(&ocaml_gen::Env) -> Vec<String>,
+    ) -> Self {
+        OcamlConstructors {
+            crate_name,
+            name,
+            to_ocaml,
+        }
+    }
+
+    /// Returns the name of the crate where the constructors were generated.
+    pub fn crate_name(&self) -> &'static str {
+        self.crate_name
+    }
+
+    /// Returns the name given to `generate_constructors!`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Generates OCaml bindings for all constructor stubs.
+    pub fn to_ocaml(&self, env: &ocaml_gen::Env) -> Vec<String> {
+        (self.to_ocaml)(env)
+    }
+}
+
+inventory::collect!(OcamlConstructors);
+
+/// Generates OCaml bindings for the constructor stubs generated by
+/// `generate_constructors!` under `name` in the crate `crate_name`. Used by
+/// `decl_constructors!` within `ocaml_gen_bindings!`.
+///
+/// # Panics
+///
+/// Panics if the crate generated no constructors under `name`.
+pub fn constructors_to_ocaml(
+    env: &ocaml_gen::Env,
+    crate_name: &str,
+    name: &str,
+) -> Vec<String> {
+    inventory::iter::<OcamlConstructors>
+        .into_iter()
+        .find(|constructors| {
+            constructors.crate_name() == crate_name && constructors.name() == name
+        })
+        .unwrap_or_else(|| {
+            panic!(
+                "no constructors named `{}' were generated in crate `{}'",
+                name, crate_name
+            )
+        })
+        .to_ocaml(env)
+}
+
+/// Generates an OCaml function `name` wrapping the external `raw_name`, so
+/// that its parameters can be labeled or optional with defaults, which
+/// externals can't express. `params` are OCaml parameters in the order of the
+/// arguments of the external, each one of `x`, `~x`, `~(x : ty)`, `?x`,
+/// `?(x = default)` or `?(x : ty = default)`. An optional parameter without a
+/// default is passed to the external as an `option`. If no unlabeled parameter
+/// follows the last optional one, the function takes a trailing `unit`, so that
+/// the optional parameters can be omitted.
+/// Used by `decl_func_with_defaults!` within `ocaml_gen_bindings!`.
+pub fn func_with_defaults(name: &str, raw_name: &str, params: &[&str]) -> String {
+    assert!(!params.is_empty(), "function {} has no parameters", name);
+    let args = params
+        .iter()
+        .map(|param| param_var(param))
+        .collect::<Vec<_>>();
+    let is_positional = |param: &&str| !param.starts_with(['~', '?']);
+    let unit = match params.iter().rposition(|param| param.starts_with('?')) {
+        Some(last_optional) if !params[last_optional..].iter().any(is_positional) => {
+            " ()"
+        }
+        _ => "",
+    };
+    format!(
+        "let {} {}{} = {} {}",
+        name,
+        params.join(" "),
+        unit,
+        raw_name,
+        args.join(" ")
+    )
+}
+
+/// Extracts the variable bound by an OCaml parameter, see
+/// `func_with_defaults`.
+fn param_var(param: &str) -> &str {
+    let pattern = param.strip_prefix(['~', '?']).unwrap_or(param);
+    let var = match pattern.strip_prefix('(') {
+        Some(inner) => inner
+            .split([':', '=', ')'])
+            .next()
+            .unwrap_or_default()
+            .trim(),
+        None => pattern,
+    };
+    let mut chars = var.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'');
+    assert!(valid, "unsupported OCaml parameter `{}'", param);
+    var
+}
+
+/// Name of the OCaml module generated for the crate `crate_name`, i.e. the
+/// crate name with dashes replaced and the first letter capitalized.
+fn module_name(crate_name: &str) -> String {
+    crate_name
+        .replace('-', "_")
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if i == 0 {
+                c.to_uppercase().next().unwrap()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Name of the `.ml` file holding the OCaml module `module_name`.
+fn ml_file_name(module_name: &str) -> String {
+    format!("{}.ml", module_name)
+}
+
+/// Returns the names of the `.ml` files `stubs_gen_main` writes when run
+/// without arguments, one per crate that registered an `OcamlGenPlugin`,
+/// without running the plugins. This lets build scripts declare generation
+/// targets up front.
+pub fn planned_outputs() -> Vec<String> {
+    let mut outputs = Vec::new();
+    for plugin in inventory::iter::<OcamlGenPlugin> {
+        let file_name = ml_file_name(&module_name(plugin.crate_name()));
+        if !outputs.contains(&file_name) {
+            outputs.push(file_name);
+        }
+    }
+    outputs
+}
+
+/// Modules declared with `decl_module!` while generating bindings into an
+/// `ocaml_gen::Env`, see `enter_module`.
+#[derive(Default)]
+struct ModuleScope {
+    /// Path of the module being generated, outermost first.
+    path: Vec<&'static str>,
+    /// Paths of the modules declared so far into the `ocaml_gen::Env`, along
+    /// with the crates which declared them.
+    declared: HashMap<Vec<&'static str>, &'static str>,
+}
+
+thread_local! {
+    static MODULE_SCOPE: RefCell<ModuleScope> = RefCell::default();
+}
+
+/// Enters the module `name` declared by the crate `crate_name` within the
+/// current module, as `decl_module!` and `decl_functor!` do within
+/// `ocaml_gen_bindings!`.
+///
+/// # Panics
+///
+/// Panics if a module of the same name was declared within the current module
+/// already, possibly by another crate whose bindings share the
+/// `ocaml_gen::Env`, as OCaml rejects such modules, and types of both would
+/// end up under the same path in the `Env`.
+pub fn enter_module(crate_name: &'static str, name: &'static str) {
+    MODULE_SCOPE.with_borrow_mut(|scope| {
+        let mut path = scope.path.clone();
+        path.push(name);
+        if let Some(previous) = scope.declared.get(&path) {
+            let parent = if scope.path.is_empty() {
+                "the top level".to_owned()
+            } else {
+                format!("module `{}'", scope.path.join("."))
+            };
+            let crates = if *previous == crate_name {
+                format!("crate `{}'", crate_name)
+            } else {
+                format!("crates `{}' and `{}'", previous, crate_name)
+            };
+            panic!(
+                "module `{}' is declared twice within {} (by {}), rename one of them",
+                name, parent, crates
+            );
+        }
+        scope.declared.insert(path.clone(), crate_name);
+        scope.path = path;
+    })
+}
+
+/// Leaves the module entered last with `enter_module`.
+pub fn leave_module() {
+    MODULE_SCOPE.with_borrow_mut(|scope| {
+        scope
+            .path
+            .pop()
+            .expect("leave_module called outside of any module");
+    })
+}
+
+/// Returns the path of the module being generated within
+/// `ocaml_gen_bindings!`, outermost first, e.g. `["Animal", "Sheep"]` within
+/// `decl_module!("Sheep", ...)` nested in `decl_module!("Animal", ...)`. It's
+/// empty at the top level. Meant for deriving names of generated items from
+/// the module they are declared in.
+pub fn current_module_path() -> Vec<&'static str> {
+    MODULE_SCOPE.with_borrow(|scope| scope.path.clone())
+}
+
+/// Forgets the modules declared so far, as bindings are about to be generated
+/// into a fresh `ocaml_gen::Env`.
+fn reset_module_scope() {
+    MODULE_SCOPE.with_borrow_mut(|scope| *scope = ModuleScope::default());
+}
+
+/// Runs `plugin` at the top level, catching its panics. Each plugin generates
+/// its own file out of a fresh `env`, so modules declared by the plugins run
+/// before are forgotten, and only a module declared twice by `plugin` itself
+/// is caught, see `enter_module`.
+fn run_plugin(
+    plugin: &OcamlGenPlugin,
+    env: &mut ocaml_gen::Env,
+) -> std::thread::Result<String> {
+    reset_module_scope();
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| plugin.generate(env)))
+}
+
+/// Extracts the message of a panic caught with `catch_unwind`.
+fn panic_message(err: &(dyn std::any::Any + Send)) -> &str {
+    match err.downcast_ref::<String>() {
+        Some(msg) => msg,
+        None => err
+            .downcast_ref::<&str>()
+            .copied()
+            .unwrap_or("unknown panic"),
+    }
+}
+
+/// Command line flag of `stubs_gen_main` which enables dumping of the
+/// `ocaml_gen::Env`, see `stubs_gen_main`.
+const DUMP_ENV_FLAG: &str = "--dump-env";
+
 /// Main function for stubs generation binaries. It collects `OcamlGenPlugin`s
 /// registered in other libraries and writes one `.ml` file per crate with
 /// generated OCaml bindings.
+///
+/// Positional arguments restrict generation to the given crates. With
+/// `--dump-env`, the state of the `ocaml_gen::Env` (registered types, their
+/// module paths and aliases) is also written to a `.env` sidecar file next to
+/// each `.ml` file, even if the plugin has failed. This helps to debug type id
+/// collisions and missing aliases, which usually surface as panics in
+/// `OCamlBinding` implementations.
 pub fn stubs_gen_main() -> std::io::Result<()> {
     crate::registry::initialize_plugins();
-    let args: Vec<String> = env::args().skip(1).collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let dump_env = flags.iter().any(|flag| flag == DUMP_ENV_FLAG);
+    if let Some(flag) = flags.iter().find(|flag| *flag != DUMP_ENV_FLAG) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("unknown flag `{}'", flag),
+        ));
+    }
 
     println!("Detected OcamlGen Plugins:");
     for plugin in inventory::iter::<OcamlGenPlugin> {
         let crate_name = plugin.crate_name();
         if args.is_empty() || args.contains(&crate_name.to_string()) {
-            let w = std::panic::catch_unwind(|| {
-                let env = &mut ocaml_gen::Env::new();
-                plugin.generate(env)
-            })
-            .map_err(|err| {
+            let module_name = module_name(crate_name);
+
+            let mut env = ocaml_gen::Env::new();
+            let w = run_plugin(plugin, &mut env);
+
+            let dump = dump_env.then(|| format!("{:#?}", env));
+            if w.is_err() {
+                // A failed plugin may leave the env within some module, which
+                // `Env` asserts against when dropped
+                std::mem::forget(env);
+            }
+
+            if let Some(dump) = dump {
+                let env_file_name = format!("{}.env", module_name);
+                let mut file = File::create(Path::new(&env_file_name))?;
+                file.write_all(dump.as_bytes())?;
+                println!(" - Crate: {}, dumped env: {}", crate_name, env_file_name);
+            }
+
+            let w = w.map_err(|err| {
                 std::io::Error::new(
                     std::io::ErrorKind::Other,
-                    format!("plugin from crate `{}' failed: {:?}", crate_name, err),
+                    format!(
+                        "plugin from crate `{}' failed: {}",
+                        crate_name,
+                        panic_message(&*err)
+                    ),
                 )
             })?;
 
-            let file_name = format!(
-                "{}.ml",
-                crate_name
-                    .replace('-', "_")
-                    .chars()
-                    .enumerate()
-                    .map(|(i, c)| if i == 0 {
-                        c.to_uppercase().next().unwrap()
-                    } else {
-                        c
-                    })
-                    .collect::<String>()
-            );
-
+            let file_name = ml_file_name(&module_name);
             let path = Path::new(&file_name);
             let mut file = File::create(path)?;
             file.write_all(w.as_bytes())?;
@@ -378,3 +1045,197 @@ pub fn stubs_gen_main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_unique_id_key_after_use() {
+        let id = stable_unique_id("Test", &42);
+        // The key in use can be set again, but not replaced
+        set_unique_id_key(*CURRENT_UNIQUE_ID_KEY.get().unwrap());
+        assert_eq!(stable_unique_id("Test", &42), id);
+        let result = std::panic::catch_unwind(|| set_unique_id_key([1, 2, 3, 4]));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("before the first unique id"), "{}", msg);
+        assert_eq!(stable_unique_id("Test", &42), id);
+    }
+
+    #[test]
+    fn test_func_with_defaults() {
+        assert_eq!(
+            func_with_defaults(
+                "create",
+                "create_raw",
+                &["~name", "?(port : int32 = 8080l)", "?(verbose = false)"]
+            ),
+            "let create ~name ?(port : int32 = 8080l) ?(verbose = false) () = \
+             create_raw name port verbose"
+        );
+        assert_eq!(
+            func_with_defaults("find", "find_raw", &["?limit", "(query)"]),
+            "let find ?limit (query) = find_raw limit query"
+        );
+        // Labeled parameters don't make the optional ones omissible
+        assert_eq!(
+            func_with_defaults("find", "find_raw", &["?limit", "~query"]),
+            "let find ?limit ~query () = find_raw limit query"
+        );
+        assert_eq!(
+            func_with_defaults("find", "find_raw", &["~query", "?limit", "(n)", "~by"]),
+            "let find ~query ?limit (n) ~by = find_raw query limit n by"
+        );
+        let result = std::panic::catch_unwind(|| {
+            func_with_defaults("create", "create_raw", &["~name:n"])
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_module_scope() {
+        reset_module_scope();
+        enter_module("farm", "Animal");
+        enter_module("farm", "Sheep");
+        assert_eq!(current_module_path(), ["Animal", "Sheep"]);
+        leave_module();
+        leave_module();
+        // Same names are fine at different levels
+        enter_module("farm", "Sheep");
+        enter_module("farm", "Animal");
+        leave_module();
+        leave_module();
+        assert!(current_module_path().is_empty());
+
+        let result = std::panic::catch_unwind(|| enter_module("zoo", "Animal"));
+        let err = result.unwrap_err();
+        assert_eq!(
+            panic_message(&*err),
+            "module `Animal' is declared twice within the top level (by crates \
+             `farm' and `zoo'), rename one of them"
+        );
+
+        reset_module_scope();
+        enter_module("farm", "Animal");
+        enter_module("farm", "Sheep");
+        leave_module();
+        let result = std::panic::catch_unwind(|| enter_module("zoo", "Sheep"));
+        let err = result.unwrap_err();
+        assert!(
+            panic_message(&*err).contains("within module `Animal'"),
+            "{}",
+            panic_message(&*err)
+        );
+    }
+
+    #[test]
+    fn test_module_scope_across_plugins() {
+        fn farm(_env: &mut ocaml_gen::Env) -> String {
+            enter_module("farm", "Wolf");
+            leave_module();
+            String::new()
+        }
+
+        fn zoo(_env: &mut ocaml_gen::Env) -> String {
+            enter_module("zoo", "Wolf");
+            leave_module();
+            enter_module("zoo", "Wolf");
+            String::new()
+        }
+
+        let farm = OcamlGenPlugin::new("farm", farm);
+        let zoo = OcamlGenPlugin::new("zoo", zoo);
+        assert!(run_plugin(&farm, &mut ocaml_gen::Env::new()).is_ok());
+        // `Farm.Wolf` and `Zoo.Wolf` are generated into different files, but
+        // `zoo` declares its `Wolf` twice
+        let err = run_plugin(&zoo, &mut ocaml_gen::Env::new()).unwrap_err();
+        assert_eq!(
+            panic_message(&*err),
+            "module `Wolf' is declared twice within the top level (by crate \
+             `zoo'), rename one of them"
+        );
+        // The modules left behind by the failed plugin are forgotten too
+        assert!(run_plugin(&farm, &mut ocaml_gen::Env::new()).is_ok());
+        assert!(current_module_path().is_empty());
+    }
+
+    // The same type can be given constructors under several names
+    inventory::submit! {
+        OcamlConstructors::new("constructors-test", "Sheep", |_| vec!["sheep".to_owned()])
+    }
+
+    inventory::submit! {
+        OcamlConstructors::new("constructors-test", "Lamb", |_| vec!["lamb".to_owned()])
+    }
+
+    #[test]
+    fn test_constructors_to_ocaml() {
+        let env = ocaml_gen::Env::new();
+        assert_eq!(
+            constructors_to_ocaml(&env, "constructors-test", "Sheep"),
+            ["sheep"]
+        );
+        assert_eq!(
+            constructors_to_ocaml(&env, "constructors-test", "Lamb"),
+            ["lamb"]
+        );
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            constructors_to_ocaml(&env, "planned-outputs-test", "Sheep")
+        }));
+        let err = result.unwrap_err();
+        assert_eq!(
+            panic_message(&*err),
+            "no constructors named `Sheep' were generated in crate \
+             `planned-outputs-test'"
+        );
+    }
+
+    struct Sheepish;
+
+    impl PolymorphicBound for Sheepish {
+        fn bound(_env: &ocaml_gen::Env, _generics: &[&str]) -> Option<String> {
+            Some("[> `Sheep ]".to_owned())
+        }
+    }
+
+    #[test]
+    fn test_polymorphic_value_desc() {
+        let env = ocaml_gen::Env::new();
+        assert_eq!(PolymorphicValue::<'a'>::ocaml_desc(&env, &[]), "'a");
+        assert_eq!(
+            PolymorphicValue::<'b', Sheepish>::ocaml_desc(&env, &[]),
+            "([> `Sheep ] as 'b)"
+        );
+        assert_eq!(
+            PolymorphicValue::<'a', AnyRustyObj>::ocaml_desc(&env, &[]),
+            "([> ] Ocaml_rs_smartptr.Rusty_obj.t as 'a)"
+        );
+        assert_eq!(
+            PolymorphicValue::<'a', BoundedBy<bool>>::ocaml_desc(&env, &[]),
+            "(bool as 'a)"
+        );
+    }
+
+    // Plugins of the same crate write the same file, which is planned once
+    inventory::submit! {
+        OcamlGenPlugin::new("planned-outputs-test", |_| String::new())
+    }
+
+    inventory::submit! {
+        OcamlGenPlugin::new("planned-outputs-test", |_| String::new())
+    }
+
+    #[test]
+    fn test_planned_outputs() {
+        assert_eq!(module_name("ocaml-rs-smartptr"), "Ocaml_rs_smartptr");
+        let outputs = planned_outputs();
+        assert_eq!(
+            outputs
+                .iter()
+                .filter(|output| *output == "Planned_outputs_test.ml")
+                .count(),
+            1
+        );
+    }
+}
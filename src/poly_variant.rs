@@ -0,0 +1,162 @@
+//! This module allows passing Rust enums across the boundary as OCaml
+//! polymorphic variants, instead of wrapping them into opaque `DynBox`es. Unlike
+//! the tags of `DynBox` types, which are nullary, variants may carry data, so
+//! sum types map naturally to OCaml.
+//!
+//! An enum with unit and tuple variants is bound to a polymorphic variant with
+//! `#[derive(OcamlPolyVariant)]`, which implements `ocaml::ToValue`,
+//! `ocaml::FromValue`, `OCamlDesc` and `OCamlBinding` for it. All variant
+//! fields have to implement `ocaml::ToValue`, `ocaml::FromValue` and
+//! `OCamlDesc`, and are converted one by one, so the value is a copy, same as
+//! with `OcamlRecord`.
+//!
+//! ```rust,ignore
+//! #[derive(OcamlPolyVariant)]
+//! pub enum Shape {
+//!     Origin,
+//!     Circle(f64),
+//!     Point(i32, i32),
+//! }
+//! ```
+//!
+//! The type is declared in bindings with `decl_type!(Shape => "t")` within
+//! `ocaml_gen_bindings!`, which emits:
+//!
+//! ```ocaml
+//! type t = [ `Origin | `Circle of float | `Point of int32 * int32 ]
+//! ```
+//!
+//! Tags are named after the variants, so variant names must be valid OCaml
+//! constructor names, which they are as long as they follow Rust naming
+//! conventions. Variants with named fields aren't supported, wrap the fields
+//! into an `OcamlRecord` struct instead. Same as records, a polymorphic variant
+//! may refer to its own type, e.g. `Node(Vec<Tree>)`.
+//!
+//! At runtime, a nullary variant is the hash of its tag (see `hash`), while a
+//! variant with arguments is a block holding the hash and the argument. Several
+//! arguments are held by a tuple, as OCaml treats `` `Point of int * int `` as
+//! `` `Point `` of a single tuple.
+
+use ocaml::{FromValue, ToValue};
+use ocaml_gen::OCamlDesc;
+
+/// Computes the hash of a polymorphic variant tag, which represents the tag at
+/// runtime, same as `caml_hash_variant` from `caml/mlvalues.h` does. The hash
+/// only depends on the name of the tag, not on the type it belongs to.
+pub const fn hash(tag: &str) -> ocaml::Int {
+    let tag = tag.as_bytes();
+    let mut accu: i64 = 0;
+    let mut i = 0;
+    while i < tag.len() {
+        accu = accu.wrapping_mul(223).wrapping_add(tag[i] as i64);
+        i += 1;
+    }
+    // OCaml truncates the tagged integer to 32 bits and sign-extends it, so
+    // that hashes are the same on 32-bit and 64-bit platforms
+    let tagged = ((accu << 1) | 1) as u32 as i32;
+    (tagged >> 1) as ocaml::Int
+}
+
+/// Converts a nullary variant with tag `tag`.
+pub fn constant(gc: &ocaml::Runtime, tag: &str) -> ocaml::Value {
+    hash(tag).to_value(gc)
+}
+
+/// Converts a variant with tag `tag` and the argument `arg`, either a single
+/// field or the tuple of several fields, see `alloc_args`.
+pub fn with_arg(gc: &ocaml::Runtime, tag: &str, arg: ocaml::Value) -> ocaml::Value {
+    unsafe {
+        let mut block = ocaml::Value::alloc(2, 0);
+        block.store_field(gc, 0, hash(tag).to_value(gc));
+        block.store_field(gc, 1, arg);
+        block
+    }
+}
+
+/// Allocates the tuple holding `size` arguments of a variant, to be filled
+/// with `record::store`.
+pub fn alloc_args(size: usize) -> ocaml::Value {
+    unsafe { ocaml::Value::alloc_tuple(size) }
+}
+
+/// Splits a variant into the hash of its tag and its argument, if any.
+pub fn destructure(v: ocaml::Value) -> (ocaml::Int, Option<ocaml::Value>) {
+    if unsafe { ocaml::sys::is_block(v.raw().0) } {
+        unsafe { (v.field(0), Some(v.field(1))) }
+    } else {
+        (ocaml::Int::from_value(v), None)
+    }
+}
+
+/// Extracts the argument of a variant with tag `tag` split by `destructure`.
+pub fn arg(tag: &str, arg: Option<ocaml::Value>) -> ocaml::Value {
+    arg.unwrap_or_else(|| panic!("polymorphic variant `{} has no argument", tag))
+}
+
+/// Fails the conversion of a variant whose tag is not one of the type `name`.
+pub fn unknown_tag(name: &str, hash: ocaml::Int) -> ! {
+    panic!(
+        "polymorphic variant with tag hash {} is not a variant of {}",
+        hash, name
+    )
+}
+
+/// Describes a variant of a polymorphic variant type, as used by
+/// `variant_binding`, out of the descriptions of its arguments.
+pub fn variant_decl(tag: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        format!("`{}", tag)
+    } else {
+        format!("`{} of {}", tag, args.join(" * "))
+    }
+}
+
+/// Describes an argument of a variant, see `variant_decl`.
+pub fn arg_decl<T: OCamlDesc>(env: &ocaml_gen::Env) -> String {
+    T::ocaml_desc(env, &[])
+}
+
+/// Generates the declaration of a polymorphic variant type with id `ty_id`
+/// out of its variants (see `variant_decl`), or an alias of it if it was
+/// declared already. The type is registered in `env` before `variants` are
+/// described, so that variants may refer to the type itself.
+pub fn variant_binding(
+    env: &mut ocaml_gen::Env,
+    rename: Option<&'static str>,
+    new_type: bool,
+    ty_id: u128,
+    name: &'static str,
+    variants: impl FnOnce(&ocaml_gen::Env) -> Vec<String>,
+) -> String {
+    if new_type {
+        let ty_name = rename.unwrap_or(name);
+        env.new_type(ty_id, ty_name);
+        format!("type {} = [ {} ]", ty_name, variants(env).join(" | "))
+    } else {
+        let ty_name = rename.expect("bug in ocaml-gen: rename should be Some");
+        let name = env.get_type(ty_id, name).0;
+        env.add_alias(ty_id, ty_name);
+        format!("type {} = {}", ty_name, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        // `A is represented by the code of 'A', and the hash accumulates over
+        // the characters of the tag
+        assert_eq!(hash("A"), 65);
+        assert_eq!(hash("Ab"), 65 * 223 + 98);
+        // Hashes are truncated to 31 bits, including the sign
+        let long = hash("A_very_long_polymorphic_variant_tag");
+        assert!((-(1 << 30)..(1 << 30)).contains(&long));
+        assert_eq!(
+            variant_decl("Point", &["int".into(), "int".into()]),
+            "`Point of int * int"
+        );
+        assert_eq!(variant_decl("Origin", &[]), "`Origin");
+    }
+}
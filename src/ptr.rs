@@ -22,10 +22,208 @@ let exclusive_box = DynBox::new_exclusive(42); // Mutex-protected
 let shared_box = DynBox::new_shared("foo"); // RwLock-protected
 ```
 
+The container can also be chosen with a builder:
+
+```rust
+use ocaml_rs_smartptr::ptr::DynBox;
+let exclusive_box = DynBox::builder(42).mutex().build(); // Mutex-protected
+let shared_box = DynBox::builder("foo").rwlock().build(); // RwLock-protected
+let immutable_box = DynBox::builder("bar").immutable().build(); // No lock
+```
+
+Immutable boxes (`new_immutable`) hold the value in the `Arc` directly, so read
+coercions never block, and write coercions panic. `from_arc` wraps an
+`Arc<T>` which is already shared with Rust code the same way, without copying
+the value.
+
+`new_shared` requires `T: Send + Sync`, as `RwLock` hands out concurrent read
+access. `new_exclusive` only requires `T: Send`, so types which are `Send` but
+not `Sync` (e.g. `RefCell<T>`) should use it. The resulting `DynBox` is still
+`Send + Sync` and can be freely cloned and passed to OCaml and back, access to
+the value is serialized by the `Mutex`.
+Types which are not even `Send` (e.g. built out of `Rc<RefCell<T>>`) can't be
+put into a `DynBox`, use `LocalDynBox` from the `local_ptr` module instead.
+
+### Lock policy
+
+`std::sync::RwLock` makes no fairness guarantees, its behavior depends on the
+platform. On Linux and other futex-based platforms, waiting writers block new
+readers, so readers can't starve a writer, while on others (e.g. Windows,
+where `SRWLock` is used) no particular order is guaranteed. With the
+`parking_lot` feature enabled, `new_shared_with` (or `build_with` of the
+builder) accepts `LockPolicy::Fair`, which uses `parking_lot::RwLock` instead.
+It's task-fair on every platform, so that a background Rust writer is not
+starved by a steady stream of OCaml readers and vice versa:
+
+```rust,ignore
+use ocaml_rs_smartptr::ptr::{DynBox, LockPolicy};
+let shared_box = DynBox::new_shared_with("foo", LockPolicy::Fair);
+```
+
+Both policies result in `ContainerKind::RwLock`, and coerce the same way.
+Boxes with `LockPolicy::Fair` additionally support `coerce_upgradable`, which
+returns a read handle that can be atomically upgraded to a write handle, for
+read-then-maybe-write patterns. With the standard library locks, a read handle
+has to be dropped before coercing mutably, which lets other writers sneak in:
+
+```rust,ignore
+let counter = DynBox::new_shared_with(0, LockPolicy::Fair);
+let value = counter.coerce_upgradable();
+if *value < 10 {
+    *value.upgrade() += 1;
+}
+```
+
 ### Coercion
 
 The `coerce` and `coerce_mut` methods return a handle that holds a lock. Be
-cautious to avoid deadlocks when using these methods.
+cautious to avoid deadlocks when using these methods. `coerce_borrowed` works
+like `coerce`, but borrows the `DynBox` instead of cloning the inner `Arc`.
+`read` and `write` run a closure against the coerced value and release the lock
+before returning, which is the preferred way for short accesses. Handles which
+were already obtained can be scoped the same way with `HandleExt::with` and
+`HandleMutExt::with_mut` from the `registry` module.
+`try_coerce` and `try_coerce_mut` return `None` instead of blocking when the
+lock is held elsewhere, and `coerce_timeout` retries the former until the given
+deadline. `is_locked` tells whether the lock is currently held, but the answer
+may be outdated by the time it's acted upon, so it's only suitable for
+diagnostics.
+
+Read handles of a `RwLock`-backed `DynBox` (created with `new_shared`) may be
+held concurrently, including nested on the same thread. A `Mutex`-backed
+`DynBox` only allows one handle at a time, so coercing it again while a handle
+is alive on the same thread deadlocks. Debug builds panic on such a reentrant
+coercion instead of blocking. `container_kind` tells which of the
+two a given `DynBox` is.
+
+Address-sensitive values (e.g. self-referential ones) can be created with
+`new_exclusive_pinned`, which keeps them in a `Pin<Box<T>>` inside a `Mutex`.
+Read coercions work as usual, but `coerce_mut` panics for such a `DynBox`, as
+`&mut T` would allow moving the value. Use `with_pinned_mut` to get
+`Pin<&mut T>` instead.
+
+There is no way to switch the container of an existing `DynBox`, but for
+`T: Clone`, `to_exclusive`, `to_shared` and `to_immutable` copy the value into a
+fresh `DynBox` with the desired container. The copy does not share the value with the
+original `DynBox`.
+
+### Trait objects
+
+A `DynBox` of a trait object, e.g. `DynBox<dyn Animal + Send>`, may hold the
+value in two ways, depending on how it was created:
+
+- `new_exclusive(sheep)` (or any other constructor taking the value) keeps the
+  concrete `Sheep` in the container, and the coercion to `dyn Animal + Send`
+  is registered by `register_type!` listing `Animal` in `object_safe_traits`.
+  The `DynBox` gets its trait object type when it's passed to OCaml and back,
+  or with `cast`.
+- `new_exclusive_boxed(Box::new(sheep) as Box<dyn Animal + Send>)` keeps the
+  `Box<dyn Animal + Send>` in the container, and the coercion of the box is
+  registered by `register_trait!` for `Animal`. The concrete type doesn't have
+  to be registered at all, which is handy for types that are not known to
+  the registering crate, e.g. closures.
+
+Either way, coercions are looked up by the type held in the container, and
+both macros register them to the same set of trait objects: the trait with
+every combination of the listed markers, including none. A box created as
+`Box<dyn Animal + Send + Sync>` coerces to `dyn Animal + Send` just like a
+`Sheep` registered with both markers does. So code
+coercing a `DynBox<dyn Animal + Send>` doesn't need to know which way it was
+created, as long as the concrete type is registered with `register_type!`
+and the trait with `register_trait!` using the same markers. `register_all!`
+does both at once.
+
+### Weak references
+
+A parent holding its children in `DynBox`es can't be referenced back by them
+with `DynBox`es too, as the cycle would keep both alive forever. `downgrade`
+creates a `DynBox` holding a weak reference instead, which coerces like the
+original one as long as the value is alive, and `upgrade` turns it back into a
+`DynBox` which keeps the value alive. Once the value is dropped, `try_coerce`
+and `try_coerce_mut` return `None`, while other coercions fail the same way as
+coercions to unregistered types do:
+
+```rust,ignore
+let parent = node.parent.try_coerce().expect("parent is dropped");
+```
+
+A weak `DynBox` and a `DynBox` of the same value don't share it in the sense
+of `ptr_eq`.
+
+### Projections
+
+OCaml code sometimes needs a handle to a part of a value, e.g. to the engine
+of a car, to pass it on to code which only knows about engines.
+`new_projection` creates a `DynBox` of the part out of a pair of functions
+projecting the value to it:
+
+```rust,ignore
+let engine: DynBox<Engine> = car.new_projection(|car| &car.engine, |car| &mut car.engine);
+```
+
+The projection is an owned `DynBox`, which can be stored on OCaml side or
+moved to other threads like any other. It holds a reference to the original
+`DynBox`, so the whole value stays alive as long as the projection does, even
+if the original `DynBox` is dropped. Coercing the projection locks the
+container of the original `DynBox`, so the part is protected by the same lock
+as the rest of the value, and coercing both at once on the same thread is
+subject to the same deadlocks as coercing the original `DynBox` twice. The
+projection coerces to the type of the part, as well as to the traits the part
+is registered with, e.g. `engine` coerces to `dyn Machine` if `Engine` does,
+which its OCaml type advertises.
+
+### Equality
+
+`dynbox_equal` compares two `DynBox`es. By default it checks physical
+equality, i.e. whether both share the same value (see `ptr_eq`), which is what
+OCaml `==` does on the objects. Types registered with `registry::register_eq`
+are compared structurally with their `PartialEq` implementation instead:
+
+```rust,ignore
+register_rtti! {
+    ocaml_rs_smartptr::registry::register_eq::<Sheep>();
+}
+```
+
+A structural comparison locks both values for reading. `decl_equal!(DynBox<T>)`
+within `ocaml_gen_bindings!` declares `equal : t -> t -> bool` in the enclosing
+module, backed by a stub shipped with the library. OCaml polymorphic `=`
+doesn't work on the objects, as they are custom blocks without a comparison
+function.
+
+### Ordering
+
+`dynbox_compare` orders two `DynBox`es. Types registered with
+`register_ord!(T)` (which registers `registry::register_eq` too) are ordered
+with their `Ord` implementation, other objects are ordered by their type names
+and addresses, which gives an arbitrary but consistent order, see
+`registry::dyn_compare`. `decl_compare!(DynBox<T>)`
+declares `compare : t -> t -> int` in the enclosing module, so that the module
+can be passed to `Set.Make` or `Map.Make` right away:
+
+```ocaml
+module Sheep_set = Set.Make (Sheep)
+```
+
+### Hashing
+
+The custom blocks have a `hash` operation, which hashes the address of the
+value shared by the `DynBox`, so that `Hashtbl.hash` is stable for the lifetime
+of the object, and the objects can be used as keys of weak hash tables (e.g.
+`Weak.Make` or `Ephemeron.K1.Make`). This is identity hashing: all the OCaml
+values holding the same `DynBox` (see `ptr_eq`) hash the same, even when they
+are distinct custom blocks, e.g. converted from Rust twice, while distinct
+objects with equal contents most likely don't. So key equality has to be
+physical too, either `==` or `equal` declared with `decl_equal!` for types
+which are not registered with `registry::register_eq`:
+
+```ocaml
+module Nodes = Weak.Make (struct
+  type t = Node.t
+  let equal = ( == )
+  let hash = Hashtbl.hash
+end)
+```
 
 ### OCaml Integration
 
@@ -37,7 +235,7 @@ Example:
 
 ```ocaml
 module Animal = struct 
-  type nonrec t = [ `Ocaml_rs_smartptr_test_stubs_animal_proxy|`Core_marker_send ] Ocaml_rs_smartptr.Rusty_obj.t
+  type nonrec t = [ `Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41|`Core_marker_send_8ae6a44a ] Ocaml_rs_smartptr.Rusty_obj.t
   external name : t -> string = "animal_name"
   external noise : t -> string = "animal_noise"
   external talk : t -> unit = "animal_talk"
@@ -45,7 +243,7 @@ end
 
 
 module Sheep = struct 
-  type nonrec t = [ `Ocaml_rs_smartptr_test_stubs_sheep|`Core_marker_sync|`Core_marker_send|`Ocaml_rs_smartptr_test_stubs_animal_proxy ] Ocaml_rs_smartptr.Rusty_obj.t
+  type nonrec t = [ `Ocaml_rs_smartptr_test_stubs_sheep_8bdf2d62|`Core_marker_sync_770eca17|`Core_marker_send_8ae6a44a|`Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41 ] Ocaml_rs_smartptr.Rusty_obj.t
   external create : string -> t = "sheep_create"
   external is_naked : t -> bool = "sheep_is_naked"
   external sheer : t -> unit = "sheep_sheer"
@@ -53,7 +251,7 @@ end
 
 
 module Wolf = struct 
-  type nonrec t = [ `Ocaml_rs_smartptr_test_stubs_wolf|`Core_marker_sync|`Core_marker_send|`Ocaml_rs_smartptr_test_stubs_animal_proxy ] Ocaml_rs_smartptr.Rusty_obj.t
+  type nonrec t = [ `Ocaml_rs_smartptr_test_stubs_wolf_3375056b|`Core_marker_sync_770eca17|`Core_marker_send_8ae6a44a|`Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41 ] Ocaml_rs_smartptr.Rusty_obj.t
   external create : string -> t = "wolf_create"
   external set_hungry : t -> bool -> unit = "wolf_set_hungry"
 end
@@ -69,31 +267,167 @@ a raw pointer to hold that raw pointer in the OCaml heap, ensuring that moving
 of that value by the OCaml GC does not affect any Rust invariants. Reverse
 operation reconstructs the `Arc` from the raw pointer. This ensures that both
 OCaml and Rust always hold valid Arc-baked references to objects they need.
+
+Collections of objects need no special handling: `Vec<DynBox<T>>` maps to an
+OCaml array. Every element received from OCaml is a clone of the `Arc` held by
+the array, and every element returned to OCaml is converted into a fresh
+`RustyObj` holding its own clone, which the OCaml GC releases along with the
+array. So each round-trip clones every `Arc` once per direction, and no
+references leak.
+
+Read-only stubs on hot paths can avoid the clone by taking `DynBoxRef<T>`
+instead of `DynBox<T>`. It borrows the `Arc` held by the OCaml argument for
+the duration of the call, and maps to the same OCaml type. The OCaml caller
+does not necessarily keep the argument alive though, so the borrow is only
+valid until the stub lets the OCaml GC run, see `DynBoxRef` for details:
+
+```rust,ignore
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_is_naked(sheep: DynBoxRef<Sheep>) -> bool {
+    sheep.coerce_borrowed().is_naked()
+}
+```
+
+Every `to_value` bumps the `Arc` reference count and allocates a fresh OCaml
+custom block of a few words with a finalizer, so the blocks can't be pooled or
+reused: OCaml code may hold each of them independently. What matters more is
+that by default the GC only sees those few words, no matter how much memory the
+Rust value holds, so lots of memory-heavy objects may pile up before a major
+collection runs their finalizers. Types which hold such memory can implement
+`registry::MemSized` and be registered with `registry::register_mem_sized`,
+then their blocks are allocated with `caml_alloc_custom_mem`, so that the GC
+accounts for the reported amount of out-of-heap memory and speeds up
+accordingly:
+
+```rust,ignore
+impl MemSized for Image {
+    fn mem_size(&self) -> usize {
+        self.pixels.capacity()
+    }
+}
+
+register_rtti! {
+    ocaml_rs_smartptr::registry::register_mem_sized::<Image>();
+}
+```
+
+The size is taken without blocking when the `DynBox` is passed to OCaml, and
+no hint is given if the value is locked for writing at that moment. Stubs can
+also give an explicit hint by wrapping the returned `DynBox` with
+`DynBox::with_mem_hint`:
+
+```rust,ignore
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn image_load(path: String) -> WithMemHint<Image> {
+    let image = Image::load(&path);
+    let size = image.byte_size();
+    DynBox::from(image).with_mem_hint(size)
+}
+```
+
+`WithMemHint<T>` maps to the same OCaml type as `DynBox<T>`.
+
+`to_value` only gets a reference, so it has to clone the `Arc`, and the
+original is dropped by its owner later on. Passing a `DynBox` to an OCaml
+callback with `OCamlFunc::call` keeps both references alive for the duration
+of the call. When the caller gives the box away, `callable::IntoValue` moves
+its `Arc` right into the custom block instead, which is what
+`OCamlFunc::call_consuming` does with its arguments. `DynBox` values returned
+by OCaml are still cloned out of their blocks, as OCaml code may keep
+referencing them.
+
+The same goes for stubs returning a `DynBox`, which `#[ocaml::func]` converts
+with `to_value` before dropping it. Stubs returning a box they don't keep,
+e.g. a freshly created one, can wrap it with `into_returned`, so that its
+reference is moved into the custom block, which saves an increment and a
+decrement of the reference count per call (see `cargo bench --bench returned`).
+`Returned<T>` maps to the same OCaml type as `DynBox<T>`. Other code can
+convert a box it owns with `into_ocaml_value`:
+
+```rust,ignore
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_create(name: String) -> Returned<Sheep> {
+    DynBox::new_exclusive(Sheep::new(name)).into_returned()
+}
+```
+
+Stubs can also accept either an object or a plain OCaml value which describes
+a new one, e.g. a sheep or just its name, by taking `OrConstruct<T>` for
+`T: FromOcamlRepr`. Plain values are converted to `T::Repr` and passed to
+`FromOcamlRepr::construct`:
+
+```rust,ignore
+impl FromOcamlRepr for Sheep {
+    type Repr = String;
+
+    fn construct(name: String) -> DynBox<Sheep> {
+        DynBox::new_exclusive(Sheep::new(name))
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_name_of(sheep: OrConstruct<Sheep>) -> String {
+    sheep.0.coerce().name()
+}
+```
+
+OCaml has no untagged unions, so `OrConstruct<T>` maps to the polymorphic
+variant `` [ `Obj of t | `Repr of repr ] ``, where `t` is the type of
+`DynBox<T>` and `repr` the one of `T::Repr`, and OCaml code passes either
+`` `Obj dolly `` or `` `Repr "shaun" ``. Values are decoded by their tag, and
+values of any other shape, which can only be forged with `Obj.magic`, fail the
+conversion with a panic instead of being read as if they were valid.
 "#]
 
-use highway::{HighwayHash, HighwayHasher};
-use ocaml_gen::{const_random, OCamlBinding, OCamlDesc};
+use ocaml_gen::{OCamlBinding, OCamlDesc};
 use static_assertions::{assert_impl_all, assert_not_impl_all};
 use std::any::{Any, TypeId};
-use std::hash::Hash;
+use std::cell::Cell;
 use std::marker::PhantomData;
-use std::sync::{Arc, Mutex, RwLock};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::callable::IntoValue;
+use crate::logging::trace;
+use crate::registry::{HandleExt, HandleMutExt};
+use crate::{ocaml_gen_extras, registry, shutdown, type_name};
 
-use crate::{registry, type_name};
+/// Types which can be stored in a `DynBox`, i.e. `Send` ones. It only exists
+/// to turn a missing `Send` bound, e.g. in a stub signature, into an error
+/// which tells what to do at the user's call site, instead of the generic
+/// `Send` error pointing into `DynBox` internals.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must be `Send` to be stored in a `DynBox`",
+    label = "`{Self}` is not `Send`",
+    note = "wrap the non-`Send` parts of `{Self}` into a `Mutex`, or use `LocalDynBox` \
+            for single-threaded values"
+)]
+pub trait DynBoxable: Send {}
+
+// Not recommended, so that errors report `DynBoxable` itself with its message,
+// rather than the unsatisfied `Send` bound of this impl
+#[diagnostic::do_not_recommend]
+impl<T: Send + ?Sized> DynBoxable for T {}
 
 /// A smart pointer around the registry's `DynArc` with `PhantomData` for type safety.
 /// Allows the user to wrap the object in a `Mutex` or shared `RwLock`.
 /// By default, using `.into()` will create a `Mutex`-protected version (exclusive).
 pub struct DynBox<T>
 where
-    T: Send + ?Sized,
+    T: DynBoxable + ?Sized,
 {
-    inner: Arc<dyn Any + Sync + Send>,
+    pub(crate) inner: Arc<dyn Any + Sync + Send>,
     _phantom: PhantomData<fn(T) -> T>, // https://doc.rust-lang.org/nomicon/phantom-data.html#table-of-phantomdata-patterns
 }
 
 impl<T: 'static + Send> DynBox<T> {
-    /// Creates a `DynBox` with a `Mutex`.
+    /// Creates a `DynBox` with a `Mutex`. Only requires `T: Send`, which makes
+    /// it suitable for `Send + !Sync` types, that can't be put into `RwLock`.
     ///
     /// # Parameters
     ///
@@ -103,13 +437,49 @@ impl<T: 'static + Send> DynBox<T> {
     ///
     /// A new `DynBox` instance with `Mutex` protection.
     pub fn new_exclusive(value: T) -> Self {
-        registry::register_type::<T>();
-        registry::register_type::<Arc<T>>();
+        Self::builder(value).mutex().build()
+    }
+
+    /// Starts building a `DynBox` around `value`. The container defaults to a
+    /// `Mutex`, see `DynBoxBuilder`.
+    pub fn builder(value: T) -> DynBoxBuilder<T, T> {
+        DynBoxBuilder::new(value)
+    }
+
+    /// Creates a `DynBox` with a `Mutex` holding `Pin<Box<T>>`, for
+    /// self-referential or otherwise address-sensitive types which must not be
+    /// moved after creation. Read coercions work as usual, while mutable
+    /// access is only available as `Pin<&mut T>` via `with_pinned_mut`.
+    ///
+    /// # Parameters
+    ///
+    /// - `value`: The value to be pinned and wrapped in the `DynBox`.
+    ///
+    /// # Returns
+    ///
+    /// A new `DynBox` instance with `Mutex` protection.
+    pub fn new_exclusive_pinned(value: T) -> Self {
+        registry::register_value_type::<T>();
         DynBox {
-            inner: Arc::new(Mutex::new(value)),
+            inner: Arc::new(Mutex::new(Box::pin(value))),
             _phantom: PhantomData,
         }
     }
+
+    /// Locks the `DynBox` created with `new_exclusive_pinned` and passes the
+    /// pinned value to `f`. The lock is released as soon as `f` returns.
+    ///
+    /// # Returns
+    ///
+    /// The result of `f`. Panics if the `DynBox` does not hold a pinned `T`.
+    pub fn with_pinned_mut<R>(&self, f: impl FnOnce(Pin<&mut T>) -> R) -> R {
+        let mutex = self
+            .inner
+            .downcast_ref::<Mutex<Pin<Box<T>>>>()
+            .expect("DynBox does not hold a pinned value, see `new_exclusive_pinned`");
+        let mut guard = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        f(guard.as_mut())
+    }
 }
 
 impl<T: 'static + Send + ?Sized> DynBox<T> {
@@ -124,17 +494,20 @@ impl<T: 'static + Send + ?Sized> DynBox<T> {
     ///
     /// A new `DynBox` instance with `Mutex` protection.
     pub fn new_exclusive_boxed(value: Box<T>) -> Self {
-        registry::register_type::<Box<T>>();
-        registry::register_type::<Arc<Box<T>>>();
-        DynBox {
-            inner: Arc::new(Mutex::new(value)),
-            _phantom: PhantomData,
-        }
+        Self::builder_boxed(value).mutex().build()
+    }
+
+    /// Starts building a `DynBox` around a Box'ed T. The container defaults to
+    /// a `Mutex`, see `DynBoxBuilder`.
+    pub fn builder_boxed(value: Box<T>) -> DynBoxBuilder<T, Box<T>> {
+        DynBoxBuilder::new(value)
     }
 }
 
 impl<T: 'static + Sync + Send> DynBox<T> {
-    /// Creates a `DynBox` with a `RwLock`.
+    /// Creates a `DynBox` with a `RwLock`. Requires `T: Sync` as readers may
+    /// access the value concurrently, use `new_exclusive` for `Send + !Sync`
+    /// types.
     ///
     /// # Parameters
     ///
@@ -144,10 +517,53 @@ impl<T: 'static + Sync + Send> DynBox<T> {
     ///
     /// A new `DynBox` instance with `RwLock` protection.
     pub fn new_shared(value: T) -> Self {
-        registry::register_type::<T>();
-        registry::register_type::<Arc<T>>();
+        Self::builder(value).rwlock().build()
+    }
+
+    /// Creates a `DynBox` with a `RwLock` of the given policy, see
+    /// `LockPolicy`.
+    ///
+    /// # Parameters
+    ///
+    /// - `value`: The value to be wrapped in the `DynBox`.
+    /// - `policy`: The `RwLock` implementation to use.
+    ///
+    /// # Returns
+    ///
+    /// A new `DynBox` instance with `RwLock` protection.
+    pub fn new_shared_with(value: T, policy: LockPolicy) -> Self {
+        Self::builder(value).rwlock().build_with(policy)
+    }
+
+    /// Creates an immutable `DynBox`, which holds the value in the `Arc`
+    /// directly, without a lock. Read coercions never block, while write
+    /// coercions panic.
+    ///
+    /// # Parameters
+    ///
+    /// - `value`: The value to be wrapped in the `DynBox`.
+    ///
+    /// # Returns
+    ///
+    /// A new immutable `DynBox` instance.
+    pub fn new_immutable(value: T) -> Self {
+        Self::builder(value).immutable().build()
+    }
+
+    /// Creates an immutable `DynBox` out of an existing `Arc`, sharing the
+    /// value with the Rust code holding it, see `new_immutable`.
+    ///
+    /// # Parameters
+    ///
+    /// - `value`: The `Arc` to be wrapped in the `DynBox`.
+    ///
+    /// # Returns
+    ///
+    /// A new immutable `DynBox` instance.
+    pub fn from_arc(value: Arc<T>) -> Self {
+        registry::register_value_type::<T>();
         DynBox {
-            inner: Arc::new(RwLock::new(value)),
+            inner: value,
             _phantom: PhantomData,
         }
     }
@@ -165,27 +581,204 @@ impl<T: 'static + Sync + Send + ?Sized> DynBox<T> {
     ///
     /// A new `DynBox` instance with `RwLock` protection.
     pub fn new_shared_boxed(value: Box<T>) -> Self {
-        registry::register_type::<Box<T>>();
-        registry::register_type::<Arc<Box<T>>>();
+        Self::builder_boxed(value).rwlock().build()
+    }
+}
+
+/// The `RwLock` implementation protecting a shared `DynBox`, see module level
+/// docs for platform-dependent behavior of the default one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum LockPolicy {
+    /// `std::sync::RwLock`, whose fairness depends on the platform.
+    #[default]
+    Std,
+    /// `parking_lot::RwLock`, which is task-fair and starves neither readers
+    /// nor writers. Requires `parking_lot` feature.
+    #[cfg(feature = "parking_lot")]
+    Fair,
+}
+
+/// Container marker for `DynBoxBuilder`, selects a `Mutex`.
+pub struct Exclusive;
+
+/// Container marker for `DynBoxBuilder`, selects a `RwLock`.
+pub struct Shared;
+
+/// Container marker for `DynBoxBuilder`, selects no container at all, the
+/// value is immutable.
+pub struct Immutable;
+
+/// A builder for `DynBox`, which allows choosing the container protecting the
+/// value. `T` is the type the `DynBox` coerces to, `V` is the stored value
+/// (either `T` itself or `Box<T>`), and `C` is the container marker.
+///
+/// The container is tracked in the type, so that the bounds it requires (e.g.
+/// `Sync` for `RwLock`) are checked at compile time.
+pub struct DynBoxBuilder<T: Send + ?Sized, V, C = Exclusive> {
+    value: V,
+    _phantom: PhantomData<fn(T) -> T>,
+    _container: PhantomData<C>,
+}
+
+impl<T: Send + ?Sized, V> DynBoxBuilder<T, V> {
+    fn new(value: V) -> Self {
+        DynBoxBuilder {
+            value,
+            _phantom: PhantomData,
+            _container: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + ?Sized, V, C> DynBoxBuilder<T, V, C> {
+    /// Protects the value with a `Mutex`. Only requires the value to be `Send`.
+    pub fn mutex(self) -> DynBoxBuilder<T, V, Exclusive> {
+        DynBoxBuilder {
+            value: self.value,
+            _phantom: PhantomData,
+            _container: PhantomData,
+        }
+    }
+
+    /// Protects the value with a `RwLock`. Requires the value to be `Sync`, as
+    /// readers may access it concurrently.
+    pub fn rwlock(self) -> DynBoxBuilder<T, V, Shared>
+    where
+        V: Sync,
+    {
+        DynBoxBuilder {
+            value: self.value,
+            _phantom: PhantomData,
+            _container: PhantomData,
+        }
+    }
+
+    /// Makes the value immutable, so that it's held without a lock. Requires
+    /// the value to be `Sync`, as readers may access it concurrently.
+    pub fn immutable(self) -> DynBoxBuilder<T, V, Immutable>
+    where
+        V: Sync,
+    {
+        DynBoxBuilder {
+            value: self.value,
+            _phantom: PhantomData,
+            _container: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + ?Sized, V: 'static + Send> DynBoxBuilder<T, V, Exclusive> {
+    /// Creates a `DynBox` with a `Mutex`.
+    pub fn build(self) -> DynBox<T> {
+        registry::register_value_type::<V>();
+        registry::register_type::<Arc<V>>();
         DynBox {
-            inner: Arc::new(RwLock::new(value)),
+            inner: Arc::new(Mutex::new(self.value)),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + ?Sized, V: 'static + Send + Sync> DynBoxBuilder<T, V, Shared> {
+    /// Creates a `DynBox` with a `RwLock`.
+    pub fn build(self) -> DynBox<T> {
+        self.build_with(LockPolicy::default())
+    }
+
+    /// Creates a `DynBox` with a `RwLock` of the given policy.
+    pub fn build_with(self, policy: LockPolicy) -> DynBox<T> {
+        registry::register_value_type::<V>();
+        registry::register_type::<Arc<V>>();
+        let inner: Arc<dyn Any + Sync + Send> = match policy {
+            LockPolicy::Std => Arc::new(RwLock::new(self.value)),
+            #[cfg(feature = "parking_lot")]
+            LockPolicy::Fair => Arc::new(parking_lot::RwLock::new(self.value)),
+        };
+        DynBox {
+            inner,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + ?Sized, V: 'static + Send + Sync> DynBoxBuilder<T, V, Immutable> {
+    /// Creates an immutable `DynBox`.
+    pub fn build(self) -> DynBox<T> {
+        registry::register_value_type::<V>();
+        registry::register_type::<Arc<V>>();
+        DynBox {
+            inner: Arc::new(self.value),
             _phantom: PhantomData,
         }
     }
 }
 
 impl<T: 'static + Send + ?Sized> DynBox<T> {
-    fn into_raw(self) -> *const (dyn Any + Send + Sync) {
+    pub(crate) fn into_raw(self) -> *const (dyn Any + Send + Sync) {
         Arc::into_raw(self.inner)
     }
 
-    fn from_raw(ptr: *const (dyn Any + Send + Sync)) -> Self {
+    pub(crate) fn from_raw(ptr: *const (dyn Any + Send + Sync)) -> Self {
         DynBox {
             inner: unsafe { Arc::from_raw(ptr) },
             _phantom: PhantomData,
         }
     }
 
+    /// Creates a `DynBox` holding a weak reference to the value, see "Weak
+    /// references" section of module level docs. Downgrading a weak `DynBox`
+    /// clones it.
+    pub fn downgrade(&self) -> DynBox<T> {
+        DynBox {
+            inner: registry::downgrade(&self.inner),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Upgrades a `DynBox` created by `downgrade` to one which keeps the value
+    /// alive. Other `DynBox`es are cloned.
+    ///
+    /// # Returns
+    ///
+    /// The upgraded `DynBox`, or `None` if the value was dropped already.
+    pub fn upgrade(&self) -> Option<DynBox<T>> {
+        registry::upgrade(&self.inner).map(|inner| DynBox {
+            inner,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Creates a `DynBox` of a part of the value, e.g. of a field, which can be
+    /// stored on its own, see "Projections" section of module level docs.
+    /// The new `DynBox` keeps this one alive, and its coercions take the lock
+    /// of this one.
+    ///
+    /// # Parameters
+    ///
+    /// - `get`: A function pointer projecting the value to its part.
+    /// - `get_mut`: A function pointer projecting the value to its part mutably.
+    ///
+    /// # Returns
+    ///
+    /// A `DynBox` which coerces to the part.
+    pub fn new_projection<U: 'static + Send + ?Sized>(
+        &self,
+        get: fn(&T) -> &U,
+        get_mut: fn(&mut T) -> &mut U,
+    ) -> DynBox<U> {
+        DynBox {
+            inner: registry::project::<T, U>(self.inner.clone(), get, get_mut),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of references to the value, held by `DynBox`es,
+    /// handles and OCaml values alike, see `Arc::strong_count`.
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
     /// Coerces the `DynBox` to a handle of the specified type.
     ///
     /// # Returns
@@ -196,6 +789,71 @@ impl<T: 'static + Send + ?Sized> DynBox<T> {
         registry::coerce::<T>(self.inner.clone())
     }
 
+    /// Attempts to coerce the `DynBox` to a handle of the specified type
+    /// without blocking.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced type, or `None` if the lock is currently held
+    /// elsewhere.
+    pub fn try_coerce(&self) -> Option<registry::Handle<T>> {
+        registry::try_coerce::<T>(self.inner.clone())
+    }
+
+    /// Coerces the `DynBox` to a handle of the specified type, waiting at most
+    /// `dur` for the lock to become available.
+    ///
+    /// `std::sync` locks don't support timed acquisition, so this is a poll
+    /// loop around `try_coerce` which sleeps between attempts. Expect the
+    /// actual wait to overshoot `dur` slightly, and prefer short timeouts.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced type, or `None` if the lock could not be
+    /// acquired before the deadline.
+    pub fn coerce_timeout(&self, dur: Duration) -> Option<registry::Handle<T>> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(1);
+        let deadline = Instant::now() + dur;
+        loop {
+            if let Some(handle) = self.try_coerce() {
+                return Some(handle);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            std::thread::sleep(remaining.min(POLL_INTERVAL));
+        }
+    }
+
+    /// Coerces the `DynBox` to a handle of the specified type, borrowing the
+    /// `DynBox` instead of cloning the inner `Arc`. Prefer this on hot paths
+    /// where the handle does not need to outlive the `DynBox`.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced type. Note that this handle holds a lock, so use
+    /// with care to avoid deadlocks.
+    pub fn coerce_borrowed(&self) -> registry::BorrowedHandle<'_, T> {
+        registry::coerce_ref::<T>(&self.inner)
+    }
+
+    /// Locks the inner container and passes the guarded value as `&dyn Any`
+    /// to `f`, which allows attempting several concrete downcasts without
+    /// registering each of them as a coercion. For boxed trait objects the
+    /// value is the `Box<T>` itself. The coercion to `dyn Any` is registered
+    /// by `DynBox` constructors, so this works for any `DynBox`, and so does
+    /// `registry::coerce::<dyn Any>` on its inner `DynArc`.
+    ///
+    /// # Returns
+    ///
+    /// The result of `f`. Note that the lock is held for the whole duration
+    /// of `f`, so keep it short and avoid touching the same `DynBox` inside.
+    pub fn with_any<R>(&self, f: impl FnOnce(&dyn Any) -> R) -> R {
+        let any = registry::coerce_ref::<dyn Any>(&self.inner);
+        f(&*any)
+    }
+
     /// Coerces the `DynBox` to a mutable handle of the specified type.
     ///
     /// # Returns
@@ -205,50 +863,356 @@ impl<T: 'static + Send + ?Sized> DynBox<T> {
     pub fn coerce_mut(&self) -> registry::HandleMut<T> {
         registry::coerce_mut::<T>(self.inner.clone())
     }
-}
 
-impl<T: 'static + Send + ?Sized> Clone for DynBox<T> {
-    fn clone(&self) -> Self {
-        DynBox {
-            inner: self.inner.clone(),
-            _phantom: PhantomData,
-        }
+    /// Attempts to coerce the `DynBox` to a mutable handle of the specified
+    /// type without blocking.
+    ///
+    /// # Returns
+    ///
+    /// A mutable handle to the coerced type, or `None` if the lock is currently
+    /// held elsewhere, be it for reading or for writing, or if the value is
+    /// pinned or immutable and can't be coerced mutably at all.
+    pub fn try_coerce_mut(&self) -> Option<registry::HandleMut<T>> {
+        registry::try_coerce_mut::<T>(self.inner.clone())
     }
-}
 
-impl<E> From<E> for DynBox<dyn std::error::Error + Send>
-where
-    E: std::error::Error + Send + 'static,
-{
-    fn from(err: E) -> Self {
-        let boxed_err: Box<dyn std::error::Error + Send> = Box::new(err);
-        DynBox::new_exclusive_boxed(boxed_err)
+    /// Checks if the value is currently locked, for reading or for writing.
+    ///
+    /// The answer is only a hint: the lock may be taken or released by another
+    /// thread right after this call returns, so don't lock based on it. Use
+    /// `try_coerce` or `try_coerce_mut` to take the lock without blocking
+    /// instead.
+    pub fn is_locked(&self) -> bool {
+        registry::is_locked(&self.inner)
     }
-}
 
-impl<T: ?Sized + Send + 'static> OCamlDesc for DynBox<T> {
-    fn ocaml_desc(env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
-        let type_id = <Self as OCamlDesc>::unique_id();
-        let typ = env
-            .get_type(type_id, type_name::get_type_name::<T>().as_str())
-            .0;
-        format!("_ {}'", typ)
+    /// Coerces the `DynBox` to a read handle which can be upgraded to a write
+    /// handle without releasing the lock in between, see "Lock policy" section
+    /// of module level docs. Only available with `parking_lot` feature, and
+    /// only for boxes built with `LockPolicy::Fair`, others panic.
+    ///
+    /// # Returns
+    ///
+    /// An upgradable handle to the coerced type. Note that this handle holds a
+    /// lock, which excludes writers and other upgradable handles.
+    #[cfg(feature = "parking_lot")]
+    pub fn coerce_upgradable(&self) -> registry::UpgradableHandle<T> {
+        registry::coerce_upgradable::<T>(self.inner.clone())
     }
 
-    fn unique_id() -> u128 {
-        let key = highway::Key([
-            const_random!(u64),
-            const_random!(u64),
-            const_random!(u64),
-            const_random!(u64),
-        ]);
-        let mut hasher = HighwayHasher::new(key);
-        let type_id = TypeId::of::<T>();
-        type_id.hash(&mut hasher);
-        let result = hasher.finalize128();
-        (result[0] as u128) | ((result[1] as u128) << 64)
+    /// Returns the kind of container protecting the value, which tells whether
+    /// read coercions of this `DynBox` may run concurrently.
+    pub fn container_kind(&self) -> registry::ContainerKind {
+        registry::container_kind(&self.inner)
     }
-}
+
+    /// Coerces the `DynBox` and passes the coerced value to `f`. The lock is
+    /// released as soon as `f` returns, so no handle can leak to the caller.
+    ///
+    /// # Returns
+    ///
+    /// The result of `f`. Avoid touching the same `DynBox` inside `f`, as the
+    /// lock is held for its whole duration.
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.coerce_borrowed().with(f)
+    }
+
+    /// Coerces the `DynBox` mutably and passes the coerced value to `f`. The
+    /// lock is released as soon as `f` returns, so no handle can leak to the
+    /// caller.
+    ///
+    /// # Returns
+    ///
+    /// The result of `f`. Avoid touching the same `DynBox` inside `f`, as the
+    /// lock is held for its whole duration.
+    pub fn write<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        self.coerce_mut().with_mut(f)
+    }
+
+    /// Coerces the `DynBox` and drives the future returned by `fut_fn` to
+    /// completion on the shared tokio runtime, e.g. to call an `async fn`
+    /// method from a synchronous stub:
+    /// `client.block_on(async |client| client.fetch(&url).await)`. See
+    /// `runtime` module docs for the runtime lifecycle.
+    ///
+    /// # Returns
+    ///
+    /// The output of the future. The value stays locked until the future
+    /// completes, so the future must not coerce the same `DynBox` mutably.
+    #[cfg(feature = "tokio")]
+    pub fn block_on<R>(&self, fut_fn: impl AsyncFnOnce(&T) -> R) -> R {
+        let value = self.coerce_borrowed();
+        crate::runtime::block_on(fut_fn(&value))
+    }
+}
+
+impl<T: 'static + Send + Clone> DynBox<T> {
+    /// Creates a new `Mutex`-backed `DynBox` holding a clone of the value,
+    /// e.g. to "thaw" a value which was created with `new_shared` and is now
+    /// exclusively mutated. The value is copied, the resulting `DynBox` does
+    /// not share it with the original one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a weak `DynBox` whose value was dropped already, see
+    /// `downgrade`.
+    pub fn to_exclusive(&self) -> DynBox<T> {
+        DynBox::new_exclusive(self.clone_value())
+    }
+
+    /// Clones the value itself into a new `Mutex`-backed `DynBox`, e.g. to
+    /// copy a value before mutating it on behalf of OCaml. Unlike
+    /// `Clone::clone`, which returns another reference to the same value,
+    /// mutations of the deep clone are not visible through the original
+    /// `DynBox`, and vice versa.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a weak `DynBox` whose value was dropped already, see
+    /// `downgrade`.
+    pub fn deep_clone(&self) -> DynBox<T> {
+        self.to_exclusive()
+    }
+
+    /// Locks the inner container and clones the value, whether it was stored
+    /// as `T`, `Box<T>` or `Pin<Box<T>>`. Immutable values need no lock. Weak
+    /// `DynBox`es and projections are cloned through a coercion, which
+    /// upgrades the weak reference or takes the lock of the parent.
+    fn clone_value(&self) -> T {
+        let any = self.inner.as_ref();
+        #[cfg(feature = "parking_lot")]
+        if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<T>>() {
+            return rwlock.read().clone();
+        }
+        #[cfg(feature = "parking_lot")]
+        if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<Box<T>>>() {
+            return T::clone(&rwlock.read());
+        }
+        if let Some(mutex) = any.downcast_ref::<Mutex<T>>() {
+            mutex.lock().unwrap_or_else(PoisonError::into_inner).clone()
+        } else if let Some(rwlock) = any.downcast_ref::<RwLock<T>>() {
+            rwlock
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        } else if let Some(mutex) = any.downcast_ref::<Mutex<Box<T>>>() {
+            T::clone(&mutex.lock().unwrap_or_else(PoisonError::into_inner))
+        } else if let Some(rwlock) = any.downcast_ref::<RwLock<Box<T>>>() {
+            T::clone(&rwlock.read().unwrap_or_else(PoisonError::into_inner))
+        } else if let Some(mutex) = any.downcast_ref::<Mutex<Pin<Box<T>>>>() {
+            T::clone(&mutex.lock().unwrap_or_else(PoisonError::into_inner))
+        } else if let Some(value) = any.downcast_ref::<T>() {
+            value.clone()
+        } else if let Some(value) = any.downcast_ref::<Box<T>>() {
+            T::clone(value)
+        } else {
+            T::clone(&self.coerce())
+        }
+    }
+}
+
+impl<T: 'static + Send + Sync + Clone> DynBox<T> {
+    /// Creates a new `RwLock`-backed `DynBox` holding a clone of the value,
+    /// e.g. to share a snapshot of an exclusively mutated value between
+    /// concurrent readers. The value is copied, the resulting `DynBox` does
+    /// not share it with the original one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a weak `DynBox` whose value was dropped already, see
+    /// `downgrade`.
+    pub fn to_shared(&self) -> DynBox<T> {
+        DynBox::new_shared(self.clone_value())
+    }
+
+    /// Creates a new immutable `DynBox` holding a clone of the value, e.g. to
+    /// "freeze" a snapshot of a mutable value so that it can be read without
+    /// locking. The value is copied, the resulting `DynBox` does not share it
+    /// with the original one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a weak `DynBox` whose value was dropped already, see
+    /// `downgrade`.
+    pub fn to_immutable(&self) -> DynBox<T> {
+        DynBox::new_immutable(self.clone_value())
+    }
+}
+
+impl<A: 'static, R: 'static> DynBox<dyn FnMut(A) -> R + Send> {
+    /// Coerces the `DynBox` to the wrapped closure and invokes it with `arg`.
+    /// Closures taking several arguments can take them as a tuple.
+    ///
+    /// The closure is expected to be created with `new_exclusive_boxed`, and
+    /// `dyn FnMut(A) -> R` to be registered with `register_trait!`.
+    pub fn call_mut(&self, arg: A) -> R {
+        let mut f = self.coerce_mut();
+        (*f)(arg)
+    }
+}
+
+impl<A: 'static, R: 'static> DynBox<dyn Fn(A) -> R + Send> {
+    /// Coerces the `DynBox` to the wrapped closure and invokes it with `arg`.
+    /// Closures taking several arguments can take them as a tuple.
+    ///
+    /// The closure is expected to be created with `new_exclusive_boxed`, and
+    /// `dyn Fn(A) -> R` to be registered with `register_trait!`.
+    pub fn call(&self, arg: A) -> R {
+        let f = self.coerce();
+        (*f)(arg)
+    }
+}
+
+impl<I: 'static> DynBox<dyn Iterator<Item = I> + Send> {
+    /// Creates a `Mutex`-backed `DynBox` out of an iterator, so that OCaml can
+    /// drive it lazily with a stub calling `next`, e.g. to stream items without
+    /// materializing them into a list.
+    ///
+    /// `dyn Iterator<Item = I>` is expected to be registered with
+    /// `register_trait!`.
+    pub fn of_iter(iter: impl Iterator<Item = I> + Send + 'static) -> Self {
+        let iter: Box<dyn Iterator<Item = I> + Send> = Box::new(iter);
+        DynBox::new_exclusive_boxed(iter)
+    }
+
+    /// Coerces the `DynBox` to the wrapped iterator and advances it.
+    pub fn next(&self) -> Option<I> {
+        let mut iter = self.coerce_mut();
+        iter.next()
+    }
+}
+
+impl<T: 'static + Send + ?Sized> DynBox<T> {
+    /// Formats the value with `fmt` if it can be coerced to `Out`, otherwise
+    /// (or if the value is currently locked) formats it as
+    /// `DynBox<TypeName>@ptr`. Never blocks or panics.
+    fn fmt_via<Out: ?Sized + 'static>(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        fmt: impl FnOnce(&Out, &mut std::fmt::Formatter<'_>) -> std::fmt::Result,
+    ) -> std::fmt::Result {
+        if registry::has_coercion::<Out>(&self.inner) {
+            if let Some(handle) = registry::try_coerce::<Out>(self.inner.clone()) {
+                return fmt(&*handle, f);
+            }
+        }
+        write!(
+            f,
+            "DynBox<{}>@{:p}",
+            registry::type_name_of(&self.inner),
+            Arc::as_ptr(&self.inner)
+        )
+    }
+}
+
+/// Prints the value if it's registered to coerce to `dyn Debug`, and the type
+/// name with the address otherwise.
+impl<T: 'static + Send + ?Sized> std::fmt::Debug for DynBox<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_via::<dyn std::fmt::Debug>(f, |v, f| v.fmt(f))
+    }
+}
+
+/// Prints the value if it's registered to coerce to `dyn Display`, and the type
+/// name with the address otherwise.
+impl<T: 'static + Send + ?Sized> std::fmt::Display for DynBox<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_via::<dyn std::fmt::Display>(f, |v, f| v.fmt(f))
+    }
+}
+
+impl<T: 'static + Send + ?Sized> DynBox<T> {
+    /// Checks if both `DynBox`es share the same value, i.e. physical equality.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Arc::as_ptr(&self.inner), Arc::as_ptr(&other.inner))
+    }
+
+    /// Recovers the concrete type of a `DynBox` which was erased to a trait
+    /// object, e.g. `DynBox<Sheep>` passed to OCaml and received back as
+    /// `DynBox<Animal>`. The container still holds the concrete value, so the
+    /// coercion is looked up in the registry by its runtime type, which
+    /// relies on the `C -> C` coercion registered by `register_type!`.
+    ///
+    /// # Returns
+    ///
+    /// A `DynBox<C>` sharing the value, or `None` if there is no registered
+    /// coercion of the value to `C`.
+    pub fn downcast_concrete<C: 'static + Send>(&self) -> Option<DynBox<C>> {
+        self.cast::<C>()
+    }
+
+    /// Views the value as another type it's registered to coerce to, e.g. a
+    /// `DynBox<Sheep>` as `DynBox<dyn Animal + Send>`, without going through
+    /// OCaml. It doesn't matter whether the value was created out of the
+    /// concrete type or out of a boxed trait object, see "Trait objects"
+    /// section of module level docs.
+    ///
+    /// # Returns
+    ///
+    /// A `DynBox<U>` sharing the value, or `None` if there is no registered
+    /// coercion of the value to `U`.
+    pub fn cast<U: 'static + Send + ?Sized>(&self) -> Option<DynBox<U>> {
+        if !registry::has_coercion::<U>(&self.inner) {
+            return None;
+        }
+        Some(DynBox {
+            inner: self.inner.clone(),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Checks if two `DynBox`es are equal, physically or structurally depending on
+/// the registration of `T`, see "Equality" section of module level docs. Stubs
+/// for OCaml are declared with `decl_equal!`.
+pub fn dynbox_equal<T: 'static + Send + ?Sized>(a: &DynBox<T>, b: &DynBox<T>) -> bool {
+    registry::dyn_eq(&a.inner, &b.inner)
+}
+
+/// Compares two `DynBox`es, structurally or by address depending on the
+/// registration of `T`, see "Ordering" section of module level docs. Stubs for
+/// OCaml are declared with `decl_compare!`.
+pub fn dynbox_compare<T: 'static + Send + ?Sized>(
+    a: &DynBox<T>,
+    b: &DynBox<T>,
+) -> std::cmp::Ordering {
+    registry::dyn_compare(&a.inner, &b.inner)
+}
+
+/// Returns another reference to the same value, use `deep_clone` to copy the
+/// value itself.
+impl<T: 'static + Send + ?Sized> Clone for DynBox<T> {
+    fn clone(&self) -> Self {
+        DynBox {
+            inner: self.inner.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<E> From<E> for DynBox<dyn std::error::Error + Send>
+where
+    E: std::error::Error + Send + 'static,
+{
+    fn from(err: E) -> Self {
+        let boxed_err: Box<dyn std::error::Error + Send> = Box::new(err);
+        DynBox::new_exclusive_boxed(boxed_err)
+    }
+}
+
+impl<T: ?Sized + Send + 'static> OCamlDesc for DynBox<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
+        let type_id = <Self as OCamlDesc>::unique_id();
+        let typ = env
+            .get_type(type_id, type_name::get_type_name::<T>().as_str())
+            .0;
+        format!("_ {}'", typ)
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id("DynBox", &TypeId::of::<T>())
+    }
+}
 
 impl<T: ?Sized + Send + 'static> OCamlBinding for DynBox<T> {
     fn ocaml_binding(
@@ -256,47 +1220,72 @@ impl<T: ?Sized + Send + 'static> OCamlBinding for DynBox<T> {
         rename: Option<&'static str>,
         new_type: bool,
     ) -> String {
-        // register the new type
-        let ty_id = Self::unique_id();
-
-        if new_type {
-            let name = Box::leak(Box::new(type_name::get_type_name::<T>()));
-            let ty_name = rename.unwrap_or(name.as_str());
-            env.new_type(ty_id, ty_name);
-        }
-
-        let name = Self::ocaml_desc(env, &[]);
-        let name = name
-            .split_whitespace()
-            .last()
-            .expect("no last element :shrug:")
-            .to_owned();
-        let name = name
-            .strip_suffix("'")
-            .expect("dynbox type name does not end with `'`!");
-
-        let names = registry::get_type_info::<T>().implementations;
-        let variants = names
-            .iter()
-            .map(|type_str| type_name::snake_case_of_fully_qualified_name(type_str))
-            .map(|v| "`".to_owned() + &v)
-            .collect::<Vec<_>>()
-            .join("|");
-
-        if new_type {
-            format!(
-                "type tags = [{}] type 'a {}' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t type {} = tags {}'",
-                variants, name, name, name
-            )
-        } else {
-            let ty_name = rename.expect("bug in ocaml-gen: rename should be Some");
-            env.add_alias(ty_id, ty_name);
+        rusty_obj_binding::<T>(
+            env,
+            rename,
+            new_type,
+            Self::unique_id(),
+            "Ocaml_rs_smartptr.Rusty_obj.t",
+            |env| Self::ocaml_desc(env, &[]),
+        )
+    }
+}
 
-            format!(
-                "type 'a {}' = 'a {}' type {} = {}",
-                ty_name, name, ty_name, name
-            )
-        }
+/// Generates the OCaml binding of a smart pointer to `T` with id `ty_id`,
+/// which is represented on OCaml side by `obj_type` tagged with polymorphic
+/// variants of the traits implemented by `T`. `desc` describes the smart
+/// pointer type within `env`, e.g. `_ sheep'`.
+pub(crate) fn rusty_obj_binding<T: ?Sized + 'static>(
+    env: &mut ::ocaml_gen::Env,
+    rename: Option<&'static str>,
+    new_type: bool,
+    ty_id: u128,
+    obj_type: &str,
+    desc: impl Fn(&::ocaml_gen::Env) -> String,
+) -> String {
+    // register the new type
+    if new_type {
+        let name = Box::leak(Box::new(type_name::get_type_name::<T>()));
+        let ty_name = rename.unwrap_or(name.as_str());
+        env.new_type(ty_id, ty_name);
+    }
+
+    let name = desc(env);
+    let name = name
+        .split_whitespace()
+        .last()
+        .expect("no last element :shrug:")
+        .to_owned();
+    let name = name
+        .strip_suffix("'")
+        .expect("dynbox type name does not end with `'`!");
+
+    let type_info = registry::get_type_info::<T>();
+    let names = registry::namespaced_implementations::<T>();
+    let variants = names
+        .iter()
+        .map(|type_str| type_name::snake_case_of_fully_qualified_name(type_str))
+        .map(|v| "`".to_owned() + &v)
+        .collect::<Vec<_>>()
+        .join("|");
+
+    if new_type {
+        let bound = match type_info.variant_kind {
+            registry::VariantKind::Open => "[> tags ]",
+            registry::VariantKind::Closed => "[ tags ]",
+        };
+        format!(
+            "type tags = [{}] type 'a {}' = ({} as 'a) {} type {} = tags {}'",
+            variants, name, bound, obj_type, name, name
+        )
+    } else {
+        let ty_name = rename.expect("bug in ocaml-gen: rename should be Some");
+        env.add_alias(ty_id, ty_name);
+
+        format!(
+            "type 'a {}' = 'a {}' type {} = {}",
+            ty_name, name, ty_name, name
+        )
     }
 }
 
@@ -314,20 +1303,39 @@ struct RustyObj(*const (dyn Any + Send + Sync));
 /// Finalizer is registered with OCaml GC, and ensures that our "leaked" `Arc`
 /// pointer is properly cleaned-up whenever OCaml drops corresponding object
 unsafe extern "C" fn rusty_obj_finalizer(v: ocaml::Raw) {
+    // The program is exiting, the object is leaked, see `shutdown` module
+    if shutdown::is_shut_down() {
+        return;
+    }
     let ptr = v.as_pointer::<RustyObj>();
     // Actual type parameter T for DynBox<T> is irrelevant here, dyn Any inside
     // DynBox would know which destructor to call, and T is only for PhantomData
     let dynbox: DynBox<i32> = DynBox::from_raw(ptr.as_ref().0);
+    trace!(
+        "finalize RustyObj of {}",
+        registry::type_name_of(&dynbox.inner)
+    );
     drop(dynbox);
     ptr.drop_in_place();
 }
 
+/// Hashes the address of the value held by the `Arc`, so that the hash is tied
+/// to the identity of the object, see "Hashing" section of module level docs.
+unsafe extern "C" fn rusty_obj_hash(v: ocaml::Raw) -> ocaml::sys::Intnat {
+    let ptr = v.as_pointer::<RustyObj>();
+    let addr = ptr.as_ref().0 as *const () as usize as u64;
+    // OCaml only mixes the low 32 bits of the result into the hash, fold the
+    // high bits of the address into them
+    (addr ^ (addr >> 32)) as ocaml::sys::Intnat
+}
+
 impl ocaml::Custom for RustyObj {
     const NAME: &'static str = "RustyObj\0";
 
     const OPS: ocaml::custom::CustomOps = ocaml::custom::CustomOps {
         identifier: Self::NAME.as_ptr() as *mut ocaml::sys::Char,
         finalize: Some(rusty_obj_finalizer),
+        hash: Some(rusty_obj_hash),
         ..ocaml::custom::DEFAULT_CUSTOM_OPS
     };
 }
@@ -337,6 +1345,8 @@ where
     T: Send + ?Sized + 'static,
 {
     fn from_value(v: ocaml::Value) -> Self {
+        // Arguments are converted on stub entry, with the domain lock held
+        crate::ml_box::mark_ocaml_thread();
         let ptr = unsafe { v.raw().as_pointer::<RustyObj>() };
         let orig_dynbox = DynBox::from_raw(ptr.as_ref().0);
         let dynbox = orig_dynbox.clone();
@@ -350,13 +1360,204 @@ where
     }
 }
 
+/// A `DynBox` borrowed from an OCaml argument of a stub, without cloning the
+/// inner `Arc`, see "RustyObj" section of module level docs. Dereferences to
+/// `DynBox<T>`, and `DynBox::clone` gives an owned `DynBox` out of it.
+///
+/// The borrow relies on the OCaml custom block holding the `Arc`, which is
+/// only guaranteed to be alive until the OCaml GC gets a chance to run. Once
+/// the stub allocates on the OCaml heap (e.g. converts a value to OCaml), calls
+/// back into OCaml or releases the domain lock, the argument may be collected
+/// if OCaml holds no other reference to it, and using the `DynBoxRef`
+/// afterwards is a use after free. Only use it in stubs which do neither, or
+/// clone it into a `DynBox` before doing so.
+pub struct DynBoxRef<'a, T: DynBoxable + ?Sized + 'static> {
+    // OCaml GC owns this reference, so it's never dropped from Rust
+    dynbox: std::mem::ManuallyDrop<DynBox<T>>,
+    // Not `Send` nor `Sync`, the borrow is only valid on the OCaml thread
+    _borrow: PhantomData<&'a *const ()>,
+}
+
+impl<T: Send + ?Sized + 'static> std::ops::Deref for DynBoxRef<'_, T> {
+    type Target = DynBox<T>;
+
+    fn deref(&self) -> &DynBox<T> {
+        &self.dynbox
+    }
+}
+
+unsafe impl<T> ocaml::FromValue for DynBoxRef<'_, T>
+where
+    T: Send + ?Sized + 'static,
+{
+    fn from_value(v: ocaml::Value) -> Self {
+        crate::ml_box::mark_ocaml_thread();
+        let ptr = unsafe { v.raw().as_pointer::<RustyObj>() };
+        DynBoxRef {
+            dynbox: std::mem::ManuallyDrop::new(DynBox::from_raw(ptr.as_ref().0)),
+            _borrow: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized + Send + 'static> OCamlDesc for DynBoxRef<'_, T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        DynBox::<T>::ocaml_desc(env, generics)
+    }
+
+    fn unique_id() -> u128 {
+        DynBox::<T>::unique_id()
+    }
+}
+
+/// `Custom_tag` from `caml/mlvalues.h`.
+const CUSTOM_TAG: ocaml::sys::Tag = 255;
+
+/// Checks if `v` is a `RustyObj` custom block, i.e. an object passed to OCaml
+/// by a `DynBox`, by the identifier of its custom operations. Blocks of other
+/// custom types, as well as immediate values and other blocks, are not.
+pub fn is_rusty_obj(v: &ocaml::Value) -> bool {
+    unsafe {
+        let raw = v.raw().0;
+        if !ocaml::sys::is_block(raw) || ocaml::sys::tag_val(raw) != CUSTOM_TAG {
+            return false;
+        }
+        // The custom operations are the first field of a custom block
+        let ops = *(raw as *const *const ocaml::custom::CustomOps);
+        std::ffi::CStr::from_ptr((*ops).identifier).to_bytes_with_nul()
+            == <RustyObj as ocaml::Custom>::NAME.as_bytes()
+    }
+}
+
+/// A type which can be constructed out of a plain OCaml value, so that stubs
+/// can take `OrConstruct<Self>` arguments, see "RustyObj" section of module
+/// level docs.
+pub trait FromOcamlRepr: Send + Sized + 'static {
+    /// The plain OCaml representation, e.g. `String` for a sheep constructed
+    /// out of its name.
+    type Repr: ocaml::FromValue + OCamlDesc;
+
+    /// Creates a new `DynBox` out of the plain OCaml value.
+    fn construct(repr: Self::Repr) -> DynBox<Self>;
+}
+
+/// A `DynBox<T>` received from OCaml either as an object, or as a plain OCaml
+/// value which it's constructed from with `FromOcamlRepr::construct`, see
+/// "RustyObj" section of module level docs.
+pub struct OrConstruct<T: FromOcamlRepr>(pub DynBox<T>);
+
+/// Tag of the `OrConstruct` variant holding an object.
+const OBJ_TAG: &str = "Obj";
+/// Tag of the `OrConstruct` variant holding a plain value.
+const REPR_TAG: &str = "Repr";
+
+/// The variants of `OrConstruct`, told apart by the hash of their tag.
+#[derive(Debug, PartialEq, Eq)]
+enum OrConstructVariant {
+    Obj,
+    Repr,
+}
+
+impl OrConstructVariant {
+    fn of_hash(hash: ocaml::Int) -> Option<Self> {
+        if hash == crate::poly_variant::hash(OBJ_TAG) {
+            Some(OrConstructVariant::Obj)
+        } else if hash == crate::poly_variant::hash(REPR_TAG) {
+            Some(OrConstructVariant::Repr)
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits `v` into the hash of its tag and its argument if it's shaped like a
+/// polymorphic variant with an argument, i.e. a block of tag 0 holding an
+/// immediate hash and the argument.
+fn split_variant(v: &ocaml::Value) -> Option<(ocaml::Int, ocaml::Value)> {
+    unsafe {
+        let raw = v.raw().0;
+        if !ocaml::sys::is_block(raw)
+            || ocaml::sys::tag_val(raw) != 0
+            || ocaml::sys::wosize_val(raw) != 2
+        {
+            return None;
+        }
+        let hash: ocaml::Value = v.field(0);
+        if ocaml::sys::is_block(hash.raw().0) {
+            return None;
+        }
+        Some((
+            <ocaml::Int as ocaml::FromValue>::from_value(hash),
+            v.field(1),
+        ))
+    }
+}
+
+unsafe impl<T: FromOcamlRepr> ocaml::FromValue for OrConstruct<T> {
+    fn from_value(v: ocaml::Value) -> Self {
+        let (hash, arg) = split_variant(&v).unwrap_or_else(|| {
+            panic!(
+                "OrConstruct<{}> expects `{} or `{} with an argument",
+                std::any::type_name::<T>(),
+                OBJ_TAG,
+                REPR_TAG
+            )
+        });
+        match OrConstructVariant::of_hash(hash) {
+            Some(OrConstructVariant::Obj) => {
+                if !is_rusty_obj(&arg) {
+                    panic!(
+                        "argument of `{} is not an object of OrConstruct<{}>",
+                        OBJ_TAG,
+                        std::any::type_name::<T>()
+                    );
+                }
+                OrConstruct(<DynBox<T> as ocaml::FromValue>::from_value(arg))
+            }
+            Some(OrConstructVariant::Repr) => {
+                OrConstruct(T::construct(<T::Repr as ocaml::FromValue>::from_value(arg)))
+            }
+            None => crate::poly_variant::unknown_tag("OrConstruct", hash),
+        }
+    }
+}
+
+impl<T: FromOcamlRepr> OCamlDesc for OrConstruct<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, _generics: &[&str]) -> String {
+        use crate::poly_variant::{arg_decl, variant_decl};
+        format!(
+            "[ {} | {} ]",
+            variant_decl(OBJ_TAG, &[arg_decl::<DynBox<T>>(env)]),
+            variant_decl(REPR_TAG, &[arg_decl::<T::Repr>(env)])
+        )
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id("OrConstruct", &TypeId::of::<T>())
+    }
+}
+
 unsafe impl<T> ocaml::ToValue for DynBox<T>
 where
     T: Send + ?Sized + 'static,
 {
     fn to_value(&self, rt: &ocaml::Runtime) -> ocaml::Value {
-        // Do a fresh clone of self and turn that into raw pointer
-        let ptr = DynBox::into_raw(self.clone());
+        // Do a fresh clone of self and hand it over to OCaml
+        self.clone().into_value(rt)
+    }
+}
+
+impl<T> IntoValue for DynBox<T>
+where
+    T: Send + ?Sized + 'static,
+{
+    fn into_value(self, rt: &ocaml::Runtime) -> ocaml::Value {
+        // Types registered as `MemSized` report their memory to the GC
+        if let Some(mem) = registry::mem_size_of(&self.inner) {
+            return rusty_obj_with_mem(self, mem);
+        }
+        // Turn our own reference into raw pointer, without cloning
+        let ptr = DynBox::into_raw(self);
         // Convert to RustyObj to ensure that finalizer will be associated with
         // raw Arc pointer
         let rusty_obj = RustyObj(ptr);
@@ -364,6 +1565,121 @@ where
     }
 }
 
+impl<T: 'static + Send + ?Sized> DynBox<T> {
+    /// Converts the `DynBox` to OCaml, moving its reference into the custom
+    /// block instead of cloning it, as `ToValue` does, see "RustyObj" section
+    /// of module level docs. Must be called with the OCaml domain lock held.
+    pub fn into_ocaml_value(self, rt: &ocaml::Runtime) -> ocaml::Value {
+        self.into_value(rt)
+    }
+
+    /// Wraps the `DynBox` to be returned from a stub, so that its reference is
+    /// moved into the OCaml value instead of cloned, see "RustyObj" section of
+    /// module level docs.
+    pub fn into_returned(self) -> Returned<T> {
+        Returned(Cell::new(Some(self)))
+    }
+}
+
+/// A `DynBox` returned by a stub, which is moved into the OCaml value rather
+/// than cloned, see `DynBox::into_returned`. `#[ocaml::func]` converts the
+/// result of a stub only once, right before dropping it, so `to_value` takes
+/// the `DynBox` out, and panics if it's called again.
+pub struct Returned<T: DynBoxable + ?Sized>(Cell<Option<DynBox<T>>>);
+
+unsafe impl<T> ocaml::ToValue for Returned<T>
+where
+    T: Send + ?Sized + 'static,
+{
+    fn to_value(&self, rt: &ocaml::Runtime) -> ocaml::Value {
+        self.0
+            .take()
+            .expect("returned DynBox was converted to OCaml already")
+            .into_value(rt)
+    }
+}
+
+impl<T: ?Sized + Send + 'static> OCamlDesc for Returned<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        DynBox::<T>::ocaml_desc(env, generics)
+    }
+
+    fn unique_id() -> u128 {
+        DynBox::<T>::unique_id()
+    }
+}
+
+extern "C" {
+    fn caml_alloc_custom_mem(
+        ops: *mut ocaml::custom::CustomOps,
+        size: usize,
+        mem: usize,
+    ) -> ocaml::Raw;
+}
+
+/// Custom operations of `RustyObj` at a fixed address, as required by
+/// `caml_alloc_custom_mem`.
+struct StaticOps(ocaml::custom::CustomOps);
+
+/// The operations are never modified, and only contain function pointers and
+/// a pointer to a static string
+unsafe impl Sync for StaticOps {}
+
+static RUSTY_OBJ_OPS: StaticOps = StaticOps(<RustyObj as ocaml::Custom>::OPS);
+
+/// A `DynBox` which tells the OCaml GC how much memory its value holds when
+/// it's passed to OCaml, see "RustyObj" section of module level docs.
+pub struct WithMemHint<T: DynBoxable + ?Sized> {
+    dynbox: DynBox<T>,
+    mem: usize,
+}
+
+impl<T: 'static + Send + ?Sized> DynBox<T> {
+    /// Attaches the amount of memory (in bytes) held by the value, which is
+    /// reported to the OCaml GC when the `DynBox` is passed to OCaml.
+    pub fn with_mem_hint(self, mem: usize) -> WithMemHint<T> {
+        WithMemHint { dynbox: self, mem }
+    }
+}
+
+unsafe impl<T> ocaml::ToValue for WithMemHint<T>
+where
+    T: Send + ?Sized + 'static,
+{
+    fn to_value(&self, _rt: &ocaml::Runtime) -> ocaml::Value {
+        rusty_obj_with_mem(self.dynbox.clone(), self.mem)
+    }
+}
+
+/// Converts `dynbox` into a `RustyObj` custom block, which accounts for `mem`
+/// bytes of memory held outside of the OCaml heap. Must be called with the
+/// OCaml domain lock held, as `ToValue` implementations are.
+fn rusty_obj_with_mem<T: Send + ?Sized + 'static>(
+    dynbox: DynBox<T>,
+    mem: usize,
+) -> ocaml::Value {
+    let ptr = DynBox::into_raw(dynbox);
+    unsafe {
+        let raw = caml_alloc_custom_mem(
+            &RUSTY_OBJ_OPS.0 as *const _ as *mut _,
+            std::mem::size_of::<RustyObj>(),
+            mem,
+        );
+        raw.as_pointer::<RustyObj>().set(RustyObj(ptr));
+        ocaml::Value::new(raw.0)
+    }
+}
+
+impl<T: ?Sized + Send + 'static> OCamlDesc for WithMemHint<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        DynBox::<T>::ocaml_desc(env, generics)
+    }
+
+    fn unique_id() -> u128 {
+        DynBox::<T>::unique_id()
+    }
+}
+
 impl<T> From<T> for DynBox<T>
 where
     T: Send + 'static,
@@ -382,7 +1698,7 @@ mod tests {
     use crate::{register_trait, register_type};
     use serial_test::serial;
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     struct MyError {
         msg: String,
     }
@@ -395,6 +1711,15 @@ mod tests {
 
     impl std::error::Error for MyError {}
 
+    // Shared by the tests below, registering it again is a no-op
+    fn register_my_error() {
+        register_type!({
+            ty: crate::ptr::tests::MyError,
+            marker_traits: [core::marker::Send],
+            object_safe_traits: [std::error::Error],
+        });
+    }
+
     fn get_error_message(error: DynBox<dyn std::error::Error + Send>) -> String {
         let error = error.coerce();
         error.to_string()
@@ -403,11 +1728,7 @@ mod tests {
     #[test]
     #[serial(registry)]
     fn test_bla() {
-        register_type!({
-            ty: crate::ptr::tests::MyError,
-            marker_traits: [core::marker::Send],
-            object_safe_traits: [std::error::Error],
-        });
+        register_my_error();
         let error = MyError {
             msg: String::from("bla-bla-bla"),
         };
@@ -420,6 +1741,443 @@ mod tests {
         assert_eq!(wrapped_error_msg, orig_error_msg);
     }
 
+    #[test]
+    #[serial(registry)]
+    fn test_coerce_borrowed() {
+        register_my_error();
+        let error = MyError {
+            msg: String::from("bla-bla-bla"),
+        };
+        let orig_error_msg = error.to_string();
+        let error = DynBox::new_shared(error);
+        let handle = error.coerce_borrowed();
+        assert_eq!(Arc::strong_count(&error.inner), 1);
+        assert_eq!(handle.to_string(), orig_error_msg);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_downcast_concrete() {
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let error: DynBox<dyn std::error::Error + Send> =
+            DynBox::from_raw(DynBox::into_raw(error));
+        assert!(error.downcast_concrete::<String>().is_none());
+        let recovered = error.downcast_concrete::<MyError>().unwrap();
+        assert_eq!(recovered.coerce().msg, "bla-bla-bla");
+        assert_eq!(error.ref_count(), 2);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_downgrade() {
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let weak = error.downgrade();
+        assert_eq!(error.ref_count(), 1);
+        assert_eq!(weak.coerce().msg, "bla-bla-bla");
+        assert!(weak.upgrade().unwrap().ptr_eq(&error));
+
+        // Lookups by the container see through the weak reference
+        crate::register_show!(MyError => |e| format!("error {}", e.msg));
+        assert_eq!(weak.container_kind(), registry::ContainerKind::Mutex);
+        assert!(!weak.is_locked());
+        let handle = error.coerce();
+        assert!(weak.is_locked());
+        drop(handle);
+        assert_eq!(
+            registry::show(&weak.inner).as_deref(),
+            Some("error bla-bla-bla")
+        );
+        assert!(format!("{:?}", weak).contains("MyError"));
+
+        drop(error);
+        assert!(weak.upgrade().is_none());
+        assert!(weak.try_coerce().is_none());
+        assert!(!weak.is_locked());
+        assert!(registry::show(&weak.inner).is_none());
+        assert!(format!("{:?}", weak).starts_with("DynBox<<dropped value>>@"));
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_new_projection() {
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let msg: DynBox<String> = error.new_projection(|e| &e.msg, |e| &mut e.msg);
+        msg.coerce_mut().push('!');
+        assert_eq!(error.coerce().msg, "bla-bla-bla!");
+        // The projection takes the lock of the original box
+        let handle = error.coerce();
+        assert!(msg.try_coerce().is_none());
+        assert!(msg.is_locked());
+        drop(handle);
+        // ... and keeps the value alive
+        drop(error);
+        assert_eq!(*msg.coerce(), "bla-bla-bla!");
+        assert_eq!(msg.container_kind(), registry::ContainerKind::Mutex);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_with_any() {
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let msg = error.with_any(|any| {
+            assert!(any.downcast_ref::<i32>().is_none());
+            any.downcast_ref::<MyError>().map(|e| e.msg.clone())
+        });
+        assert_eq!(msg.as_deref(), Some("bla-bla-bla"));
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_builder() {
+        register_my_error();
+        let exclusive = DynBox::builder(MyError {
+            msg: String::from("exclusive"),
+        })
+        .build();
+        assert!(exclusive.inner.downcast_ref::<Mutex<MyError>>().is_some());
+        assert_eq!(exclusive.container_kind(), registry::ContainerKind::Mutex);
+        let shared = DynBox::builder(MyError {
+            msg: String::from("shared"),
+        })
+        .rwlock()
+        .build();
+        assert!(shared.inner.downcast_ref::<RwLock<MyError>>().is_some());
+        assert_eq!(shared.container_kind(), registry::ContainerKind::RwLock);
+        assert_eq!(shared.read(|e| e.msg.clone()), "shared");
+
+        let boxed: Box<dyn std::error::Error + Send> = Box::new(MyError {
+            msg: String::from("boxed"),
+        });
+        let boxed = DynBox::builder_boxed(boxed).build();
+        assert!(boxed
+            .inner
+            .downcast_ref::<Mutex<Box<dyn std::error::Error + Send>>>()
+            .is_some());
+
+        let immutable = DynBox::builder(MyError {
+            msg: String::from("immutable"),
+        })
+        .immutable()
+        .build();
+        assert!(immutable.inner.downcast_ref::<MyError>().is_some());
+        assert_eq!(
+            immutable.container_kind(),
+            registry::ContainerKind::Immutable
+        );
+        assert_eq!(immutable.read(|e| e.msg.clone()), "immutable");
+    }
+
+    #[test]
+    fn test_ref_count() {
+        let dynbox = DynBox::new_exclusive(42);
+        assert_eq!(dynbox.ref_count(), 1);
+        let clone = dynbox.clone();
+        assert_eq!(dynbox.ref_count(), 2);
+        drop(clone);
+        assert_eq!(dynbox.ref_count(), 1);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_from_arc() {
+        register_my_error();
+        let shared = Arc::new(MyError {
+            msg: String::from("shared with Rust"),
+        });
+        let immutable = DynBox::from_arc(shared.clone());
+        assert!(Arc::ptr_eq(
+            &(shared.clone() as Arc<dyn Any + Sync + Send>),
+            &immutable.inner
+        ));
+        // Concurrent read handles, as there is no lock to hold
+        let first = immutable.coerce();
+        let second = immutable.try_coerce().unwrap();
+        assert_eq!(first.msg, second.msg);
+        assert!(!immutable.is_locked());
+        drop((first, second));
+
+        register_trait!({
+            ty: std::error::Error,
+            marker_traits: [core::marker::Send],
+        });
+        // The following line mimics the dynbox being sent to OCaml and received
+        // back as a trait object
+        let error: DynBox<dyn std::error::Error + Send> =
+            DynBox::from_raw(DynBox::into_raw(DynBox::from_arc(shared)));
+        assert_eq!(get_error_message(error), "shared with Rust");
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_debug_display() {
+        register_my_error();
+        registry::register::<MyError, dyn std::fmt::Display>(|x| x, |x| x);
+        registry::register::<MyError, dyn std::fmt::Debug>(|x| x, |x| x);
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        assert_eq!(format!("{}", error), "bla-bla-bla");
+        assert_eq!(format!("{:?}", error), "MyError { msg: \"bla-bla-bla\" }");
+
+        // Locked values are printed as type name and address
+        let held = error.coerce_mut();
+        let printed = format!("{:?}", error);
+        assert!(printed.starts_with("DynBox<"), "{}", printed);
+        assert!(printed.contains("MyError>@0x"), "{}", printed);
+        drop(held);
+
+        // So are values without a registered coercion
+        let cell = DynBox::new_exclusive(std::cell::Cell::new(1));
+        assert!(format!("{}", cell).starts_with("DynBox<"));
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_read_write() {
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        error.write(|e| e.msg.push('!'));
+        let msg = error.read(|e| e.msg.clone());
+        assert_eq!(msg, "bla-bla-bla!");
+        // Both closures released the lock on return
+        assert!(error.try_coerce().is_some());
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_container_copies() {
+        register_my_error();
+        let shared = DynBox::new_shared(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let exclusive = shared.to_exclusive();
+        assert_eq!(exclusive.container_kind(), registry::ContainerKind::Mutex);
+        exclusive.write(|e| e.msg.push('!'));
+        assert_eq!(shared.read(|e| e.msg.clone()), "bla-bla-bla");
+        assert_eq!(exclusive.read(|e| e.msg.clone()), "bla-bla-bla!");
+
+        let shared_again = exclusive.to_shared();
+        assert_eq!(
+            shared_again.container_kind(),
+            registry::ContainerKind::RwLock
+        );
+        shared_again.write(|e| e.msg.push('?'));
+        assert_eq!(exclusive.read(|e| e.msg.clone()), "bla-bla-bla!");
+        assert_eq!(shared_again.read(|e| e.msg.clone()), "bla-bla-bla!?");
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_to_immutable() {
+        register_my_error();
+        let exclusive = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let frozen = exclusive.to_immutable();
+        assert_eq!(frozen.container_kind(), registry::ContainerKind::Immutable);
+        exclusive.write(|e| e.msg.push('!'));
+        assert_eq!(frozen.read(|e| e.msg.clone()), "bla-bla-bla");
+        assert_eq!(exclusive.read(|e| e.msg.clone()), "bla-bla-bla!");
+
+        // Weak references and projections are copied as well
+        let weak = exclusive.downgrade();
+        assert_eq!(weak.to_immutable().read(|e| e.msg.clone()), "bla-bla-bla!");
+        let msg = exclusive.new_projection(|e| &e.msg, |e| &mut e.msg);
+        let msg_copy = msg.to_exclusive();
+        msg_copy.write(|m| m.push('?'));
+        assert_eq!(exclusive.read(|e| e.msg.clone()), "bla-bla-bla!");
+        assert_eq!(msg_copy.read(|m| m.clone()), "bla-bla-bla!?");
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_deep_clone() {
+        register_my_error();
+        let original = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let shared_ref = original.clone();
+        let copy = original.deep_clone();
+        copy.write(|e| e.msg.push('!'));
+        assert_eq!(original.read(|e| e.msg.clone()), "bla-bla-bla");
+        assert_eq!(copy.read(|e| e.msg.clone()), "bla-bla-bla!");
+        shared_ref.write(|e| e.msg.push('?'));
+        assert_eq!(original.read(|e| e.msg.clone()), "bla-bla-bla?");
+        assert_eq!(copy.read(|e| e.msg.clone()), "bla-bla-bla!");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    #[serial(registry)]
+    fn test_block_on() {
+        impl MyError {
+            async fn describe(&self, prefix: &str) -> String {
+                tokio::task::yield_now().await;
+                format!("{}: {}", prefix, self.msg)
+            }
+        }
+
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let prefix = String::from("error");
+        assert_eq!(
+            error.block_on(async |e| e.describe(&prefix).await),
+            "error: bla-bla-bla"
+        );
+        // The value is unlocked once the future completes
+        error.write(|e| e.msg.push('!'));
+        // The runtime can be driven from any thread
+        let other = error.clone();
+        let described =
+            std::thread::spawn(move || other.block_on(async |e| e.to_string()))
+                .join()
+                .unwrap();
+        assert_eq!(described, "bla-bla-bla!");
+    }
+
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    #[serial(registry)]
+    fn test_fair_lock_policy() {
+        register_my_error();
+        let fair = DynBox::new_shared_with(
+            MyError {
+                msg: String::from("bla-bla-bla"),
+            },
+            LockPolicy::Fair,
+        );
+        assert_eq!(fair.container_kind(), registry::ContainerKind::RwLock);
+        let reader = fair.coerce();
+        assert!(fair.try_coerce().is_some());
+        drop(reader);
+        fair.write(|e| e.msg.push('!'));
+        assert_eq!(fair.to_exclusive().read(|e| e.msg.clone()), "bla-bla-bla!");
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_pinned() {
+        struct SelfAware {
+            addr: usize,
+            _pin: std::marker::PhantomPinned,
+        }
+
+        registry::register::<SelfAware, SelfAware>(|x| x, |x| x);
+        let pinned = DynBox::new_exclusive_pinned(SelfAware {
+            addr: 0,
+            _pin: std::marker::PhantomPinned,
+        });
+        pinned.with_pinned_mut(|this| {
+            let addr = &*this as *const SelfAware as usize;
+            unsafe { this.get_unchecked_mut() }.addr = addr;
+        });
+        // The value has not moved since its address was recorded
+        let handle = pinned.coerce();
+        assert_eq!(handle.addr, &*handle as *const SelfAware as usize);
+        drop(handle);
+        assert_eq!(pinned.container_kind(), registry::ContainerKind::Mutex);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_coerce_timeout() {
+        register_my_error();
+        let error = DynBox::new_exclusive(MyError {
+            msg: String::from("bla-bla-bla"),
+        });
+        let held = error.coerce_mut();
+        assert!(error.try_coerce().is_none());
+        assert!(error.coerce_timeout(Duration::from_millis(5)).is_none());
+        drop(held);
+
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let holder = error.clone();
+        let releaser = std::thread::spawn(move || {
+            let held = holder.coerce_mut();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+            drop(held);
+        });
+        locked_rx.recv().unwrap();
+        let coerced = error.coerce_timeout(Duration::from_secs(10));
+        assert_eq!(
+            coerced.map(|e| e.msg.clone()).as_deref(),
+            Some("bla-bla-bla")
+        );
+        releaser.join().unwrap();
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_send_not_sync() {
+        use std::cell::Cell;
+        assert_not_impl_all!(Cell<i32>: Sync);
+        assert_impl_all!(DynBox<Cell<i32>>: Sync, Send);
+
+        registry::register::<Cell<i32>, Cell<i32>>(|x| x, |x| x);
+        let cell = DynBox::new_exclusive(Cell::new(1));
+        // The following line mimics the dynbox being sent to OCaml and received
+        // back
+        let cell_clone: DynBox<Cell<i32>> =
+            DynBox::from_raw(DynBox::into_raw(cell.clone()));
+        cell_clone.coerce().set(2);
+        assert_eq!(cell.coerce().get(), 2);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_boxed_closure() {
+        register_trait!({
+            ty: core::ops::FnMut(i32) -> i32,
+            marker_traits: [core::marker::Send],
+        });
+        let mut total = 0;
+        let adder: Box<dyn FnMut(i32) -> i32 + Send> = Box::new(move |x| {
+            total += x;
+            total
+        });
+        let adder = DynBox::new_exclusive_boxed(adder);
+        // The following line mimics the dynbox being sent to OCaml and received
+        // back
+        let adder: DynBox<dyn FnMut(i32) -> i32 + Send> =
+            DynBox::from_raw(DynBox::into_raw(adder));
+        assert_eq!(adder.call_mut(1), 1);
+        assert_eq!(adder.call_mut(2), 3);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_iterator() {
+        register_trait!({
+            ty: core::iter::Iterator<Item = i32>,
+            marker_traits: [core::marker::Send],
+        });
+        let iter = DynBox::of_iter(1..3);
+        // The following line mimics the dynbox being sent to OCaml and received
+        // back
+        let iter: DynBox<dyn Iterator<Item = i32> + Send> =
+            DynBox::from_raw(DynBox::into_raw(iter));
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), None);
+    }
+
     #[test]
     #[serial(registry)]
     fn test_error_boxing() {
@@ -439,27 +2197,135 @@ mod tests {
         assert_eq!(wrapped_error_msg, orig_error_msg);
     }
 
-    // Unfortunately supertrait support does not work yet with stable Rust :(
-    // rust: cannot cast `dyn Error` to `dyn Display`, trait upcasting coercion is experimental
-    // see issue #65991 <https://github.com/rust-lang/rust/issues/65991> for more information
-    // required when coercing `&dyn Error` into `&dyn Display`
-    // #[test]
-    // #[serial(registry)]
-    // fn test_dyn_bla() {
-    //     register_trait!({
-    //         ty: crate::ptr::tests::ErrorCombined,
-    //         marker_traits: [core::marker::Sync, core::marker::Send],
-    //         super_traits: [std::fmt::Display, core::fmt::Debug],
-    //     });
-    //     let error = MyError {
-    //         msg: String::from("bla-bla-bla"),
-    //     };
-    //     let orig_error_msg = error.to_string();
-    //     let error = DynBox::new_shared(error);
-    //     // The following line mimics the dynbox being sent to OCaml and received
-    //     // back as another type
-    //     let error = DynBox::from_raw(DynBox::into_raw(error));
-    //     let wrapped_error_msg = get_error_message(error);
-    //     assert_eq!(wrapped_error_msg, orig_error_msg);
-    // }
+    pub trait Counter {
+        fn get(&self) -> i32;
+        fn incr(&mut self, by: i32);
+        fn describe(&self) -> String;
+    }
+
+    struct SheepCounter(i32);
+
+    impl Counter for SheepCounter {
+        fn get(&self) -> i32 {
+            self.0
+        }
+
+        fn incr(&mut self, by: i32) {
+            self.0 += by
+        }
+
+        fn describe(&self) -> String {
+            format!("{} sheep", self.0)
+        }
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_trait_boxing() {
+        // The trait is registered before any box is created, as it's usually
+        // done at startup
+        register_trait!({
+            ty: crate::ptr::tests::Counter,
+            marker_traits: [core::marker::Send, core::marker::Sync],
+        });
+
+        let exclusive: DynBox<dyn Counter + Send> =
+            DynBox::new_exclusive_boxed(Box::new(SheepCounter(1)));
+        // The following line mimics the dynbox being sent to OCaml and received
+        // back
+        let exclusive: DynBox<dyn Counter + Send> =
+            DynBox::from_raw(DynBox::into_raw(exclusive));
+        assert_eq!(exclusive.container_kind(), registry::ContainerKind::Mutex);
+        exclusive.coerce_mut().incr(2);
+        assert_eq!(exclusive.coerce().get(), 3);
+        assert_eq!(exclusive.read(|c| c.describe()), "3 sheep");
+
+        let shared: DynBox<dyn Counter + Send + Sync> =
+            DynBox::new_shared_boxed(Box::new(SheepCounter(10)));
+        let shared: DynBox<dyn Counter + Send + Sync> =
+            DynBox::from_raw(DynBox::into_raw(shared));
+        assert_eq!(shared.container_kind(), registry::ContainerKind::RwLock);
+        shared.write(|c| c.incr(5));
+        // Concurrent read handles, as the value is behind a `RwLock`
+        let first = shared.coerce();
+        let second = shared.coerce();
+        assert_eq!(first.get() + second.get(), 30);
+        assert_eq!(second.describe(), "15 sheep");
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_trait_object_construction_paths() {
+        register_type!({
+            ty: crate::ptr::tests::SheepCounter,
+            marker_traits: [core::marker::Send, core::marker::Sync],
+            object_safe_traits: [crate::ptr::tests::Counter],
+        });
+        register_trait!({
+            ty: crate::ptr::tests::Counter,
+            marker_traits: [core::marker::Send, core::marker::Sync],
+        });
+
+        let concrete: DynBox<dyn Counter + Send + Sync> =
+            DynBox::new_shared(SheepCounter(1)).cast().unwrap();
+        let boxed: DynBox<dyn Counter + Send + Sync> =
+            DynBox::new_shared_boxed(Box::new(SheepCounter(10)));
+        for counter in [&concrete, &boxed] {
+            counter.write(|c| c.incr(1));
+            // Both coerce to the trait with fewer markers
+            let counter: DynBox<dyn Counter + Send> = counter.cast().unwrap();
+            assert!(registry::has_coercion::<dyn Counter>(&counter.inner));
+            assert!(registry::has_coercion::<dyn Counter + Sync>(&counter.inner));
+            assert!(counter.cast::<String>().is_none());
+        }
+        assert_eq!(concrete.coerce().describe(), "2 sheep");
+        assert_eq!(boxed.read(|c| c.describe()), "11 sheep");
+
+        // Received back from OCaml as a trait object with fewer markers
+        let concrete: DynBox<dyn Counter + Send> =
+            DynBox::from_raw(DynBox::into_raw(concrete));
+        let boxed: DynBox<dyn Counter + Send> = DynBox::from_raw(DynBox::into_raw(boxed));
+        assert_eq!(concrete.coerce().get() + boxed.coerce().get(), 13);
+        // Only the concrete value can be recovered, the box hides its type
+        assert!(concrete.downcast_concrete::<SheepCounter>().is_some());
+        assert!(boxed.downcast_concrete::<SheepCounter>().is_none());
+    }
+
+    #[test]
+    fn test_or_construct_variant() {
+        use crate::poly_variant::hash;
+        assert_eq!(
+            OrConstructVariant::of_hash(hash("Obj")),
+            Some(OrConstructVariant::Obj)
+        );
+        assert_eq!(
+            OrConstructVariant::of_hash(hash("Repr")),
+            Some(OrConstructVariant::Repr)
+        );
+        // A wrong-shaped value, e.g. a plain string or another variant passed
+        // with `Obj.magic`, has no hash of either tag
+        assert_eq!(OrConstructVariant::of_hash(hash("Sheep")), None);
+        assert_eq!(OrConstructVariant::of_hash(0), None);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_super_traits() {
+        register_trait!({
+            ty: std::error::Error,
+            marker_traits: [core::marker::Send],
+            super_traits: [std::fmt::Display, core::fmt::Debug],
+        });
+        let error: DynBox<dyn std::error::Error + Send> = MyError {
+            msg: String::from("bla-bla-bla"),
+        }
+        .into();
+        // The boxed trait object is upcast to its supertraits, which aren't
+        // `Send`, so they are coerced to through the registry directly
+        let display = registry::coerce::<dyn std::fmt::Display>(error.inner.clone());
+        assert_eq!(display.to_string(), "bla-bla-bla");
+        drop(display);
+        let debug = registry::coerce::<dyn std::fmt::Debug>(error.inner.clone());
+        assert_eq!(format!("{:?}", &*debug), "MyError { msg: \"bla-bla-bla\" }");
+    }
 }
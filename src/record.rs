@@ -0,0 +1,93 @@
+//! This module allows passing plain-data Rust structs across the boundary as
+//! OCaml records, instead of wrapping them into opaque `DynBox`es or returning
+//! tuples which lose the field names. It's meant for DTO-style structs, e.g.
+//! several related results of a single computation.
+//!
+//! A struct with named fields is bound to an OCaml record with
+//! `#[derive(OcamlRecord)]`, which implements `ocaml::ToValue`,
+//! `ocaml::FromValue`, `OCamlDesc` and `OCamlBinding` for it. All fields have
+//! to implement `ocaml::ToValue`, `ocaml::FromValue` and `OCamlDesc`, and are
+//! converted one by one, so a record is a copy: changes made on one side are
+//! not seen by the other.
+//!
+//! ```rust,ignore
+//! #[derive(OcamlRecord)]
+//! pub struct Census {
+//!     pub count: i32,
+//!     pub names: Vec<String>,
+//! }
+//! ```
+//!
+//! The record type is declared in bindings with `decl_type!(Census => "t")`
+//! within `ocaml_gen_bindings!`, which emits:
+//!
+//! ```ocaml
+//! type t =
+//!   { count : int32
+//!   ; names : string array
+//!   }
+//! ```
+//!
+//! OCaml type declarations are recursive, so a record may have fields of its
+//! own type, e.g. `children: Vec<Tree>`. Records referring to each other
+//! aren't supported though, as each one is declared on its own, and the one
+//! declared first can't refer to the other one. Wrapping one of them into a
+//! `DynBox` breaks the cycle, as `DynBox` types are opaque.
+//!
+//! At runtime, a record is a block holding its fields in the order they are
+//! declared. OCaml unboxes records which only have float fields, so the derive
+//! rejects structs whose fields are all `f64` or `f32`. It can't see through
+//! type aliases though, so don't hide floats behind them.
+
+use ocaml_gen::OCamlDesc;
+
+/// Allocates a record block of `size` fields, see `store`.
+pub fn alloc(size: usize) -> ocaml::Value {
+    unsafe { ocaml::Value::alloc_tuple(size) }
+}
+
+/// Converts `value` and stores it into the field at `index` of a record
+/// allocated with `alloc`.
+pub fn store<T: ocaml::ToValue>(
+    gc: &ocaml::Runtime,
+    record: &mut ocaml::Value,
+    index: usize,
+    value: &T,
+) {
+    let value = value.to_value(gc);
+    unsafe { record.store_field(gc, index, value) }
+}
+
+/// Converts the field at `index` of a record.
+pub fn field<T: ocaml::FromValue>(record: &ocaml::Value, index: usize) -> T {
+    unsafe { record.field(index) }
+}
+
+/// Describes a field of a record, as used by `record_binding`.
+pub fn field_decl<T: OCamlDesc>(env: &ocaml_gen::Env, name: &str) -> String {
+    format!("{} : {}", name, T::ocaml_desc(env, &[]))
+}
+
+/// Generates the declaration of a record type with id `ty_id` out of its
+/// fields (see `field_decl`), or an alias of it if it was declared already.
+/// The type is registered in `env` before `fields` are described, so that
+/// fields may refer to the record itself.
+pub fn record_binding(
+    env: &mut ocaml_gen::Env,
+    rename: Option<&'static str>,
+    new_type: bool,
+    ty_id: u128,
+    name: &'static str,
+    fields: impl FnOnce(&ocaml_gen::Env) -> Vec<String>,
+) -> String {
+    if new_type {
+        let ty_name = rename.unwrap_or(name);
+        env.new_type(ty_id, ty_name);
+        format!("type {} = {{ {} }}", ty_name, fields(env).join("; "))
+    } else {
+        let ty_name = rename.expect("bug in ocaml-gen: rename should be Some");
+        let name = env.get_type(ty_id, name).0;
+        env.add_alias(ty_id, ty_name);
+        format!("type {} = {}", ty_name, name)
+    }
+}
@@ -17,19 +17,35 @@
 //! providing the basis for building this module.
 
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{
-    Arc, Mutex, MutexGuard, Once, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    Arc, Mutex, MutexGuard, Once, OnceLock, PoisonError, RwLock, RwLockReadGuard,
+    RwLockWriteGuard, TryLockError, TryLockResult, Weak,
 };
 
-use owning_ref::{ErasedBoxRef, ErasedBoxRefMut, OwningHandle, OwningRef, OwningRefMut};
+use owning_ref::{
+    Erased, ErasedBoxRef, ErasedBoxRefMut, OwningHandle, OwningRef, OwningRefMut,
+};
+
+use crate::logging::trace;
 
-/// An enum representing a read guard for either a `Mutex` or `RwLock`.
-/// This allows for a unified interface for read access to the underlying data.
+/// An enum representing a read guard for either a `Mutex` or `RwLock`, a
+/// `Mutex` holding a pinned value, or a plain reference to an immutable value,
+/// which needs no lock. This allows for a unified interface for read access to
+/// the underlying data.
 enum LockReadGuard<'a, T> {
-    Mutex(MutexGuard<'a, T>),
+    Mutex(HeldMutexGuard<'a, T>),
     RwLockRead(RwLockReadGuard<'a, T>),
+    Pinned(HeldMutexGuard<'a, Pin<Box<T>>>),
+    Immutable(&'a T),
+    #[cfg(feature = "parking_lot")]
+    FairRwLockRead(parking_lot::RwLockReadGuard<'a, T>),
 }
 
 impl<T> Deref for LockReadGuard<'_, T> {
@@ -39,19 +55,27 @@ impl<T> Deref for LockReadGuard<'_, T> {
         match self {
             LockReadGuard::Mutex(guard) => guard,
             LockReadGuard::RwLockRead(guard) => guard,
+            LockReadGuard::Pinned(guard) => guard.as_ref().get_ref(),
+            LockReadGuard::Immutable(value) => value,
+            #[cfg(feature = "parking_lot")]
+            LockReadGuard::FairRwLockRead(guard) => guard,
         }
     }
 }
 
-/// Both MutexGuard and RwLockReadGuard are StableDeref, so enum of those two is
-/// also StableDeref
+/// Both MutexGuard and RwLockReadGuard (including the `parking_lot` one) are
+/// StableDeref, and neither a pinned value nor one behind a plain reference
+/// ever moves, so enum of those is also
+/// StableDeref
 unsafe impl<T> stable_deref_trait::StableDeref for LockReadGuard<'_, T> {}
 
 /// An enum representing a write guard for either a `Mutex` or `RwLock`.
 /// This allows for a unified interface for write access to the underlying data.
 enum LockWriteGuard<'a, T> {
-    Mutex(MutexGuard<'a, T>),
+    Mutex(HeldMutexGuard<'a, T>),
     RwLockWrite(RwLockWriteGuard<'a, T>),
+    #[cfg(feature = "parking_lot")]
+    FairRwLockWrite(parking_lot::RwLockWriteGuard<'a, T>),
 }
 
 impl<T> Deref for LockWriteGuard<'_, T> {
@@ -61,6 +85,8 @@ impl<T> Deref for LockWriteGuard<'_, T> {
         match self {
             LockWriteGuard::Mutex(guard) => guard,
             LockWriteGuard::RwLockWrite(guard) => guard,
+            #[cfg(feature = "parking_lot")]
+            LockWriteGuard::FairRwLockWrite(guard) => guard,
         }
     }
 }
@@ -70,555 +96,4785 @@ impl<T> DerefMut for LockWriteGuard<'_, T> {
         match self {
             LockWriteGuard::Mutex(guard) => &mut *guard,
             LockWriteGuard::RwLockWrite(guard) => &mut *guard,
+            #[cfg(feature = "parking_lot")]
+            LockWriteGuard::FairRwLockWrite(guard) => &mut *guard,
         }
     }
 }
 
-/// Both MutexGuard and RwLockWriteGuard are StableDeref, so enum of those two
-/// is also StableDeref
+/// Both MutexGuard and RwLockWriteGuard (including the `parking_lot` one) are
+/// StableDeref, so enum of those is also StableDeref
 unsafe impl<T> stable_deref_trait::StableDeref for LockWriteGuard<'_, T> {}
 
+thread_local! {
+    /// Addresses of `Mutex` containers locked by coercions on this thread.
+    static HELD_MUTEXES: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+}
+
+/// Marks a `Mutex` container as locked by a coercion on the current thread for
+/// as long as it's alive. Unlike read coercions of a `RwLock`, which may be
+/// freely nested, a second coercion of the same `Mutex` on the same thread
+/// deadlocks. Debug builds use this to panic on such reentrant coercions
+/// instead of blocking, release builds don't track anything.
+struct HeldMutex(usize);
+
+impl HeldMutex {
+    /// Locks `mutex`, panicking first in debug builds if the current thread
+    /// already holds it, as locking it again would deadlock.
+    fn lock<'a, T>(mutex: &'a Mutex<T>, type_name: &str) -> HeldMutexGuard<'a, T> {
+        let addr = mutex as *const Mutex<T> as usize;
+        if Self::is_held(addr) {
+            panic!(
+                "reentrant coercion of a Mutex-backed value (type: {:?}) on the \
+                 same thread would deadlock; use a RwLock-backed DynBox if nested \
+                 reads are needed",
+                type_name
+            );
+        }
+        HeldMutexGuard {
+            guard: mutex.lock().unwrap_or_else(PoisonError::into_inner),
+            _held: Self::acquired(addr),
+        }
+    }
+
+    /// Attempts to lock `mutex` without blocking.
+    fn try_lock<T>(mutex: &Mutex<T>) -> Option<HeldMutexGuard<'_, T>> {
+        let addr = mutex as *const Mutex<T> as usize;
+        try_acquire(mutex.try_lock()).map(|guard| HeldMutexGuard {
+            guard,
+            _held: Self::acquired(addr),
+        })
+    }
+
+    fn acquired(addr: usize) -> Self {
+        if cfg!(debug_assertions) {
+            HELD_MUTEXES.with(|held| held.borrow_mut().insert(addr));
+        }
+        HeldMutex(addr)
+    }
+
+    /// Checks if the `Mutex` at `addr` is locked by a coercion on the current
+    /// thread. Always `false` in release builds.
+    fn is_held(addr: usize) -> bool {
+        cfg!(debug_assertions) && HELD_MUTEXES.with(|held| held.borrow().contains(&addr))
+    }
+}
+
+impl Drop for HeldMutex {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            HELD_MUTEXES.with(|held| held.borrow_mut().remove(&self.0));
+        }
+    }
+}
+
+/// A `MutexGuard` which keeps its `Mutex` marked as held by the current thread.
+struct HeldMutexGuard<'a, T> {
+    guard: MutexGuard<'a, T>,
+    _held: HeldMutex,
+}
+
+impl<T> Deref for HeldMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for HeldMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// The kind of container protecting a value stored in a `DynArc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContainerKind {
+    /// The value is protected by a `Mutex`, every coercion is exclusive.
+    Mutex,
+    /// The value is protected by a `RwLock`, read coercions may be concurrent.
+    RwLock,
+    /// The value is immutable and held by the `Arc` directly, read coercions
+    /// never block and write coercions are not supported.
+    Immutable,
+}
+
+/// A set of container kinds, see `TypeInfo::containers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerSet {
+    mutex: bool,
+    rwlock: bool,
+    immutable: bool,
+}
+
+impl ContainerSet {
+    /// The set of all container kinds.
+    pub const ALL: ContainerSet = ContainerSet {
+        mutex: true,
+        rwlock: true,
+        immutable: true,
+    };
+
+    /// Creates a set out of the given container kinds.
+    pub const fn of(kinds: &[ContainerKind]) -> Self {
+        let mut set = ContainerSet {
+            mutex: false,
+            rwlock: false,
+            immutable: false,
+        };
+        let mut i = 0;
+        while i < kinds.len() {
+            match kinds[i] {
+                ContainerKind::Mutex => set.mutex = true,
+                ContainerKind::RwLock => set.rwlock = true,
+                ContainerKind::Immutable => set.immutable = true,
+            }
+            i += 1;
+        }
+        set
+    }
+
+    /// Checks if the set contains the given container kind.
+    pub fn contains(&self, kind: ContainerKind) -> bool {
+        match kind {
+            ContainerKind::Mutex => self.mutex,
+            ContainerKind::RwLock => self.rwlock,
+            ContainerKind::Immutable => self.immutable,
+        }
+    }
+
+    /// Checks if the set contains a container which supports write
+    /// coercions, i.e. a `Mutex` or a `RwLock`.
+    pub fn supports_mut(&self) -> bool {
+        self.mutex || self.rwlock
+    }
+}
+
+/// The bound of the polymorphic variant in the OCaml type of a registered
+/// type, see `TypeInfo::variant_kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VariantKind {
+    /// `[> tags ]`, stubs taking the type also accept objects tagged with
+    /// more variants, e.g. a narrower type declared as a subtype on OCaml side.
+    #[default]
+    Open,
+    /// `[ tags ]`, stubs taking the type only accept objects tagged with
+    /// exactly the variants of the type.
+    Closed,
+}
+
 /// A type alias for an `Arc` containing a dynamically typed value that is both
 /// `Sync` and `Send`. This is used to store values in the registry.
 type DynArc = Arc<dyn Any + Sync + Send>;
 
+/// A type alias for a weak reference to the value of a `DynArc`. A `DynArc`
+/// holding a `DynWeak` (see `downgrade`) coerces like the `DynArc` it refers
+/// to, as long as the value is alive.
+type DynWeak = Weak<dyn Any + Sync + Send>;
+
 /// Type alias for a function that takes a `DynArc` and returns a boxed `dyn Any`.
 /// This is used for type coercion in the registry.
 type CoercionInAny = Arc<dyn Fn(DynArc) -> Box<dyn Any> + Sync + Send>;
 
+/// A type alias for an `Rc` containing a dynamically typed value, which is
+/// neither `Sync` nor `Send`. This is used to store values of `LocalDynBox`,
+/// which are always held in a `RefCell`.
+type LocalRc = Rc<dyn Any>;
+
+/// Type alias for a function that takes a `LocalRc` and returns a boxed
+/// `dyn Any`. This is used for type coercion of `LocalDynBox` values.
+type LocalCoercionInAny = Arc<dyn Fn(LocalRc) -> Box<dyn Any> + Sync + Send>;
+
+/// Type alias for a function that reports the memory held by the value in a
+/// container, or `None` if the container is locked for writing.
+type MemSizeFn = Arc<dyn Fn(&(dyn Any + Sync + Send)) -> Option<usize> + Sync + Send>;
+
+/// Type alias for a function that renders the value in a container as a
+/// string, see `register_show`.
+type ShowFn = Arc<dyn Fn(&(dyn Any + Sync + Send)) -> String + Sync + Send>;
+
+/// Type alias for a function that checks if a container is locked, see
+/// `probe_locked`.
+type LockProbeFn = fn(&(dyn Any + Sync + Send)) -> bool;
+
+/// Type alias for a function that composes the coercion functions of a value
+/// to some type `In` with the conversion of `In` to `Out`, for newtypes which
+/// are transparent over `In` (see `register_transparent`) and projections to
+/// `In` (see `project`). Takes the name of the value for error messages.
+type DelegateFn = Arc<dyn Fn(&CoercionFns, &str) -> CoercionFns + Sync + Send>;
+
+/// A newtype registered as transparent over the type it wraps, see
+/// `register_transparent`.
+#[derive(Clone)]
+struct TransparentType {
+    /// Name of the newtype, for error messages.
+    name: String,
+    /// `TypeId`s of the containers which may hold the newtype.
+    containers: Vec<TypeId>,
+    /// Coercion functions of the newtype to the wrapped type, which the
+    /// delegated coercions start with.
+    inner: CoercionFns,
+}
+
+/// Contents of a `DynArc` created by `project`, which coerces to a part of the
+/// value of its parent. The parent is held by the projection, so the value
+/// stays alive as long as the projection does, and coercing the projection
+/// takes the lock of the parent.
+struct Projection {
+    /// The `DynArc` the part is projected out of.
+    parent: DynArc,
+    /// `TypeId` of the part, the only type the projection coerces to.
+    part: TypeId,
+    /// Name of the part, for error messages.
+    part_name: &'static str,
+    /// Coercion functions of the parent to its type, composed with the
+    /// projection, see `projection_fns`.
+    fns: CoercionFns,
+    /// Coercion functions of the part to the other types it's registered to
+    /// be coerced to, composed with `fns` on the first coercion to any of
+    /// them, see `Registry::projection_delegates`.
+    delegated: OnceLock<HashMap<TypeId, CoercionFns>>,
+}
+
+/// A type which holds memory outside of the OCaml heap, e.g. a large buffer.
+/// Once registered with `register_mem_sized`, `DynBox`es of the type report it
+/// to the OCaml GC when they are passed to OCaml, so that the GC speeds up
+/// accordingly instead of letting such objects pile up.
+pub trait MemSized {
+    /// Returns the amount of memory held by the value, in bytes.
+    fn mem_size(&self) -> usize;
+}
+
+/// Type alias for a type-erased `BorrowedCoercion<Out>`, downcasted back to
+/// the concrete type by `Out` at the call site.
+type CoercionRefAny = Arc<dyn Any + Sync + Send>;
+
+/// Type alias for a function that borrows the contents of a `DynArc` and
+/// returns a `BorrowedHandle` tied to the lifetime of that borrow.
+type BorrowedCoercion<Out> = Arc<
+    dyn for<'a> Fn(&'a (dyn Any + Sync + Send)) -> BorrowedHandle<'a, Out> + Sync + Send,
+>;
+
 /// A type alias for a handle to a read-only reference of type `Out`.
-/// This is used to represent coerced values in the registry.
+/// This is used to represent coerced values in the registry. Besides `Deref`,
+/// it implements `AsRef<Out>` and `Borrow<Out>`, along with `Eq`, `Ord` and
+/// `Hash` of `Out`, so handles may be passed to generic APIs expecting those,
+/// e.g. used as keys of a `HashMap` looked up by `&Out`.
 pub type Handle<Out> = ErasedBoxRef<Out>; // Holds a lock on DynArc
 
 /// A type alias for a handle to a mutable reference of type `Out`.
-/// This is used to represent coerced mutable values in the registry.
+/// This is used to represent coerced mutable values in the registry. It
+/// implements `AsRef<Out>` and `AsMut<Out>`, but not `Borrow<Out>`.
 pub type HandleMut<Out> = ErasedBoxRefMut<Out>; // Holds a lock on DynArc
 
-/// A struct representing type information, including the fully qualified name
-/// and a list of implementations.
-#[derive(Clone)]
-pub struct TypeInfo {
-    pub fq_name: &'static str,
-    pub implementations: Vec<&'static str>,
+/// A type alias for a handle to a read-only reference of type `Out`, which
+/// borrows the `DynArc` instead of owning a clone of it. It implements the same
+/// traits as `Handle`.
+pub type BorrowedHandle<'a, Out> = OwningRef<Box<dyn Erased + 'a>, Out>; // Holds a lock on borrowed DynArc
+
+/// Scoped access to the value behind a `Handle` or a `BorrowedHandle`.
+pub trait HandleExt<Out: ?Sized> {
+    /// Passes the coerced value to `f`, and drops the handle (releasing the
+    /// lock) before returning, so that the lock is held no longer than needed.
+    ///
+    /// # Returns
+    ///
+    /// The result of `f`.
+    fn with<R>(self, f: impl FnOnce(&Out) -> R) -> R;
 }
 
-/// The `Registry` struct holds mappings for type coercions and type information.
-/// It allows registering coercion functions for converting between types and
-/// retrieving type information.
-#[derive(Default)]
-struct Registry {
-    traits: HashMap<(TypeId, TypeId), (CoercionInAny, CoercionInAny)>,
-    types: HashMap<TypeId, String>,
-    type_info_map: HashMap<TypeId, TypeInfo>,
+/// Covers `Handle` as well, which is a `BorrowedHandle<'static, Out>`.
+impl<Out: ?Sized> HandleExt<Out> for BorrowedHandle<'_, Out> {
+    fn with<R>(self, f: impl FnOnce(&Out) -> R) -> R {
+        let result = f(&*self);
+        drop(self);
+        result
+    }
 }
 
-impl Registry {
-    /// Creates a new `Registry` instance.
+/// Scoped access to the value behind a `HandleMut`.
+pub trait HandleMutExt<Out: ?Sized> {
+    /// Passes the coerced value to `f` mutably, and drops the handle
+    /// (releasing the lock) before returning, so that the lock is held no
+    /// longer than needed.
     ///
     /// # Returns
     ///
-    /// A new `Registry` instance.
-    fn new() -> Self {
-        Self::default()
+    /// The result of `f`.
+    fn with_mut<R>(self, f: impl FnOnce(&mut Out) -> R) -> R;
+}
+
+impl<Out: ?Sized> HandleMutExt<Out> for HandleMut<Out> {
+    fn with_mut<R>(mut self, f: impl FnOnce(&mut Out) -> R) -> R {
+        let result = f(&mut *self);
+        drop(self);
+        result
     }
+}
 
-    /// Registers coercion functions for converting between types `In` and `Out`.
-    ///
-    /// # Parameters
-    ///
-    /// - `fs`: A tuple containing two `CoercionInAny` functions for read and write coercions.
-    fn register_coercion_fns<In: Sized + 'static, Out: ?Sized + 'static>(
-        &mut self,
-        fs: (CoercionInAny, CoercionInAny),
-    ) {
-        self.traits
-            .insert((TypeId::of::<In>(), TypeId::of::<Out>()), fs);
+/// A read handle which can be atomically upgraded to a `HandleMut`, obtained
+/// with `coerce_upgradable`. Other readers may hold the lock along with it, but
+/// writers and other upgradable handles can't, so nothing else can modify the
+/// value between the read and the upgrade. Only available with `parking_lot`
+/// feature.
+#[cfg(feature = "parking_lot")]
+pub struct UpgradableHandle<Out: ?Sized + 'static>(Box<dyn UpgradableGuard<Out>>);
+
+#[cfg(feature = "parking_lot")]
+impl<Out: ?Sized + 'static> UpgradableHandle<Out> {
+    /// Upgrades the handle to a write handle, blocking until all other readers
+    /// release the lock.
+    pub fn upgrade(self) -> HandleMut<Out> {
+        self.0.upgrade()
     }
+}
 
-    /// Registers a type in the registry.
-    ///
-    /// # Parameters
-    ///
-    /// - `In`: The trait object type to register.
-    /// - `InReal`: The real type that implements the trait.
-    fn register_type<In: ?Sized + 'static, InReal: ?Sized>(&mut self) {
-        self.types
-            .insert(TypeId::of::<In>(), std::any::type_name::<InReal>().into());
+#[cfg(feature = "parking_lot")]
+impl<Out: ?Sized + 'static> Deref for UpgradableHandle<Out> {
+    type Target = Out;
+
+    fn deref(&self) -> &Out {
+        self.0.get()
     }
+}
 
-    /// Registers type information in the registry.
-    ///
-    /// # Parameters
-    ///
-    /// - `In`: The trait object type to register.
-    /// - `fq_name`: The fully qualified name of the type.
-    /// - `impls`: A vector of strings representing the implementations of the type.
-    fn register_type_info<In: ?Sized + 'static>(
-        &mut self,
-        fq_name: &'static str,
-        impls: Vec<&'static str>,
-    ) {
-        self.type_info_map.insert(
-            TypeId::of::<In>(),
-            TypeInfo {
-                fq_name,
-                implementations: impls,
-            },
-        );
+#[cfg(feature = "parking_lot")]
+impl<Out: ?Sized + 'static> AsRef<Out> for UpgradableHandle<Out> {
+    fn as_ref(&self) -> &Out {
+        self.0.get()
     }
+}
 
-    /// Registers coercion functions for converting between types `In` and `Out`.
-    ///
-    /// # Parameters
-    ///
-    /// - `conv`: A function pointer for read coercion.
-    /// - `conv_mut`: A function pointer for write coercion.
-    fn register<In: Sized + 'static, Out: ?Sized + 'static>(
-        &mut self,
-        conv: fn(&In) -> &Out,
-        conv_mut: fn(&mut In) -> &mut Out,
-    ) {
-        // Retrieve the type name for the input type.
-        let type_in_name = String::from(self.type_name(&TypeId::of::<In>()));
-        // Clone the type name for use in the mutable coercion function.
-        let type_in_name_mut = type_in_name.clone();
-        // Create the read coercion function.
-        let f: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
-            let ohandle = OwningHandle::new_with_fn(boxed_t, |bt| {
-                let any = unsafe { bt.as_ref() }.unwrap();
-                let guard = if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
-                    LockReadGuard::Mutex(mutex.lock().unwrap())
-                } else if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
-                    LockReadGuard::RwLockRead(rwlock.read().unwrap())
-                } else {
-                    panic!(
-                        "unsupported container provided for coersion (type: {:?})",
-                        type_in_name
-                    );
-                };
-                OwningRef::new(guard).map(conv)
-            });
-            Box::new(OwningRef::new(ohandle).map_owner_box().erase_owner())
-        });
-        // Create the write coercion function.
-        let f_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
-            let ohandle = OwningHandle::new_with_fn(boxed_t, |bt| {
-                let any = unsafe { bt.as_ref() }.unwrap();
-                let guard = if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
-                    LockWriteGuard::Mutex(mutex.lock().unwrap())
-                } else if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
-                    LockWriteGuard::RwLockWrite(rwlock.write().unwrap())
-                } else {
-                    panic!(
-                        "unsupported container provided for mut coersion (type: {:?})",
-                        type_in_name_mut
-                    );
-                };
-                OwningRefMut::new(guard).map_mut(conv_mut)
-            });
-            Box::new(OwningRefMut::new(ohandle).map_owner_box().erase_owner())
-        });
-        // Clone the coercion functions for registration.
-        let clone = || (f.clone(), f_mut.clone());
-        // Register the coercion functions for `Mutex<In>` to `Out`.
-        self.register_coercion_fns::<Mutex<In>, Out>(clone());
-        // Register the coercion functions for `RwLock<In>` to `Out`.
-        self.register_coercion_fns::<RwLock<In>, Out>(clone());
+#[cfg(feature = "parking_lot")]
+impl<Out: ?Sized + 'static> std::borrow::Borrow<Out> for UpgradableHandle<Out> {
+    fn borrow(&self) -> &Out {
+        self.0.get()
     }
+}
 
-    /// Retrieves the coercion functions for a given output type.
-    ///
-    /// # Parameters
-    ///
-    /// - `input`: A reference to a `DynArc` input.
-    ///
-    /// # Returns
-    ///
-    /// A tuple containing two `CoercionInAny` functions for read and write coercions.
-    fn get_coerce_fns<Out: ?Sized + 'static>(
-        &self,
-        input: &DynArc,
-    ) -> &(CoercionInAny, CoercionInAny) {
-        // Retrieve the `TypeId` of the input type.
-        // `**` is for: &Arc<dyn Any> -> Arc<dyn Any> -> dyn Any
-        let type_in = (**input).type_id();
-        // Retrieve the `TypeId` of the output type.
-        let type_out = TypeId::of::<Out>();
-        // Retrieve the type name for the input type.
-        let type_in_name = self.type_name(&type_in);
-        // Retrieve the coercion functions from the registry.
-        self.traits.get(&(type_in, type_out)).unwrap_or_else(|| {
-            panic!(
-                "there is no registered coercion for {:?} => {:?}",
-                type_in_name,
-                std::any::type_name::<Out>()
-            )
-        })
+/// Type-erased upgradable read guard of a `parking_lot::RwLock<In>`, along
+/// with the conversions of `In` to `Out`.
+#[cfg(feature = "parking_lot")]
+trait UpgradableGuard<Out: ?Sized> {
+    fn get(&self) -> &Out;
+    fn upgrade(self: Box<Self>) -> HandleMut<Out>;
+}
+
+#[cfg(feature = "parking_lot")]
+struct Upgradable<In: 'static, F, FMut> {
+    guard: parking_lot::ArcRwLockUpgradableReadGuard<parking_lot::RawRwLock, In>,
+    conv: F,
+    conv_mut: FMut,
+    type_in_name: String,
+}
+
+/// Write guard of an upgraded `Upgradable`, which owns a clone of the `Arc`
+/// holding the lock.
+#[cfg(feature = "parking_lot")]
+struct UpgradedGuard<In: 'static>(
+    parking_lot::ArcRwLockWriteGuard<parking_lot::RawRwLock, In>,
+);
+
+#[cfg(feature = "parking_lot")]
+impl<In> Deref for UpgradedGuard<In> {
+    type Target = In;
+
+    fn deref(&self) -> &In {
+        &self.0
     }
+}
 
-    /// Retrieves the type name for a given `TypeId`.
-    ///
-    /// # Parameters
-    ///
-    /// - `type_in`: A reference to a `TypeId`.
-    ///
-    /// # Returns
-    ///
-    /// A string slice representing the type name.
-    fn type_name(&self, type_in: &TypeId) -> &str {
-        // Retrieve the type name from the registry.
-        match self.types.get(type_in) {
-            Some(name) => name.as_str(),
-            None => "<unregistered type>",
+#[cfg(feature = "parking_lot")]
+impl<In> DerefMut for UpgradedGuard<In> {
+    fn deref_mut(&mut self) -> &mut In {
+        &mut self.0
+    }
+}
+
+/// The guarded value lives in the `Arc` owned by the guard, so it doesn't move
+/// along with the guard
+#[cfg(feature = "parking_lot")]
+unsafe impl<In> stable_deref_trait::StableDeref for UpgradedGuard<In> {}
+
+#[cfg(feature = "parking_lot")]
+impl<In, Out, F, FMut> UpgradableGuard<Out> for Upgradable<In, F, FMut>
+where
+    In: 'static,
+    Out: ?Sized + 'static,
+    F: for<'a> Fn(&'a In) -> Option<&'a Out>,
+    FMut: for<'a> Fn(&'a mut In) -> Option<&'a mut Out>,
+{
+    fn get(&self) -> &Out {
+        // The value is not modified while the guard is held, so a conversion
+        // which succeeded in `coerce_upgradable` keeps succeeding
+        (self.conv)(&self.guard).expect("upgradable coercion stopped converting")
+    }
+
+    fn upgrade(self: Box<Self>) -> HandleMut<Out> {
+        let Upgradable {
+            guard,
+            conv_mut,
+            type_in_name,
+            ..
+        } = *self;
+        let guard =
+            UpgradedGuard(parking_lot::ArcRwLockUpgradableReadGuard::upgrade(guard));
+        OwningRefMut::new(guard)
+            .try_map_mut(|x| conv_mut(x).ok_or(()))
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name))
+            .map_owner_box()
+            .erase_owner()
+    }
+}
+
+/// Creates the upgradable read coercion function, which returns a boxed
+/// `UpgradableHandle<Out>`. Only `parking_lot::RwLock` containers support it.
+#[cfg(feature = "parking_lot")]
+fn upgradable_coercion<In, Out, F, FMut>(
+    type_in_name: String,
+    conv: F,
+    conv_mut: FMut,
+) -> CoercionInAny
+where
+    In: Sized + 'static,
+    Out: ?Sized + 'static,
+    F: for<'a> Fn(&'a In) -> Option<&'a Out> + Clone + Send + Sync + 'static,
+    FMut: for<'a> Fn(&'a mut In) -> Option<&'a mut Out> + Clone + Send + Sync + 'static,
+{
+    Arc::new(move |boxed_t: DynArc| {
+        if !boxed_t.is::<parking_lot::RwLock<In>>() {
+            coercion_panic(format!(
+                "upgradable coercion is only supported for parking_lot::RwLock \
+                 containers (type: {:?}), use `LockPolicy::Fair`",
+                type_in_name
+            ));
+        }
+        // Same as `Arc::downcast`, which can't be used as `In` is not
+        // necessarily `Send + Sync`. The type was checked just above.
+        let rwlock = unsafe {
+            Arc::from_raw(Arc::into_raw(boxed_t) as *const parking_lot::RwLock<In>)
+        };
+        let guard = rwlock.upgradable_read_arc();
+        if conv(&guard).is_none() {
+            // Release the lock before panicking
+            drop(guard);
+            conversion_failed::<Out>(&type_in_name);
         }
+        let handle = UpgradableHandle::<Out>(Box::new(Upgradable {
+            guard,
+            conv: conv.clone(),
+            conv_mut: conv_mut.clone(),
+            type_in_name: type_in_name.clone(),
+        }));
+        Box::new(handle)
+    })
+}
+
+/// Upgradable handle of the value wrapped by a transparent newtype, along with
+/// the conversions of the wrapped value `In` to `Out`.
+#[cfg(feature = "parking_lot")]
+struct DelegatedUpgradable<In: 'static, F, FMut> {
+    handle: UpgradableHandle<In>,
+    conv: F,
+    conv_mut: FMut,
+    type_in_name: String,
+}
+
+#[cfg(feature = "parking_lot")]
+impl<In, Out, F, FMut> UpgradableGuard<Out> for DelegatedUpgradable<In, F, FMut>
+where
+    In: 'static,
+    Out: ?Sized + 'static,
+    F: for<'a> Fn(&'a In) -> Option<&'a Out>,
+    FMut: for<'a> Fn(&'a mut In) -> Option<&'a mut Out>,
+{
+    fn get(&self) -> &Out {
+        (self.conv)(&self.handle).expect("upgradable coercion stopped converting")
     }
 
-    /// Coerces a `DynArc` input to a handle of the specified output type.
-    ///
-    /// # Parameters
-    ///
-    /// - `input`: A `DynArc` input.
-    ///
-    /// # Returns
-    ///
-    /// A handle to the coerced output type.
-    fn coerce<Out: ?Sized + 'static>(&self, input: DynArc) -> Handle<Out> {
-        // Retrieve the read coercion function.
-        let (f, _) = self.get_coerce_fns::<Out>(&input);
-        // Coerce the input to the output type.
-        // Coerce the input to the mutable output type.
-        *f(input.clone())
-            .downcast()
-            .expect("coercion fn returned wrong type")
+    fn upgrade(self: Box<Self>) -> HandleMut<Out> {
+        let DelegatedUpgradable {
+            handle,
+            conv_mut,
+            type_in_name,
+            ..
+        } = *self;
+        handle
+            .upgrade()
+            .try_map_mut(|x| conv_mut(x).ok_or(()))
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name))
     }
+}
 
-    /// Coerces a `DynArc` input to a mutable handle of the specified output type.
+/// Upgradable handle of the parent of a projection, along with the projection
+/// of its value `In` to the part `Out`, see `project`.
+#[cfg(feature = "parking_lot")]
+struct ProjectedUpgradable<In: ?Sized + 'static, Out: ?Sized + 'static> {
+    handle: UpgradableHandle<In>,
+    get: fn(&In) -> &Out,
+    get_mut: fn(&mut In) -> &mut Out,
+}
+
+#[cfg(feature = "parking_lot")]
+impl<In: ?Sized + 'static, Out: ?Sized + 'static> UpgradableGuard<Out>
+    for ProjectedUpgradable<In, Out>
+{
+    fn get(&self) -> &Out {
+        (self.get)(&self.handle)
+    }
+
+    fn upgrade(self: Box<Self>) -> HandleMut<Out> {
+        let ProjectedUpgradable {
+            handle, get_mut, ..
+        } = *self;
+        handle.upgrade().map_mut(get_mut)
+    }
+}
+
+/// Coercion of `In` to some type `Out`, registered along with coercions of
+/// `In` to other types in one go with `register_multi`, which takes them as
+/// `RegisterCoercion` trait objects so that `Out` is erased without boxing.
+pub struct Coercion<In: 'static, Out: ?Sized + 'static> {
+    conv: fn(&In) -> &Out,
+    conv_mut: fn(&mut In) -> &mut Out,
+}
+
+impl<In: Sized + 'static, Out: ?Sized + 'static> Coercion<In, Out> {
+    /// Creates a coercion of `In` to `Out`, same as the one registered by
+    /// `register`.
     ///
     /// # Parameters
     ///
-    /// - `input`: A `DynArc` input.
-    ///
-    /// # Returns
-    ///
-    /// A mutable handle to the coerced output type.
-    fn coerce_mut<Out: ?Sized + 'static>(&self, input: DynArc) -> HandleMut<Out> {
-        // Retrieve the write coercion function.
-        let (_, f) = self.get_coerce_fns::<Out>(&input);
-        *f(input.clone())
-            .downcast()
-            .expect("coercion fn returned wrong type")
+    /// - `conv`: A function pointer for read coercion.
+    /// - `conv_mut`: A function pointer for write coercion.
+    pub fn new(conv: fn(&In) -> &Out, conv_mut: fn(&mut In) -> &mut Out) -> Self {
+        Coercion { conv, conv_mut }
     }
+}
 
-    /// Retrieves the type information for a given input type.
-    ///
-    /// # Parameters
-    ///
-    /// - `In`: The trait object type to retrieve information for.
-    ///
-    /// # Returns
-    ///
-    /// A `TypeInfo` struct containing the type information.
-    fn get_type_info<In: ?Sized + 'static>(&self) -> TypeInfo {
-        // Retrieve the `TypeId` of the input type.
-        let type_id = TypeId::of::<In>();
-        // Retrieve the type information from the registry.
-        let type_info = self.type_info_map.get(&type_id).unwrap_or_else(|| {
-            panic!(
-                "registry does not contain a registered type info for {}",
-                std::any::type_name::<In>()
-            )
-        });
-        type_info.clone()
+// Sealed, the trait is only nameable here, so `Registry` never leaks out
+#[allow(private_interfaces)]
+mod private {
+    use super::{trace, Coercion, Registry};
+
+    /// Registration of a `Coercion` of `In`, with its output type erased.
+    pub trait RegisterCoercion<In> {
+        fn register(&self, registry: &mut Registry);
+    }
+
+    impl<In: Sized + 'static, Out: ?Sized + 'static> RegisterCoercion<In>
+        for Coercion<In, Out>
+    {
+        fn register(&self, registry: &mut Registry) {
+            trace!(
+                "register coercion {} => {}",
+                std::any::type_name::<In>(),
+                std::any::type_name::<Out>()
+            );
+            registry.register_conversion::<In, Out>(self.conv, self.conv_mut)
+        }
     }
 }
 
-/// Returns a reference to the global registry.
+use private::RegisterCoercion;
+
+/// Set of coercion functions registered for a single `(In, Out)` pair.
+#[derive(Clone)]
+struct CoercionFns {
+    /// Read coercion, returns a boxed `Handle<Out>`.
+    coerce: CoercionInAny,
+    /// Write coercion, returns a boxed `HandleMut<Out>`.
+    coerce_mut: CoercionInAny,
+    /// Non-blocking read coercion, returns a boxed `Option<Handle<Out>>`.
+    try_coerce: CoercionInAny,
+    /// Non-blocking write coercion, returns a boxed `Option<HandleMut<Out>>`.
+    try_coerce_mut: CoercionInAny,
+    /// Borrowing read coercion, holds a `BorrowedCoercion<Out>`.
+    coerce_ref: CoercionRefAny,
+    /// Upgradable read coercion, returns a boxed `UpgradableHandle<Out>`.
+    #[cfg(feature = "parking_lot")]
+    coerce_upgradable: CoercionInAny,
+}
+
+/// Set of coercion functions registered for a single `(RefCell<In>, Out)`
+/// pair, see `LocalRc`.
+#[derive(Clone)]
+struct LocalCoercionFns {
+    /// Read coercion, borrows the `RefCell` and returns a boxed `Handle<Out>`.
+    coerce: LocalCoercionInAny,
+    /// Write coercion, borrows the `RefCell` mutably and returns a boxed
+    /// `HandleMut<Out>`.
+    coerce_mut: LocalCoercionInAny,
+}
+
+/// Structural equality of type-erased values, implemented for any
+/// `PartialEq` type. Once registered with `register_eq`, `dyn_eq` compares
+/// values of the type with `==` instead of comparing their addresses.
+pub trait DynEq {
+    /// Checks if `other` is a value of the same type which is equal to this one.
+    fn dyn_eq(&self, other: &dyn Any) -> bool;
+}
+
+impl<T: PartialEq + 'static> DynEq for T {
+    fn dyn_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<T>().is_some_and(|other| self == other)
+    }
+}
+
+/// Total ordering of type-erased values, implemented for any `Ord` type. Once
+/// registered with `register_ord`, `dyn_compare` orders values of the type
+/// with `Ord::cmp` instead of ordering them by their addresses.
+pub trait DynOrd {
+    /// Compares this value with `other`, or returns `None` if `other` is a
+    /// value of another type.
+    fn dyn_cmp(&self, other: &dyn Any) -> Option<std::cmp::Ordering>;
+}
+
+impl<T: Ord + 'static> DynOrd for T {
+    fn dyn_cmp(&self, other: &dyn Any) -> Option<std::cmp::Ordering> {
+        other.downcast_ref::<T>().map(|other| self.cmp(other))
+    }
+}
+
+/// Helper to pin down the higher-ranked signature of a borrowing coercion
+/// closure, which can't be inferred from the closure alone.
+fn borrowed_coercion<Out: ?Sized + 'static, F>(f: F) -> BorrowedCoercion<Out>
+where
+    F: for<'a> Fn(&'a (dyn Any + Sync + Send)) -> BorrowedHandle<'a, Out>
+        + Sync
+        + Send
+        + 'static,
+{
+    Arc::new(f)
+}
+
+/// Counters of the registry activity, see `stats`. They are global rather
+/// than per `Registry`, so that they survive `with_registry` and `restore`.
+#[cfg(feature = "metrics")]
+struct Counters {
+    coercions: std::sync::atomic::AtomicU64,
+    failures: std::sync::atomic::AtomicU64,
+    registrations: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(feature = "metrics")]
+static COUNTERS: Counters = Counters {
+    coercions: std::sync::atomic::AtomicU64::new(0),
+    failures: std::sync::atomic::AtomicU64::new(0),
+    registrations: std::sync::atomic::AtomicU64::new(0),
+};
+
+/// Increments the counter `$counter` of `COUNTERS` with `metrics` feature,
+/// and compiles to nothing otherwise.
+macro_rules! count {
+    ($counter:ident) => {
+        #[cfg(feature = "metrics")]
+        COUNTERS.$counter.fetch_add(1, Ordering::Relaxed);
+    };
+}
+
+/// Snapshot of the counters of the registry activity, see `stats`.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RegistryStats {
+    /// Coercions performed, including the failed ones and the non-blocking
+    /// ones which found the lock held.
+    pub coercions: u64,
+    /// Coercions which failed, i.e. panicked or raised `Coercion_error`.
+    pub failures: u64,
+    /// Coercions registered, including the implicit ones to `dyn Any` and
+    /// the ones delegated to transparent newtypes.
+    pub registrations: u64,
+}
+
+/// Tells what failed coercions do, e.g. the ones of values which have no
+/// registered coercion to the requested type, see `set_panic_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// Failed coercions panic, which aborts the process if the panic reaches
+    /// the stub. Meant for development, where a missing registration is a bug
+    /// to be fixed right away.
+    #[default]
+    Panic,
+    /// Failed coercions within `catch_coercion_error`, e.g. in stubs defined
+    /// with `#[smartptr_func]`, raise
+    /// `Ocaml_rs_smartptr.Registry.Coercion_error` with the message the panic
+    /// would have once the stub unwinds. Others still panic.
+    RaiseOcaml,
+}
+
+/// Whether `PanicPolicy::RaiseOcaml` is in effect.
+static RAISE_OCAML: AtomicBool = AtomicBool::new(false);
+
+/// Name of the OCaml exception raised under `PanicPolicy::RaiseOcaml`, as
+/// registered with `Callback.register_exception` by the `Registry` module.
+const COERCION_ERROR: &str = "Ocaml_rs_smartptr.Coercion_error";
+
+/// A coercion failed under `PanicPolicy::RaiseOcaml`, as returned by
+/// `catch_coercion_error`. Converts to an `ocaml::Error` raising
+/// `Ocaml_rs_smartptr.Registry.Coercion_error` with the message, so stubs
+/// returning `Result<_, ocaml::Error>` may propagate it with `?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoercionError(String);
+
+impl CoercionError {
+    /// Returns the message the coercion would have panicked with.
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for CoercionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CoercionError {}
+
+impl From<CoercionError> for ocaml::Error {
+    /// Looks the exception up in the OCaml runtime, so it must be called with
+    /// the domain lock held, e.g. at the end of the stub.
+    fn from(err: CoercionError) -> Self {
+        ocaml::Error::raise_with_arg(COERCION_ERROR, err.0)
+            .expect_err("raise_with_arg always returns an error")
+    }
+}
+
+thread_local! {
+    /// Whether coercion failures on this thread are caught by
+    /// `catch_coercion_error`.
+    static RAISING_COERCION: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Fails a coercion with `message`, according to the panic policy.
+fn coercion_panic(message: String) -> ! {
+    count!(failures);
+    if RAISING_COERCION.with(Cell::get) {
+        // Unlike `panic!`, doesn't run the panic hook, as the message is
+        // returned by `catch_coercion_error`
+        std::panic::resume_unwind(Box::new(CoercionError(message)));
+    }
+    panic!("{}", message)
+}
+
+/// Runs `f`, returning the failure of a coercion within it as an error under
+/// `PanicPolicy::RaiseOcaml`, see `set_panic_policy`. The failure unwinds `f`
+/// like a panic does, so the locks taken and the values owned by `f` are
+/// released by the time the error is returned. Locks poisoned on the way are
+/// recovered by later coercions. Other panics are passed
+/// through, and under `PanicPolicy::Panic` failed coercions panic as usual.
 ///
 /// # Returns
 ///
-/// A reference to the global `RwLock<Registry>`.
-fn global_registry() -> &'static RwLock<Registry> {
-    // Initialize the global registry.
-    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
-    REGISTRY.get_or_init(|| RwLock::new(Registry::new()))
+/// The result of `f`, or the error of the coercion which failed.
+pub fn catch_coercion_error<R>(f: impl FnOnce() -> R) -> Result<R, CoercionError> {
+    if panic_policy() == PanicPolicy::Panic {
+        return Ok(f());
+    }
+    let outer = RAISING_COERCION.with(|raising| raising.replace(true));
+    let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+    RAISING_COERCION.with(|raising| raising.set(outer));
+    result.map_err(|payload| match payload.downcast::<CoercionError>() {
+        Ok(err) => *err,
+        Err(payload) => std::panic::resume_unwind(payload),
+    })
 }
 
-/// Registers coercion functions for converting between types `In` and `Out` in the global registry.
+/// Runs the body `f` of a stub, and raises `Coercion_error` if a coercion
+/// within it fails under `PanicPolicy::RaiseOcaml`, see
+/// `catch_coercion_error`. `#[smartptr_func]` and the stubs generated by
+/// `OcamlAccessors` wrap their bodies with it. `f` should own the arguments of
+/// the stub, as raising skips the frame of the stub itself.
 ///
-/// # Parameters
+/// # Returns
 ///
-/// - `conv`: A function pointer for read coercion.
-/// - `conv_mut`: A function pointer for write coercion.
-pub fn register<In: Sized + 'static, Out: ?Sized + 'static>(
-    conv: fn(&In) -> &Out,
-    conv_mut: fn(&mut In) -> &mut Out,
-) {
-    // Obtain a write lock on the global registry.
-    let mut registry = global_registry()
-        .write()
-        .expect("unable to obtain write lock on global registry");
-    registry.register::<In, Out>(conv, conv_mut)
+/// The result of `f`.
+pub fn raise_coercion_errors<R>(gc: &ocaml::Runtime, f: impl FnOnce() -> R) -> R {
+    match catch_coercion_error(f) {
+        Ok(result) => result,
+        Err(err) => {
+            // Converting an error to OCaml raises it, same as returning it
+            // from a stub
+            ocaml::ToValue::to_value(&Err::<(), _>(ocaml::Error::from(err)), gc);
+            unreachable!("OCaml exception {} was not raised", COERCION_ERROR)
+        }
+    }
 }
 
-/// Registers a type in the global registry.
-///
-/// # Parameters
-///
-/// - `In`: The trait object type to register.
-pub fn register_type<In: ?Sized + 'static>() {
-    let mut registry = global_registry()
-        .write()
-        .expect("unable to obtain write lock on global registry");
-    registry.register_type::<In, In>();
-    registry.register_type::<Mutex<In>, In>();
-    registry.register_type::<RwLock<In>, In>();
+/// Fails a fallible coercion whose conversion yielded `None`, see
+/// `register_fallible`.
+fn conversion_failed<Out: ?Sized>(type_in_name: &str) -> ! {
+    coercion_panic(format!(
+        "fallible coercion of {:?} to {:?} failed",
+        type_in_name,
+        std::any::type_name::<Out>()
+    ))
 }
 
-/// Registers type information in the global registry.
-///
-/// # Parameters
-///
-/// - `In`: The trait object type to register.
-/// - `fq_name`: The fully qualified name of the type.
-/// - `impls`: A vector of strings representing the implementations of the type.
-pub fn register_type_info<In: ?Sized + 'static>(
-    fq_name: &'static str,
-    impls: Vec<&'static str>,
-) {
-    let mut registry = global_registry()
-        .write()
-        .expect("unable to obtain write lock on global registry");
-    registry.register_type_info::<In>(fq_name, impls);
+/// Upgrades a `DynArc` input holding a `DynWeak` to the `DynArc` it refers
+/// to, other inputs are returned as is. Returns `None` if the value behind
+/// the weak reference was dropped already.
+fn upgrade_weak(input: DynArc) -> Option<DynArc> {
+    match (*input).downcast_ref::<DynWeak>() {
+        Some(weak) => weak.upgrade(),
+        None => Some(input),
+    }
 }
 
-/// Coerces a `DynArc` input to a handle of the specified output type using the global registry.
+/// Same as `upgrade_weak`, but fails the coercion to `Out` if the value
+/// behind the weak reference was dropped already.
+fn upgrade_weak_or_fail<Out: ?Sized>(input: DynArc) -> DynArc {
+    upgrade_weak(input).unwrap_or_else(|| {
+        coercion_panic(format!(
+            "the value behind a weak reference to be coerced to {:?} was dropped",
+            std::any::type_name::<Out>()
+        ))
+    })
+}
+
+/// Resolves a `DynArc` input holding a `Projection` to the `DynArc` the value
+/// is ultimately projected out of, which holds the lock shared by the
+/// projections. Other inputs are returned as is.
+fn projection_root(mut input: &DynArc) -> &DynArc {
+    while let Some(projection) = (**input).downcast_ref::<Projection>() {
+        input = &projection.parent;
+    }
+    input
+}
+
+/// Resolves a `DynArc` input to the `DynArc` holding the container of the
+/// value, for lookups keyed by the type of the container: weak references are
+/// upgraded (see `upgrade_weak`), and projections resolved to their root (see
+/// `projection_root`). Returns `None` if the value behind a weak reference was
+/// dropped already.
+fn container_root(input: &DynArc) -> Option<DynArc> {
+    let input = upgrade_weak(input.clone())?;
+    Some(projection_root(&input).clone())
+}
+
+/// Returns the parent of a `Projection` held by a `DynArc`, which the
+/// coercion functions of the projection are only ever called with.
+fn projection_of(any: &(dyn Any + Sync + Send)) -> &Projection {
+    any.downcast_ref::<Projection>()
+        .expect("projection coercion fn called for another container")
+}
+
+/// Creates the coercion functions converting a value to `Out`, which coerce
+/// the value to `In` with `inner` first, then convert `In` to `Out`, see
+/// `DelegateFn`.
 ///
 /// # Parameters
 ///
-/// - `input`: A `DynArc` input.
+/// - `inner`: The coercion functions of the value to `In`.
+/// - `type_in_name`: The name of the value, for error messages.
+/// - `conv`: A function for read coercion, `None` means that the value can't
+///   be coerced.
+/// - `conv_mut`: A function for write coercion, `None` means that the value
+///   can't be coerced.
+fn delegated_coercion_fns<In, Out, F, FMut>(
+    inner: &CoercionFns,
+    type_in_name: &str,
+    conv: F,
+    conv_mut: FMut,
+) -> CoercionFns
+where
+    In: Sized + 'static,
+    Out: ?Sized + 'static,
+    F: for<'a> Fn(&'a In) -> Option<&'a Out> + Clone + Send + Sync + 'static,
+    FMut: for<'a> Fn(&'a mut In) -> Option<&'a mut Out> + Clone + Send + Sync + 'static,
+{
+    let type_in_name = type_in_name.to_owned();
+    let type_in_name_mut = type_in_name.clone();
+    let type_in_name_ref = type_in_name.clone();
+    #[cfg(feature = "parking_lot")]
+    let type_in_name_upgradable = type_in_name.clone();
+    let conv_read = conv.clone();
+    let coerce = inner.coerce.clone();
+    let f: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: Handle<In> = *coerce(boxed_t)
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        // The handle is dropped along with its lock before we panic below,
+        // if the conversion fails.
+        let handle: Handle<Out> = handle
+            .try_map(|x| conv_read(x).ok_or(()))
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name));
+        Box::new(handle)
+    });
+    let conv_mut_write = conv_mut.clone();
+    let coerce_mut = inner.coerce_mut.clone();
+    let f_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: HandleMut<In> = *coerce_mut(boxed_t)
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        let handle: HandleMut<Out> = handle
+            .try_map_mut(|x| conv_mut_write(x).ok_or(()))
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name_mut));
+        Box::new(handle)
+    });
+    let coerce_ref = inner
+        .coerce_ref
+        .downcast_ref::<BorrowedCoercion<In>>()
+        .expect("coercion fn has wrong type")
+        .clone();
+    let conv_ref = conv.clone();
+    let f_ref: CoercionRefAny = Arc::new(borrowed_coercion(move |any| {
+        coerce_ref(any)
+            .try_map(|x| conv_ref(x).ok_or(()))
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name_ref))
+    }));
+    let conv_try = conv.clone();
+    let try_coerce = inner.try_coerce.clone();
+    let f_try: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: Option<Handle<In>> = *try_coerce(boxed_t)
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        // Both lock contention and failed conversion result in `None`.
+        let handle: Option<Handle<Out>> =
+            handle.and_then(|handle| handle.try_map(|x| conv_try(x).ok_or(())).ok());
+        Box::new(handle)
+    });
+    let conv_mut_try = conv_mut.clone();
+    let try_coerce_mut = inner.try_coerce_mut.clone();
+    let f_try_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: Option<HandleMut<In>> = *try_coerce_mut(boxed_t)
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        let handle: Option<HandleMut<Out>> = handle
+            .and_then(|handle| handle.try_map_mut(|x| conv_mut_try(x).ok_or(())).ok());
+        Box::new(handle)
+    });
+    #[cfg(feature = "parking_lot")]
+    let coerce_upgradable = inner.coerce_upgradable.clone();
+    #[cfg(feature = "parking_lot")]
+    let f_upgradable: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: UpgradableHandle<In> = *coerce_upgradable(boxed_t)
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        if conv(&handle).is_none() {
+            // Release the lock before panicking
+            drop(handle);
+            conversion_failed::<Out>(&type_in_name_upgradable);
+        }
+        let handle = UpgradableHandle::<Out>(Box::new(DelegatedUpgradable {
+            handle,
+            conv: conv.clone(),
+            conv_mut: conv_mut.clone(),
+            type_in_name: type_in_name_upgradable.clone(),
+        }));
+        Box::new(handle)
+    });
+    CoercionFns {
+        coerce: f,
+        coerce_mut: f_mut,
+        try_coerce: f_try,
+        try_coerce_mut: f_try_mut,
+        coerce_ref: f_ref,
+        #[cfg(feature = "parking_lot")]
+        coerce_upgradable: f_upgradable,
+    }
+}
+
+/// Composes the coercion functions of a parent to `In` (see
+/// `get_coerce_fns`) with the projection of `In` to its part `Out`. The
+/// composed functions take the `DynArc` holding the `Projection`.
+fn projection_fns<In: ?Sized + 'static, Out: ?Sized + 'static>(
+    inner: &CoercionFns,
+    get: fn(&In) -> &Out,
+    get_mut: fn(&mut In) -> &mut Out,
+) -> CoercionFns {
+    let coerce = inner.coerce.clone();
+    let f: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: Handle<In> = *coerce(projection_of(&*boxed_t).parent.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        Box::new(handle.map(get))
+    });
+    let coerce_mut = inner.coerce_mut.clone();
+    let f_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: HandleMut<In> = *coerce_mut(projection_of(&*boxed_t).parent.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type");
+        Box::new(handle.map_mut(get_mut))
+    });
+    let try_coerce = inner.try_coerce.clone();
+    let f_try: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: Option<Handle<In>> =
+            *try_coerce(projection_of(&*boxed_t).parent.clone())
+                .downcast()
+                .expect("coercion fn returned wrong type");
+        Box::new(handle.map(|handle| handle.map(get)))
+    });
+    let try_coerce_mut = inner.try_coerce_mut.clone();
+    let f_try_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: Option<HandleMut<In>> =
+            *try_coerce_mut(projection_of(&*boxed_t).parent.clone())
+                .downcast()
+                .expect("coercion fn returned wrong type");
+        Box::new(handle.map(|handle| handle.map_mut(get_mut)))
+    });
+    let coerce_ref = inner
+        .coerce_ref
+        .downcast_ref::<BorrowedCoercion<In>>()
+        .expect("coercion fn has wrong type")
+        .clone();
+    let f_ref: CoercionRefAny = Arc::new(borrowed_coercion(move |any| {
+        coerce_ref(&*projection_of(any).parent).map(get)
+    }));
+    #[cfg(feature = "parking_lot")]
+    let coerce_upgradable = inner.coerce_upgradable.clone();
+    #[cfg(feature = "parking_lot")]
+    let f_upgradable: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+        let handle: UpgradableHandle<In> =
+            *coerce_upgradable(projection_of(&*boxed_t).parent.clone())
+                .downcast()
+                .expect("coercion fn returned wrong type");
+        let handle = UpgradableHandle::<Out>(Box::new(ProjectedUpgradable {
+            handle,
+            get,
+            get_mut,
+        }));
+        Box::new(handle)
+    });
+    CoercionFns {
+        coerce: f,
+        coerce_mut: f_mut,
+        try_coerce: f_try,
+        try_coerce_mut: f_try_mut,
+        coerce_ref: f_ref,
+        #[cfg(feature = "parking_lot")]
+        coerce_upgradable: f_upgradable,
+    }
+}
+
+/// Fails a write coercion of a container which doesn't support it.
+fn unsupported_mut_container<In: 'static>(
+    any: &(dyn Any + Sync + Send),
+    kind: &str,
+    type_in_name: &str,
+) -> ! {
+    if any.is::<Mutex<Pin<Box<In>>>>() {
+        // Handing out `&mut In` would allow moving the pinned value
+        coercion_panic(format!(
+            "pinned value can't be coerced mutably (type: {:?}), use \
+             `DynBox::with_pinned_mut` instead",
+            type_in_name
+        ));
+    }
+    if any.is::<In>() {
+        coercion_panic(format!(
+            "immutable value can't be coerced mutably (type: {:?}), use \
+             `DynBox::to_exclusive` to get a mutable copy",
+            type_in_name
+        ));
+    }
+    unsupported_container(kind, type_in_name)
+}
+
+/// Fails a coercion of a container which doesn't hold a value of the
+/// registered type.
+fn unsupported_container(kind: &str, type_in_name: &str) -> ! {
+    coercion_panic(format!(
+        "unsupported container provided for {}coersion (type: {:?})",
+        kind, type_in_name
+    ))
+}
+
+/// Fails a local coercion of a `LocalRc` which doesn't hold a `RefCell`.
+fn unsupported_local_container(type_in_name: &str) -> ! {
+    unsupported_container("local ", type_in_name)
+}
+
+/// Checks if the container holding a value of type `In` is locked, i.e. if it
+/// can't be locked for writing right now.
 ///
 /// # Returns
 ///
-/// A handle to the coerced output type.
-pub fn coerce<Out: ?Sized + 'static>(input: DynArc) -> Handle<Out> {
-    // Note: This function holds a lock on DynArc. Use with care to avoid deadlocks.
-    // Obtain a read lock on the global registry.
-    let registry = global_registry()
-        .read()
-        .expect("unable to obtain read lock on global registry");
-    registry.coerce::<Out>(input)
+/// `None` if `any` is not a supported container of `In`.
+fn probe_locked<In: 'static>(any: &(dyn Any + Sync + Send)) -> Option<bool> {
+    fn would_block<G>(result: TryLockResult<G>) -> bool {
+        matches!(result, Err(TryLockError::WouldBlock))
+    }
+    if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
+        return Some(would_block(mutex.try_lock()));
+    }
+    if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
+        return Some(would_block(rwlock.try_write()));
+    }
+    if let Some(mutex) = any.downcast_ref::<Mutex<Pin<Box<In>>>>() {
+        return Some(would_block(mutex.try_lock()));
+    }
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<In>>() {
+        return Some(rwlock.try_write().is_none());
+    }
+    if any.is::<In>() {
+        // There is no lock to hold
+        return Some(false);
+    }
+    None
 }
 
-/// Coerces a `DynArc` input to a mutable handle of the specified output type using the global registry.
+/// Computes the Levenshtein distance between two strings, used to suggest
+/// similar type names in error messages.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(cur).min(row[j])
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Formats a list of type names for error messages.
+fn quote_names(names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| format!("{:?}", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Converts the result of a `try_lock`/`try_read` into an `Option`, treating
+/// contention as `None`. Poisoned locks are recovered, same as in blocking
+/// coercions: a failed coercion unwinds the stub past the guards it holds (see
+/// `raise_coercion_errors`), which poisons them without leaving the values any
+/// less consistent than an OCaml exception raised by the stub would.
+fn try_acquire<G>(result: TryLockResult<G>) -> Option<G> {
+    match result {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::WouldBlock) => None,
+        Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+    }
+}
+
+/// Locks the container holding a value of type `In` for reading, blocking
+/// until the lock is acquired.
 ///
-/// # Parameters
+/// # Returns
 ///
-/// - `input`: A `DynArc` input.
+/// The read guard, or `None` if `any` is not a supported container of `In`. An
+/// immutable value (held by the `Arc` directly) is borrowed without locking.
+fn lock_read<'a, In: 'static>(
+    any: &'a (dyn Any + Sync + Send),
+    type_name: &str,
+) -> Option<LockReadGuard<'a, In>> {
+    if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
+        return Some(LockReadGuard::Mutex(HeldMutex::lock(mutex, type_name)));
+    }
+    if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
+        return Some(LockReadGuard::RwLockRead(
+            rwlock.read().unwrap_or_else(PoisonError::into_inner),
+        ));
+    }
+    if let Some(mutex) = any.downcast_ref::<Mutex<Pin<Box<In>>>>() {
+        return Some(LockReadGuard::Pinned(HeldMutex::lock(mutex, type_name)));
+    }
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<In>>() {
+        return Some(LockReadGuard::FairRwLockRead(rwlock.read()));
+    }
+    if let Some(value) = any.downcast_ref::<In>() {
+        return Some(LockReadGuard::Immutable(value));
+    }
+    None
+}
+
+/// Attempts to lock the container holding a value of type `In` for reading
+/// without blocking.
 ///
 /// # Returns
 ///
-/// A mutable handle to the coerced output type.
-pub fn coerce_mut<Out: ?Sized + 'static>(input: DynArc) -> HandleMut<Out> {
-    // Note: This function holds a lock on DynArc. Use with care to avoid deadlocks.
-    let registry = global_registry()
-        .read()
-        .expect("unable to obtain read lock on global registry");
-    registry.coerce_mut::<Out>(input)
+/// `None` if `any` is not a supported container of `In`, and `Some(None)` if
+/// the lock is held elsewhere.
+fn try_lock_read<In: 'static>(
+    any: &(dyn Any + Sync + Send),
+) -> Option<Option<LockReadGuard<'_, In>>> {
+    if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
+        return Some(HeldMutex::try_lock(mutex).map(LockReadGuard::Mutex));
+    }
+    if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
+        return Some(try_acquire(rwlock.try_read()).map(LockReadGuard::RwLockRead));
+    }
+    if let Some(mutex) = any.downcast_ref::<Mutex<Pin<Box<In>>>>() {
+        return Some(HeldMutex::try_lock(mutex).map(LockReadGuard::Pinned));
+    }
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<In>>() {
+        return Some(rwlock.try_read().map(LockReadGuard::FairRwLockRead));
+    }
+    if let Some(value) = any.downcast_ref::<In>() {
+        return Some(Some(LockReadGuard::Immutable(value)));
+    }
+    None
 }
 
-/// Retrieves the type information for a given input type from the global registry.
+/// Locks the container holding a value of type `In` for writing, blocking
+/// until the lock is acquired.
 ///
-/// # Parameters
+/// # Returns
 ///
-/// - `In`: The trait object type to retrieve information for.
+/// The write guard, or `None` if `any` is not a supported container of `In`
+/// (which includes pinned containers and immutable values).
+fn lock_write<'a, In: 'static>(
+    any: &'a (dyn Any + Sync + Send),
+    type_name: &str,
+) -> Option<LockWriteGuard<'a, In>> {
+    if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
+        return Some(LockWriteGuard::Mutex(HeldMutex::lock(mutex, type_name)));
+    }
+    if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
+        return Some(LockWriteGuard::RwLockWrite(
+            rwlock.write().unwrap_or_else(PoisonError::into_inner),
+        ));
+    }
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<In>>() {
+        return Some(LockWriteGuard::FairRwLockWrite(rwlock.write()));
+    }
+    None
+}
+
+/// Attempts to lock the container holding a value of type `In` for writing
+/// without blocking.
 ///
 /// # Returns
 ///
-/// A `TypeInfo` struct containing the type information.
-pub fn get_type_info<In: ?Sized + 'static>() -> TypeInfo {
-    let registry = global_registry()
-        .read()
-        .expect("unable to obtain read lock on global registry");
-    registry.get_type_info::<In>()
+/// `None` if `any` is not a supported container of `In` (which includes pinned
+/// containers and immutable values), and `Some(None)` if the lock is held
+/// elsewhere.
+fn try_lock_write<In: 'static>(
+    any: &(dyn Any + Sync + Send),
+) -> Option<Option<LockWriteGuard<'_, In>>> {
+    if let Some(mutex) = any.downcast_ref::<Mutex<In>>() {
+        return Some(HeldMutex::try_lock(mutex).map(LockWriteGuard::Mutex));
+    }
+    if let Some(rwlock) = any.downcast_ref::<RwLock<In>>() {
+        return Some(try_acquire(rwlock.try_write()).map(LockWriteGuard::RwLockWrite));
+    }
+    #[cfg(feature = "parking_lot")]
+    if let Some(rwlock) = any.downcast_ref::<parking_lot::RwLock<In>>() {
+        return Some(rwlock.try_write().map(LockWriteGuard::FairRwLockWrite));
+    }
+    None
 }
 
-/// The `Plugin` struct represents a plugin with an initializer function.
-pub struct Plugin {
-    /// A function pointer to the initializer function.
-    initializer: fn(),
+/// Same as `try_lock_write`, but pinned containers and immutable values, which
+/// can't be coerced mutably (see `unsupported_mut_container`), are treated
+/// like a lock held elsewhere, so that non-blocking write coercions return
+/// `None` for them instead of failing.
+fn try_lock_write_or_skip<'a, In: 'static>(
+    any: &'a (dyn Any + Sync + Send),
+    type_in_name: &str,
+) -> Option<LockWriteGuard<'a, In>> {
+    try_lock_write::<In>(any).unwrap_or_else(|| {
+        if any.is::<Mutex<Pin<Box<In>>>>() || any.is::<In>() {
+            None
+        } else {
+            unsupported_container("try mut ", type_in_name)
+        }
+    })
 }
 
-impl Plugin {
-    /// Creates a new `Plugin` with the given initializer function.
-    ///
-    /// # Parameters
-    ///
-    /// - `initializer`: A function pointer to the initializer function.
+/// A struct representing type information, including the fully qualified name
+/// and a list of implementations.
+#[derive(Clone)]
+pub struct TypeInfo {
+    pub crate_name: &'static str,
+    pub fq_name: &'static str,
+    pub implementations: Vec<&'static str>,
+    /// The containers the type is meant to be held in, see
+    /// `register_type_containers`. All of them unless the type was registered
+    /// with a restricted set.
+    pub containers: ContainerSet,
+    /// The bound of the polymorphic variant in the OCaml type, see
+    /// `register_type_variant_kind`. Open unless the type was registered as
+    /// closed.
+    pub variant_kind: VariantKind,
+}
+
+/// Resolves `name` registered by `crate_name` to a path rooted in the crate it
+/// names: `crate` is replaced with `crate_name`, while other paths are
+/// absolute already, same as `register_type!` and `register_trait!` spell
+/// them, and are returned as is, see `TypeInfo::namespaced_name`.
+fn namespace(crate_name: &str, name: &str) -> String {
+    match name.strip_prefix("crate::") {
+        Some(path) => format!("{}::{}", crate_name, path),
+        None => name.to_owned(),
+    }
+}
+
+impl TypeInfo {
+    /// Returns the fully qualified name of the type, with `crate` replaced by
+    /// the name of the crate which registered it. Other names are absolute
+    /// paths, e.g. `core::fmt::Display` or `other_crate::Animal`, and are
+    /// returned as is.
+    pub fn namespaced_name(&self) -> String {
+        namespace(self.crate_name, self.fq_name)
+    }
+
+    /// Checks if `trait_fq_name` (e.g. `core::fmt::Display`) is among the
+    /// implementations of the type, i.e. if it was listed in `marker_traits`
+    /// or `object_safe_traits` when the type was registered.
+    pub fn implements(&self, trait_fq_name: &str) -> bool {
+        let trait_fq_name = trait_fq_name.trim_start_matches("::");
+        self.implementations.contains(&trait_fq_name)
+    }
+}
+
+/// Returns `TypeId`s of the containers which may hold `In` in a `DynArc`:
+/// `Mutex<In>`, `RwLock<In>`, `Mutex<Pin<Box<In>>>` and immutable `In`
+/// itself, as well as `parking_lot::RwLock<In>` with `parking_lot` feature.
+fn container_type_ids<In: 'static>() -> Vec<TypeId> {
+    vec![
+        TypeId::of::<Mutex<In>>(),
+        TypeId::of::<RwLock<In>>(),
+        TypeId::of::<Mutex<Pin<Box<In>>>>(),
+        #[cfg(feature = "parking_lot")]
+        TypeId::of::<parking_lot::RwLock<In>>(),
+        TypeId::of::<In>(),
+    ]
+}
+
+/// The `Registry` struct holds mappings for type coercions and type information.
+/// It allows registering coercion functions for converting between types and
+/// retrieving type information.
+#[derive(Default, Clone)]
+struct Registry {
+    traits: HashMap<(TypeId, TypeId), CoercionFns>,
+    local_traits: HashMap<(TypeId, TypeId), LocalCoercionFns>,
+    types: HashMap<TypeId, String>,
+    type_info_map: HashMap<TypeId, TypeInfo>,
+    containers: HashMap<TypeId, ContainerKind>,
+    mem_sizes: HashMap<TypeId, MemSizeFn>,
+    shows: HashMap<TypeId, ShowFn>,
+    lock_probes: HashMap<TypeId, LockProbeFn>,
+    transparent: HashMap<TypeId, Vec<TransparentType>>,
+    delegates: HashMap<TypeId, Vec<(TypeId, DelegateFn)>>,
+}
+
+impl Registry {
+    /// Creates a new `Registry` instance.
     ///
     /// # Returns
     ///
-    /// A new `Plugin` instance.
-    pub const fn new(initializer: fn()) -> Self {
-        // Create a new `Plugin` instance with the given initializer function.
+    /// A new `Registry` instance.
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the same coercion functions to the type identified by `out`
+    /// for `Mutex<In>`, `RwLock<In>`, `Mutex<Pin<Box<In>>>` and immutable `In`
+    /// (i.e. `Arc<In>`), as well as `parking_lot::RwLock<In>` with
+    /// `parking_lot` feature. This doesn't depend on the output type, so that
+    /// its code is shared by all the coercions of `In`.
+    ///
+    /// # Parameters
+    ///
+    /// - `out`: The `TypeId` of the output type.
+    /// - `fs`: A `CoercionFns` set of functions for read and write coercions.
+    fn register_container_coercion_fns<In: Sized + 'static>(
+        &mut self,
+        out: TypeId,
+        fs: CoercionFns,
+    ) {
+        for container in container_type_ids::<In>() {
+            self.traits.insert((container, out), fs.clone());
+        }
+    }
+
+    /// Registers a type in the registry.
+    ///
+    /// # Parameters
+    ///
+    /// - `In`: The trait object type to register.
+    /// - `InReal`: The real type that implements the trait.
+    fn register_type<In: ?Sized + 'static, InReal: ?Sized>(&mut self) {
+        self.types
+            .insert(TypeId::of::<In>(), std::any::type_name::<InReal>().into());
+    }
+
+    /// Registers the name of `In` for the type itself and for all containers
+    /// which may hold it, along with the kinds of those containers.
+    fn register_type_and_containers<In: ?Sized + 'static>(&mut self) {
+        trace!("register type {}", std::any::type_name::<In>());
+        self.register_type::<In, In>();
+        self.register_type::<Mutex<In>, In>();
+        self.register_type::<RwLock<In>, In>();
+        self.register_type::<Mutex<Pin<Box<In>>>, In>();
+        #[cfg(feature = "parking_lot")]
+        self.register_type::<parking_lot::RwLock<In>, In>();
+        self.register_type::<RefCell<In>, In>();
+        // Immutable values are held by the `Arc` directly, so the name of `In`
+        // covers them, while `Arc<In>` is named for values wrapped by callers
+        self.register_type::<Arc<In>, In>();
+        self.register_containers::<In>();
+    }
+
+    /// Checks if `In` was registered with `register_type`, along with its
+    /// containers.
+    fn is_type_registered<In: ?Sized + 'static>(&self) -> bool {
+        self.containers.contains_key(&TypeId::of::<Mutex<In>>())
+    }
+
+    /// Checks if the coercion of `In` to `dyn Any` is registered, see
+    /// `register_any`.
+    fn has_any_coercion<In: 'static>(&self) -> bool {
+        self.traits
+            .contains_key(&(TypeId::of::<Mutex<In>>(), TypeId::of::<dyn Any>()))
+    }
+
+    /// Registers the containers which may hold a value of type `In`.
+    ///
+    /// # Parameters
+    ///
+    /// - `In`: The type of the value held by the containers.
+    fn register_containers<In: ?Sized + 'static>(&mut self) {
+        self.containers
+            .insert(TypeId::of::<Mutex<In>>(), ContainerKind::Mutex);
+        self.containers
+            .insert(TypeId::of::<RwLock<In>>(), ContainerKind::RwLock);
+        self.containers
+            .insert(TypeId::of::<Mutex<Pin<Box<In>>>>(), ContainerKind::Mutex);
+        #[cfg(feature = "parking_lot")]
+        self.containers.insert(
+            TypeId::of::<parking_lot::RwLock<In>>(),
+            ContainerKind::RwLock,
+        );
+        self.containers
+            .insert(TypeId::of::<In>(), ContainerKind::Immutable);
+    }
+
+    /// Registers the memory size function of `In` for all containers which may
+    /// hold it.
+    fn register_mem_sized<In: MemSized + 'static>(&mut self) {
+        let f: MemSizeFn = Arc::new(|any| {
+            try_lock_read::<In>(any)
+                .flatten()
+                .map(|guard| guard.mem_size())
+        });
+        self.mem_sizes.insert(TypeId::of::<Mutex<In>>(), f.clone());
+        self.mem_sizes.insert(TypeId::of::<RwLock<In>>(), f.clone());
+        self.mem_sizes
+            .insert(TypeId::of::<Mutex<Pin<Box<In>>>>(), f.clone());
+        #[cfg(feature = "parking_lot")]
+        self.mem_sizes
+            .insert(TypeId::of::<parking_lot::RwLock<In>>(), f.clone());
+        self.mem_sizes.insert(TypeId::of::<In>(), f.clone());
+    }
+
+    /// Registers the show function of `In` for all containers which may hold
+    /// it. The value is locked for reading while it's shown.
+    fn register_show<In: 'static>(&mut self, show: fn(&In) -> String) {
+        self.ensure_type_registered::<In>();
+        let type_in_name = String::from(self.type_name(&TypeId::of::<In>()));
+        let f: ShowFn = Arc::new(move |any| {
+            let guard = lock_read::<In>(any, &type_in_name).unwrap_or_else(|| {
+                panic!(
+                    "unsupported container provided for show (type: {:?})",
+                    type_in_name
+                )
+            });
+            show(&guard)
+        });
+        self.shows.insert(TypeId::of::<Mutex<In>>(), f.clone());
+        self.shows.insert(TypeId::of::<RwLock<In>>(), f.clone());
+        self.shows
+            .insert(TypeId::of::<Mutex<Pin<Box<In>>>>(), f.clone());
+        #[cfg(feature = "parking_lot")]
+        self.shows
+            .insert(TypeId::of::<parking_lot::RwLock<In>>(), f.clone());
+        self.shows.insert(TypeId::of::<In>(), f);
+    }
+
+    /// Retrieves the show function of a `DynArc` input, see `register_show`.
+    ///
+    /// # Returns
+    ///
+    /// The show function, or `None` if the type is not registered with
+    /// `register_show`.
+    fn get_show_fn(&self, input: &DynArc) -> Option<ShowFn> {
+        self.shows.get(&(**input).type_id()).cloned()
+    }
+
+    /// Retrieves the memory held by the value of a `DynArc` input.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A reference to a `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// The memory size, or `None` if the type is not registered with
+    /// `register_mem_sized`, the value is locked, or the value behind a weak
+    /// reference was dropped.
+    fn mem_size_of(&self, input: &DynArc) -> Option<usize> {
+        let input = upgrade_weak(input.clone())?;
+        let f = self.mem_sizes.get(&(*input).type_id())?;
+        f(&*input)
+    }
+
+    /// Registers type information in the registry.
+    ///
+    /// # Parameters
+    ///
+    /// - `In`: The trait object type to register.
+    /// - `crate_name`: The name of the crate registering the type.
+    /// - `fq_name`: The fully qualified name of the type.
+    /// - `impls`: A vector of strings representing the implementations of the type.
+    fn register_type_info<In: ?Sized + 'static>(
+        &mut self,
+        crate_name: &'static str,
+        fq_name: &'static str,
+        impls: Vec<&'static str>,
+    ) {
+        // Containers and variant kind may be set by an earlier registration
+        let (containers, variant_kind) = self
+            .type_info_map
+            .get(&TypeId::of::<In>())
+            .map_or((ContainerSet::ALL, VariantKind::Open), |type_info| {
+                (type_info.containers, type_info.variant_kind)
+            });
+        self.type_info_map.insert(
+            TypeId::of::<In>(),
+            TypeInfo {
+                crate_name,
+                fq_name,
+                implementations: impls,
+                containers,
+                variant_kind,
+            },
+        );
+    }
+
+    /// Records the bound of the polymorphic variant in the OCaml type of `In`,
+    /// see `TypeInfo::variant_kind`. The type info of `In` has to be
+    /// registered.
+    fn register_type_variant_kind<In: ?Sized + 'static>(
+        &mut self,
+        variant_kind: VariantKind,
+    ) {
+        let type_info = self
+            .type_info_map
+            .get_mut(&TypeId::of::<In>())
+            .unwrap_or_else(|| {
+                panic!(
+                    "registry does not contain a registered type info for {}",
+                    std::any::type_name::<In>()
+                )
+            });
+        type_info.variant_kind = variant_kind;
+    }
+
+    /// Records the containers `In` is meant to be held in, see
+    /// `TypeInfo::containers`. The type info of `In` has to be registered.
+    fn register_type_containers<In: ?Sized + 'static>(
+        &mut self,
+        containers: ContainerSet,
+    ) {
+        let type_info = self
+            .type_info_map
+            .get_mut(&TypeId::of::<In>())
+            .unwrap_or_else(|| {
+                panic!(
+                    "registry does not contain a registered type info for {}",
+                    std::any::type_name::<In>()
+                )
+            });
+        type_info.containers = containers;
+    }
+
+    /// Fails the coercion with an explanation if `input` is an immutable value
+    /// of a type which is registered for immutable use only, as opposed to
+    /// values which just happen to be put into an immutable box.
+    fn check_mut_coercion(&self, input: &DynArc) {
+        if let Some(type_info) = self.immutable_only_type_info(input) {
+            coercion_panic(format!(
+                "{:?} is registered immutable, mutable coercion is unavailable",
+                type_info.fq_name
+            ));
+        }
+    }
+
+    /// Retrieves the type info of the value of `input` if it's an immutable
+    /// value of a type which is registered for immutable use only, see
+    /// `check_mut_coercion`.
+    fn immutable_only_type_info(&self, input: &DynArc) -> Option<&TypeInfo> {
+        // Immutable values are held by the `Arc` directly, so the type of the
+        // value is the registered type itself
+        let type_in = (**projection_root(input)).type_id();
+        if self.containers.get(&type_in) != Some(&ContainerKind::Immutable) {
+            return None;
+        }
+        self.type_info_map
+            .get(&type_in)
+            .filter(|type_info| !type_info.containers.supports_mut())
+    }
+
+    /// Registers coercion functions for converting between types `In` and `Out`.
+    /// A coercion from `In` to `dyn Any` is registered along the way, so that
+    /// any registered value can be inspected at runtime. When `In` and `Out`
+    /// are the same type, the conversion functions are not used, see
+    /// `register_identity`. `In` itself is registered too, see
+    /// `ensure_type_registered`.
+    ///
+    /// # Parameters
+    ///
+    /// - `conv`: A function pointer for read coercion.
+    /// - `conv_mut`: A function pointer for write coercion.
+    fn register<In: Sized + 'static, Out: ?Sized + 'static>(
+        &mut self,
+        conv: fn(&In) -> &Out,
+        conv_mut: fn(&mut In) -> &mut Out,
+    ) {
+        trace!(
+            "register coercion {} => {}",
+            std::any::type_name::<In>(),
+            std::any::type_name::<Out>()
+        );
+        self.ensure_type_registered::<In>();
+        self.register_conversion::<In, Out>(conv, conv_mut);
+        self.register_any::<In>();
+    }
+
+    /// Registers coercions of `In` to several types at once, see
+    /// `register_multi`. `In` is registered along with its coercion to
+    /// `dyn Any` once for all of them, same as with `register`.
+    fn register_multi<In: Sized + 'static>(
+        &mut self,
+        coercions: &[&dyn RegisterCoercion<In>],
+    ) {
+        trace!(
+            "register {} coercions of {}",
+            coercions.len(),
+            std::any::type_name::<In>()
+        );
+        self.ensure_type_registered::<In>();
+        for coercion in coercions {
+            coercion.register(self);
+        }
+        self.register_any::<In>();
+    }
+
+    /// Registers coercion functions for converting between types `In` and
+    /// `Out`, or the identity coercion when they are the same type, leaving
+    /// the registration of `In` itself to the caller.
+    fn register_conversion<In: Sized + 'static, Out: ?Sized + 'static>(
+        &mut self,
+        conv: fn(&In) -> &Out,
+        conv_mut: fn(&mut In) -> &mut Out,
+    ) {
+        if TypeId::of::<In>() == TypeId::of::<Out>() {
+            self.register_identity::<In>();
+        } else {
+            self.register_coercion::<In, Out, _, _>(
+                move |x: &In| Some(conv(x)),
+                move |x: &mut In| Some(conv_mut(x)),
+            );
+        }
+    }
+
+    /// Registers coercion functions for converting between types `In` and
+    /// `Out`, where the conversion may fail, e.g. when `Out` is a view of a
+    /// specific enum variant. Blocking coercions panic when the conversion
+    /// yields `None`, while `try_coerce` returns `None`.
+    ///
+    /// # Parameters
+    ///
+    /// - `conv`: A function pointer for read coercion.
+    /// - `conv_mut`: A function pointer for write coercion.
+    fn register_fallible<In: Sized + 'static, Out: ?Sized + 'static>(
+        &mut self,
+        conv: fn(&In) -> Option<&Out>,
+        conv_mut: fn(&mut In) -> Option<&mut Out>,
+    ) {
+        trace!(
+            "register fallible coercion {} => {}",
+            std::any::type_name::<In>(),
+            std::any::type_name::<Out>()
+        );
+        self.ensure_type_registered::<In>();
+        self.register_coercion::<In, Out, _, _>(conv, conv_mut);
+        self.register_any::<In>();
+    }
+
+    /// Registers coercion closures for converting between types `In` and
+    /// `Out`, see `register_with`.
+    ///
+    /// # Parameters
+    ///
+    /// - `conv`: A closure for read coercion.
+    /// - `conv_mut`: A closure for write coercion.
+    fn register_with<In: Sized + 'static, Out: ?Sized + 'static>(
+        &mut self,
+        conv: Arc<dyn Fn(&In) -> &Out + Send + Sync>,
+        conv_mut: Arc<dyn Fn(&mut In) -> &mut Out + Send + Sync>,
+    ) {
+        trace!(
+            "register coercion closures {} => {}",
+            std::any::type_name::<In>(),
+            std::any::type_name::<Out>()
+        );
+        self.ensure_type_registered::<In>();
+        self.register_coercion::<In, Out, _, _>(
+            move |x: &In| Some(conv(x)),
+            move |x: &mut In| Some(conv_mut(x)),
+        );
+        self.register_any::<In>();
+    }
+
+    /// Registers the newtype `W` as transparent over `Inner`, see
+    /// `register_transparent`.
+    fn register_transparent<W, Inner>(&mut self)
+    where
+        W: AsRef<Inner> + AsMut<Inner> + 'static,
+        Inner: 'static,
+    {
+        trace!(
+            "register transparent newtype {} => {}",
+            std::any::type_name::<W>(),
+            std::any::type_name::<Inner>()
+        );
+        self.ensure_type_registered::<Inner>();
+        self.register::<W, Inner>(W::as_ref, W::as_mut);
+        let newtype = TransparentType {
+            name: self.type_name(&TypeId::of::<W>()).into(),
+            containers: container_type_ids::<W>(),
+            inner: self.traits[&(TypeId::of::<W>(), TypeId::of::<Inner>())].clone(),
+        };
+        let delegates = self.delegates.get(&TypeId::of::<Inner>()).cloned();
+        for (type_out, delegate) in delegates.iter().flatten() {
+            self.register_delegated_coercion(&newtype, *type_out, delegate);
+        }
+        self.transparent
+            .entry(TypeId::of::<Inner>())
+            .or_default()
+            .push(newtype);
+    }
+
+    /// Registers `In` along with its containers, unless it's registered
+    /// already. Coercions are often registered before any value of `In` is
+    /// boxed, e.g. `register_trait!` registers `Box<dyn Trait>` coercions long
+    /// before `DynBox::new_exclusive_boxed` registers `Box<dyn Trait>` itself,
+    /// so the name of `In` has to be known by then for the error messages of
+    /// the coercions, as well as its containers for `container_kind`.
+    fn ensure_type_registered<In: 'static>(&mut self) {
+        if !self.is_type_registered::<In>() {
+            self.register_type_and_containers::<In>();
+        }
+    }
+
+    /// Registers the coercion of `In` to `dyn Any`, along with lock probes of
+    /// the containers which may hold `In`. A coercion to `dyn Any` which was
+    /// registered explicitly with `register` is left intact.
+    fn register_any<In: Sized + 'static>(&mut self) {
+        if !self.has_any_coercion::<In>() {
+            self.register_coercion::<In, dyn Any, _, _>(
+                |x: &In| Some(x as &dyn Any),
+                |x: &mut In| Some(x as &mut dyn Any),
+            );
+        }
+        let probe: LockProbeFn = |any| {
+            probe_locked::<In>(any).expect("lock probe registered for a wrong container")
+        };
+        for container in container_type_ids::<In>() {
+            self.lock_probes.insert(container, probe);
+        }
+    }
+
+    /// Creates and registers coercion functions for converting between types
+    /// `In` and `Out`, for `Mutex<In>`, `RwLock<In>` and `Mutex<Pin<Box<In>>>`
+    /// containers, as well as `parking_lot::RwLock<In>` with `parking_lot`
+    /// feature, and immutable `In` held by the `Arc` directly. Pinned
+    /// containers and immutable values only support read coercions, as a
+    /// mutable reference would allow moving the pinned value or modifying a
+    /// value shared without a lock.
+    ///
+    /// # Parameters
+    ///
+    /// - `conv`: A function for read coercion, `None` means that the value
+    ///   can't be coerced.
+    /// - `conv_mut`: A function for write coercion, `None` means that the value
+    ///   can't be coerced.
+    fn register_coercion<In, Out, F, FMut>(&mut self, conv: F, conv_mut: FMut)
+    where
+        In: Sized + 'static,
+        Out: ?Sized + 'static,
+        F: for<'a> Fn(&'a In) -> Option<&'a Out> + Clone + Send + Sync + 'static,
+        FMut:
+            for<'a> Fn(&'a mut In) -> Option<&'a mut Out> + Clone + Send + Sync + 'static,
+    {
+        count!(registrations);
+        // Retrieve the type name for the input type.
+        let type_in_name = String::from(self.type_name(&TypeId::of::<In>()));
+        // Clone the type name for use in the mutable coercion function.
+        let type_in_name_mut = type_in_name.clone();
+        // Clone the type name for use in the borrowing coercion function.
+        let type_in_name_ref = type_in_name.clone();
+        // Clone the type name for use in the non-blocking coercion function.
+        let type_in_name_try = type_in_name.clone();
+        // Clone the type name for use in the non-blocking write coercion function.
+        let type_in_name_try_mut = type_in_name.clone();
+        // Clone the type name for use in the upgradable coercion function.
+        #[cfg(feature = "parking_lot")]
+        let type_in_name_upgradable = type_in_name.clone();
+        // Clone the conversions for use in the borrowing and non-blocking
+        // coercion functions, the originals are used further below.
+        let conv_ref = conv.clone();
+        let conv_try = conv.clone();
+        let conv_mut_try = conv_mut.clone();
+        // Create the read coercion function.
+        let conv_read = conv.clone();
+        let f: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::try_new(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                let guard = lock_read::<In>(any, &type_in_name)
+                    .unwrap_or_else(|| unsupported_container("", &type_in_name));
+                // The guard is released before we panic below, if the
+                // conversion fails, so that the lock is not poisoned.
+                OwningRef::new(guard).try_map(|x| conv_read(x).ok_or(()))
+            })
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name));
+            Box::new(OwningRef::new(ohandle).map_owner_box().erase_owner())
+        });
+        // Create the write coercion function.
+        let conv_mut_write = conv_mut.clone();
+        let f_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::try_new(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                let guard =
+                    lock_write::<In>(any, &type_in_name_mut).unwrap_or_else(|| {
+                        unsupported_mut_container::<In>(any, "mut ", &type_in_name_mut)
+                    });
+                OwningRefMut::new(guard).try_map_mut(|x| conv_mut_write(x).ok_or(()))
+            })
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name_mut));
+            Box::new(OwningRefMut::new(ohandle).map_owner_box().erase_owner())
+        });
+        // Create the borrowing read coercion function.
+        let f_ref: CoercionRefAny = Arc::new(borrowed_coercion(move |any| {
+            let guard = lock_read::<In>(any, &type_in_name_ref)
+                .unwrap_or_else(|| unsupported_container("ref ", &type_in_name_ref));
+            OwningRef::new(guard)
+                .try_map(|x| conv_ref(x).ok_or(()))
+                .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name_ref))
+                .map_owner_box()
+                .erase_owner()
+        }));
+        // Create the non-blocking read coercion function.
+        let f_try: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::try_new(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                let guard = try_lock_read::<In>(any)
+                    .unwrap_or_else(|| unsupported_container("try ", &type_in_name_try));
+                // Both lock contention and failed conversion result in `None`.
+                guard.ok_or(()).and_then(|guard| {
+                    OwningRef::new(guard).try_map(|x| conv_try(x).ok_or(()))
+                })
+            });
+            let handle: Option<Handle<Out>> = ohandle
+                .ok()
+                .map(|ohandle| OwningRef::new(ohandle).map_owner_box().erase_owner());
+            Box::new(handle)
+        });
+        // Create the non-blocking write coercion function.
+        let f_try_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::try_new(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                let guard = try_lock_write_or_skip::<In>(any, &type_in_name_try_mut);
+                // Lock contention, containers which can't be written to, and
+                // failed conversion result in `None`.
+                guard.ok_or(()).and_then(|guard| {
+                    OwningRefMut::new(guard).try_map_mut(|x| conv_mut_try(x).ok_or(()))
+                })
+            });
+            let handle: Option<HandleMut<Out>> = ohandle
+                .ok()
+                .map(|ohandle| OwningRefMut::new(ohandle).map_owner_box().erase_owner());
+            Box::new(handle)
+        });
+        // Create the upgradable read coercion function.
+        #[cfg(feature = "parking_lot")]
+        let f_upgradable = upgradable_coercion::<In, Out, _, _>(
+            type_in_name_upgradable,
+            conv.clone(),
+            conv_mut.clone(),
+        );
+        // Register the coercion functions for all the containers of `In`.
+        self.register_container_coercion_fns::<In>(
+            TypeId::of::<Out>(),
+            CoercionFns {
+                coerce: f,
+                coerce_mut: f_mut,
+                try_coerce: f_try,
+                try_coerce_mut: f_try_mut,
+                coerce_ref: f_ref,
+                #[cfg(feature = "parking_lot")]
+                coerce_upgradable: f_upgradable,
+            },
+        );
+        // Register the coercion functions for `RefCell<In>` to `Out`.
+        self.register_local_coercion::<In, Out, _, _>(conv.clone(), conv_mut.clone());
+        // Delegate the coercion from newtypes transparent over `In`, both the
+        // ones registered already and the ones to come. The coercion to
+        // `dyn Any` is not delegated, as it exposes the newtype itself.
+        if TypeId::of::<Out>() != TypeId::of::<dyn Any>() {
+            let delegate: DelegateFn = Arc::new(move |inner, type_in_name| {
+                delegated_coercion_fns::<In, Out, _, _>(
+                    inner,
+                    type_in_name,
+                    conv.clone(),
+                    conv_mut.clone(),
+                )
+            });
+            let newtypes = self.transparent.get(&TypeId::of::<In>()).cloned();
+            for newtype in newtypes.iter().flatten() {
+                self.register_delegated_coercion(newtype, TypeId::of::<Out>(), &delegate);
+            }
+            self.delegates
+                .entry(TypeId::of::<In>())
+                .or_default()
+                .push((TypeId::of::<Out>(), delegate));
+        }
+    }
+
+    /// Creates and registers coercion functions for converting between types
+    /// `In` and `Out` for `RefCell<In>` container of `LocalDynBox`, which
+    /// borrow the `RefCell` instead of locking. Conflicting borrows panic, as
+    /// `RefCell` does.
+    ///
+    /// # Parameters
+    ///
+    /// - `conv`: A function for read coercion, `None` means that the value
+    ///   can't be coerced.
+    /// - `conv_mut`: A function for write coercion, `None` means that the value
+    ///   can't be coerced.
+    fn register_local_coercion<In, Out, F, FMut>(&mut self, conv: F, conv_mut: FMut)
+    where
+        In: Sized + 'static,
+        Out: ?Sized + 'static,
+        F: for<'a> Fn(&'a In) -> Option<&'a Out> + Clone + Send + Sync + 'static,
+        FMut:
+            for<'a> Fn(&'a mut In) -> Option<&'a mut Out> + Clone + Send + Sync + 'static,
+    {
+        let type_in_name = String::from(self.type_name(&TypeId::of::<In>()));
+        let type_in_name_mut = type_in_name.clone();
+        let f: LocalCoercionInAny = Arc::new(move |rc: LocalRc| {
+            let ohandle = OwningHandle::try_new(rc, |cell| {
+                let cell = unsafe { cell.as_ref() }
+                    .unwrap()
+                    .downcast_ref::<RefCell<In>>()
+                    .unwrap_or_else(|| unsupported_local_container(&type_in_name));
+                OwningRef::new(cell.borrow()).try_map(|x| conv(x).ok_or(()))
+            })
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name));
+            let handle: Handle<Out> =
+                OwningRef::new(ohandle).map_owner_box().erase_owner();
+            Box::new(handle)
+        });
+        let f_mut: LocalCoercionInAny = Arc::new(move |rc: LocalRc| {
+            let ohandle = OwningHandle::try_new(rc, |cell| {
+                let cell = unsafe { cell.as_ref() }
+                    .unwrap()
+                    .downcast_ref::<RefCell<In>>()
+                    .unwrap_or_else(|| unsupported_local_container(&type_in_name_mut));
+                OwningRefMut::new(cell.borrow_mut())
+                    .try_map_mut(|x| conv_mut(x).ok_or(()))
+            })
+            .unwrap_or_else(|()| conversion_failed::<Out>(&type_in_name_mut));
+            let handle: HandleMut<Out> =
+                OwningRefMut::new(ohandle).map_owner_box().erase_owner();
+            Box::new(handle)
+        });
+        self.local_traits.insert(
+            (TypeId::of::<RefCell<In>>(), TypeId::of::<Out>()),
+            LocalCoercionFns {
+                coerce: f,
+                coerce_mut: f_mut,
+            },
+        );
+    }
+
+    /// Registers coercion functions for converting the containers of a
+    /// transparent newtype to the type identified by `type_out`, which coerce
+    /// the newtype to the type it wraps first, see `DelegateFn`. Coercions
+    /// which the newtype has registered itself are left intact.
+    fn register_delegated_coercion(
+        &mut self,
+        newtype: &TransparentType,
+        type_out: TypeId,
+        delegate: &DelegateFn,
+    ) {
+        count!(registrations);
+        let fs = delegate(&newtype.inner, &newtype.name);
+        for container in &newtype.containers {
+            self.traits
+                .entry((*container, type_out))
+                .or_insert_with(|| fs.clone());
+        }
+    }
+
+    /// Registers coercion functions for converting `In` to itself, which is
+    /// what `DynBox<T>::coerce` does for concrete types. Handles are built
+    /// right out of lock guards, without going through a conversion function.
+    /// They are boxed as `Handle<In>`, which is the same type as `Handle<Out>`
+    /// at the call site, so downcasting them succeeds.
+    fn register_identity<In: Sized + 'static>(&mut self) {
+        count!(registrations);
+        let type_in_name = String::from(self.type_name(&TypeId::of::<In>()));
+        let type_in_name_mut = type_in_name.clone();
+        let type_in_name_ref = type_in_name.clone();
+        let type_in_name_try = type_in_name.clone();
+        let type_in_name_try_mut = type_in_name.clone();
+        #[cfg(feature = "parking_lot")]
+        let type_in_name_upgradable = type_in_name.clone();
+        let f: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::new_with_fn(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                lock_read::<In>(any, &type_in_name)
+                    .unwrap_or_else(|| unsupported_container("", &type_in_name))
+            });
+            let handle: Handle<In> =
+                OwningRef::new(ohandle).map_owner_box().erase_owner();
+            Box::new(handle)
+        });
+        let f_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::new_with_fn(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                lock_write::<In>(any, &type_in_name_mut).unwrap_or_else(|| {
+                    unsupported_mut_container::<In>(any, "mut ", &type_in_name_mut)
+                })
+            });
+            let handle: HandleMut<In> =
+                OwningRefMut::new(ohandle).map_owner_box().erase_owner();
+            Box::new(handle)
+        });
+        let f_ref: CoercionRefAny = Arc::new(borrowed_coercion(move |any| {
+            let guard = lock_read::<In>(any, &type_in_name_ref)
+                .unwrap_or_else(|| unsupported_container("ref ", &type_in_name_ref));
+            OwningRef::new(guard).map_owner_box().erase_owner()
+        }));
+        let f_try: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::try_new(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                try_lock_read::<In>(any)
+                    .unwrap_or_else(|| unsupported_container("try ", &type_in_name_try))
+                    .ok_or(())
+            });
+            let handle: Option<Handle<In>> = ohandle
+                .ok()
+                .map(|ohandle| OwningRef::new(ohandle).map_owner_box().erase_owner());
+            Box::new(handle)
+        });
+        let f_try_mut: CoercionInAny = Arc::new(move |boxed_t: DynArc| {
+            let ohandle = OwningHandle::try_new(boxed_t, |bt| {
+                let any = unsafe { bt.as_ref() }.unwrap();
+                try_lock_write_or_skip::<In>(any, &type_in_name_try_mut).ok_or(())
+            });
+            let handle: Option<HandleMut<In>> = ohandle
+                .ok()
+                .map(|ohandle| OwningRefMut::new(ohandle).map_owner_box().erase_owner());
+            Box::new(handle)
+        });
+        #[cfg(feature = "parking_lot")]
+        let f_upgradable = upgradable_coercion::<In, In, _, _>(
+            type_in_name_upgradable,
+            |x: &In| Some(x),
+            |x: &mut In| Some(x),
+        );
+        self.register_container_coercion_fns::<In>(
+            TypeId::of::<In>(),
+            CoercionFns {
+                coerce: f,
+                coerce_mut: f_mut,
+                try_coerce: f_try,
+                try_coerce_mut: f_try_mut,
+                coerce_ref: f_ref,
+                #[cfg(feature = "parking_lot")]
+                coerce_upgradable: f_upgradable,
+            },
+        );
+        self.register_local_coercion::<In, In, _, _>(
+            |x: &In| Some(x),
+            |x: &mut In| Some(x),
+        );
+    }
+
+    /// Retrieves the coercion functions for a given output type.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A reference to a `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// A `CoercionFns` set of functions for read and write coercions.
+    fn get_coerce_fns<'a, Out: ?Sized + 'static>(
+        &'a self,
+        input: &'a DynArc,
+    ) -> &'a CoercionFns {
+        // Projections carry their coercion functions, see `project`
+        if let Some(projection) = (**input).downcast_ref::<Projection>() {
+            if projection.part == TypeId::of::<Out>() {
+                return &projection.fns;
+            }
+            let delegated = projection
+                .delegated
+                .get_or_init(|| self.projection_delegates(projection));
+            return delegated.get(&TypeId::of::<Out>()).unwrap_or_else(|| {
+                coercion_panic(format!(
+                    "there is no registered coercion for a projection to {:?} => {:?}",
+                    projection.part_name,
+                    std::any::type_name::<Out>()
+                ))
+            });
+        }
+        // Retrieve the `TypeId` of the input type.
+        // `**` is for: &Arc<dyn Any> -> Arc<dyn Any> -> dyn Any
+        let type_in = (**input).type_id();
+        // Retrieve the `TypeId` of the output type.
+        let type_out = TypeId::of::<Out>();
+        trace!(
+            "coerce {} => {}",
+            self.type_name(&type_in),
+            std::any::type_name::<Out>()
+        );
+        // Retrieve the coercion functions from the registry.
+        self.traits.get(&(type_in, type_out)).unwrap_or_else(|| {
+            coercion_panic(
+                self.missing_coercion_message(&type_in, std::any::type_name::<Out>()),
+            )
+        })
+    }
+
+    /// Composes the coercion functions of a projection to its part with the
+    /// coercions registered for the type of the part, except the one to
+    /// `dyn Any`, see `DelegateFn`. Coercions registered after the first
+    /// coercion of the projection to a type other than its part are missed.
+    ///
+    /// # Returns
+    ///
+    /// The composed coercion functions, by the `TypeId` of the output type.
+    fn projection_delegates(
+        &self,
+        projection: &Projection,
+    ) -> HashMap<TypeId, CoercionFns> {
+        self.delegates
+            .get(&projection.part)
+            .into_iter()
+            .flatten()
+            .map(|(type_out, delegate)| {
+                (*type_out, delegate(&projection.fns, projection.part_name))
+            })
+            .collect()
+    }
+
+    /// Retrieves the coercion functions of a `LocalRc` input for a given
+    /// output type, same as `get_coerce_fns`.
+    fn get_local_coerce_fns<Out: ?Sized + 'static>(
+        &self,
+        input: &LocalRc,
+    ) -> &LocalCoercionFns {
+        let type_in = (**input).type_id();
+        let type_out = TypeId::of::<Out>();
+        trace!(
+            "coerce local {} => {}",
+            self.type_name(&type_in),
+            std::any::type_name::<Out>()
+        );
+        self.local_traits
+            .get(&(type_in, type_out))
+            .unwrap_or_else(|| {
+                coercion_panic(
+                    self.missing_coercion_message(&type_in, std::any::type_name::<Out>()),
+                )
+            })
+    }
+
+    /// Builds the error message for a missing coercion, suggesting registered
+    /// output types which might have been meant instead. It's only used on the
+    /// error path, so it may scan the whole registry.
+    ///
+    /// # Parameters
+    ///
+    /// - `type_in`: A reference to the `TypeId` of the input type.
+    /// - `type_out_name`: The name of the requested output type.
+    ///
+    /// # Returns
+    ///
+    /// The error message, with suggestions if any.
+    fn missing_coercion_message(&self, type_in: &TypeId, type_out_name: &str) -> String {
+        let type_in_name = self.type_name(type_in);
+        let mut msg = format!(
+            "there is no registered coercion for {:?} => {:?}",
+            type_in_name, type_out_name
+        );
+        // Names of output types registered for the input type. Outputs without
+        // a name (e.g. `dyn Any`) are not worth suggesting.
+        let mut targets: Vec<&str> = self
+            .traits
+            .keys()
+            .filter(|(input, _)| input == type_in)
+            .filter_map(|(_, output)| self.types.get(output).map(String::as_str))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        // Names of all output types registered for any input type, which look
+        // similar to the requested one.
+        let threshold = (type_out_name.len() / 4).max(3);
+        let mut similar: Vec<&str> = self
+            .traits
+            .keys()
+            .filter_map(|(_, output)| self.types.get(output).map(String::as_str))
+            .filter(|name| {
+                name.starts_with(type_out_name)
+                    || levenshtein(name, type_out_name) <= threshold
+            })
+            .collect();
+        similar.sort_unstable();
+        similar.dedup();
+        if !similar.is_empty() {
+            msg.push_str(&format!("\n  did you mean: {}?", quote_names(&similar)));
+        }
+        if targets.is_empty() {
+            msg.push_str(&format!(
+                "\n  no coercions are registered for {:?}, make sure that its type is \
+                 registered",
+                type_in_name
+            ));
+        } else {
+            msg.push_str(&format!(
+                "\n  registered coercions for {:?}: {}",
+                type_in_name,
+                quote_names(&targets)
+            ));
+        }
+        msg
+    }
+
+    /// Retrieves the type name for a given `TypeId`.
+    ///
+    /// # Parameters
+    ///
+    /// - `type_in`: A reference to a `TypeId`.
+    ///
+    /// # Returns
+    ///
+    /// A string slice representing the type name.
+    fn type_name(&self, type_in: &TypeId) -> &str {
+        // Retrieve the type name from the registry.
+        match self.types.get(type_in) {
+            Some(name) => name.as_str(),
+            None => "<unregistered type>",
+        }
+    }
+
+    /// Checks if there is a registered coercion of a `DynArc` input to the
+    /// specified output type.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A reference to a `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the input can be coerced to the output type.
+    fn has_coercion<Out: ?Sized + 'static>(&self, input: &DynArc) -> bool {
+        match upgrade_weak(input.clone()) {
+            Some(input) => match (*input).downcast_ref::<Projection>() {
+                Some(projection) => {
+                    projection.part == TypeId::of::<Out>()
+                        || self
+                            .delegates
+                            .get(&projection.part)
+                            .is_some_and(|delegates| {
+                                delegates
+                                    .iter()
+                                    .any(|(type_out, _)| *type_out == TypeId::of::<Out>())
+                            })
+                }
+                None => self
+                    .traits
+                    .contains_key(&((*input).type_id(), TypeId::of::<Out>())),
+            },
+            None => false,
+        }
+    }
+
+    /// Coerces a `DynArc` input to a handle of the specified output type.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced output type.
+    fn coerce<Out: ?Sized + 'static>(&self, input: DynArc) -> Handle<Out> {
+        count!(coercions);
+        let input = upgrade_weak_or_fail::<Out>(input);
+        // Retrieve the read coercion function.
+        let f = &self.get_coerce_fns::<Out>(&input).coerce;
+        // Coerce the input to the output type.
+        // Coerce the input to the mutable output type.
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Coerces a `DynArc` input to a mutable handle of the specified output type.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// A mutable handle to the coerced output type.
+    fn coerce_mut<Out: ?Sized + 'static>(&self, input: DynArc) -> HandleMut<Out> {
+        count!(coercions);
+        let input = upgrade_weak_or_fail::<Out>(input);
+        self.check_mut_coercion(&input);
+        // Retrieve the write coercion function.
+        let f = &self.get_coerce_fns::<Out>(&input).coerce_mut;
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Coerces a `LocalRc` input to a handle of the specified output type,
+    /// borrowing the `RefCell` which holds the value.
+    fn coerce_local<Out: ?Sized + 'static>(&self, input: LocalRc) -> Handle<Out> {
+        count!(coercions);
+        let f = &self.get_local_coerce_fns::<Out>(&input).coerce;
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Coerces a `LocalRc` input to a mutable handle of the specified output
+    /// type, borrowing the `RefCell` which holds the value mutably.
+    fn coerce_local_mut<Out: ?Sized + 'static>(&self, input: LocalRc) -> HandleMut<Out> {
+        count!(coercions);
+        let f = &self.get_local_coerce_fns::<Out>(&input).coerce_mut;
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Attempts to coerce a `DynArc` input to a handle of the specified output
+    /// type without blocking.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced output type, or `None` if the lock is held
+    /// elsewhere, a fallible conversion failed or the value behind a weak
+    /// reference was dropped.
+    fn try_coerce<Out: ?Sized + 'static>(&self, input: DynArc) -> Option<Handle<Out>> {
+        count!(coercions);
+        let input = upgrade_weak(input)?;
+        // Retrieve the non-blocking read coercion function.
+        let f = &self.get_coerce_fns::<Out>(&input).try_coerce;
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Attempts to coerce a `DynArc` input to a mutable handle of the specified
+    /// output type without blocking.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// A mutable handle to the coerced output type, or `None` if the lock is
+    /// held elsewhere, the value is pinned or immutable, a fallible conversion
+    /// failed or the value behind a weak reference was dropped.
+    fn try_coerce_mut<Out: ?Sized + 'static>(
+        &self,
+        input: DynArc,
+    ) -> Option<HandleMut<Out>> {
+        count!(coercions);
+        let input = upgrade_weak(input)?;
+        if self.immutable_only_type_info(&input).is_some() {
+            return None;
+        }
+        // Retrieve the non-blocking write coercion function.
+        let f = &self.get_coerce_fns::<Out>(&input).try_coerce_mut;
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Checks if the container of a `DynArc` input is locked.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A reference to a `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the container can't be locked for writing right now, `false`
+    /// if the value behind a weak reference was dropped.
+    fn is_locked(&self, input: &DynArc) -> bool {
+        // A dropped value is not locked by anyone
+        let Some(input) = container_root(input) else {
+            return false;
+        };
+        let type_in = (*input).type_id();
+        let probe = self.lock_probes.get(&type_in).unwrap_or_else(|| {
+            panic!(
+                "there is no registered container for {:?}",
+                self.type_name(&type_in)
+            )
+        });
+        probe(&*input)
+    }
+
+    /// Coerces a borrowed `DynArc` input to a handle of the specified output
+    /// type without cloning the `Arc`.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A reference to a `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the coerced output type, borrowing the input.
+    fn coerce_ref<'a, Out: ?Sized + 'static>(
+        &self,
+        input: &'a DynArc,
+    ) -> BorrowedHandle<'a, Out> {
+        // The value behind a weak reference can't be borrowed, as the `Arc`
+        // it's upgraded to is not held by the input, so it's coerced as usual
+        if (**input).is::<DynWeak>() {
+            return self.coerce(input.clone());
+        }
+        count!(coercions);
+        // Retrieve the borrowing read coercion function.
+        let f = self
+            .get_coerce_fns::<Out>(input)
+            .coerce_ref
+            .downcast_ref::<BorrowedCoercion<Out>>()
+            .expect("coercion fn has wrong type");
+        f(&**input)
+    }
+
+    /// Coerces a `DynArc` input to an upgradable read handle of the specified
+    /// output type.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// An upgradable handle to the coerced output type.
+    #[cfg(feature = "parking_lot")]
+    fn coerce_upgradable<Out: ?Sized + 'static>(
+        &self,
+        input: DynArc,
+    ) -> UpgradableHandle<Out> {
+        count!(coercions);
+        let input = upgrade_weak_or_fail::<Out>(input);
+        // Retrieve the upgradable read coercion function.
+        let f = &self.get_coerce_fns::<Out>(&input).coerce_upgradable;
+        *f(input.clone())
+            .downcast()
+            .expect("coercion fn returned wrong type")
+    }
+
+    /// Creates a `DynArc` holding a `Projection` of the value of a `DynArc`
+    /// input to its part, see `project`.
+    ///
+    /// # Parameters
+    ///
+    /// - `parent`: A `DynArc` input, which has to coerce to `In`.
+    /// - `get`: A function pointer projecting the value to its part.
+    /// - `get_mut`: A function pointer projecting the value to its part mutably.
+    ///
+    /// # Returns
+    ///
+    /// A `DynArc` which coerces to the part.
+    fn project<In: ?Sized + 'static, Out: ?Sized + 'static>(
+        &self,
+        parent: DynArc,
+        get: fn(&In) -> &Out,
+        get_mut: fn(&mut In) -> &mut Out,
+    ) -> DynArc {
+        trace!(
+            "project {} => {}",
+            std::any::type_name::<In>(),
+            std::any::type_name::<Out>()
+        );
+        let parent = upgrade_weak_or_fail::<In>(parent);
+        let fns = projection_fns(self.get_coerce_fns::<In>(&parent), get, get_mut);
+        Arc::new(Projection {
+            parent,
+            part: TypeId::of::<Out>(),
+            part_name: std::any::type_name::<Out>(),
+            fns,
+            delegated: OnceLock::new(),
+        })
+    }
+
+    /// Retrieves the kind of container of a `DynArc` input.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: A reference to a `DynArc` input.
+    ///
+    /// # Returns
+    ///
+    /// The kind of container holding the value. Panics if the value behind a
+    /// weak reference was dropped.
+    fn container_kind(&self, input: &DynArc) -> ContainerKind {
+        let input = container_root(input)
+            .unwrap_or_else(|| panic!("the value behind a weak reference was dropped"));
+        let type_in = (*input).type_id();
+        *self.containers.get(&type_in).unwrap_or_else(|| {
+            panic!(
+                "there is no registered container for {:?}",
+                self.type_name(&type_in)
+            )
+        })
+    }
+
+    /// Checks if `In` implements the trait with the given fully qualified name,
+    /// see `TypeInfo::implements`. Types without type info implement nothing.
+    fn implements<In: ?Sized + 'static>(&self, trait_fq_name: &str) -> bool {
+        self.type_info_map
+            .get(&TypeId::of::<In>())
+            .is_some_and(|type_info| type_info.implements(trait_fq_name))
+    }
+
+    /// Retrieves the type information for a given input type.
+    ///
+    /// # Parameters
+    ///
+    /// - `In`: The trait object type to retrieve information for.
+    ///
+    /// # Returns
+    ///
+    /// A `TypeInfo` struct containing the type information.
+    fn get_type_info<In: ?Sized + 'static>(&self) -> TypeInfo {
+        // Retrieve the `TypeId` of the input type.
+        let type_id = TypeId::of::<In>();
+        // Retrieve the type information from the registry.
+        let type_info = self.type_info_map.get(&type_id).unwrap_or_else(|| {
+            panic!(
+                "registry does not contain a registered type info for {}",
+                std::any::type_name::<In>()
+            )
+        });
+        type_info.clone()
+    }
+}
+
+/// Returns a reference to the global registry.
+///
+/// # Returns
+///
+/// A reference to the global `RwLock<Registry>`.
+fn global_registry() -> &'static RwLock<Registry> {
+    // Initialize the global registry.
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(Registry::new()))
+}
+
+thread_local! {
+    /// Registry installed by `with_registry` on this thread, if any.
+    static SCOPED_REGISTRY: RefCell<Option<Rc<RwLock<Registry>>>> =
+        const { RefCell::new(None) };
+}
+
+/// Passes the registry in effect on the current thread to `f`: the one
+/// installed by `with_registry`, or the global one otherwise.
+fn with_current_registry<R>(f: impl FnOnce(&RwLock<Registry>) -> R) -> R {
+    // Clone the `Rc` out, so that `f` may call `with_registry` itself
+    match SCOPED_REGISTRY.with(|scoped| scoped.borrow().clone()) {
+        Some(registry) => f(&registry),
+        None => f(global_registry()),
+    }
+}
+
+/// Puts back the registry which was in effect before `with_registry`, even if
+/// `f` panics.
+struct ScopedRegistryGuard(Option<Rc<RwLock<Registry>>>);
+
+impl Drop for ScopedRegistryGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        SCOPED_REGISTRY.with(|scoped| *scoped.borrow_mut() = previous);
+    }
+}
+
+/// Runs `f` with a fresh, empty registry in place of the global one on the
+/// current thread, and restores the previous registry afterwards. All
+/// registrations and coercions made by `f` on this thread (including the ones
+/// made by `DynBox` methods) use the scoped registry, and don't affect the
+/// global one. Threads spawned by `f` keep using the global registry.
+///
+/// This lets tests register types in isolation and run in parallel without
+/// `#[serial]`. Handles obtained within `f` stay valid after it returns, but
+/// further coercions of the same values outside of `f` need their types
+/// registered in the global registry.
+///
+/// # Returns
+///
+/// The result of `f`.
+pub fn with_registry<R>(f: impl FnOnce() -> R) -> R {
+    let fresh = Rc::new(RwLock::new(Registry::new()));
+    let previous = SCOPED_REGISTRY.with(|scoped| scoped.borrow_mut().replace(fresh));
+    let _guard = ScopedRegistryGuard(previous);
+    f()
+}
+
+/// Sets what failed coercions do project-wide, e.g. coercions of values which
+/// have no registered coercion to the requested type, or mutable coercions of
+/// immutable values. They panic by default, which aborts the whole OCaml
+/// program when the panic reaches a stub. With `PanicPolicy::RaiseOcaml`, they
+/// unwind the stub up to its boundary instead, where
+/// `Ocaml_rs_smartptr.Registry.Coercion_error` is raised with the same
+/// message, which OCaml code may handle. Other panics are not affected.
+///
+/// The boundary is set by `#[smartptr_func]` (and the stubs generated by
+/// `OcamlAccessors`), or by hand with `catch_coercion_error`, whose error
+/// converts to `ocaml::Error`. Failures outside of any boundary, e.g. in stubs
+/// defined with plain `#[ocaml::func]` or on threads not running OCaml code,
+/// still panic, as the exception can only be raised by the stub.
+///
+/// # Parameters
+///
+/// - `policy`: The policy to apply to all subsequent coercions.
+pub fn set_panic_policy(policy: PanicPolicy) {
+    RAISE_OCAML.store(policy == PanicPolicy::RaiseOcaml, Ordering::Relaxed);
+}
+
+/// Returns the policy set with `set_panic_policy`.
+pub fn panic_policy() -> PanicPolicy {
+    if RAISE_OCAML.load(Ordering::Relaxed) {
+        PanicPolicy::RaiseOcaml
+    } else {
+        PanicPolicy::Panic
+    }
+}
+
+/// Registers coercion functions for converting between types `In` and `Out` in the global registry.
+///
+/// # Parameters
+///
+/// - `conv`: A function pointer for read coercion.
+/// - `conv_mut`: A function pointer for write coercion.
+pub fn register<In: Sized + 'static, Out: ?Sized + 'static>(
+    conv: fn(&In) -> &Out,
+    conv_mut: fn(&mut In) -> &mut Out,
+) {
+    // Obtain a write lock on the global registry.
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register::<In, Out>(conv, conv_mut)
+    })
+}
+
+/// Registers coercions of `In` to several types in the global registry, with
+/// a single write lock. The outcome is the same as calling `register` for each
+/// of them, `register_type!` uses this to register all the coercions of a type
+/// at once, which keeps the generated code smaller.
+///
+/// # Parameters
+///
+/// - `coercions`: References to the coercions to register, see
+///   `Coercion::new`.
+pub fn register_multi<In: Sized + 'static>(coercions: &[&dyn RegisterCoercion<In>]) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_multi::<In>(coercions)
+    })
+}
+
+/// Registers fallible coercion functions for converting between types `In` and
+/// `Out` in the global registry. Blocking coercions panic if the conversion
+/// yields `None`, while `try_coerce` returns `None`.
+///
+/// # Parameters
+///
+/// - `conv`: A function pointer for read coercion.
+/// - `conv_mut`: A function pointer for write coercion.
+pub fn register_fallible<In: Sized + 'static, Out: ?Sized + 'static>(
+    conv: fn(&In) -> Option<&Out>,
+    conv_mut: fn(&mut In) -> Option<&mut Out>,
+) {
+    // Obtain a write lock on the global registry.
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_fallible::<In, Out>(conv, conv_mut)
+    })
+}
+
+/// Registers coercion closures for converting between types `In` and `Out` in
+/// the global registry, for coercions which can't be expressed with plain
+/// function pointers, e.g. ones driven by a table or a configuration captured
+/// by the closures. Coercions behave the same as the ones registered with
+/// `register`, except that the closures are always used, even when `In` and
+/// `Out` are the same type.
+///
+/// Each coercion goes through an extra indirect call of the closure behind the
+/// `Arc`, which the compiler can't inline, and coercion functions keep a
+/// reference to the closures for the lifetime of the registry. Prefer
+/// `register` when a function pointer does the job.
+///
+/// # Parameters
+///
+/// - `conv`: A closure for read coercion.
+/// - `conv_mut`: A closure for write coercion.
+pub fn register_with<In: Sized + 'static, Out: ?Sized + 'static>(
+    conv: Arc<dyn Fn(&In) -> &Out + Send + Sync>,
+    conv_mut: Arc<dyn Fn(&mut In) -> &mut Out + Send + Sync>,
+) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_with::<In, Out>(conv, conv_mut)
+    })
+}
+
+/// Registers the newtype `W` as transparent over the type `Inner` it wraps in
+/// the global registry, see `register_transparent!`. Values of `W` coerce to
+/// `Inner` through `AsRef` and `AsMut`, and to every type `Inner` coerces to,
+/// whether those coercions are registered before or after this call.
+/// Coercions registered for `W` itself take precedence, while `dyn Any` always
+/// exposes `W`. Values of `W` held by a `LocalDynBox` only coerce to `Inner`.
+pub fn register_transparent<W, Inner>()
+where
+    W: AsRef<Inner> + AsMut<Inner> + 'static,
+    Inner: 'static,
+{
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_transparent::<W, Inner>()
+    })
+}
+
+/// Registers a type in the global registry. Registering the same type again
+/// is a no-op, which only takes the read lock on the registry.
+///
+/// # Parameters
+///
+/// - `In`: The trait object type to register.
+pub fn register_type<In: ?Sized + 'static>() {
+    with_current_registry(|registry| {
+        // `DynBox` constructors call this for every new box, so skip the write
+        // lock along with the type name allocations once `In` is known. The
+        // check can't be cached in a static, as those are shared by all
+        // instantiations of a generic function, and the answer depends on the
+        // registry in use anyway (see `with_registry` and `restore`).
+        let registered = registry
+            .read()
+            .expect("unable to obtain read lock on global registry")
+            .is_type_registered::<In>();
+        if registered {
+            return;
+        }
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_type_and_containers::<In>();
+    })
+}
+
+/// Registers a concrete type in the global registry, same as `register_type`,
+/// along with the coercion of its containers to `dyn Any`. `DynBox`
+/// constructors register values this way, so that any `DynBox` can be coerced
+/// to `dyn Any` and downcast, e.g. with `DynBox::with_any`. Registering the
+/// same type again is a no-op, which only takes the read lock on the registry.
+///
+/// # Parameters
+///
+/// - `In`: The type to register.
+pub fn register_value_type<In: Sized + 'static>() {
+    with_current_registry(|registry| {
+        // Same fast path as in `register_type`
+        let registered = {
+            let registry = registry
+                .read()
+                .expect("unable to obtain read lock on global registry");
+            registry.is_type_registered::<In>() && registry.has_any_coercion::<In>()
+        };
+        if registered {
+            return;
+        }
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_type_and_containers::<In>();
+        registry.register_any::<In>();
+    })
+}
+
+/// Registers type information in the global registry.
+///
+/// # Parameters
+///
+/// - `In`: The trait object type to register.
+/// - `crate_name`: The name of the crate registering the type.
+/// - `fq_name`: The fully qualified name of the type.
+/// - `impls`: A vector of strings representing the implementations of the type.
+pub fn register_type_info<In: ?Sized + 'static>(
+    crate_name: &'static str,
+    fq_name: &'static str,
+    impls: Vec<&'static str>,
+) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_type_info::<In>(crate_name, fq_name, impls);
+    })
+}
+
+/// Records the containers `In` is meant to be held in, in the global registry.
+/// Write coercions of immutable values of types which are registered without
+/// a `Mutex` or a `RwLock` panic with a message saying so. `register_type!`
+/// calls it when the `containers` field is given:
+///
+/// ```rust,ignore
+/// register_type!({
+///     ty: crate::config::Config,
+///     marker_traits: [core::marker::Sync, core::marker::Send],
+///     containers: [Immutable],
+/// });
+/// ```
+///
+/// # Parameters
+///
+/// - `In`: The type to record the containers of, its type info has to be
+///   registered with `register_type_info`.
+/// - `containers`: The set of containers.
+pub fn register_type_containers<In: ?Sized + 'static>(containers: ContainerSet) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_type_containers::<In>(containers);
+    })
+}
+
+/// Records the bound of the polymorphic variant in the OCaml type of `In`, in
+/// the global registry. Bindings of closed types use `[ tags ]` instead of the
+/// default `[> tags ]`, so that stubs taking the type only accept objects
+/// with exactly its tags. `register_type!` calls it when the `variant_kind`
+/// field is given:
+///
+/// ```rust,ignore
+/// register_type!({
+///     ty: crate::config::Config,
+///     marker_traits: [core::marker::Sync, core::marker::Send],
+///     variant_kind: closed,
+/// });
+/// ```
+///
+/// # Parameters
+///
+/// - `In`: The type to record the variant kind of, its type info has to be
+///   registered with `register_type_info`.
+/// - `variant_kind`: The bound of the polymorphic variant.
+pub fn register_type_variant_kind<In: ?Sized + 'static>(variant_kind: VariantKind) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_type_variant_kind::<In>(variant_kind);
+    })
+}
+
+/// Registers `In` as holding memory outside of the OCaml heap in the global
+/// registry, see `MemSized`.
+///
+/// # Parameters
+///
+/// - `In`: The type of the value, as held by `DynBox`.
+pub fn register_mem_sized<In: MemSized + 'static>() {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_mem_sized::<In>();
+    })
+}
+
+/// Registers the coercion of `In` to `dyn DynEq` in the global registry, which
+/// makes `dyn_eq` compare values of `In` structurally.
+///
+/// # Parameters
+///
+/// - `In`: The type of the value, as held by `DynBox`.
+pub fn register_eq<In: PartialEq + 'static>() {
+    register::<In, dyn DynEq>(|x: &In| x as &dyn DynEq, |x: &mut In| x as &mut dyn DynEq);
+}
+
+/// Checks if two `DynArc` inputs are equal using the global registry. Inputs
+/// holding the same value are always equal. Otherwise, inputs are compared
+/// structurally if the type of `a` is registered with `register_eq`, and are
+/// never equal if it's not.
+///
+/// Both containers are locked for reading during a structural comparison, in
+/// the order of their addresses, so that concurrent comparisons of the same
+/// inputs can't deadlock each other.
+///
+/// # Parameters
+///
+/// - `a`, `b`: References to the `DynArc` inputs.
+///
+/// # Returns
+///
+/// `true` if the inputs are equal.
+pub fn dyn_eq(a: &DynArc, b: &DynArc) -> bool {
+    let address = |input: &DynArc| Arc::as_ptr(input) as *const () as usize;
+    if address(a) == address(b) {
+        return true;
+    }
+    if !has_coercion::<dyn DynEq>(a) || !has_coercion::<dyn Any>(b) {
+        return false;
+    }
+    if address(a) < address(b) {
+        let a = coerce_ref::<dyn DynEq>(a);
+        let b = coerce_ref::<dyn Any>(b);
+        a.dyn_eq(&*b)
+    } else {
+        let b = coerce_ref::<dyn Any>(b);
+        let a = coerce_ref::<dyn DynEq>(a);
+        a.dyn_eq(&*b)
+    }
+}
+
+/// Registers the coercion of `In` to `dyn DynOrd` in the global registry, which
+/// makes `dyn_compare` order values of `In` structurally. `In` is registered
+/// with `register_eq` as well, so that `dyn_eq` agrees with the ordering.
+/// `register_ord!` is a shorthand for it.
+///
+/// # Parameters
+///
+/// - `In`: The type of the value, as held by `DynBox`.
+pub fn register_ord<In: Ord + 'static>() {
+    register::<In, dyn DynOrd>(
+        |x: &In| x as &dyn DynOrd,
+        |x: &mut In| x as &mut dyn DynOrd,
+    );
+    register_eq::<In>();
+}
+
+/// Registers the structural ordering of a type in the global registry, see
+/// `registry::register_ord`:
+///
+/// ```rust,ignore
+/// register_ord!(Sheep);
+/// ```
+#[macro_export]
+macro_rules! register_ord {
+    ($ty:ty) => {
+        $crate::registry::register_ord::<$ty>()
+    };
+}
+
+/// Compares two `DynArc` inputs using the global registry, which gives a total
+/// order suitable for OCaml `Set` and `Map`. Inputs holding the same value are
+/// always equal. Inputs holding values of the same type registered with
+/// `register_ord` are compared structurally. Otherwise, inputs are ordered by
+/// the names of the types of their values, then by their addresses, which are
+/// stable for the lifetime of the values.
+///
+/// Both containers are locked for reading during a structural comparison, in
+/// the order of their addresses, same as with `dyn_eq`.
+///
+/// # Parameters
+///
+/// - `a`, `b`: References to the `DynArc` inputs.
+///
+/// # Returns
+///
+/// The ordering of `a` relative to `b`.
+pub fn dyn_compare(a: &DynArc, b: &DynArc) -> std::cmp::Ordering {
+    let address = |input: &DynArc| Arc::as_ptr(input) as *const () as usize;
+    if address(a) == address(b) {
+        return std::cmp::Ordering::Equal;
+    }
+    if has_coercion::<dyn DynOrd>(a) && has_coercion::<dyn Any>(b) {
+        let ordering = if address(a) < address(b) {
+            let a = coerce_ref::<dyn DynOrd>(a);
+            let b = coerce_ref::<dyn Any>(b);
+            a.dyn_cmp(&*b)
+        } else {
+            let b = coerce_ref::<dyn Any>(b);
+            let a = coerce_ref::<dyn DynOrd>(a);
+            a.dyn_cmp(&*b)
+        };
+        if let Some(ordering) = ordering {
+            return ordering;
+        }
+    }
+    type_name_of(a)
+        .cmp(&type_name_of(b))
+        .then(address(a).cmp(&address(b)))
+}
+
+/// Registers a function rendering values of `In` as strings in the global
+/// registry, e.g. for OCaml printers, see `show`. `register_show!` is a
+/// shorthand for it.
+///
+/// # Parameters
+///
+/// - `In`: The type of the value, as held by `DynBox`.
+/// - `show`: A function rendering the value.
+pub fn register_show<In: 'static>(show: fn(&In) -> String) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        registry.register_show::<In>(show);
+    })
+}
+
+/// Renders the value of a `DynArc` input as a string, using the function
+/// registered with `register_show`. The container is locked for reading while
+/// the function runs, but the global registry is not, so the function may use
+/// the registry itself.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// The rendered value, or `None` if the type is not registered with
+/// `register_show` or the value behind a weak reference was dropped.
+pub fn show(input: &DynArc) -> Option<String> {
+    let input = upgrade_weak(input.clone())?;
+    let f = with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.get_show_fn(&input)
+    })?;
+    Some(f(&*input))
+}
+
+/// Registers a function rendering values of a type as strings, see
+/// `registry::show`:
+///
+/// ```rust,ignore
+/// register_show!(Sheep => |sheep| format!("sheep {}", sheep.name()));
+/// ```
+#[macro_export]
+macro_rules! register_show {
+    ($ty:ty => $show:expr) => {
+        $crate::registry::register_show::<$ty>($show)
+    };
+}
+
+/// Retrieves the memory held by the value of a `DynArc` input, using the global
+/// registry. Never blocks.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// The memory size, or `None` if the type is not registered with
+/// `register_mem_sized`, the value is locked, or the value behind a weak
+/// reference was dropped.
+pub fn mem_size_of(input: &DynArc) -> Option<usize> {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.mem_size_of(input)
+    })
+}
+
+/// Coerces a `DynArc` input to a handle of the specified output type using the global registry.
+///
+/// # Parameters
+///
+/// - `input`: A `DynArc` input.
+///
+/// # Returns
+///
+/// A handle to the coerced output type.
+pub fn coerce<Out: ?Sized + 'static>(input: DynArc) -> Handle<Out> {
+    // Note: This function holds a lock on DynArc. Use with care to avoid deadlocks.
+    // Obtain a read lock on the global registry.
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.coerce::<Out>(input)
+    })
+}
+
+/// Coerces a `DynArc` input to a mutable handle of the specified output type using the global registry.
+///
+/// # Parameters
+///
+/// - `input`: A `DynArc` input.
+///
+/// # Returns
+///
+/// A mutable handle to the coerced output type.
+pub fn coerce_mut<Out: ?Sized + 'static>(input: DynArc) -> HandleMut<Out> {
+    // Note: This function holds a lock on DynArc. Use with care to avoid deadlocks.
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.coerce_mut::<Out>(input)
+    })
+}
+
+/// Coerces a `LocalDynBox` value to a handle of the specified output type
+/// using the global registry. The handle borrows the `RefCell` holding the
+/// value, so it panics if the value is mutably borrowed elsewhere.
+///
+/// # Parameters
+///
+/// - `input`: A `LocalRc` input.
+///
+/// # Returns
+///
+/// A handle to the coerced output type.
+pub fn coerce_local<Out: ?Sized + 'static>(input: LocalRc) -> Handle<Out> {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.coerce_local::<Out>(input)
+    })
+}
+
+/// Coerces a `LocalDynBox` value to a mutable handle of the specified output
+/// type using the global registry. The handle borrows the `RefCell` holding
+/// the value mutably, so it panics if the value is borrowed elsewhere.
+///
+/// # Parameters
+///
+/// - `input`: A `LocalRc` input.
+///
+/// # Returns
+///
+/// A mutable handle to the coerced output type.
+pub fn coerce_local_mut<Out: ?Sized + 'static>(input: LocalRc) -> HandleMut<Out> {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.coerce_local_mut::<Out>(input)
+    })
+}
+
+/// Coerces a borrowed `DynArc` input to a handle of the specified output type
+/// using the global registry. Unlike `coerce`, this does not clone the `Arc`,
+/// so the returned handle can't outlive the borrow.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// A handle to the coerced output type, borrowing the input.
+pub fn coerce_ref<Out: ?Sized + 'static>(input: &DynArc) -> BorrowedHandle<'_, Out> {
+    // Note: This function holds a lock on DynArc. Use with care to avoid deadlocks.
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.coerce_ref::<Out>(input)
+    })
+}
+
+/// Coerces a `DynArc` input to an upgradable read handle of the specified
+/// output type using the global registry. Only `parking_lot::RwLock` containers
+/// (i.e. `DynBox`es built with `LockPolicy::Fair`) are supported, others panic.
+///
+/// # Parameters
+///
+/// - `input`: A `DynArc` input.
+///
+/// # Returns
+///
+/// An upgradable handle to the coerced output type, see `UpgradableHandle`.
+#[cfg(feature = "parking_lot")]
+pub fn coerce_upgradable<Out: ?Sized + 'static>(input: DynArc) -> UpgradableHandle<Out> {
+    // Note: This function holds a lock on DynArc. Use with care to avoid deadlocks.
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.coerce_upgradable::<Out>(input)
+    })
+}
+
+/// Attempts to coerce a `DynArc` input to a handle of the specified output type
+/// using the global registry, without blocking on the container lock.
+///
+/// # Parameters
+///
+/// - `input`: A `DynArc` input.
+///
+/// # Returns
+///
+/// A handle to the coerced output type, or `None` if the lock is currently
+/// held elsewhere, or a coercion registered with `register_fallible` yielded
+/// `None`.
+pub fn try_coerce<Out: ?Sized + 'static>(input: DynArc) -> Option<Handle<Out>> {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.try_coerce::<Out>(input)
+    })
+}
+
+/// Attempts to coerce a `DynArc` input to a mutable handle of the specified
+/// output type using the global registry, without blocking on the container
+/// lock.
+///
+/// # Parameters
+///
+/// - `input`: A `DynArc` input.
+///
+/// # Returns
+///
+/// A mutable handle to the coerced output type, or `None` if the lock is
+/// currently held elsewhere, the value is pinned or immutable, or a coercion
+/// registered with `register_fallible` yielded `None`.
+pub fn try_coerce_mut<Out: ?Sized + 'static>(input: DynArc) -> Option<HandleMut<Out>> {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.try_coerce_mut::<Out>(input)
+    })
+}
+
+/// Checks if the container of a `DynArc` input is currently locked, i.e. it
+/// can't be locked for writing without blocking. The answer may be outdated as
+/// soon as it's returned, use `try_coerce_mut` to actually take the lock.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// `true` if the container is locked for reading or writing, `false` if the
+/// value behind a weak reference was dropped.
+pub fn is_locked(input: &DynArc) -> bool {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.is_locked(input)
+    })
+}
+
+/// Checks if there is a registered coercion of a `DynArc` input to the
+/// specified output type in the global registry.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// `true` if the input can be coerced to the output type.
+pub fn has_coercion<Out: ?Sized + 'static>(input: &DynArc) -> bool {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.has_coercion::<Out>(input)
+    })
+}
+
+/// Creates a `DynArc` holding a weak reference to the value of a `DynArc`
+/// input, e.g. for a back-reference of a child to its parent, which would
+/// otherwise form a cycle and leak both. It coerces like the input as long as
+/// the value is alive, after which `try_coerce` and `try_coerce_mut` return
+/// `None`, while other coercions fail. An input which is a weak reference
+/// already is returned as is.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// A `DynArc` which doesn't keep the value alive.
+pub fn downgrade(input: &DynArc) -> DynArc {
+    if (**input).is::<DynWeak>() {
+        return input.clone();
+    }
+    let weak: DynWeak = Arc::downgrade(input);
+    Arc::new(weak)
+}
+
+/// Upgrades a `DynArc` input created by `downgrade` back to the `DynArc` it
+/// refers to. Other inputs are returned as is.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// A `DynArc` which keeps the value alive, or `None` if the value was dropped
+/// already.
+pub fn upgrade(input: &DynArc) -> Option<DynArc> {
+    upgrade_weak(input.clone())
+}
+
+/// Creates a `DynArc` which coerces to a part of the value of a `DynArc` input,
+/// e.g. to a field of a struct, so that the part can be handed out on its own.
+/// The projection holds the input, which keeps the value alive for as long as
+/// the projection is alive, even if all other references to the input are
+/// dropped. Coercing the projection locks the container of the input, so the
+/// part is never accessed without the lock protecting the whole value.
+///
+/// The projection only coerces to the type of the part, and the coercion of
+/// the input to `In` is looked up once, when the projection is created. A weak
+/// input is upgraded, so the projection keeps the value alive as well.
+///
+/// # Parameters
+///
+/// - `parent`: A `DynArc` input, which has to coerce to `In`.
+/// - `get`: A function pointer projecting the value to its part.
+/// - `get_mut`: A function pointer projecting the value to its part mutably.
+///
+/// # Returns
+///
+/// A `DynArc` which coerces to the part.
+pub fn project<In: ?Sized + 'static, Out: ?Sized + 'static>(
+    parent: DynArc,
+    get: fn(&In) -> &Out,
+    get_mut: fn(&mut In) -> &mut Out,
+) -> DynArc {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.project::<In, Out>(parent, get, get_mut)
+    })
+}
+
+/// Retrieves the counters of the registry activity, which are incremented
+/// with relaxed atomics by coercions and registrations, for monitoring e.g.
+/// whether coercions fail in production. Counters are only maintained with
+/// `metrics` feature, and are global, i.e. shared by all registries (see
+/// `with_registry`). There is no per-type breakdown, as it would take a lock
+/// on every coercion.
+///
+/// # Returns
+///
+/// A snapshot of the counters.
+#[cfg(feature = "metrics")]
+pub fn stats() -> RegistryStats {
+    RegistryStats {
+        coercions: COUNTERS.coercions.load(Ordering::Relaxed),
+        failures: COUNTERS.failures.load(Ordering::Relaxed),
+        registrations: COUNTERS.registrations.load(Ordering::Relaxed),
+    }
+}
+
+/// Retrieves the name of the value type of a `DynArc` input from the global
+/// registry.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// The type name, or `<unregistered type>` if the type is not registered, and
+/// `<dropped value>` if the value behind a weak reference was dropped.
+pub fn type_name_of(input: &DynArc) -> String {
+    let Some(input) = upgrade_weak(input.clone()) else {
+        return "<dropped value>".to_owned();
+    };
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.type_name(&(*input).type_id()).to_owned()
+    })
+}
+
+/// Retrieves the kind of container of a `DynArc` input from the global registry.
+///
+/// # Parameters
+///
+/// - `input`: A reference to a `DynArc` input.
+///
+/// # Returns
+///
+/// The kind of container holding the value. Panics if the value behind a weak
+/// reference was dropped.
+pub fn container_kind(input: &DynArc) -> ContainerKind {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.container_kind(input)
+    })
+}
+
+/// Retrieves the type information for a given input type from the global registry.
+///
+/// # Parameters
+///
+/// - `In`: The trait object type to retrieve information for.
+///
+/// # Returns
+///
+/// A `TypeInfo` struct containing the type information.
+pub fn get_type_info<In: ?Sized + 'static>() -> TypeInfo {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.get_type_info::<In>()
+    })
+}
+
+/// Returns the implementations of `In`, i.e. its own name and the traits it
+/// was registered with, resolved the same way as `TypeInfo::namespaced_name`.
+/// Only paths rooted in `crate` name items of the crate which registered `In`
+/// and get its name, while absolute paths of foreign traits (e.g.
+/// `crate_a::Animal` listed by `crate_b`) are kept. Tags are derived from
+/// these, so same-named types registered by different crates don't collide,
+/// while the tags of a type still include the tags of the traits it coerces
+/// to.
+///
+/// # Panics
+///
+/// Panics if `In` has no type info registered.
+pub fn namespaced_implementations<In: ?Sized + 'static>() -> Vec<String> {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        let type_info = registry.get_type_info::<In>();
+        type_info
+            .implementations
+            .iter()
+            .map(|name| namespace(type_info.crate_name, name))
+            .collect()
+    })
+}
+
+/// Checks if `In` implements the trait with the given fully qualified name
+/// according to its type info in the global registry, see
+/// `TypeInfo::implements`. Only traits listed when registering `In` (e.g. with
+/// `register_type!`) are known, so this allows runtime decisions like printing
+/// an object with `Display` if it's registered as such, and falling back to its
+/// type name otherwise.
+///
+/// # Parameters
+///
+/// - `In`: The type to check.
+/// - `trait_fq_name`: The fully qualified name of the trait, e.g.
+///   `core::fmt::Display`.
+///
+/// # Returns
+///
+/// `true` if the trait is listed in the type info of `In`, `false` otherwise,
+/// including when `In` has no type info registered.
+pub fn implements<In: ?Sized + 'static>(trait_fq_name: &str) -> bool {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        registry.implements::<In>(trait_fq_name)
+    })
+}
+
+/// A copy of the global registry state, obtained with `snapshot` and put back
+/// with `restore`.
+#[derive(Clone)]
+pub struct RegistrySnapshot(Registry);
+
+/// Takes a snapshot of the global registry. Useful in tests, which can save
+/// the global state, register some types, and roll back afterwards.
+///
+/// # Returns
+///
+/// A `RegistrySnapshot` with copies of all registered coercions and types.
+pub fn snapshot() -> RegistrySnapshot {
+    with_current_registry(|registry| {
+        let registry = registry
+            .read()
+            .expect("unable to obtain read lock on global registry");
+        RegistrySnapshot(registry.clone())
+    })
+}
+
+/// Restores the global registry from a snapshot, discarding everything that
+/// was registered after the snapshot was taken.
+///
+/// # Parameters
+///
+/// - `snapshot`: A `RegistrySnapshot` previously obtained with `snapshot`.
+pub fn restore(snapshot: RegistrySnapshot) {
+    with_current_registry(|registry| {
+        let mut registry = registry
+            .write()
+            .expect("unable to obtain write lock on global registry");
+        *registry = snapshot.0;
+    })
+}
+
+/// The `Plugin` struct represents a plugin with an initializer function.
+pub struct Plugin {
+    /// A function pointer to the initializer function.
+    initializer: fn(),
+}
+
+impl Plugin {
+    /// Creates a new `Plugin` with the given initializer function.
+    ///
+    /// # Parameters
+    ///
+    /// - `initializer`: A function pointer to the initializer function.
+    ///
+    /// # Returns
+    ///
+    /// A new `Plugin` instance.
+    pub const fn new(initializer: fn()) -> Self {
+        // Create a new `Plugin` instance with the given initializer function.
         Plugin { initializer }
     }
 
-    /// Initializes the plugin by calling its initializer function.
-    fn initialize(&self) {
-        // Call the initializer function.
-        (self.initializer)();
+    /// Initializes the plugin by calling its initializer function.
+    fn initialize(&self) {
+        // Call the initializer function.
+        (self.initializer)();
+    }
+}
+
+inventory::collect!(Plugin);
+
+static INIT: Once = Once::new();
+
+/// Initializes all registered plugins. This function is called once.
+pub fn initialize_plugins() {
+    // Initialize all registered plugins.
+    INIT.call_once(|| {
+        for plugin in inventory::iter::<Plugin> {
+            plugin.initialize();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    macro_rules! register_trait {
+        ($type:ty, $($trait:tt)+) => {
+            $crate::registry::register_type::<$type>();
+            $crate::registry::register_type::<$($trait)+>();
+            $crate::registry::register::<$type, $($trait)+>(
+                |x: &$type| x as &($($trait)+),
+                |x: &mut $type| x as &mut ($($trait)+)
+            );
+        };
+    }
+
+    fn reinit_global_registry() {
+        let mut registry = global_registry().write().unwrap();
+        *registry = Registry::new()
+    }
+
+    trait Foo {
+        fn bar(&self) -> String;
+    }
+
+    impl Foo for i32 {
+        fn bar(&self) -> String {
+            format!("Foo for i32 ({:?})", self)
+        }
+    }
+
+    impl Foo for String {
+        fn bar(&self) -> String {
+            format!("Foo for String ({:?})", self)
+        }
+    }
+
+    trait FooMut {
+        fn bar_mut(&mut self) -> String;
+    }
+
+    impl FooMut for i32 {
+        fn bar_mut(&mut self) -> String {
+            *self += 1;
+            format!("FooMut for i32 ({:?})", self)
+        }
+    }
+
+    impl FooMut for String {
+        fn bar_mut(&mut self) -> String {
+            self.push('!');
+            format!("FooMut for String ({:?})", self)
+        }
+    }
+
+    fn test_display(values: Vec<DynArc>) -> Vec<String> {
+        let mut results = Vec::new();
+        for value in values {
+            let coerced = coerce::<dyn std::fmt::Display>(value);
+            let coerced = coerced.deref();
+            results.push(format!("{coerced}"));
+        }
+        results
+    }
+
+    fn test_foo(values: Vec<DynArc>) -> Vec<String> {
+        let mut results = Vec::new();
+        for value in values {
+            let coerced = coerce::<dyn Foo>(value);
+            let coerced = coerced.deref();
+            results.push(coerced.bar());
+        }
+        results
+    }
+
+    fn test_foo_send(values: Vec<DynArc>) -> Vec<String> {
+        let mut results = Vec::new();
+        for value in values {
+            let coerced = coerce::<dyn Foo + Send>(value);
+            let coerced = coerced.deref();
+            results.push(coerced.bar());
+        }
+        results
+    }
+
+    fn test_foo_ref(values: &[DynArc]) -> Vec<String> {
+        let mut results = Vec::new();
+        for value in values {
+            let coerced = coerce_ref::<dyn Foo>(value);
+            // Borrowing coercion should not bump the reference count
+            assert_eq!(Arc::strong_count(value), 1);
+            results.push(coerced.bar());
+        }
+        results
+    }
+
+    fn test_foo_mut(values: Vec<DynArc>) -> Vec<String> {
+        let mut results = Vec::new();
+        for value in values {
+            let mut coerced = coerce_mut::<dyn FooMut>(value);
+            results.push(coerced.bar_mut());
+        }
+        results
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_display() {
+        reinit_global_registry();
+        register_trait!(i32, dyn std::fmt::Display);
+        register_trait!(i32, dyn core::fmt::Debug);
+        register_trait!(i32, dyn Foo);
+        register_trait!(String, dyn std::fmt::Display);
+        register_trait!(String, dyn core::fmt::Debug);
+        register_trait!(String, dyn Foo);
+
+        let values: Vec<DynArc> = vec![
+            Arc::new(Mutex::new(1)),
+            Arc::new(RwLock::new(String::from("two"))),
+        ];
+        let results = test_display(values);
+
+        assert_eq!(results, vec!["1", "two"]);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_foo() {
+        reinit_global_registry();
+        register_trait!(i32, dyn std::fmt::Display);
+        register_trait!(i32, dyn core::fmt::Debug);
+        register_trait!(i32, dyn Foo);
+        register_trait!(String, dyn std::fmt::Display);
+        register_trait!(String, dyn core::fmt::Debug);
+        register_trait!(String, dyn Foo);
+
+        let values: Vec<DynArc> = vec![
+            Arc::new(Mutex::new(3)),
+            Arc::new(RwLock::new(String::from("four"))),
+        ];
+        let results = test_foo(values);
+
+        assert_eq!(
+            results,
+            vec!["Foo for i32 (3)", "Foo for String (\"four\")"]
+        );
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_local() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+        register_trait!(i32, i32);
+
+        // Rc<RefCell<T>> graphs are shared with LocalRc as is
+        let cell = Rc::new(RefCell::new(1));
+        let value: LocalRc = cell.clone();
+        assert_eq!(
+            coerce_local::<dyn Foo>(value.clone()).bar(),
+            "Foo for i32 (1)"
+        );
+        assert_eq!(
+            coerce_local_mut::<dyn FooMut>(value.clone()).bar_mut(),
+            "FooMut for i32 (2)"
+        );
+        *coerce_local_mut::<i32>(value.clone()) += 1;
+        assert_eq!(*cell.borrow(), 3);
+
+        // Handles are RefCell borrows, shared ones can be nested
+        let a = coerce_local::<dyn Foo>(value.clone());
+        let b = coerce_local::<i32>(value.clone());
+        assert_eq!(a.bar(), format!("Foo for i32 ({})", *b));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce_local_mut::<i32>(value.clone());
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_foo_ref() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(String, dyn Foo);
+
+        let values: Vec<DynArc> = vec![
+            Arc::new(Mutex::new(5)),
+            Arc::new(RwLock::new(String::from("six"))),
+        ];
+        let results = test_foo_ref(&values);
+
+        assert_eq!(results, vec!["Foo for i32 (5)", "Foo for String (\"six\")"]);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_any() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(String, dyn Foo);
+
+        let values: Vec<DynArc> = vec![
+            Arc::new(Mutex::new(7)),
+            Arc::new(RwLock::new(String::from("eight"))),
+        ];
+        let results: Vec<String> = values
+            .iter()
+            .map(|value| {
+                let any = coerce_ref::<dyn Any>(value);
+                if let Some(i) = any.downcast_ref::<i32>() {
+                    format!("i32 ({:?})", i)
+                } else if let Some(s) = any.downcast_ref::<String>() {
+                    format!("String ({:?})", s)
+                } else {
+                    "unknown".to_owned()
+                }
+            })
+            .collect();
+
+        assert_eq!(results, vec!["i32 (7)", "String (\"eight\")"]);
+    }
+
+    #[test]
+    fn test_dyn_eq() {
+        with_registry(|| {
+            register_value_type::<String>();
+            let dolly: DynArc = Arc::new(Mutex::new(String::from("dolly")));
+            let other_dolly: DynArc = Arc::new(RwLock::new(String::from("dolly")));
+            let shaun: DynArc = Arc::new(Mutex::new(String::from("shaun")));
+            let number: DynArc = Arc::new(Mutex::new(7));
+
+            // Physical equality unless registered with `register_eq`
+            assert!(dyn_eq(&dolly, &dolly.clone()));
+            assert!(!dyn_eq(&dolly, &other_dolly));
+
+            register_eq::<String>();
+            assert!(dyn_eq(&dolly, &other_dolly));
+            assert!(dyn_eq(&other_dolly, &dolly));
+            assert!(!dyn_eq(&dolly, &shaun));
+            assert!(!dyn_eq(&dolly, &number));
+        });
+    }
+
+    #[test]
+    fn test_dyn_compare() {
+        use std::cmp::Ordering;
+
+        with_registry(|| {
+            register_value_type::<String>();
+            register_value_type::<i32>();
+            let shaun: DynArc = Arc::new(Mutex::new(String::from("shaun")));
+            let dolly: DynArc = Arc::new(RwLock::new(String::from("dolly")));
+            let other_dolly: DynArc = Arc::new(String::from("dolly"));
+            let number: DynArc = Arc::new(Mutex::new(7));
+
+            // Unregistered values are only equal to themselves
+            assert_eq!(dyn_compare(&dolly, &dolly.clone()), Ordering::Equal);
+            assert_ne!(dyn_compare(&dolly, &other_dolly), Ordering::Equal);
+            assert_eq!(
+                dyn_compare(&dolly, &other_dolly),
+                dyn_compare(&other_dolly, &dolly).reverse()
+            );
+
+            register_ord::<String>();
+            assert_eq!(dyn_compare(&dolly, &other_dolly), Ordering::Equal);
+            assert!(dyn_eq(&dolly, &other_dolly));
+            assert_eq!(dyn_compare(&shaun, &dolly), Ordering::Greater);
+            // Values of other types are ordered by type name, and
+            // "alloc::string::String" < "i32"
+            assert_eq!(dyn_compare(&number, &dolly), Ordering::Greater);
+            assert_eq!(dyn_compare(&shaun, &number), Ordering::Less);
+
+            register_show::<String>(|x| x.clone());
+            register_show::<i32>(|x| x.to_string());
+            let mut values = [number, shaun, dolly];
+            values.sort_by(dyn_compare);
+            let shown: Vec<String> = values.iter().filter_map(show).collect();
+            assert_eq!(shown, vec!["dolly", "shaun", "7"]);
+        });
+    }
+
+    #[test]
+    fn test_register_value_type() {
+        struct Fleece(u32);
+
+        with_registry(|| {
+            let value: DynArc = Arc::new(RwLock::new(3u16));
+            assert!(!has_coercion::<dyn Any>(&value));
+            register_value_type::<u16>();
+            assert_eq!(coerce::<dyn Any>(value).downcast_ref::<u16>(), Some(&3));
+
+            // Explicitly registered coercions to `dyn Any` take precedence
+            let fleece: DynArc = Arc::new(Mutex::new(Fleece(4)));
+            register::<Fleece, dyn Any>(|x| &x.0, |x| &mut x.0);
+            register_value_type::<Fleece>();
+            register::<Fleece, Fleece>(|x| x, |x| x);
+            assert_eq!(
+                coerce_ref::<dyn Any>(&fleece).downcast_ref::<u32>(),
+                Some(&4)
+            );
+        });
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_try_coerce() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+
+        let mutex: DynArc = Arc::new(Mutex::new(9));
+        let held = coerce::<dyn Foo>(mutex.clone());
+        assert!(try_coerce::<dyn Foo>(mutex.clone()).is_none());
+        drop(held);
+        let coerced = try_coerce::<dyn Foo>(mutex).expect("mutex should be unlocked");
+        assert_eq!(coerced.bar(), "Foo for i32 (9)");
+
+        let rwlock: DynArc = Arc::new(RwLock::new(10));
+        let reader = coerce::<dyn Foo>(rwlock.clone());
+        assert!(try_coerce::<dyn Foo>(rwlock.clone()).is_some());
+        drop(reader);
+        let writer = coerce_mut::<dyn FooMut>(rwlock.clone());
+        assert!(try_coerce::<dyn Foo>(rwlock.clone()).is_none());
+        drop(writer);
+        assert!(try_coerce::<dyn Foo>(rwlock).is_some());
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_weak() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+
+        let strong: DynArc = Arc::new(RwLock::new(3));
+        let weak = downgrade(&strong);
+        assert!(Arc::ptr_eq(&downgrade(&weak), &weak));
+        assert_eq!(Arc::strong_count(&strong), 1);
+        assert!(has_coercion::<dyn Foo>(&weak));
+        assert_eq!(coerce::<dyn Foo>(weak.clone()).bar(), "Foo for i32 (3)");
+        assert_eq!(coerce_ref::<dyn Foo>(&weak).bar(), "Foo for i32 (3)");
+        coerce_mut::<dyn FooMut>(weak.clone()).bar_mut();
+        assert!(Arc::ptr_eq(&upgrade(&weak).unwrap(), &strong));
+        // The handle keeps the value alive
+        let handle = coerce::<dyn Foo>(weak.clone());
+        drop(strong);
+        assert_eq!(handle.bar(), "Foo for i32 (4)");
+        drop(handle);
+
+        assert!(upgrade(&weak).is_none());
+        assert!(!has_coercion::<dyn Foo>(&weak));
+        assert!(try_coerce::<dyn Foo>(weak.clone()).is_none());
+        assert!(try_coerce_mut::<dyn FooMut>(weak.clone()).is_none());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce::<dyn Foo>(weak.clone());
+        }));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("was dropped"), "{}", msg);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_projection() {
+        reinit_global_registry();
+        register::<(i32, (i32, i32)), (i32, (i32, i32))>(|x| x, |x| x);
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+
+        let pair: DynArc = Arc::new(RwLock::new((1, (2, 3))));
+        let first =
+            project::<(i32, (i32, i32)), i32>(pair.clone(), |x| &x.0, |x| &mut x.0);
+        let inner = project::<(i32, (i32, i32)), (i32, i32)>(
+            pair.clone(),
+            |x| &x.1,
+            |x| &mut x.1,
+        );
+        // Projections compose
+        let last = project::<(i32, i32), i32>(inner.clone(), |x| &x.1, |x| &mut x.1);
+        assert!(has_coercion::<i32>(&first));
+        assert!(!has_coercion::<(i32, (i32, i32))>(&first));
+        *coerce_mut::<i32>(first.clone()) += 10;
+        *coerce_mut::<i32>(last.clone()) += 10;
+        assert_eq!(*coerce::<(i32, (i32, i32))>(pair.clone()), (11, (2, 13)));
+        assert_eq!(*coerce_ref::<i32>(&last), 13);
+        assert_eq!(container_kind(&last), ContainerKind::RwLock);
+
+        // Projections share the lock of the parent
+        let handle = coerce_mut::<(i32, (i32, i32))>(pair.clone());
+        assert!(is_locked(&first));
+        assert!(try_coerce::<i32>(first.clone()).is_none());
+        assert!(try_coerce_mut::<i32>(last.clone()).is_none());
+        drop(handle);
+
+        // Projections keep the parent alive
+        drop(pair);
+        drop(inner);
+        assert_eq!(*coerce::<i32>(last.clone()), 13);
+
+        // Projections coerce to the types their part is registered to be
+        // coerced to, except `dyn Any`
+        assert!(has_coercion::<dyn Foo>(&last));
+        assert_eq!(coerce::<dyn Foo>(last.clone()).bar(), "Foo for i32 (13)");
+        assert_eq!(
+            coerce_mut::<dyn FooMut>(last.clone()).bar_mut(),
+            "FooMut for i32 (14)"
+        );
+        assert!(try_coerce::<dyn Foo>(first.clone()).is_some());
+        assert_eq!(coerce_ref::<dyn Foo>(&last).bar(), "Foo for i32 (14)");
+        assert!(!has_coercion::<dyn Any>(&last));
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce::<i64>(first.clone());
+        }));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(
+            msg.contains("no registered coercion for a projection to \"i32\""),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    #[serial(registry)]
+    #[cfg(feature = "metrics")]
+    fn test_stats() {
+        reinit_global_registry();
+        let before = stats();
+        register_trait!(i32, dyn Foo);
+        let value: DynArc = Arc::new(Mutex::new(1));
+        coerce::<dyn Foo>(value.clone());
+        assert!(try_coerce::<dyn Foo>(value.clone()).is_some());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce::<dyn FooMut>(value.clone());
+        }));
+        assert!(result.is_err());
+        // Counters are global, so tests running concurrently may add to them
+        let after = stats();
+        assert!(after.coercions >= before.coercions + 3);
+        assert!(after.failures > before.failures);
+        assert!(after.registrations >= before.registrations + 2);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_is_locked() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+
+        let mutex: DynArc = Arc::new(Mutex::new(1));
+        assert!(!is_locked(&mutex));
+        let held = coerce::<dyn Foo>(mutex.clone());
+        assert!(is_locked(&mutex));
+        assert!(try_coerce_mut::<dyn FooMut>(mutex.clone()).is_none());
+        drop(held);
+        let mut writer =
+            try_coerce_mut::<dyn FooMut>(mutex.clone()).expect("should be unlocked");
+        assert_eq!(writer.bar_mut(), "FooMut for i32 (2)");
+        assert!(is_locked(&mutex));
+        drop(writer);
+
+        let rwlock: DynArc = Arc::new(RwLock::new(2));
+        let reader = coerce::<dyn Foo>(rwlock.clone());
+        // Readers don't block each other, but are still reported as locked
+        assert!(is_locked(&rwlock));
+        assert!(try_coerce::<dyn Foo>(rwlock.clone()).is_some());
+        assert!(try_coerce_mut::<dyn FooMut>(rwlock.clone()).is_none());
+        drop(reader);
+        assert!(!is_locked(&rwlock));
+        assert!(try_coerce_mut::<dyn FooMut>(rwlock).is_some());
+
+        let pinned: DynArc = Arc::new(Mutex::new(Box::pin(3)));
+        let held = coerce::<dyn Foo>(pinned.clone());
+        assert!(is_locked(&pinned));
+        drop(held);
+        assert!(!is_locked(&pinned));
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_fallible() {
+        reinit_global_registry();
+        register_fallible::<Option<i32>, dyn Foo>(
+            |x| x.as_ref().map(|x| x as &dyn Foo),
+            |x| x.as_mut().map(|x| x as &mut dyn Foo),
+        );
+
+        let some: DynArc = Arc::new(Mutex::new(Some(3)));
+        assert_eq!(coerce::<dyn Foo>(some.clone()).bar(), "Foo for i32 (3)");
+        let coerced = try_coerce::<dyn Foo>(some).expect("conversion should succeed");
+        assert_eq!(coerced.bar(), "Foo for i32 (3)");
+
+        let none: DynArc = Arc::new(Mutex::new(None::<i32>));
+        assert!(try_coerce::<dyn Foo>(none.clone()).is_none());
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce::<dyn Foo>(none.clone()).bar()
+        }));
+        assert!(res.is_err());
+        // The lock is released before panicking, so it's not poisoned
+        assert!(try_coerce::<dyn Any>(none).is_some());
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_register_with() {
+        reinit_global_registry();
+        // The coercion picks an element by an index known at runtime only
+        let index = 1;
+        register_with::<[i32; 3], dyn Foo>(
+            Arc::new(move |x| &x[index]),
+            Arc::new(move |x| &mut x[index]),
+        );
+
+        let value: DynArc = Arc::new(Mutex::new([1, 2, 3]));
+        assert_eq!(coerce::<dyn Foo>(value.clone()).bar(), "Foo for i32 (2)");
+        assert_eq!(
+            coerce_mut::<dyn Foo>(value.clone()).bar(),
+            "Foo for i32 (2)"
+        );
+        let coerced = try_coerce::<dyn Foo>(value.clone()).expect("value is not locked");
+        assert_eq!(coerced.bar(), "Foo for i32 (2)");
+        drop(coerced);
+        assert!(try_coerce::<dyn Any>(value).is_some());
+    }
+
+    #[test]
+    fn test_missing_coercion_message() {
+        let mut registry = Registry::new();
+        registry.register_type::<Mutex<i32>, i32>();
+        registry.register_type::<dyn Foo + Send, dyn Foo + Send>();
+        registry.register::<i32, dyn Foo + Send>(
+            |x| x as &(dyn Foo + Send),
+            |x| x as &mut (dyn Foo + Send),
+        );
+        let foo = std::any::type_name::<dyn Foo>();
+        let foo_send = std::any::type_name::<dyn Foo + Send>();
+
+        let msg = registry.missing_coercion_message(&TypeId::of::<Mutex<i32>>(), foo);
+        assert!(msg.contains(&format!("did you mean: {:?}?", foo_send)));
+        assert!(
+            msg.contains(&format!("registered coercions for \"i32\": {:?}", foo_send))
+        );
+
+        let msg = registry.missing_coercion_message(&TypeId::of::<Mutex<u8>>(), "u8");
+        assert!(!msg.contains("did you mean"));
+        assert!(msg.contains("no coercions are registered"));
+
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("abc", "abc"), 0);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_identity() {
+        reinit_global_registry();
+        register_type::<i32>();
+        register::<i32, i32>(|x| x, |x| x);
+
+        let values: Vec<DynArc> = vec![
+            Arc::new(Mutex::new(1)),
+            Arc::new(RwLock::new(2)),
+            Arc::new(Mutex::new(Box::pin(3))),
+        ];
+        for (value, expected) in values.iter().zip(1..) {
+            assert_eq!(*coerce::<i32>(value.clone()), expected);
+            assert_eq!(*coerce_ref::<i32>(value), expected);
+            assert_eq!(try_coerce::<i32>(value.clone()).map(|x| *x), Some(expected));
+        }
+        *coerce_mut::<i32>(values[0].clone()) += 10;
+        *coerce_mut::<i32>(values[1].clone()) += 10;
+        assert_eq!(*coerce::<i32>(values[0].clone()), 11);
+        assert_eq!(*coerce::<i32>(values[1].clone()), 12);
+        assert_eq!(
+            *coerce::<dyn Any>(values[1].clone())
+                .downcast_ref::<i32>()
+                .unwrap(),
+            12
+        );
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce_mut::<i32>(values[2].clone());
+        }));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_mem_size_of() {
+        struct Buffer(Vec<u8>);
+
+        impl MemSized for Buffer {
+            fn mem_size(&self) -> usize {
+                self.0.capacity()
+            }
+        }
+
+        with_registry(|| {
+            let buffer: DynArc = Arc::new(RwLock::new(Buffer(Vec::with_capacity(64))));
+            assert_eq!(mem_size_of(&buffer), None);
+            register_type::<Buffer>();
+            register_mem_sized::<Buffer>();
+            assert_eq!(mem_size_of(&buffer), Some(64));
+            // Never blocks on a locked value
+            let rwlock = buffer.downcast_ref::<RwLock<Buffer>>().unwrap();
+            let guard = rwlock.write().unwrap();
+            assert_eq!(mem_size_of(&buffer), None);
+            drop(guard);
+        });
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_pinned() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+
+        let pinned: DynArc = Arc::new(Mutex::new(Box::pin(7)));
+        assert_eq!(test_foo(vec![pinned.clone()]), vec!["Foo for i32 (7)"]);
+        let pinned_ref: DynArc = Arc::new(Mutex::new(Box::pin(8)));
+        assert_eq!(test_foo_ref(&[pinned_ref]), vec!["Foo for i32 (8)"]);
+        let held = try_coerce::<dyn Foo>(pinned.clone()).expect("should be unlocked");
+        assert!(try_coerce::<dyn Foo>(pinned.clone()).is_none());
+        drop(held);
+        assert_eq!(container_kind(&pinned), ContainerKind::Mutex);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce_mut::<dyn FooMut>(pinned.clone());
+        }));
+        assert!(res.is_err());
+        assert!(try_coerce_mut::<dyn FooMut>(pinned.clone()).is_none());
     }
-}
 
-inventory::collect!(Plugin);
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    #[serial(registry)]
+    fn test_registry_fair_rwlock() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
 
-static INIT: Once = Once::new();
+        let fair: DynArc = Arc::new(parking_lot::RwLock::new(5));
+        assert_eq!(test_foo(vec![fair.clone()]), vec!["Foo for i32 (5)"]);
+        let fair_ref: DynArc = Arc::new(parking_lot::RwLock::new(6));
+        assert_eq!(test_foo_ref(&[fair_ref]), vec!["Foo for i32 (6)"]);
+        let reader = try_coerce::<dyn Foo>(fair.clone()).expect("should be unlocked");
+        assert!(try_coerce::<dyn Foo>(fair.clone()).is_some());
+        drop(reader);
+        assert_eq!(test_foo_mut(vec![fair.clone()]).len(), 1);
+        assert_eq!(container_kind(&fair), ContainerKind::RwLock);
+    }
 
-/// Initializes all registered plugins. This function is called once.
-pub fn initialize_plugins() {
-    // Initialize all registered plugins.
-    INIT.call_once(|| {
-        for plugin in inventory::iter::<Plugin> {
-            plugin.initialize();
-        }
-    });
-}
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    #[serial(registry)]
+    fn test_registry_upgradable() {
+        reinit_global_registry();
+        register_type::<i32>();
+        register::<i32, i32>(|x| x, |x| x);
+        register_trait!(i32, dyn FooMut);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serial_test::serial;
+        let fair: DynArc = Arc::new(parking_lot::RwLock::new(5));
+        let upgradable = coerce_upgradable::<i32>(fair.clone());
+        assert_eq!(*upgradable, 5);
+        assert_eq!(std::borrow::Borrow::<i32>::borrow(&upgradable), &5);
+        // Plain readers may share the lock with an upgradable handle
+        let reader = try_coerce::<i32>(fair.clone()).expect("should allow readers");
+        drop(reader);
+        let mut writer = upgradable.upgrade();
+        *writer += 1;
+        assert!(try_coerce::<i32>(fair.clone()).is_none());
+        drop(writer);
+        assert_eq!(*coerce::<i32>(fair.clone()), 6);
 
-    macro_rules! register_trait {
-        ($type:ty, $($trait:tt)+) => {
-            $crate::registry::register_type::<$type>();
-            $crate::registry::register_type::<$($trait)+>();
-            $crate::registry::register::<$type, $($trait)+>(
-                |x: &$type| x as &($($trait)+),
-                |x: &mut $type| x as &mut ($($trait)+)
-            );
-        };
-    }
+        let mut writer = coerce_upgradable::<dyn FooMut>(fair.clone()).upgrade();
+        assert_eq!(writer.bar_mut(), "FooMut for i32 (7)");
+        drop(writer);
 
-    fn reinit_global_registry() {
-        let mut registry = global_registry().write().unwrap();
-        *registry = Registry::new()
+        let exclusive: DynArc = Arc::new(Mutex::new(1));
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce_upgradable::<i32>(exclusive.clone());
+        }));
+        assert!(res.is_err());
     }
 
-    trait Foo {
-        fn bar(&self) -> String;
+    #[test]
+    #[serial(registry)]
+    fn test_handle_with() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+        register_trait!(i32, dyn FooMut);
+
+        let mutex: DynArc = Arc::new(Mutex::new(3));
+        let bar = coerce::<dyn Foo>(mutex.clone()).with(|foo| foo.bar());
+        assert_eq!(bar, "Foo for i32 (3)");
+        // The lock is released by the time `with` returns
+        assert!(try_coerce::<dyn Foo>(mutex.clone()).is_some());
+        let bar = coerce_mut::<dyn FooMut>(mutex.clone()).with_mut(|foo| foo.bar_mut());
+        assert_eq!(bar, "FooMut for i32 (4)");
+        assert!(try_coerce::<dyn Foo>(mutex.clone()).is_some());
+        let bar = coerce_ref::<dyn Foo>(&mutex).with(|foo| foo.bar());
+        assert_eq!(bar, "Foo for i32 (4)");
     }
 
-    impl Foo for i32 {
-        fn bar(&self) -> String {
-            format!("Foo for i32 ({:?})", self)
-        }
+    #[test]
+    #[serial(registry)]
+    fn test_handle_borrow() {
+        reinit_global_registry();
+        register_type::<String>();
+        register::<String, str>(|x| x.as_str(), |x| x.as_mut_str());
+
+        let values: Vec<DynArc> = vec![
+            Arc::new(RwLock::new(String::from("four"))),
+            Arc::new(String::from("five")),
+        ];
+        let lengths: HashMap<Handle<str>, usize> = values
+            .iter()
+            .map(|value| {
+                let handle = coerce::<str>(value.clone());
+                let len = handle.len();
+                (handle, len)
+            })
+            .collect();
+        // Looked up by `&str` through `Borrow<str>` of the keys
+        assert_eq!(lengths.get("four"), Some(&4));
+        assert_eq!(lengths.get("five"), Some(&4));
+        assert_eq!(lengths.get("six"), None);
+        assert!(coerce::<str>(values[0].clone()).as_ref().starts_with("fo"));
     }
 
-    impl Foo for String {
-        fn bar(&self) -> String {
-            format!("Foo for String ({:?})", self)
-        }
+    #[test]
+    #[serial(registry)]
+    fn test_registry_nested_reads() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+
+        // Nested read coercions of a RwLock are fine
+        let rwlock: DynArc = Arc::new(RwLock::new(1));
+        let outer = coerce::<dyn Foo>(rwlock.clone());
+        let inner = coerce::<dyn Foo>(rwlock.clone());
+        assert_eq!(outer.bar(), inner.bar());
+        drop((outer, inner));
+
+        // A Mutex is tracked as held by the current thread while coerced
+        let mutex: DynArc = Arc::new(Mutex::new(2));
+        let addr = mutex.downcast_ref::<Mutex<i32>>().unwrap() as *const Mutex<i32>;
+        let handle = coerce::<dyn Foo>(mutex.clone());
+        assert_eq!(HeldMutex::is_held(addr as usize), cfg!(debug_assertions));
+        drop(handle);
+        assert!(!HeldMutex::is_held(addr as usize));
     }
 
-    trait FooMut {
-        fn bar_mut(&mut self) -> String;
+    #[test]
+    #[serial(registry)]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "reentrant coercion of a Mutex-backed value")]
+    fn test_registry_reentrant_mutex() {
+        reinit_global_registry();
+        register_trait!(i32, dyn Foo);
+
+        let mutex: DynArc = Arc::new(Mutex::new(1));
+        let _outer = coerce::<dyn Foo>(mutex.clone());
+        // Would deadlock without the detection
+        let _inner = coerce::<dyn Foo>(mutex.clone());
     }
 
-    impl FooMut for i32 {
-        fn bar_mut(&mut self) -> String {
-            *self += 1;
-            format!("FooMut for i32 ({:?})", self)
-        }
+    #[test]
+    #[serial(registry)]
+    fn test_container_kind() {
+        reinit_global_registry();
+        register_type::<i32>();
+        register_type::<Box<dyn Foo + Send + Sync>>();
+
+        let mutex: DynArc = Arc::new(Mutex::new(1));
+        let rwlock: DynArc = Arc::new(RwLock::new(2));
+        let boxed: Box<dyn Foo + Send + Sync> = Box::new(3);
+        let boxed: DynArc = Arc::new(RwLock::new(boxed));
+
+        assert_eq!(container_kind(&mutex), ContainerKind::Mutex);
+        assert_eq!(container_kind(&rwlock), ContainerKind::RwLock);
+        assert_eq!(container_kind(&boxed), ContainerKind::RwLock);
     }
 
-    impl FooMut for String {
-        fn bar_mut(&mut self) -> String {
-            self.push('!');
-            format!("FooMut for String ({:?})", self)
-        }
+    #[test]
+    #[serial(registry)]
+    fn test_registry_immutable() {
+        reinit_global_registry();
+        register_type::<i32>();
+        register::<i32, dyn Foo>(|x| x as &dyn Foo, |x| x as &mut dyn Foo);
+        register::<i32, i32>(|x| x, |x| x);
+
+        // An `Arc<i32>` shared with Rust code, which holds no lock
+        let shared = Arc::new(42);
+        let immutable: DynArc = shared.clone();
+        assert_eq!(container_kind(&immutable), ContainerKind::Immutable);
+        assert_eq!(
+            coerce::<dyn Foo>(immutable.clone()).bar(),
+            "Foo for i32 (42)"
+        );
+        // Read handles don't block each other, nor `try_coerce`
+        let handle = coerce::<i32>(immutable.clone());
+        assert_eq!(*try_coerce::<i32>(immutable.clone()).unwrap(), 42);
+        assert!(!is_locked(&immutable));
+        drop(handle);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce_mut::<i32>(immutable.clone());
+        }));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(
+            msg.contains("immutable value can't be coerced mutably"),
+            "{}",
+            msg
+        );
+        assert!(try_coerce_mut::<i32>(immutable.clone()).is_none());
+        assert_eq!(*shared, 42);
     }
 
-    fn test_display(values: Vec<DynArc>) -> Vec<String> {
-        let mut results = Vec::new();
-        for value in values {
-            let coerced = coerce::<dyn std::fmt::Display>(value);
-            let coerced = coerced.deref();
-            results.push(format!("{coerced}"));
-        }
-        results
+    #[test]
+    #[serial(registry)]
+    fn test_registry_immutable_only() {
+        reinit_global_registry();
+        register_type::<i32>();
+        register_type_info::<i32>("crate_a", "i32", vec!["i32"]);
+        register::<i32, i32>(|x| x, |x| x);
+        assert_eq!(get_type_info::<i32>().containers, ContainerSet::ALL);
+        register_type_containers::<i32>(ContainerSet::of(&[ContainerKind::Immutable]));
+        // Re-registering the type info keeps the containers
+        register_type_info::<i32>("crate_a", "i32", vec!["i32"]);
+        let containers = get_type_info::<i32>().containers;
+        assert!(containers.contains(ContainerKind::Immutable));
+        assert!(!containers.contains(ContainerKind::Mutex));
+        assert!(!containers.supports_mut());
+
+        let immutable: DynArc = Arc::new(42);
+        assert_eq!(*coerce::<i32>(immutable.clone()), 42);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce_mut::<i32>(immutable.clone());
+        }));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("\"i32\" is registered immutable"), "{}", msg);
+        // The non-blocking coercion doesn't fail
+        assert!(try_coerce_mut::<i32>(immutable.clone()).is_none());
+
+        // Values in other containers are coerced as usual
+        let mutex: DynArc = Arc::new(Mutex::new(42));
+        *coerce_mut::<i32>(mutex.clone()) += 1;
+        assert_eq!(*coerce::<i32>(mutex), 43);
     }
 
-    fn test_foo(values: Vec<DynArc>) -> Vec<String> {
-        let mut results = Vec::new();
-        for value in values {
-            let coerced = coerce::<dyn Foo>(value);
-            let coerced = coerced.deref();
-            results.push(coerced.bar());
-        }
-        results
+    #[test]
+    fn test_registry_variant_kind() {
+        with_registry(|| {
+            register_type_info::<i32>("crate_a", "i32", vec!["i32"]);
+            assert_eq!(get_type_info::<i32>().variant_kind, VariantKind::Open);
+            register_type_variant_kind::<i32>(VariantKind::Closed);
+            // Re-registering the type info keeps the variant kind
+            register_type_info::<i32>("crate_a", "i32", vec!["i32"]);
+            assert_eq!(get_type_info::<i32>().variant_kind, VariantKind::Closed);
+        });
     }
 
-    fn test_foo_send(values: Vec<DynArc>) -> Vec<String> {
-        let mut results = Vec::new();
-        for value in values {
-            let coerced = coerce::<dyn Foo + Send>(value);
-            let coerced = coerced.deref();
-            results.push(coerced.bar());
-        }
-        results
+    #[test]
+    #[serial(registry)]
+    fn test_catch_coercion_error() {
+        with_registry(|| {
+            register_trait!(i32, dyn Foo);
+            let value: DynArc = Arc::new(Mutex::new(1));
+            let other: DynArc = Arc::new(Mutex::new(2));
+            assert_eq!(panic_policy(), PanicPolicy::Panic);
+
+            // Failures panic by default, even within `catch_coercion_error`
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                let _ = catch_coercion_error(|| coerce::<dyn FooMut>(value.clone()));
+            }));
+            let err = result.unwrap_err();
+            let msg = err.downcast_ref::<String>().unwrap();
+            assert!(msg.contains("there is no registered coercion"), "{}", msg);
+
+            // Otherwise they unwind with the message, without running the
+            // panic hook, and release the handles held by the closure
+            set_panic_policy(PanicPolicy::RaiseOcaml);
+            let result = catch_coercion_error(|| {
+                let _handle = coerce::<dyn Foo>(other.clone());
+                coerce::<dyn FooMut>(value.clone());
+            });
+            let err = result.unwrap_err();
+            assert!(
+                err.message().contains("there is no registered coercion"),
+                "{}",
+                err
+            );
+            assert!(!is_locked(&value));
+            assert!(!is_locked(&other));
+
+            // Other panics are passed through
+            let result = std::panic::catch_unwind(|| {
+                catch_coercion_error(|| -> i32 { panic!("not a coercion") })
+            });
+            assert_eq!(
+                *result.unwrap_err().downcast::<&str>().unwrap(),
+                "not a coercion"
+            );
+
+            // Failures outside of `catch_coercion_error` still panic
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                coerce::<dyn FooMut>(value.clone());
+            }));
+            assert!(result.unwrap_err().downcast_ref::<String>().is_some());
+            set_panic_policy(PanicPolicy::Panic);
+        });
     }
 
-    fn test_foo_mut(values: Vec<DynArc>) -> Vec<String> {
-        let mut results = Vec::new();
-        for value in values {
-            let mut coerced = coerce_mut::<dyn FooMut>(value);
-            results.push(coerced.bar_mut());
-        }
-        results
+    #[test]
+    #[serial(registry)]
+    fn test_coercion_error_recovers_held_locks() {
+        with_registry(|| {
+            register_trait!(i32, dyn Foo);
+            register_trait!(i32, dyn FooMut);
+            let mutex: DynArc = Arc::new(Mutex::new(1));
+            let rwlock: DynArc = Arc::new(RwLock::new(2));
+            let unregistered: DynArc = Arc::new(Mutex::new(String::new()));
+
+            set_panic_policy(PanicPolicy::RaiseOcaml);
+            let result = catch_coercion_error(|| {
+                let _read = coerce::<dyn Foo>(mutex.clone());
+                let _write = coerce_mut::<dyn FooMut>(rwlock.clone());
+                coerce::<dyn Foo>(unregistered.clone());
+            });
+            set_panic_policy(PanicPolicy::Panic);
+            assert!(result.is_err());
+
+            // The failure unwound past the guards of the held handles, which
+            // poisoned their locks
+            assert!(mutex.downcast_ref::<Mutex<i32>>().unwrap().is_poisoned());
+            assert!(rwlock.downcast_ref::<RwLock<i32>>().unwrap().is_poisoned());
+
+            // Later coercions of the same values recover the locks
+            assert_eq!(coerce::<dyn Foo>(mutex.clone()).bar(), "Foo for i32 (1)");
+            assert_eq!(
+                coerce_mut::<dyn FooMut>(rwlock.clone()).bar_mut(),
+                "FooMut for i32 (3)"
+            );
+            assert!(try_coerce::<dyn Foo>(mutex.clone()).is_some());
+            assert!(try_coerce_mut::<dyn FooMut>(rwlock.clone()).is_some());
+        });
     }
 
     #[test]
     #[serial(registry)]
-    fn test_registry_display() {
+    fn test_type_info_namespacing() {
+        struct SheepA;
+        struct SheepB;
+
         reinit_global_registry();
-        register_trait!(i32, dyn std::fmt::Display);
-        register_trait!(i32, dyn core::fmt::Debug);
-        register_trait!(i32, dyn Foo);
-        register_trait!(String, dyn std::fmt::Display);
-        register_trait!(String, dyn core::fmt::Debug);
-        register_trait!(String, dyn Foo);
+        // Two crates registering different types under the same path relative
+        // to their crate root, along with traits of their own
+        register_type_info::<SheepA>(
+            "crate_a",
+            "crate::animals::Sheep",
+            vec![
+                "crate::animals::Sheep",
+                "core::marker::Send",
+                "crate::animals::Animal",
+            ],
+        );
+        register_type_info::<dyn Foo>(
+            "crate_a",
+            "crate::animals::Animal",
+            vec!["crate::animals::Animal"],
+        );
+        // A foreign trait, registered by its own crate, and one which isn't
+        // registered at all
+        register_type_info::<SheepB>(
+            "crate_b",
+            "crate::animals::Sheep",
+            vec![
+                "crate::animals::Sheep",
+                "core::marker::Send",
+                "crate_c::Grazer",
+                "serde::Serialize",
+            ],
+        );
+        register_type_info::<dyn FooMut>(
+            "crate_c",
+            "crate::Grazer",
+            vec!["crate::Grazer"],
+        );
+        // Absolute paths are kept as is
+        register_type_info::<i32>(
+            "crate_a",
+            "crate_a::animals::Wolf",
+            vec!["crate_a::animals::Wolf", "crate_b::Animal"],
+        );
+        register_type_info::<String>("crate_a", "core::fmt::Display", vec![]);
 
-        let values: Vec<DynArc> = vec![
-            Arc::new(Mutex::new(1)),
-            Arc::new(RwLock::new(String::from("two"))),
-        ];
-        let results = test_display(values);
+        assert_eq!(
+            namespaced_implementations::<SheepA>(),
+            vec![
+                "crate_a::animals::Sheep",
+                "core::marker::Send",
+                "crate_a::animals::Animal"
+            ]
+        );
+        // Tags of the trait are among the tags of the type
+        assert_eq!(
+            namespaced_implementations::<dyn Foo>(),
+            vec!["crate_a::animals::Animal"]
+        );
+        assert_eq!(
+            namespaced_implementations::<SheepB>(),
+            vec![
+                "crate_b::animals::Sheep",
+                "core::marker::Send",
+                "crate_c::Grazer",
+                "serde::Serialize"
+            ]
+        );
+        assert_eq!(
+            namespaced_implementations::<dyn FooMut>(),
+            vec!["crate_c::Grazer"]
+        );
+        assert_eq!(
+            namespaced_implementations::<i32>(),
+            vec!["crate_a::animals::Wolf", "crate_b::Animal"]
+        );
+        assert_eq!(
+            get_type_info::<SheepB>().namespaced_name(),
+            "crate_b::animals::Sheep"
+        );
+        assert_eq!(
+            get_type_info::<String>().namespaced_name(),
+            "core::fmt::Display"
+        );
+    }
 
-        assert_eq!(results, vec!["1", "two"]);
+    #[test]
+    #[serial(registry)]
+    fn test_implements() {
+        reinit_global_registry();
+        register_type_info::<i32>(
+            "crate_a",
+            "i32",
+            vec!["i32", "core::marker::Send", "core::fmt::Display"],
+        );
+
+        assert!(implements::<i32>("core::fmt::Display"));
+        assert!(implements::<i32>("::core::marker::Send"));
+        assert!(!implements::<i32>("core::fmt::Debug"));
+        // Types without type info implement nothing
+        assert!(!implements::<String>("core::fmt::Display"));
     }
 
     #[test]
     #[serial(registry)]
-    fn test_registry_foo() {
+    fn test_registry_snapshot_restore() {
+        fn has_coercion<In: 'static, Out: ?Sized + 'static>() -> bool {
+            let registry = global_registry().read().unwrap();
+            registry
+                .traits
+                .contains_key(&(TypeId::of::<Mutex<In>>(), TypeId::of::<Out>()))
+        }
+
         reinit_global_registry();
-        register_trait!(i32, dyn std::fmt::Display);
-        register_trait!(i32, dyn core::fmt::Debug);
         register_trait!(i32, dyn Foo);
-        register_trait!(String, dyn std::fmt::Display);
-        register_trait!(String, dyn core::fmt::Debug);
+        let saved = snapshot();
+
         register_trait!(String, dyn Foo);
+        assert!(has_coercion::<i32, dyn Foo>());
+        assert!(has_coercion::<String, dyn Foo>());
 
-        let values: Vec<DynArc> = vec![
-            Arc::new(Mutex::new(3)),
-            Arc::new(RwLock::new(String::from("four"))),
-        ];
-        let results = test_foo(values);
+        restore(saved);
+        assert!(has_coercion::<i32, dyn Foo>());
+        assert!(!has_coercion::<String, dyn Foo>());
+    }
+
+    // Not serialized with other tests, as it doesn't touch the global registry
+    #[test]
+    fn test_with_registry() {
+        struct Scoped(i32);
 
+        impl Foo for Scoped {
+            fn bar(&self) -> String {
+                format!("Foo for Scoped ({:?})", self.0)
+            }
+        }
+
+        let value: DynArc = Arc::new(Mutex::new(Scoped(5)));
+        let result = with_registry(|| {
+            register_trait!(Scoped, dyn Foo);
+            assert!(with_registry(|| !has_coercion::<dyn Foo>(&value)));
+            coerce_ref::<dyn Foo>(&value).bar()
+        });
+        assert_eq!(result, "Foo for Scoped (5)");
+        assert!(!has_coercion::<dyn Foo>(&value));
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_registry_boxed_trait_first() {
+        reinit_global_registry();
+        // Same order as `register_trait!` at startup followed by
+        // `DynBox::new_exclusive_boxed`, which registers `Box<dyn Foo>` last
+        register::<Box<dyn Foo + Send + Sync>, dyn Foo + Send + Sync>(
+            |x| x.as_ref(),
+            |x| x.as_mut(),
+        );
+        let boxed: Box<dyn Foo + Send + Sync> = Box::new(String::from("boxed"));
+        let exclusive: DynArc = Arc::new(Mutex::new(boxed));
+        assert_eq!(container_kind(&exclusive), ContainerKind::Mutex);
         assert_eq!(
-            results,
-            vec!["Foo for i32 (3)", "Foo for String (\"four\")"]
+            type_name_of(&exclusive),
+            std::any::type_name::<Box<dyn Foo + Send + Sync>>()
+        );
+        assert_eq!(
+            coerce::<dyn Foo + Send + Sync>(exclusive.clone()).bar(),
+            "Foo for String (\"boxed\")"
         );
+
+        // The coercion knows the name of the type it fails on
+        register_fallible::<Box<dyn Foo + Send + Sync>, String>(|_| None, |_| None);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            coerce::<String>(exclusive.clone());
+        }));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(!msg.contains("<unregistered type>"), "{}", msg);
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_show() {
+        reinit_global_registry();
+        register_type::<i32>();
+        register_show!(i32 => |x| format!("i32 of {}", x));
+
+        let mutex: DynArc = Arc::new(Mutex::new(1));
+        let rwlock: DynArc = Arc::new(RwLock::new(2));
+        let immutable: DynArc = Arc::new(3);
+        assert_eq!(show(&mutex).as_deref(), Some("i32 of 1"));
+        assert_eq!(show(&rwlock).as_deref(), Some("i32 of 2"));
+        assert_eq!(show(&immutable).as_deref(), Some("i32 of 3"));
+
+        // Types without a show function are not rendered
+        register_type::<String>();
+        let string: DynArc = Arc::new(Mutex::new(String::from("foo")));
+        assert_eq!(show(&string), None);
+    }
+
+    #[test]
+    fn test_register_type_repeatedly() {
+        struct Lamb;
+
+        let lamb: DynArc = Arc::new(RwLock::new(Lamb));
+        let name = std::any::type_name::<Lamb>();
+        with_registry(|| {
+            register_type::<Lamb>();
+            register_type::<Lamb>();
+            assert_eq!(type_name_of(&lamb), name);
+            assert_eq!(container_kind(&lamb), ContainerKind::RwLock);
+            // A registry created later has to learn about the type anew
+            with_registry(|| {
+                assert_eq!(type_name_of(&lamb), "<unregistered type>");
+                register_type::<Lamb>();
+                assert_eq!(type_name_of(&lamb), name);
+            });
+        });
     }
 
     #[test]
@@ -646,6 +4902,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_register_multi() {
+        with_registry(|| {
+            register_multi::<i32>(&[
+                &Coercion::<_, i32>::new(|x| x, |x| x),
+                &Coercion::<_, dyn Foo>::new(|x| x as &dyn Foo, |x| x as &mut dyn Foo),
+                &Coercion::<_, dyn Foo + Send>::new(
+                    |x| x as &(dyn Foo + Send),
+                    |x| x as &mut (dyn Foo + Send),
+                ),
+            ]);
+
+            let value: DynArc = Arc::new(Mutex::new(3));
+            assert_eq!(type_name_of(&value), "i32");
+            assert_eq!(coerce::<dyn Foo>(value.clone()).bar(), "Foo for i32 (3)");
+            assert_eq!(test_foo_send(vec![value.clone()]), vec!["Foo for i32 (3)"]);
+            *coerce_mut::<i32>(value.clone()) += 1;
+            assert_eq!(*coerce::<i32>(value.clone()), 4);
+            assert!(coerce_ref::<dyn Any>(&value).is::<i32>());
+        });
+    }
+
     #[test]
     #[serial(registry)]
     fn test_registry_foo_mut() {
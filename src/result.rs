@@ -0,0 +1,162 @@
+//! This module standardizes bindings of fallible Rust constructors, e.g.
+//! `Sheep::try_new(name) -> Result<Sheep, InvalidName>`, which would otherwise
+//! need manual error handling in every stub. `TryIntoDynBox` is implemented for
+//! `Result<T, E>` with `E: std::error::Error + Send`, and wraps the outcome
+//! into `DynBox`es in one of the following ways.
+//!
+//! Errors bound to an OCaml exception with `register_exception!` can be raised,
+//! the stub then returns the object itself on OCaml side:
+//!
+//! ```rust,ignore
+//! register_exception!(InvalidName => "Invalid_name");
+//!
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn sheep_try_create(name: String) -> Result<DynBox<Sheep>, ocaml::Error> {
+//!     Sheep::try_new(name).try_into_dynbox_or_raise()
+//! }
+//! ```
+//!
+//! Otherwise, `try_into_dynbox` wraps the error into a
+//! `DynBox<dyn std::error::Error + Send>`, same as `DynBox::from` does. Stubs
+//! return it to OCaml as a regular `result` with `OCamlResult`, which maps
+//! `Ok` and `Err` to `Ok` and `Error` constructors:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn sheep_try_create_result(
+//!     name: String,
+//! ) -> OCamlResult<DynBox<Sheep>, DynBox<dyn std::error::Error + Send>> {
+//!     Sheep::try_new(name).try_into_dynbox().into()
+//! }
+//! ```
+//!
+//! The error type then needs an OCaml binding like any other `DynBox`.
+//! `OCamlResult` accepts any error type which can be converted to OCaml, e.g.
+//! a `String` with the message of the error.
+
+use ocaml_gen::OCamlDesc;
+
+use crate::exception::{self, OCamlException};
+use crate::ocaml_gen_extras;
+use crate::ptr::DynBox;
+
+/// Wraps the outcome of a fallible constructor into `DynBox`es, see module
+/// level docs.
+pub trait TryIntoDynBox<T: Send + 'static, E> {
+    /// Wraps the value into a new exclusive `DynBox`, and the error into a
+    /// `DynBox<dyn std::error::Error + Send>`.
+    fn try_into_dynbox(self) -> Result<DynBox<T>, DynBox<dyn std::error::Error + Send>>;
+
+    /// Wraps the value into a new exclusive `DynBox`, and converts the error
+    /// into an `ocaml::Error` which raises the OCaml exception registered for
+    /// `E` when the stub returns.
+    fn try_into_dynbox_or_raise(self) -> Result<DynBox<T>, ocaml::Error>
+    where
+        E: OCamlException;
+}
+
+impl<T, E> TryIntoDynBox<T, E> for Result<T, E>
+where
+    T: Send + 'static,
+    E: std::error::Error + Send + 'static,
+{
+    fn try_into_dynbox(self) -> Result<DynBox<T>, DynBox<dyn std::error::Error + Send>> {
+        self.map(DynBox::new_exclusive).map_err(DynBox::from)
+    }
+
+    fn try_into_dynbox_or_raise(self) -> Result<DynBox<T>, ocaml::Error>
+    where
+        E: OCamlException,
+    {
+        self.map(DynBox::new_exclusive)
+            .map_err(|err| exception::to_ocaml_error(&err))
+    }
+}
+
+/// A Rust `Result` passed to OCaml as a `result`, instead of raising an
+/// exception on error, see module level docs.
+pub struct OCamlResult<T, E>(pub Result<T, E>);
+
+impl<T, E> From<Result<T, E>> for OCamlResult<T, E> {
+    fn from(result: Result<T, E>) -> Self {
+        OCamlResult(result)
+    }
+}
+
+unsafe impl<T: ocaml::ToValue, E: ocaml::ToValue> ocaml::ToValue for OCamlResult<T, E> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        // `Ok` and `Error` are the first and the second non-constant
+        // constructors of `result`, each one holds a single field
+        let tag = if self.0.is_ok() { 0 } else { 1 };
+        let mut block = unsafe { ocaml::Value::alloc(1, tag) };
+        let value = match &self.0 {
+            Ok(value) => value.to_value(gc),
+            Err(err) => err.to_value(gc),
+        };
+        unsafe { block.store_field(gc, 0, value) };
+        block
+    }
+}
+
+impl<T: OCamlDesc, E: OCamlDesc> OCamlDesc for OCamlResult<T, E> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        format!(
+            "({}, {}) result",
+            T::ocaml_desc(env, generics),
+            E::ocaml_desc(env, generics)
+        )
+    }
+
+    fn unique_id() -> u128 {
+        ocaml_gen_extras::stable_unique_id(
+            "OCamlResult",
+            &(T::unique_id(), E::unique_id()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as ocaml_rs_smartptr; // For proc macro use below to work
+    use crate::register_trait;
+    use crate::registry;
+    use serial_test::serial;
+
+    #[derive(Debug)]
+    struct InvalidName;
+
+    impl std::fmt::Display for InvalidName {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "sheep name can't be empty")
+        }
+    }
+
+    impl std::error::Error for InvalidName {}
+
+    fn try_new(name: &str) -> Result<String, InvalidName> {
+        if name.is_empty() {
+            return Err(InvalidName);
+        }
+        Ok(name.to_owned())
+    }
+
+    #[test]
+    #[serial(registry)]
+    fn test_try_into_dynbox() {
+        registry::register::<String, String>(|x| x, |x| x);
+        register_trait!({
+            ty: std::error::Error,
+            marker_traits: [core::marker::Send],
+        });
+
+        let sheep = try_new("dolly").try_into_dynbox().unwrap();
+        assert_eq!(sheep.container_kind(), registry::ContainerKind::Mutex);
+        assert_eq!(sheep.read(|name| name.clone()), "dolly");
+
+        let err = try_new("").try_into_dynbox().unwrap_err();
+        assert_eq!(err.coerce().to_string(), "sheep name can't be empty");
+    }
+}
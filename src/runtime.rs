@@ -0,0 +1,71 @@
+//! This module manages the tokio runtime which drives async Rust code called
+//! from synchronous OCaml stubs, for bindings to async Rust libraries. It's
+//! only available with the `tokio` feature. Stubs usually don't use it
+//! directly, but call `async fn` methods of a `DynBox` with
+//! `DynBox::block_on`:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn client_fetch(client: DynBox<Client>, url: String) -> String {
+//!     client.block_on(async |client| client.fetch(&url).await)
+//! }
+//! ```
+//!
+//! The runtime is shared by all crates of the program. Unless another one is
+//! installed with `set_runtime`, a current-thread runtime with all drivers
+//! enabled (IO and timers, as far as tokio features of the program enable
+//! them) is created on the first `block_on` call. Tasks spawned on it only
+//! make progress while a stub is blocked on it, which suits libraries doing
+//! request/response work within the call. Libraries relying on background
+//! tasks (e.g. connection pools) need a multi-threaded runtime, to be built by
+//! the program and installed with `set_runtime` before the first call.
+//!
+//! The runtime lives until the program exits, it's never shut down, so tasks
+//! left running are just abandoned at exit.
+//!
+//! `block_on` keeps the OCaml domain lock, so other OCaml threads don't run
+//! until the future completes. Stubs which may block for long should release
+//! the lock around the call with `gc.releasing_runtime`, as long as the future
+//! doesn't touch OCaml values. `block_on` can't be called from within the
+//! runtime, e.g. from a task spawned on it, as tokio panics then.
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+use tokio::runtime::Runtime;
+
+/// The runtime in use, installed by `set_runtime` or created by the first
+/// `block_on` call, whichever comes first.
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Installs `runtime` as the one driving `block_on` calls, e.g. a
+/// multi-threaded runtime for libraries relying on background tasks. It has
+/// to be set before the first `block_on` call, e.g. at the very beginning of
+/// the program, or from its OCaml initialization code.
+///
+/// # Panics
+///
+/// Panics if a runtime is already in use.
+pub fn set_runtime(runtime: Runtime) {
+    if RUNTIME.set(runtime).is_err() {
+        panic!("tokio runtime must be set before it's first used by `block_on`");
+    }
+}
+
+/// Returns the runtime in use, creating the default current-thread runtime if
+/// none is installed yet.
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("unable to build tokio runtime")
+    })
+}
+
+/// Drives `future` to completion on the runtime, blocking the current thread,
+/// see module level docs.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
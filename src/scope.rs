@@ -0,0 +1,186 @@
+//! This module provides `CoercionScope`, which memoizes coercions of `DynBox`es
+//! for the duration of a stub. Every `DynBox::coerce` call looks the coercion
+//! up in the registry and takes the lock of the container, so a stub coercing
+//! the same `DynBox` to the same type several times (e.g. in helper functions
+//! it calls) pays for it every time. Within a scope, the first coercion of a
+//! `DynBox` to some type takes the lock, and subsequent ones return the value
+//! behind the handle held by the scope, until the scope is dropped:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn sheep_describe(sheep: DynBox<Sheep>) -> String {
+//!     let scope = CoercionScope::new();
+//!     format!(
+//!         "{} ({}, {})",
+//!         scope.coerce(&sheep).name(),
+//!         wool_of(&scope, &sheep),
+//!         age_of(&scope, &sheep),
+//!     )
+//! }
+//! ```
+//!
+//! Cached handles keep their locks for the whole scope, so the same caveats as
+//! for holding a handle apply: don't keep a scope open across calls which may
+//! block on the same `DynBox`, e.g. into OCaml code. Coercions are memoized per
+//! `DynBox` and per type, so coercing a `Mutex`-backed `DynBox` to another type
+//! within the same scope deadlocks, same as nested coercions do.
+//!
+//! Write coercions follow the borrowing rules: a `DynBox` can be coerced
+//! mutably to a given type only once per scope, and not at all if it was
+//! coerced to the same type for reading, as the scope would otherwise hand out
+//! aliasing references. Violations panic, same as conflicting `RefCell`
+//! borrows.
+//!
+//! Scopes only pay off for stubs coercing the same `DynBox` repeatedly, as
+//! the first coercion costs a hash map insertion on top of the regular one,
+//! see `cargo bench --bench scope`.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::ptr::DynBox;
+use crate::registry::Handle;
+
+/// Handle cached by a `CoercionScope`.
+struct CachedHandle {
+    /// `Handle<T>` of a read coercion, which may be handed out repeatedly, or
+    /// `HandleMut<T>` of a write coercion, which was handed out already.
+    handle: Box<dyn Any>,
+    /// Whether the handle is a `HandleMut<T>`.
+    mutable: bool,
+}
+
+/// Memoizes coercions of `DynBox`es until it's dropped, see module level docs.
+#[derive(Default)]
+pub struct CoercionScope {
+    /// Handles indexed by the address of the `DynBox` contents and the type
+    /// they coerce to. The handles keep the contents alive, so addresses are
+    /// not reused while the scope holds them.
+    handles: RefCell<HashMap<(usize, TypeId), CachedHandle>>,
+}
+
+impl CoercionScope {
+    /// Creates an empty scope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Coerces `dynbox` like `DynBox::coerce` does, unless it was coerced to
+    /// the same type within this scope already, in which case the value
+    /// behind the cached handle is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dynbox` was coerced mutably to the same type within this
+    /// scope, as well as whenever `DynBox::coerce` would.
+    pub fn coerce<T: 'static + Send + ?Sized>(&self, dynbox: &DynBox<T>) -> &T {
+        let key = Self::key(dynbox);
+        let mut handles = self.handles.borrow_mut();
+        let cached = handles.entry(key).or_insert_with(|| CachedHandle {
+            handle: Box::new(dynbox.coerce()),
+            mutable: false,
+        });
+        if cached.mutable {
+            panic!(
+                "{} is coerced mutably within this scope already",
+                std::any::type_name::<DynBox<T>>()
+            );
+        }
+        let value: *const T = &**cached
+            .handle
+            .downcast_ref::<Handle<T>>()
+            .expect("cached handle has wrong type");
+        // Safety: the value lives behind the lock held by the handle, which
+        // stays in the scope until it's dropped, and is not moved along with
+        // the handle. No mutable reference to it is handed out by the scope.
+        unsafe { &*value }
+    }
+
+    /// Coerces `dynbox` mutably like `DynBox::coerce_mut` does, and keeps the
+    /// handle until the scope is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dynbox` was coerced to the same type within this scope,
+    /// whether for reading or for writing, as well as whenever
+    /// `DynBox::coerce_mut` would.
+    // The reference is unique, as the scope refuses to coerce the same
+    // `DynBox` to the same type again, which clippy can't tell
+    #[allow(clippy::mut_from_ref)]
+    pub fn coerce_mut<T: 'static + Send + ?Sized>(&self, dynbox: &DynBox<T>) -> &mut T {
+        let key = Self::key(dynbox);
+        let mut handles = self.handles.borrow_mut();
+        if handles.contains_key(&key) {
+            panic!(
+                "{} is coerced within this scope already, it can't be coerced \
+                 mutably",
+                std::any::type_name::<DynBox<T>>()
+            );
+        }
+        let mut handle = Box::new(dynbox.coerce_mut());
+        let value: *mut T = &mut **handle;
+        handles.insert(
+            key,
+            CachedHandle {
+                handle,
+                mutable: true,
+            },
+        );
+        // Safety: same as for `coerce`, and the key is taken, so no other
+        // reference to the value is handed out by the scope.
+        unsafe { &mut *value }
+    }
+
+    /// Checks if `dynbox` was coerced to `T` within this scope.
+    pub fn contains<T: 'static + Send + ?Sized>(&self, dynbox: &DynBox<T>) -> bool {
+        self.handles.borrow().contains_key(&Self::key(dynbox))
+    }
+
+    fn key<T: 'static + Send + ?Sized>(dynbox: &DynBox<T>) -> (usize, TypeId) {
+        (
+            Arc::as_ptr(&dynbox.inner) as *const () as usize,
+            TypeId::of::<T>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry;
+    use serial_test::serial;
+
+    #[test]
+    #[serial(registry)]
+    fn test_coercion_scope() {
+        registry::register::<i32, i32>(|x| x, |x| x);
+        let counter = DynBox::new_shared(1);
+        let other = DynBox::new_exclusive(10);
+        let scope = CoercionScope::new();
+        assert!(!scope.contains(&counter));
+        let first = scope.coerce(&counter);
+        let second = scope.coerce(&counter);
+        assert!(std::ptr::eq(first, second));
+        assert!(scope.contains(&counter));
+        // The cached read handle holds the lock until the scope is dropped
+        assert!(counter.try_coerce_mut().is_none());
+
+        *scope.coerce_mut(&other) += 1;
+        assert!(other.try_coerce().is_none());
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scope.coerce(&other);
+        }));
+        assert!(res.is_err());
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scope.coerce_mut(&counter);
+        }));
+        assert!(res.is_err());
+
+        drop(scope);
+        assert_eq!(*counter.coerce(), 1);
+        assert_eq!(*other.coerce(), 11);
+    }
+}
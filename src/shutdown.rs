@@ -0,0 +1,101 @@
+//! This module provides a deterministic shutdown of Rust-side state for OCaml
+//! programs. When an OCaml program exits with `OCAMLRUNPARAM=c` (or calls
+//! `caml_shutdown` when embedded), the runtime frees its heap and runs the
+//! finalizers of all remaining custom blocks, e.g. `RustyObj`s. By then Rust
+//! destructors may run in an arbitrary order with respect to the resources they
+//! rely on (background threads, thread-locals, global state of other
+//! libraries), which crashes the program at exit.
+//!
+//! `shutdown` runs the hooks registered with `register_at_exit`, in the reverse
+//! order of registration, so that libraries release what they manage (e.g.
+//! join their threads, flush their buffers) while everything is still alive.
+//! Afterwards, finalizers of the objects passed to OCaml leak them instead of
+//! dropping them, as the process is about to exit anyway.
+//!
+//! OCaml programs call it with `Ocaml_rs_smartptr.Registry.shutdown`, which is
+//! best registered with `at_exit` right at the start of the program, so that it
+//! runs after the `at_exit` functions registered later, which may still use
+//! Rust objects:
+//!
+//! ```ocaml
+//! let () = at_exit Ocaml_rs_smartptr.Registry.shutdown
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::logging::trace;
+
+/// A hook registered with `register_at_exit`.
+type Hook = Box<dyn FnOnce() + Send>;
+
+/// Hooks to run on `shutdown`, in the order of registration.
+static HOOKS: Mutex<Vec<Hook>> = Mutex::new(Vec::new());
+
+/// Whether `shutdown` was called.
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Registers `hook` to be run by `shutdown`, e.g. to release a resource which
+/// must not outlive the program, see module level docs. Hooks registered after
+/// `shutdown` run right away.
+pub fn register_at_exit(hook: impl FnOnce() + Send + 'static) {
+    let mut hooks = HOOKS
+        .lock()
+        .expect("unable to obtain lock on at exit hooks");
+    if SHUT_DOWN.load(Ordering::Acquire) {
+        drop(hooks);
+        hook();
+    } else {
+        hooks.push(Box::new(hook));
+    }
+}
+
+/// Runs the hooks registered with `register_at_exit` in the reverse order of
+/// registration, and makes finalizers leak the objects they finalize from now
+/// on, see module level docs. Subsequent calls do nothing.
+pub fn shutdown() {
+    let hooks = {
+        let mut hooks = HOOKS
+            .lock()
+            .expect("unable to obtain lock on at exit hooks");
+        if SHUT_DOWN.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        std::mem::take(&mut *hooks)
+    };
+    trace!("shutdown, running {} at exit hooks", hooks.len());
+    for hook in hooks.into_iter().rev() {
+        hook();
+    }
+}
+
+/// Checks if `shutdown` was called, i.e. if finalizers should leak the objects
+/// they finalize.
+pub fn is_shut_down() -> bool {
+    SHUT_DOWN.load(Ordering::Acquire)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_shutdown() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            let log = log.clone();
+            register_at_exit(move || log.lock().unwrap().push(i));
+        }
+        assert!(!is_shut_down());
+        shutdown();
+        assert!(is_shut_down());
+        assert_eq!(*log.lock().unwrap(), vec![2, 1, 0]);
+
+        // Hooks registered afterwards run right away, and only once
+        let late_log = log.clone();
+        register_at_exit(move || late_log.lock().unwrap().push(3));
+        shutdown();
+        assert_eq!(*log.lock().unwrap(), vec![2, 1, 0, 3]);
+    }
+}
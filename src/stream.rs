@@ -0,0 +1,113 @@
+//! This module provides `OCamlStream<T>`, an interop type which allows
+//! bindings to return a Rust iterator to OCaml without materializing it into
+//! a list first. It maps to OCaml `unit -> 'a option` closure, which pulls the
+//! next item out of the iterator each time it's called, and returns `None`
+//! once the iterator is exhausted:
+//!
+//! ```rust,ignore
+//! #[ocaml_gen::func]
+//! #[ocaml::func]
+//! pub fn flock_endless(prefix: String) -> OCamlStream<DynBox<Sheep>> {
+//!     OCamlStream::new((1..).map(move |i| {
+//!         let sheep: Sheep = animals::Animal::new(format!("{} #{}", prefix, i));
+//!         sheep.into()
+//!     }))
+//! }
+//! ```
+//!
+//! Items are produced only when OCaml asks for them, so the iterator may be
+//! large or even infinite, and OCaml decides how much of it to consume, e.g.
+//! `Seq.of_dispenser (Flock.endless "sheep") |> Seq.take 2`.
+//!
+//! Unlike `DynBox::of_iter`, which requires the iterator trait object to be
+//! registered, and an explicit `next` binding for it, `OCamlStream` needs no
+//! registration. The OCaml side of this interop is registered by the
+//! `Rusty_stream` module of `ocaml-rs-smartptr` library.
+
+use std::sync::{Arc, Mutex};
+
+use ocaml_gen::OCamlDesc;
+
+use crate::ocaml_gen_extras::stable_unique_id;
+use crate::shutdown;
+
+/// Name of the function which wraps a `StreamThunk` into a closure,
+/// registered with `Callback` on OCaml side.
+const STREAM_OF_THUNK: &str = "ocaml_rs_smartptr_stream_of_thunk";
+
+/// A Rust iterator over `T`, which maps to OCaml `unit -> T option`, see
+/// module level docs.
+pub struct OCamlStream<T>(Arc<Mutex<Box<dyn Iterator<Item = T> + Send>>>);
+
+impl<T> OCamlStream<T> {
+    /// Creates a new `OCamlStream` out of `iter`.
+    pub fn new(iter: impl Iterator<Item = T> + Send + 'static) -> Self {
+        OCamlStream(Arc::new(Mutex::new(Box::new(iter))))
+    }
+
+    /// Advances the underlying iterator from Rust side. Items taken this way
+    /// are not seen by OCaml.
+    pub fn next(&self) -> Option<T> {
+        self.0.lock().expect("stream mutex is poisoned").next()
+    }
+}
+
+impl<T> Clone for OCamlStream<T> {
+    /// Clones the `OCamlStream`, sharing the underlying iterator. Custom Clone
+    /// implementation lifts the requirement for T to be Clone
+    fn clone(&self) -> Self {
+        OCamlStream(self.0.clone())
+    }
+}
+
+/// Type-erased step of an `OCamlStream<T>`, which is held by the OCaml heap.
+/// Returns the OCaml value of the next item as `T option`.
+struct StreamThunk(Box<dyn Fn(&ocaml::Runtime) -> ocaml::Value>);
+
+/// Finalizer is registered with OCaml GC, and drops the thunk (and the
+/// iterator it holds) whenever OCaml drops it.
+unsafe extern "C" fn stream_thunk_finalizer(v: ocaml::Raw) {
+    if shutdown::is_shut_down() {
+        return;
+    }
+    let ptr = v.as_pointer::<StreamThunk>();
+    ptr.drop_in_place();
+}
+
+impl ocaml::Custom for StreamThunk {
+    const NAME: &'static str = "StreamThunk\0";
+
+    const OPS: ocaml::custom::CustomOps = ocaml::custom::CustomOps {
+        identifier: Self::NAME.as_ptr() as *mut ocaml::sys::Char,
+        finalize: Some(stream_thunk_finalizer),
+        ..ocaml::custom::DEFAULT_CUSTOM_OPS
+    };
+}
+
+/// Pulls the next item of a stream on behalf of OCaml, see
+/// `ocaml_rs_smartptr_stream_next` stub.
+pub(crate) fn next_item(gc: &ocaml::Runtime, thunk: ocaml::Value) -> ocaml::Value {
+    let ptr = unsafe { thunk.raw().as_pointer::<StreamThunk>() };
+    (ptr.as_ref().0)(gc)
+}
+
+unsafe impl<T: ocaml::ToValue + 'static> ocaml::ToValue for OCamlStream<T> {
+    fn to_value(&self, gc: &ocaml::Runtime) -> ocaml::Value {
+        let stream = self.clone();
+        let thunk = StreamThunk(Box::new(move |gc| stream.next().to_value(gc)));
+        let thunk = ocaml::Pointer::from(thunk).to_value(gc);
+        let of_thunk = crate::lazy::named_callback(STREAM_OF_THUNK);
+        unsafe { of_thunk.call1(gc, thunk) }
+            .expect("wrapping Rust stream into OCaml closure failed")
+    }
+}
+
+impl<T: OCamlDesc> OCamlDesc for OCamlStream<T> {
+    fn ocaml_desc(env: &::ocaml_gen::Env, generics: &[&str]) -> String {
+        format!("(unit -> {} option)", T::ocaml_desc(env, generics))
+    }
+
+    fn unique_id() -> u128 {
+        stable_unique_id("OCamlStream", &T::unique_id())
+    }
+}
@@ -4,3 +4,72 @@
 pub fn ocaml_rs_smartptr_init_registry() {
     crate::registry::initialize_plugins()
 }
+
+/// Switches the panic policy between `PanicPolicy::RaiseOcaml` and
+/// `PanicPolicy::Panic`, see `registry::set_panic_policy`.
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_set_raise_on_coercion_failure(raise: bool) {
+    crate::registry::set_panic_policy(if raise {
+        crate::registry::PanicPolicy::RaiseOcaml
+    } else {
+        crate::registry::PanicPolicy::Panic
+    })
+}
+
+/// Runs the hooks registered with `register_at_exit` and makes finalizers leak
+/// objects from now on, see `shutdown` module.
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_shutdown() {
+    crate::shutdown::shutdown()
+}
+
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_lazy_run(thunk: ocaml::Value) -> ocaml::Value {
+    crate::lazy::run_thunk(gc, thunk)
+}
+
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_stream_next(thunk: ocaml::Value) -> ocaml::Value {
+    crate::stream::next_item(gc, thunk)
+}
+
+/// Returns the registered Rust type name of the value held by any
+/// `Rusty_obj.t`, which helps to find out what an object actually is when
+/// coercions misbehave.
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_rusty_obj_type_name(
+    obj: crate::ptr::DynBox<dyn std::any::Any + Send>,
+) -> String {
+    crate::registry::type_name_of(&obj.inner)
+}
+
+/// Renders the value held by any `Rusty_obj.t` with the function registered
+/// with `register_show!`, falling back to its registered Rust type name.
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_rusty_obj_to_string(
+    obj: crate::ptr::DynBox<dyn std::any::Any + Send>,
+) -> String {
+    crate::registry::show(&obj.inner)
+        .unwrap_or_else(|| crate::registry::type_name_of(&obj.inner))
+}
+
+/// Checks if two `Rusty_obj.t` are equal, see `ptr::dynbox_equal`. Bindings
+/// declare it for specific types with `decl_equal!`.
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_rusty_obj_equal(
+    a: crate::ptr::DynBox<dyn std::any::Any + Send>,
+    b: crate::ptr::DynBox<dyn std::any::Any + Send>,
+) -> bool {
+    crate::ptr::dynbox_equal(&a, &b)
+}
+
+/// Compares two `Rusty_obj.t` the way OCaml `compare` does, see
+/// `ptr::dynbox_compare`. Bindings declare it for specific types with
+/// `decl_compare!`.
+#[ocaml::func]
+pub fn ocaml_rs_smartptr_rusty_obj_compare(
+    a: crate::ptr::DynBox<dyn std::any::Any + Send>,
+    b: crate::ptr::DynBox<dyn std::any::Any + Send>,
+) -> ocaml::Int {
+    crate::ptr::dynbox_compare(&a, &b) as ocaml::Int
+}
@@ -2,6 +2,12 @@ use regex::Regex;
 
 use crate::registry;
 
+// Shared with the macros, so that they name types the same way
+#[path = "../macro/src/snake_case.rs"]
+mod snake_case;
+
+use snake_case::to_snake_case;
+
 /// Helper function to extract the core type name.
 fn extract_type_name(type_str: &str) -> &str {
     let re = Regex::new(r"::(\w+)(<|$)").unwrap();
@@ -26,21 +32,6 @@ fn capture_segments(type_str: &str) -> Vec<&str> {
     segments[..=index].to_vec()
 }
 
-/// Convert a module path to snake_case.
-fn convert_to_snake_case(segment: &str) -> String {
-    segment
-        .chars()
-        .enumerate()
-        .map(|(i, c)| {
-            if i > 0 && c.is_uppercase() {
-                format!("_{}", c.to_lowercase())
-            } else {
-                c.to_lowercase().to_string()
-            }
-        })
-        .collect::<String>()
-}
-
 /// Function to capitalize the first letter.
 fn capitalize_first_letter(s: &str) -> String {
     let mut chars = s.chars();
@@ -51,19 +42,97 @@ fn capitalize_first_letter(s: &str) -> String {
     }
 }
 
+/// Computes a 32-bit FNV-1a hash of the complete type string. It's stable
+/// across builds and platforms, and is used to keep tags of distinct types
+/// distinct even if their readable parts are identical.
+fn path_hash(type_str: &str) -> u32 {
+    const FNV_OFFSET_BASIS: u32 = 0x811c9dc5;
+    const FNV_PRIME: u32 = 0x01000193;
+    type_str.bytes().fold(FNV_OFFSET_BASIS, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(FNV_PRIME)
+    })
+}
+
 /// Function to return the core type name.
 pub(crate) fn get_type_name<T: ?Sized + 'static>() -> String {
     let type_info = registry::get_type_info::<T>();
     extract_type_name(type_info.fq_name).to_string()
 }
 
-/// Function to return the fully qualified name as Snake_cased with the first letter capitalized.
+/// Function to return the fully qualified name as Snake_cased with the first letter capitalized,
+/// suffixed with a hash of the complete type string to avoid collisions between
+/// types whose paths snake-case identically.
 pub(crate) fn snake_case_of_fully_qualified_name(type_str: &str) -> String {
     let segments = capture_segments(type_str);
     let snake_cased = segments
         .into_iter()
-        .map(convert_to_snake_case)
+        .map(to_snake_case)
         .collect::<Vec<String>>()
         .join("_");
-    capitalize_first_letter(&snake_cased)
+    format!(
+        "{}_{:08x}",
+        capitalize_first_letter(&snake_cased),
+        path_hash(type_str)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_is_stable() {
+        assert_eq!(
+            snake_case_of_fully_qualified_name("core::marker::Send"),
+            "Core_marker_send_8ae6a44a"
+        );
+        assert_eq!(
+            snake_case_of_fully_qualified_name("ocaml_rs_smartptr_test::stubs::Sheep"),
+            "Ocaml_rs_smartptr_test_stubs_sheep_8bdf2d62"
+        );
+    }
+
+    #[test]
+    fn test_tag_of_acronyms() {
+        let tag = snake_case_of_fully_qualified_name("my_crate::net::HTTPServer");
+        assert!(tag.starts_with("My_crate_net_http_server_"), "{}", tag);
+        assert!(tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    }
+
+    #[test]
+    fn test_same_named_types_in_different_modules() {
+        let a = snake_case_of_fully_qualified_name("my_crate::foo_bar::Thing");
+        let b = snake_case_of_fully_qualified_name("my_crate::foo::bar::Thing");
+        let c = snake_case_of_fully_qualified_name("my_crate::FooBar::Thing");
+        assert!(a.starts_with("My_crate_foo_bar_thing_"));
+        assert!(b.starts_with("My_crate_foo_bar_thing_"));
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(b, c);
+    }
+
+    #[test]
+    fn test_fn_trait_tag() {
+        assert_eq!(
+            snake_case_of_fully_qualified_name("core::ops::FnMut (i32) -> i32"),
+            "Core_ops_fn_mut_i32_i32_f62eca32"
+        );
+    }
+
+    #[test]
+    fn test_iterator_trait_tag() {
+        assert_eq!(
+            snake_case_of_fully_qualified_name(
+                "core::iter::Iterator < Item = DynBox < Sheep > >"
+            ),
+            "Core_iter_iterator_item_dyn_box_sheep_e6db7085"
+        );
+    }
+
+    #[test]
+    fn test_generic_args_are_distinguished() {
+        let a = snake_case_of_fully_qualified_name("my_crate::Wrapper<my_crate::A>");
+        let b = snake_case_of_fully_qualified_name("my_crate::Wrapper<my_crate::B>");
+        assert_ne!(a, b);
+    }
 }
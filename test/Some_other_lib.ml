@@ -2,10 +2,14 @@ module Barn = struct
   type t
 end
 
+module Tagged_barn = struct
+  type 'a t
+end
+
 module Animal = struct
   type tags =
-    [ `Ocaml_rs_smartptr_test_stubs_animal_proxy
-    | `Core_marker_send
+    [ `Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41
+    | `Core_marker_send_8ae6a44a
     ]
 
   type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
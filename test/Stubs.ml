@@ -1,7 +1,7 @@
 module Animal = struct
   type tags =
-    [ `Ocaml_rs_smartptr_test_stubs_animal_proxy
-    | `Core_marker_send
+    [ `Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41
+    | `Core_marker_send_8ae6a44a
     ]
 
   type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
@@ -11,41 +11,124 @@ module Animal = struct
   external noise : _ t' -> string = "animal_noise"
   external talk : _ t' -> unit = "animal_talk"
   external create_random : string -> _ t' = "animal_create_random"
+  external find : string -> _ t' option = "animal_find"
+  external sort_by_name : _ t' array -> _ t' array = "animal_sort_by_name"
+
+  external chorus
+    :  _ t'
+    -> _ t'
+    -> _ t'
+    -> _ t'
+    -> _ t'
+    -> _ t'
+    -> string
+    = "animal_chorus_bytecode" "animal_chorus"
+
+  external describe : 'a -> string = "animal_describe"
 end
 
 module Sheep = struct
   type tags =
-    [ `Ocaml_rs_smartptr_test_stubs_sheep
-    | `Core_marker_sync
-    | `Core_marker_send
-    | `Ocaml_rs_smartptr_test_stubs_animal_proxy
+    [ `Ocaml_rs_smartptr_test_stubs_sheep_8bdf2d62
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    | `Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41
     ]
 
   type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
   type t = tags t'
 
+  exception Invalid_name of string
+
+  let () = Callback.register_exception "Invalid_name" (Invalid_name "")
+
   external create : string -> _ t' = "sheep_create"
+  external create_exclusive : string -> _ t' = "sheep_create_exclusive"
+  external create_shared : string -> _ t' = "sheep_create_shared"
   external is_naked : _ t' -> bool = "sheep_is_naked"
   external sheer : _ t' -> unit = "sheep_sheer"
+  external write_name : _ t' -> bytes -> int32 = "sheep_write_name"
+  external create_lazy : string -> _ t' lazy_t = "sheep_create_lazy"
+  external name_of : [ `Obj of _ t' | `Repr of string ] -> string = "sheep_name_of"
+  external try_create : string -> _ t' = "sheep_try_create"
+  external try_create_result : string -> (_ t', string) result = "sheep_try_create_result"
+  external create_immutable : string -> _ t' = "sheep_create_immutable"
+  external of_animal : _ Animal.t' -> _ t' option = "sheep_of_animal"
+  external equal : _ t' -> _ t' -> bool = "ocaml_rs_smartptr_rusty_obj_equal"
+  external compare : _ t' -> _ t' -> int = "ocaml_rs_smartptr_rusty_obj_compare"
 end
 
 module Wolf = struct
   type tags =
-    [ `Ocaml_rs_smartptr_test_stubs_wolf
-    | `Core_marker_sync
-    | `Core_marker_send
-    | `Ocaml_rs_smartptr_test_stubs_animal_proxy
+    [ `Ocaml_rs_smartptr_test_stubs_wolf_3375056b
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    | `Ocaml_rs_smartptr_test_stubs_animal_proxy_82125e41
     ]
 
   type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
   type t = tags t'
 
+  exception Not_hungry of string
+
+  let () = Callback.register_exception "Not_hungry" (Not_hungry "")
+
   external create : string -> _ t' = "wolf_create"
   external set_hungry : _ t' -> bool -> unit = "wolf_set_hungry"
+  external feed : _ t' -> unit = "wolf_feed"
+  external ref_count : _ t' -> int32 = "wolf_ref_count"
+  external equal : _ t' -> _ t' -> bool = "ocaml_rs_smartptr_rusty_obj_equal"
+end
+
+module Fleece = struct
+  type tags =
+    [ `Ocaml_rs_smartptr_test_stubs_fleece_e4e7125f
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external create : string -> int32 -> _ t' = "fleece_create"
+  external create_immutable : string -> int32 -> _ t' = "fleece_create_immutable"
+  external color : _ t' -> string = "fleece_color"
+  external weight : _ t' -> int32 = "fleece_weight"
+  external set_weight : _ t' -> int32 -> unit = "fleece_set_weight"
+end
+
+module Herd = struct
+  external create : string -> _ Sheep.t' * _ Wolf.t' = "herd_create"
+
+  external add_stray
+    :  _ Sheep.t'
+    -> _ Wolf.t'
+    -> string
+    -> _ Sheep.t' * _ Wolf.t' * _ Animal.t'
+    = "herd_add_stray"
 end
 
 module Test_callback = struct
   external call_cb : _ Wolf.t' -> (_ Wolf.t' -> _ Animal.t') -> _ Animal.t' = "call_cb"
+  external count_in_background : int32 -> (int32 -> unit) -> unit = "count_in_background"
+
+  external call_cb_propagate
+    :  _ Sheep.t'
+    -> (_ Sheep.t' -> unit)
+    -> bool
+    = "call_cb_propagate"
+
+  external call_cb_consuming
+    :  _ Wolf.t'
+    -> (_ Wolf.t' -> _ Animal.t')
+    -> _ Animal.t'
+    = "call_cb_consuming"
+
+  external call_cb_consuming_option
+    :  _ Wolf.t'
+    -> (_ Wolf.t' option -> _ Animal.t')
+    -> _ Animal.t'
+    = "call_cb_consuming_option"
 end
 
 module Animal_alias = struct
@@ -66,4 +149,196 @@ module Export_import = struct
     :  string
     -> _ Some_other_lib.Animal.t'
     = "dynbox_with_animal_create"
+
+  type nonrec 'a tagged_barn = 'a Some_other_lib.Tagged_barn.t
+
+  external tagged_barn_create : int32 -> 'a tagged_barn = "tagged_barn_create"
+end
+
+module Adder = struct
+  type tags =
+    [ `Core_ops_fn_mut_i32_i32_f62eca32
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external create : int32 -> _ t' = "adder_create"
+  external call : _ t' -> int32 -> int32 = "adder_call"
+end
+
+module Flock = struct
+  type tags =
+    [ `Core_iter_iterator_item_dyn_box_sheep_e6db7085
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external create : int32 -> _ t' = "flock_create"
+  external next : _ t' -> _ Sheep.t' option = "flock_next"
+  external endless : string -> (unit -> _ Sheep.t' option) = "flock_endless"
+end
+
+module Drove = struct
+  type tags =
+    [ `Ocaml_rs_smartptr_test_stubs_drove_2d0e39e7
+    | `Core_marker_send_8ae6a44a
+    | `Core_iter_iterator_item_dyn_box_sheep_e6db7085
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external create : string array -> _ t' = "drove_create"
+end
+
+module Pen = struct
+  type tags = [ `Ocaml_rs_smartptr_test_stubs_pen_45539dce ]
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_local_obj.t
+  type t = tags t'
+
+  external create : string -> _ t' = "pen_create"
+  external create_inner : _ t' -> string -> _ t' = "pen_create_inner"
+  external add : _ t' -> unit = "pen_add"
+  external describe : _ t' -> string = "pen_describe"
+end
+
+module Census = struct
+  type t =
+    { count : int32
+    ; naked : int32
+    ; names : string array
+    }
+
+  external of_flock : _ Sheep.t' array -> t = "census_of_flock"
+  external describe : t -> string = "census_describe"
+end
+
+module Node = struct
+  type tags =
+    [ `Ocaml_rs_smartptr_test_stubs_node_e84cdbc3
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([ tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  type summary =
+    { label : string
+    ; children : summary array
+    }
+
+  external create : string -> _ t' = "node_create"
+  external add_child : _ t' -> _ t' -> unit = "node_add_child"
+  external children : _ t' -> _ t' array = "node_children"
+  external summary : _ t' -> summary = "node_summary"
+end
+
+module Whereabouts = struct
+  type t = [ `Barn | `Pasture of string | `Coords of int32 * int32 ]
+
+  external describe : t -> string = "whereabouts_describe"
+  external wander : t -> t = "whereabouts_wander"
+end
+
+module Config = struct
+  type tags =
+    [ `Ocaml_rs_smartptr_test_stubs_config_0b4630f1
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external create_raw : string -> int32 -> bool -> _ t' = "config_create"
+
+  let create ~name ?(port = 8080l) ?(verbose = false) () = create_raw name port verbose
+
+  external describe : _ t' -> string = "config_describe"
+end
+
+module Samples = struct
+  type tags =
+    [ `Std_vec_vec_f64_22cf7674
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external of_array : float array -> _ t' = "samples_of_array"
+  external scale : _ t' -> float -> unit = "samples_scale"
+  external to_array : _ t' -> float array = "samples_to_array"
+end
+
+module Payload = struct
+  type tags =
+    [ `Std_vec_vec_u8_549ca269
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external of_string : string -> _ t' = "payload_of_string"
+
+  external view
+    :  _ t'
+    -> (char, Bigarray.int8_unsigned_elt, Bigarray.c_layout) Bigarray.Array1.t
+    = "payload_view"
+
+  external to_string : _ t' -> string = "payload_to_string"
+end
+
+module Connection = struct
+  type tags =
+    [ `Ocaml_rs_smartptr_test_stubs_connection_cb475b63
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a t' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type t = tags t'
+
+  external connect : string -> [ tags | `Open ] t' = "connection_connect"
+  external send : [ tags | `Open ] t' -> string -> unit = "connection_send"
+  external request : [ tags | `Open ] t' -> string -> string = "connection_request"
+  external close : [ tags | `Open ] t' -> [ tags | `Closed ] t' = "connection_close"
+  external is_open : _ t' -> bool = "connection_is_open"
+end
+
+module Stack (Elt : sig
+    type t
+  end) =
+struct
+  type tags =
+    [ `Ocaml_rs_smartptr_test_stubs_stack_ocaml_rs_smartptr_functor_element_bf0ca79c
+    | `Core_marker_sync_770eca17
+    | `Core_marker_send_8ae6a44a
+    ]
+
+  type 'a stack' = ([> tags ] as 'a) Ocaml_rs_smartptr.Rusty_obj.t
+  type stack = tags stack'
+  type t = [ tags | `Elt of Elt.t ] stack'
+
+  external create : unit -> [ tags | `Elt of Elt.t ] stack' = "stack_create"
+  external push : [ tags | `Elt of Elt.t ] stack' -> Elt.t -> unit = "stack_push"
+  external pop : [ tags | `Elt of Elt.t ] stack' -> Elt.t option = "stack_pop"
+  external length : _ stack' -> int32 = "stack_length"
+end
+
+module Shepherd = struct
+  module type S = sig
+    val greet : string -> string
+    val whistle : string -> string
+  end
+
+  external tend : (module S) -> _ Sheep.t' -> unit = "shepherd_tend"
 end
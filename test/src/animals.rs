@@ -12,11 +12,27 @@ pub trait Animal {
     }
 }
 
+#[derive(PartialEq, Eq)]
 pub struct Sheep {
     naked: bool,
     name: String,
 }
 
+// Sheep are ordered by name, woolly ones first among namesakes
+impl Ord for Sheep {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name
+            .cmp(&other.name)
+            .then(self.naked.cmp(&other.naked))
+    }
+}
+
+impl PartialOrd for Sheep {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Sheep {
     pub fn is_naked(&self) -> bool {
         self.naked
@@ -69,6 +85,10 @@ impl Wolf {
     pub fn set_hungry(&mut self, hungry: bool) {
         self.hungry = hungry
     }
+
+    pub fn is_hungry(&self) -> bool {
+        self.hungry
+    }
 }
 
 // Implement the `Animal` trait for `Wolf`.
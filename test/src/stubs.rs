@@ -1,12 +1,24 @@
 use crate::animals;
+use ocaml_rs_smartptr::bigarray::Bigarray;
+use ocaml_rs_smartptr::bytes::OcamlBytesMut;
+use ocaml_rs_smartptr::float_array::{FloatArray, FloatArrayOf};
 use ocaml_rs_smartptr::func::OCamlFunc;
-use ocaml_rs_smartptr::ptr::DynBox;
+use ocaml_rs_smartptr::functor::{Element, Instance};
+use ocaml_rs_smartptr::lazy::Lazy;
+use ocaml_rs_smartptr::local_ptr::LocalDynBox;
+use ocaml_rs_smartptr::ocaml_gen_extras::{Phantom, PhantomState, PolymorphicValue};
+use ocaml_rs_smartptr::ptr::{DynBox, DynBoxRef, FromOcamlRepr, OrConstruct, Returned};
+use ocaml_rs_smartptr::result::{OCamlResult, TryIntoDynBox};
 use ocaml_rs_smartptr::{
-    ocaml_gen_bindings, register_rtti, register_trait, register_type,
+    generate_constructors, ocaml_gen_bindings, register_exception, register_ord,
+    register_rtti, register_show, register_trait, register_transparent, register_type,
+    smartptr_func, FromModule, OcamlAccessors, OcamlPolyVariant, OcamlRecord,
 };
 
 extern crate derive_more;
-use derive_more::AsRef;
+use derive_more::{AsMut, AsRef};
+use std::cell::RefCell;
+use std::rc::Rc;
 
 // Animal bindings
 
@@ -70,30 +82,126 @@ pub fn animal_talk(animal: DynBox<Animal>) {
 pub type Sheep = animals::Sheep;
 
 #[allow(dead_code)]
-#[derive(AsRef)]
+#[derive(AsRef, AsMut)]
 pub struct SheepWrapper(animals::Sheep);
 
 #[ocaml_gen::func]
 #[ocaml::func]
-pub fn sheep_create(name: String) -> DynBox<Sheep> {
+pub fn sheep_create(name: String) -> Returned<Sheep> {
     let sheep: Sheep = animals::Animal::new(name);
-    sheep.into()
+    // The box is only referenced by OCaml, so it's moved rather than cloned
+    DynBox::from(sheep).into_returned()
 }
 
+// Same as `sheep_create`, but with the container picked by OCaml code
+generate_constructors!(Sheep, "Sheep", [String] => animals::Animal::new);
+
+// Read-only stub which doesn't touch the OCaml runtime, so it can borrow the
+// argument instead of cloning the `Arc`
 #[ocaml_gen::func]
 #[ocaml::func]
-pub fn sheep_is_naked(sheep: DynBox<Sheep>) -> bool {
-    let sheep = sheep.coerce();
+pub fn sheep_is_naked(sheep: DynBoxRef<Sheep>) -> bool {
+    let sheep = sheep.coerce_borrowed();
     sheep.is_naked()
 }
 
+// OCaml allocates the buffer, and the stub fills it in place
 #[ocaml_gen::func]
 #[ocaml::func]
+pub fn sheep_write_name(sheep: DynBox<Sheep>, mut buf: OcamlBytesMut) -> i32 {
+    let name = animals::Animal::name(&*sheep.coerce());
+    let len = name.len().min(buf.len());
+    buf.as_mut_slice()[..len].copy_from_slice(&name.as_bytes()[..len]);
+    len as i32
+}
+
+// Raises `Coercion_error` for immutable sheep under `PanicPolicy::RaiseOcaml`,
+// as `#[smartptr_func]` catches failed coercions
+#[smartptr_func]
 pub fn sheep_sheer(sheep: DynBox<Sheep>) {
     let mut sheep = sheep.coerce_mut();
     sheep.shear()
 }
 
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_create_lazy(name: String) -> Lazy<DynBox<Sheep>> {
+    Lazy::new(move || {
+        println!("(creating {})", name);
+        let sheep: Sheep = animals::Animal::new(name);
+        sheep.into()
+    })
+}
+
+// Stubs taking `OrConstruct<Sheep>` accept a sheep, or the name of a new one
+impl FromOcamlRepr for Sheep {
+    type Repr = String;
+
+    fn construct(name: String) -> DynBox<Sheep> {
+        let sheep: Sheep = animals::Animal::new(name);
+        sheep.into()
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_name_of(sheep: OrConstruct<Sheep>) -> String {
+    animals::Animal::name(&*sheep.0.coerce())
+}
+
+#[derive(Debug)]
+pub struct InvalidName;
+
+impl std::fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sheep name can't be empty")
+    }
+}
+
+impl std::error::Error for InvalidName {}
+
+register_exception!(InvalidName => "Invalid_name");
+
+fn sheep_try_new(name: String) -> Result<Sheep, InvalidName> {
+    if name.is_empty() {
+        return Err(InvalidName);
+    }
+    Ok(animals::Animal::new(name))
+}
+
+// Fallible construction, which raises `Invalid_name` on error
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_try_create(name: String) -> Result<DynBox<Sheep>, ocaml::Error> {
+    sheep_try_new(name).try_into_dynbox_or_raise()
+}
+
+// Same, but the error message is returned as `Error`
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_try_create_result(name: String) -> OCamlResult<DynBox<Sheep>, String> {
+    sheep_try_new(name)
+        .try_into_dynbox()
+        .map_err(|err| err.coerce().to_string())
+        .into()
+}
+
+// Immutable sheep can't be sheared, which fails the coercion in `sheep_sheer`
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_create_immutable(name: String) -> DynBox<Sheep> {
+    let sheep: Sheep = animals::Animal::new(name);
+    DynBox::new_immutable(sheep)
+}
+
+// Sheep passed to OCaml are still held as `Sheep` after being upcast to
+// `Animal` with `:>`, so they can be recovered, while wolves can't
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn sheep_of_animal(animal: DynBox<Animal>) -> Option<DynBox<Sheep>> {
+    animal.downcast_concrete::<Sheep>()
+}
+
 // Wolf bindings
 pub type Wolf = animals::Wolf;
 
@@ -104,13 +212,63 @@ pub fn wolf_create(name: String) -> DynBox<Wolf> {
     wolf.into()
 }
 
-#[ocaml_gen::func]
-#[ocaml::func]
+#[smartptr_func]
 pub fn wolf_set_hungry(wolf: DynBox<Wolf>, hungry: bool) {
     let mut wolf = wolf.coerce_mut();
     wolf.set_hungry(hungry);
 }
 
+// Borrows the argument, so that the count isn't affected by the stub itself
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn wolf_ref_count(wolf: DynBoxRef<Wolf>) -> i32 {
+    wolf.ref_count() as i32
+}
+
+#[derive(Debug)]
+pub struct NotHungry(String);
+
+impl std::fmt::Display for NotHungry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not hungry", self.0)
+    }
+}
+
+register_exception!(NotHungry => "Not_hungry");
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn wolf_feed(wolf: DynBox<Wolf>) -> Result<(), ocaml::Error> {
+    let mut wolf = wolf.coerce_mut();
+    if !wolf.is_hungry() {
+        return Err(NotHungry(animals::Animal::name(&*wolf)).into());
+    }
+    wolf.set_hungry(false);
+    Ok(())
+}
+
+// Fleece bindings, with derived getters and a setter of the weight
+
+#[derive(OcamlAccessors)]
+pub struct Fleece {
+    pub color: String,
+    #[smartptr(set)]
+    pub weight: i32,
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn fleece_create(color: String, weight: i32) -> DynBox<Fleece> {
+    Fleece { color, weight }.into()
+}
+
+// The derived setter fails the coercion for immutable fleeces
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn fleece_create_immutable(color: String, weight: i32) -> DynBox<Fleece> {
+    DynBox::new_immutable(Fleece { color, weight })
+}
+
 // Boxed trait bindings
 
 #[ocaml_gen::func]
@@ -121,6 +279,71 @@ pub fn animal_create_random(name: String) -> DynBox<Animal> {
     DynBox::new_exclusive_boxed(animal)
 }
 
+// Optional return values are mapped to OCaml `option`
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn animal_find(name: String) -> Option<DynBox<Animal>> {
+    // Dolly is the only resident of this farm
+    if name != "dolly" {
+        return None;
+    }
+    let sheep: Sheep = animals::Animal::new(name);
+    let animal: Box<Animal> = Box::new(sheep);
+    Some(DynBox::new_exclusive_boxed(animal))
+}
+
+// Arrays are mapped to `Vec`, each element is a clone of the `DynBox` held by
+// OCaml, and the returned elements are handed over to OCaml GC
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn animal_sort_by_name(animals: Vec<DynBox<Animal>>) -> Vec<DynBox<Animal>> {
+    let mut animals = animals;
+    animals.sort_by_cached_key(|animal| animal.coerce().name());
+    animals
+}
+
+// Stubs of more than 5 arguments need a separate entry point for bytecode,
+// which receives the arguments as an array. `#[ocaml::func]` generates it as
+// `animal_chorus_bytecode`, and the external names both entry points
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn animal_chorus(
+    a: DynBox<Animal>,
+    b: DynBox<Animal>,
+    c: DynBox<Animal>,
+    d: DynBox<Animal>,
+    e: DynBox<Animal>,
+    f: DynBox<Animal>,
+) -> String {
+    [a, b, c, d, e, f]
+        .iter()
+        .map(|animal| animal.coerce().noise())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Tuples are mapped to OCaml tuples, each `DynBox` in a tuple is handed over
+// to OCaml GC on its own, so the elements outlive the tuple independently
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn herd_create(name: String) -> (DynBox<Sheep>, DynBox<Wolf>) {
+    let sheep: Sheep = animals::Animal::new(format!("{} sheep", name));
+    let wolf: Wolf = animals::Animal::new(format!("{} wolf", name));
+    (sheep.into(), wolf.into())
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn herd_add_stray(
+    sheep: DynBox<Sheep>,
+    wolf: DynBox<Wolf>,
+    name: String,
+) -> (DynBox<Sheep>, DynBox<Wolf>, DynBox<Animal>) {
+    let stray: Sheep = animals::Animal::new(name);
+    let stray: Box<Animal> = Box::new(stray);
+    (sheep, wolf, DynBox::new_exclusive_boxed(stray))
+}
+
 // OCamlFunc bindings
 
 #[ocaml_gen::func]
@@ -139,6 +362,485 @@ pub fn call_cb(
     res
 }
 
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn call_cb_consuming(
+    wolf: DynBox<Wolf>,
+    cb: OCamlFunc<(DynBox<Wolf>,), DynBox<Animal>>,
+) -> DynBox<Animal> {
+    // The wolf isn't used after the call, so our reference is moved into the
+    // callback argument
+    cb.call_consuming(gc, (wolf,))
+}
+
+// Same, with the wolf moved into an `option` argument
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn call_cb_consuming_option(
+    wolf: DynBox<Wolf>,
+    cb: OCamlFunc<(Option<DynBox<Wolf>>,), DynBox<Animal>>,
+) -> DynBox<Animal> {
+    cb.call_consuming(gc, (Some(wolf),))
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn count_in_background(count: i32, cb: OCamlFunc<(i32,), ()>) {
+    // The worker notifies OCaml on its own, while the calling thread releases
+    // the domain lock to let it do so. The callback is handed back to be
+    // dropped here, as its root can only be released under the lock
+    let worker = std::thread::spawn(move || {
+        for i in 1..=count {
+            cb.call_from_any_thread((i,));
+        }
+        cb
+    });
+    let cb = gc
+        .releasing_runtime(|| worker.join())
+        .expect("background worker panicked");
+    drop(cb)
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn call_cb_propagate(
+    sheep: DynBox<Sheep>,
+    cb: OCamlFunc<(DynBox<Sheep>,), ()>,
+) -> Result<bool, ocaml::Error> {
+    // Exceptions raised by the callback are re-raised when the stub returns,
+    // so that the outer OCaml code can handle them
+    cb.call_propagate(gc, (sheep.clone(),))?;
+    Ok(sheep.coerce().is_naked())
+}
+
+// First-class module bindings
+
+// A shepherd is implemented on OCaml side and passed as `(module S)`, its
+// functions are projected out of the module by their position in `S`
+#[derive(FromModule)]
+#[module(type_name = "S")]
+pub struct Shepherd {
+    pub greet: OCamlFunc<(String,), String>,
+    #[module(name = "whistle")]
+    pub signal: OCamlFunc<(String,), String>,
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn shepherd_tend(shepherd: Shepherd, sheep: DynBox<Sheep>) {
+    let name = animals::Animal::name(&*sheep.coerce());
+    println!("{}", shepherd.greet.call(gc, (name,)));
+    println!("{}", shepherd.signal.call(gc, ("come by".to_owned(),)));
+}
+
+// Rust closure bindings
+
+pub type Adder = dyn FnMut(i32) -> i32 + Send;
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn adder_create(step: i32) -> DynBox<Adder> {
+    let mut total = 0;
+    let adder: Box<Adder> = Box::new(move |x| {
+        total += x * step;
+        total
+    });
+    DynBox::new_exclusive_boxed(adder)
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn adder_call(adder: DynBox<Adder>, x: i32) -> i32 {
+    adder.call_mut(x)
+}
+
+// Rust iterator bindings
+
+pub type Flock = dyn Iterator<Item = DynBox<Sheep>> + Send;
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn flock_create(size: i32) -> DynBox<Flock> {
+    DynBox::of_iter((1..=size).map(|i| {
+        // Sheep are created one by one, as OCaml asks for the next one
+        println!("(creating sheep #{})", i);
+        let sheep: Sheep = animals::Animal::new(format!("sheep #{}", i));
+        sheep.into()
+    }))
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn flock_next(flock: DynBox<Flock>) -> Option<DynBox<Sheep>> {
+    flock.next()
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn flock_endless(prefix: String) -> OCamlStream<DynBox<Sheep>> {
+    // Never ends, OCaml decides how many sheep it needs
+    OCamlStream::new((1..).map(move |i| {
+        println!("(creating {} #{})", prefix, i);
+        let sheep: Sheep = animals::Animal::new(format!("{} #{}", prefix, i));
+        sheep.into()
+    }))
+}
+
+// A concrete iterator registered with `Iterator<Item = DynBox<Sheep>>` as an
+// object-safe trait, so a drove can be passed wherever a flock is expected
+
+pub struct Drove {
+    names: std::vec::IntoIter<String>,
+}
+
+impl Iterator for Drove {
+    type Item = DynBox<Sheep>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sheep: Sheep = animals::Animal::new(self.names.next()?);
+        Some(sheep.into())
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn drove_create(names: Vec<String>) -> DynBox<Drove> {
+    DynBox::new_exclusive(Drove {
+        names: names.into_iter(),
+    })
+}
+
+// Single-threaded object graph bindings
+
+// Pens are nested into each other, and every pen counts the sheep of its
+// inner pens too. The graph is built out of `Rc<RefCell<_>>`, so it can't be
+// put into a `DynBox`
+pub struct Pen {
+    name: String,
+    count: i32,
+    outer: Option<Rc<RefCell<Pen>>>,
+}
+
+impl Pen {
+    fn add(&mut self) {
+        self.count += 1;
+        if let Some(outer) = &self.outer {
+            outer.borrow_mut().add()
+        }
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn pen_create(name: String) -> LocalDynBox<Pen> {
+    LocalDynBox::new(Pen {
+        name,
+        count: 0,
+        outer: None,
+    })
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn pen_create_inner(outer: LocalDynBox<Pen>, name: String) -> LocalDynBox<Pen> {
+    LocalDynBox::new(Pen {
+        name,
+        count: 0,
+        outer: Some(outer.to_rc().expect("outer pen is not a Pen")),
+    })
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn pen_add(pen: LocalDynBox<Pen>) {
+    pen.coerce_mut().add()
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn pen_describe(pen: LocalDynBox<Pen>) -> String {
+    let pen = pen.coerce();
+    format!("{}: {} sheep", pen.name, pen.count)
+}
+
+// Record bindings
+
+// Plain data, copied field by field into an OCaml record
+#[derive(OcamlRecord)]
+pub struct Census {
+    pub count: i32,
+    pub naked: i32,
+    pub names: Vec<String>,
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn census_of_flock(flock: Vec<DynBox<Sheep>>) -> Census {
+    use animals::Animal;
+    let flock: Vec<_> = flock.iter().map(|sheep| sheep.coerce()).collect();
+    Census {
+        count: flock.len() as i32,
+        naked: flock.iter().filter(|sheep| sheep.is_naked()).count() as i32,
+        names: flock.iter().map(|sheep| sheep.name()).collect(),
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn census_describe(census: Census) -> String {
+    format!(
+        "{} sheep ({} naked): {}",
+        census.count,
+        census.naked,
+        census.names.join(", ")
+    )
+}
+
+// Recursive type bindings
+
+// A tree node referencing nodes of its own type, which is opaque to OCaml
+pub struct Node {
+    label: String,
+    children: Vec<DynBox<Node>>,
+}
+
+// A copy of a tree, as a record referencing records of its own type
+#[derive(OcamlRecord)]
+pub struct NodeSummary {
+    pub label: String,
+    pub children: Vec<NodeSummary>,
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn node_create(label: String) -> DynBox<Node> {
+    DynBox::new_shared(Node {
+        label,
+        children: vec![],
+    })
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn node_add_child(node: DynBox<Node>, child: DynBox<Node>) {
+    node.coerce_mut().children.push(child);
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn node_children(node: DynBox<Node>) -> Vec<DynBox<Node>> {
+    node.coerce().children.clone()
+}
+
+fn summarize(node: &DynBox<Node>) -> NodeSummary {
+    let node = node.coerce();
+    NodeSummary {
+        label: node.label.clone(),
+        children: node.children.iter().map(summarize).collect(),
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn node_summary(node: DynBox<Node>) -> NodeSummary {
+    summarize(&node)
+}
+
+// Polymorphic variant bindings
+
+// Where a sheep is, copied variant by variant into an OCaml polymorphic variant
+#[derive(OcamlPolyVariant)]
+pub enum Whereabouts {
+    Barn,
+    Pasture(String),
+    Coords(i32, i32),
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn whereabouts_describe(whereabouts: Whereabouts) -> String {
+    match whereabouts {
+        Whereabouts::Barn => "in the barn".to_owned(),
+        Whereabouts::Pasture(name) => format!("grazing on {}", name),
+        Whereabouts::Coords(x, y) => format!("lost at ({}, {})", x, y),
+    }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn whereabouts_wander(whereabouts: Whereabouts) -> Whereabouts {
+    match whereabouts {
+        Whereabouts::Barn => Whereabouts::Pasture("the meadow".to_owned()),
+        Whereabouts::Pasture(_) => Whereabouts::Coords(3, -4),
+        Whereabouts::Coords(x, y) => Whereabouts::Coords(x + 1, y - 1),
+    }
+}
+
+// Default arguments bindings
+
+pub struct Config {
+    name: String,
+    port: i32,
+    verbose: bool,
+}
+
+// Declared with `decl_func_with_defaults!`, so that OCaml only has to pass the
+// name, and the rest of the arguments default in the generated wrapper
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn config_create(name: String, port: i32, verbose: bool) -> DynBox<Config> {
+    DynBox::new_shared(Config {
+        name,
+        port,
+        verbose,
+    })
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn config_describe(config: DynBox<Config>) -> String {
+    config.read(|config| {
+        let verbose = if config.verbose { " (verbose)" } else { "" };
+        format!("{} on port {}{}", config.name, config.port, verbose)
+    })
+}
+
+// Float array bindings
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn samples_of_array(values: FloatArray) -> DynBox<Vec<f64>> {
+    DynBox::new_exclusive(values.0)
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn samples_scale(samples: DynBox<Vec<f64>>, factor: f64) {
+    samples.write(|values| values.iter_mut().for_each(|value| *value *= factor))
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn samples_to_array(samples: DynBox<Vec<f64>>) -> FloatArrayOf {
+    FloatArrayOf(samples)
+}
+
+// Bigarray view bindings
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn payload_of_string(data: String) -> DynBox<Vec<u8>> {
+    DynBox::new_shared(data.into_bytes())
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn payload_view(payload: DynBox<Vec<u8>>) -> Bigarray {
+    // Safety: payloads are never resized once they're created
+    unsafe { Bigarray::new(payload) }
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn payload_to_string(payload: DynBox<Vec<u8>>) -> String {
+    payload.read(|data| String::from_utf8_lossy(data).into_owned())
+}
+
+// Phantom state bindings
+
+pub struct Connection {
+    peer: String,
+    open: bool,
+}
+
+impl Connection {
+    // Stands for a method of an async client library
+    async fn request(&self, msg: &str) -> String {
+        tokio::task::yield_now().await;
+        format!("{} replied to {:?}", self.peer, msg)
+    }
+}
+
+pub struct Open;
+pub struct Closed;
+
+impl PhantomState for Open {
+    const TAG: &'static str = "Open";
+}
+
+impl PhantomState for Closed {
+    const TAG: &'static str = "Closed";
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn connection_connect(peer: String) -> Phantom<Open, DynBox<Connection>> {
+    DynBox::new_shared(Connection { peer, open: true }).into()
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn connection_send(conn: Phantom<Open, DynBox<Connection>>, msg: String) {
+    conn.read(|conn| println!("sending {:?} to {}", msg, conn.peer));
+}
+
+// The async method is driven to completion on the shared tokio runtime
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn connection_request(
+    conn: Phantom<Open, DynBox<Connection>>,
+    msg: String,
+) -> String {
+    conn.block_on(async |conn| conn.request(&msg).await)
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn connection_close(
+    conn: Phantom<Open, DynBox<Connection>>,
+) -> Phantom<Closed, DynBox<Connection>> {
+    conn.write(|conn| conn.open = false);
+    conn.into_inner().into()
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn connection_is_open(conn: DynBox<Connection>) -> bool {
+    conn.read(|conn| conn.open)
+}
+
+// Functor bindings
+
+// A generic container, bound as the `Stack` functor over the module of its
+// elements, which are kept as OCaml values
+pub struct Stack<T>(Vec<T>);
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn stack_create() -> Instance<DynBox<Stack<Element>>> {
+    DynBox::new_exclusive(Stack(Vec::new())).into()
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn stack_push(stack: Instance<DynBox<Stack<Element>>>, elt: Element) {
+    stack.write(|stack| stack.0.push(elt));
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn stack_pop(stack: Instance<DynBox<Stack<Element>>>) -> Option<Element> {
+    stack.write(|stack| stack.0.pop())
+}
+
+// The length doesn't depend on the elements, so stacks of any element type
+// are accepted
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn stack_length(stack: DynBox<Stack<Element>>) -> i32 {
+    stack.read(|stack| stack.0.len() as i32)
+}
+
 // ocaml_export!  bindings
 
 #[derive(ocaml::ToValue, ocaml::FromValue, ocaml_gen::CustomType)]
@@ -146,6 +848,11 @@ pub struct Barn {
     size: u32,
 }
 
+#[derive(ocaml::ToValue, ocaml::FromValue, ocaml_gen::CustomType)]
+pub struct TaggedBarn {
+    size: u32,
+}
+
 type DynBoxWithAnimal = DynBox<dyn AnimalProxy + Send + Sync>;
 
 pub mod exports {
@@ -155,6 +862,12 @@ pub mod exports {
         DynBoxWithAnimal,
         "Some_other_lib.Animal.t"
     );
+    ocaml_rs_smartptr::ocaml_export!(
+        crate::stubs::TaggedBarn,
+        TaggedBarn,
+        "Some_other_lib.Tagged_barn.t",
+        ocaml_rs_smartptr::ocaml_gen_extras::P1<'a'>
+    );
 }
 
 #[ocaml_gen::func]
@@ -163,6 +876,12 @@ pub fn barn_create(size: u32) -> exports::Barn {
     Barn { size }.into()
 }
 
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn tagged_barn_create(size: u32) -> exports::TaggedBarn {
+    TaggedBarn { size }.into()
+}
+
 #[ocaml_gen::func]
 #[ocaml::func]
 pub fn dynbox_with_animal_create(name: String) -> exports::DynBoxWithAnimal {
@@ -179,6 +898,13 @@ register_rtti! {
             marker_traits: [core::marker::Sync, core::marker::Send],
         }
     );
+    // Errors of fallible constructors, see `sheep_try_create_result`
+    register_trait!(
+        {
+            ty: std::error::Error,
+            marker_traits: [core::marker::Send],
+        }
+    );
     register_type!(
         {
             ty: crate::stubs::Sheep,
@@ -186,6 +912,7 @@ register_rtti! {
             object_safe_traits: [crate::stubs::AnimalProxy],
         }
     );
+    register_transparent!(crate::stubs::SheepWrapper => crate::stubs::Sheep);
     register_type!(
         {
             ty: crate::stubs::Wolf,
@@ -193,6 +920,94 @@ register_rtti! {
             object_safe_traits: [crate::stubs::AnimalProxy],
         }
     );
+    register_type!(
+        {
+            ty: crate::stubs::Fleece,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    register_type!(
+        {
+            ty: crate::stubs::Drove,
+            marker_traits: [core::marker::Send],
+            object_safe_traits: [core::iter::Iterator<Item = DynBox<Sheep>>],
+        }
+    );
+    register_type!(
+        {
+            ty: crate::stubs::Pen,
+            marker_traits: [],
+        }
+    );
+    // Nodes are never passed where other objects are expected, so their
+    // OCaml type is closed
+    register_type!(
+        {
+            ty: crate::stubs::Node,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+            variant_kind: closed,
+        }
+    );
+    register_type!(
+        {
+            ty: std::vec::Vec<f64>,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    register_type!(
+        {
+            ty: std::vec::Vec<u8>,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    register_type!(
+        {
+            ty: crate::stubs::Connection,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    register_type!(
+        {
+            ty: crate::stubs::Config,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    register_type!(
+        {
+            ty: crate::stubs::Stack<ocaml_rs_smartptr::functor::Element>,
+            marker_traits: [core::marker::Sync, core::marker::Send],
+        }
+    );
+    // Sheep are compared and ordered structurally, while wolves are only equal
+    // to themselves
+    register_ord!(crate::stubs::Sheep);
+    // Sheep are shown by OCaml printers, while wolves fall back to their type
+    // name
+    register_show!(crate::stubs::Sheep => |sheep| {
+        let naked = if sheep.is_naked() { "naked" } else { "woolly" };
+        format!("{} ({})", animals::Animal::name(sheep), naked)
+    });
+    register_trait!(
+        {
+            ty: core::ops::FnMut(i32) -> i32,
+            marker_traits: [core::marker::Send],
+        }
+    );
+    register_trait!(
+        {
+            ty: core::iter::Iterator<Item = DynBox<Sheep>>,
+            marker_traits: [core::marker::Send],
+        }
+    );
+}
+
+#[ocaml_gen::func]
+#[ocaml::func]
+pub fn animal_describe(value: PolymorphicValue<'a'>) -> String {
+    match value.try_as_dynbox::<Animal>() {
+        Some(animal) => format!("an animal named {}", animal.coerce().name()),
+        None => "not an animal".to_string(),
+    }
 }
 
 // OCaml bindings generation
@@ -203,23 +1018,58 @@ ocaml_gen_bindings! {
         decl_func!(animal_noise => "noise");
         decl_func!(animal_talk => "talk");
         decl_func!(animal_create_random => "create_random");
+        decl_func!(animal_find => "find");
+        decl_func!(animal_sort_by_name => "sort_by_name");
+        decl_func!(animal_chorus => "chorus");
+        decl_func!(animal_describe => "describe");
     });
 
     decl_module!("Sheep", {
         decl_type!(DynBox<Sheep> => "t");
+        decl_exception!(InvalidName);
         decl_func!(sheep_create => "create");
+        decl_constructors!("Sheep");
         decl_func!(sheep_is_naked => "is_naked");
         decl_func!(sheep_sheer => "sheer");
+        decl_func!(sheep_write_name => "write_name");
+        decl_func!(sheep_create_lazy => "create_lazy");
+        decl_func!(sheep_name_of => "name_of");
+        decl_func!(sheep_try_create => "try_create");
+        decl_func!(sheep_try_create_result => "try_create_result");
+        decl_func!(sheep_create_immutable => "create_immutable");
+        decl_func!(sheep_of_animal => "of_animal");
+        decl_equal!(DynBox<Sheep>);
+        decl_compare!(DynBox<Sheep>);
     });
 
     decl_module!("Wolf", {
         decl_type!(DynBox<Wolf> => "t");
+        decl_exception!(NotHungry);
         decl_func!(wolf_create => "create");
         decl_func!(wolf_set_hungry => "set_hungry");
+        decl_func!(wolf_feed => "feed");
+        decl_func!(wolf_ref_count => "ref_count");
+        decl_equal!(DynBox<Wolf>);
+    });
+
+    decl_module!("Fleece", {
+        decl_type!(DynBox<Fleece> => "t");
+        decl_func!(fleece_create => "create");
+        decl_func!(fleece_create_immutable => "create_immutable");
+        decl_accessors!(Fleece);
+    });
+
+    decl_module!("Herd", {
+        decl_func!(herd_create => "create");
+        decl_func!(herd_add_stray => "add_stray");
     });
 
     decl_module!("Test_callback", {
         decl_func!(call_cb => "call_cb");
+        decl_func!(count_in_background => "count_in_background");
+        decl_func!(call_cb_propagate => "call_cb_propagate");
+        decl_func!(call_cb_consuming => "call_cb_consuming");
+        decl_func!(call_cb_consuming_option => "call_cb_consuming_option");
     });
 
     decl_module!("Animal_alias", {
@@ -232,5 +1082,101 @@ ocaml_gen_bindings! {
         decl_type_alias!("barn" => exports::Barn);
         decl_func!(barn_create => "barn_create_with_alias");
         decl_func!(dynbox_with_animal_create => "dynbox_with_animal_create");
+        decl_type_alias!("tagged_barn" => exports::TaggedBarn);
+        decl_func!(tagged_barn_create => "tagged_barn_create");
+    });
+
+    decl_module!("Adder", {
+        decl_type!(DynBox<Adder> => "t");
+        decl_func!(adder_create => "create");
+        decl_func!(adder_call => "call");
+    });
+
+    decl_module!("Flock", {
+        decl_type!(DynBox<Flock> => "t");
+        decl_func!(flock_create => "create");
+        decl_func!(flock_next => "next");
+        decl_func!(flock_endless => "endless");
+    });
+
+    decl_module!("Drove", {
+        decl_type!(DynBox<Drove> => "t");
+        decl_func!(drove_create => "create");
+    });
+
+    decl_module!("Pen", {
+        decl_type!(LocalDynBox<Pen> => "t");
+        decl_func!(pen_create => "create");
+        decl_func!(pen_create_inner => "create_inner");
+        decl_func!(pen_add => "add");
+        decl_func!(pen_describe => "describe");
+    });
+
+    decl_module!("Census", {
+        decl_type!(Census => "t");
+        decl_func!(census_of_flock => "of_flock");
+        decl_func!(census_describe => "describe");
+    });
+
+    decl_module!("Node", {
+        decl_type!(DynBox<Node> => "t");
+        decl_type!(NodeSummary => "summary");
+        decl_func!(node_create => "create");
+        decl_func!(node_add_child => "add_child");
+        decl_func!(node_children => "children");
+        decl_func!(node_summary => "summary");
+    });
+
+    decl_module!("Whereabouts", {
+        decl_type!(Whereabouts => "t");
+        decl_func!(whereabouts_describe => "describe");
+        decl_func!(whereabouts_wander => "wander");
+    });
+
+    decl_module!("Config", {
+        decl_type!(DynBox<Config> => "t");
+        decl_func_with_defaults!(config_create => "create", [
+            "~name",
+            "?(port = 8080l)",
+            "?(verbose = false)",
+        ]);
+        decl_func!(config_describe => "describe");
+    });
+
+    decl_module!("Samples", {
+        decl_type!(DynBox<Vec<f64>> => "t");
+        decl_func!(samples_of_array => "of_array");
+        decl_func!(samples_scale => "scale");
+        decl_func!(samples_to_array => "to_array");
+    });
+
+    decl_module!("Payload", {
+        decl_type!(DynBox<Vec<u8>> => "t");
+        decl_func!(payload_of_string => "of_string");
+        decl_func!(payload_view => "view");
+        decl_func!(payload_to_string => "to_string");
+    });
+
+    decl_module!("Connection", {
+        decl_type!(DynBox<Connection> => "t");
+        decl_func!(connection_connect => "connect");
+        decl_func!(connection_send => "send");
+        decl_func!(connection_request => "request");
+        decl_func!(connection_close => "close");
+        decl_func!(connection_is_open => "is_open");
+    });
+
+    decl_functor!("Stack", "Elt", {
+        decl_type!(DynBox<Stack<Element>> => "stack");
+        decl_instance_type!(Instance<DynBox<Stack<Element>>> => "t");
+        decl_func!(stack_create => "create");
+        decl_func!(stack_push => "push");
+        decl_func!(stack_pop => "pop");
+        decl_func!(stack_length => "length");
+    });
+
+    decl_module!("Shepherd", {
+        decl_module_type!(Shepherd);
+        decl_func!(shepherd_tend => "tend");
     });
 }
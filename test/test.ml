@@ -14,10 +14,32 @@ module Wolf = struct
   include Stubs.Wolf
 end
 
+module Herd = struct
+  include Stubs.Herd
+end
+
 module Test_callback = struct
   include Stubs.Test_callback
 end
 
+module Adder = struct
+  include Stubs.Adder
+end
+
+module Flock = struct
+  include Stubs.Flock
+
+  let to_seq flock = Seq.of_dispenser (fun () -> next flock)
+end
+
+module Drove = struct
+  include Stubs.Drove
+end
+
+module Connection = struct
+  include Stubs.Connection
+end
+
 (* Now use hand-written bindings in actual code: *)
 
 let sheep_test () =
@@ -27,7 +49,18 @@ let sheep_test () =
   Sheep.sheer sheep;
   (* inclusion of Animal into Sheep allows to call Animal methods on Sheep right
      from Sheep module for convenience *)
-  Sheep.talk sheep
+  Sheep.talk sheep;
+  print_endline (Ocaml_rs_smartptr.Rusty_obj.type_name sheep);
+  Format.printf "shown: %a@." Ocaml_rs_smartptr.Rusty_obj.pp sheep
+;;
+
+let constructors_test () =
+  print_endline "\n*** Constructors test";
+  let exclusive = Sheep.create_exclusive "shaun" in
+  let shared = Sheep.create_shared "timmy" in
+  Sheep.sheer shared;
+  Sheep.talk exclusive;
+  Sheep.talk shared
 ;;
 
 let wolf_test () =
@@ -42,7 +75,9 @@ let wolf_test () =
       Gc.full_major ();
       wolf)
   in
-  Animal.talk animal
+  Animal.talk animal;
+  (* Wolves have no registered show function *)
+  print_endline (Ocaml_rs_smartptr.Rusty_obj.to_string wolf)
 ;;
 
 let random_animal_test () =
@@ -51,10 +86,439 @@ let random_animal_test () =
   Animal.talk animal
 ;;
 
+let find_animal_test () =
+  print_endline "\n*** Find animal test";
+  let find name =
+    match Animal.find name with
+    | Some animal -> Animal.talk animal
+    | None -> Printf.printf "%s is not found\n" name
+  in
+  find "dolly";
+  find "shaun"
+;;
+
+let sort_animals_test () =
+  print_endline "\n*** Sort animals test";
+  let animals =
+    [| Wolf.create "big bad wolf"; Sheep.create "dolly"; Animal.create_random "anonymous" |]
+  in
+  let sorted = Animal.sort_by_name animals in
+  Gc.full_major ();
+  Array.iter Animal.talk sorted;
+  (* Both arrays share the same objects, the original one is left intact *)
+  animals |> Array.map Animal.name |> Array.to_list |> String.concat ", " |> print_endline
+;;
+
+let chorus_test () =
+  print_endline "\n*** Chorus test";
+  Animal.chorus
+    (Sheep.create "dolly")
+    (Sheep.create "shaun")
+    (Sheep.create "timmy")
+    (Sheep.create "shirley")
+    (Sheep.create "nuts")
+    (Wolf.create "big bad wolf")
+  |> print_endline
+;;
+
+let herd_test () =
+  print_endline "\n*** Herd test";
+  let sheep, wolf = Herd.create "lonely" in
+  let sheep, wolf, stray = Herd.add_stray sheep wolf "shaun" in
+  (* The tuples are gone, while their elements are still alive *)
+  Gc.full_major ();
+  Sheep.talk sheep;
+  Wolf.talk wolf;
+  Animal.talk stray
+;;
+
+let feed_test () =
+  print_endline "\n*** Feed test";
+  let wolf = Wolf.create "big bad wolf" in
+  let feed () =
+    match Wolf.feed wolf with
+    | () -> print_endline "the wolf is fed"
+    | exception Wolf.Not_hungry msg -> print_endline ("Not_hungry: " ^ msg)
+  in
+  feed ();
+  Wolf.set_hungry wolf true;
+  feed ()
+;;
+
+let adder_test () =
+  print_endline "\n*** Adder test";
+  let adder = Adder.create 2l in
+  ignore (Adder.call adder 1l);
+  Printf.printf "total: %ld\n" (Adder.call adder 3l)
+;;
+
+let flock_test () =
+  print_endline "\n*** Flock test";
+  let flock = Flock.create 3l in
+  print_endline "no sheep are created yet";
+  Flock.to_seq flock |> Seq.take 2 |> Seq.iter Sheep.talk;
+  (* Droves implement the same iterator trait, so they are flocks too *)
+  let drove = Drove.create [| "shaun"; "timmy" |] in
+  Flock.to_seq drove |> Seq.iter Sheep.talk
+;;
+
+let stream_test () =
+  print_endline "\n*** Stream test";
+  let next = Flock.endless "lamb" in
+  print_endline "no lambs are created yet";
+  Seq.of_dispenser next |> Seq.take 3 |> Seq.iter Sheep.talk
+;;
+
+let pen_test () =
+  print_endline "\n*** Pen test";
+  let field = Pen.create "field" in
+  let fold = Pen.create_inner field "fold" in
+  Pen.add fold;
+  Pen.add fold;
+  Pen.add field;
+  print_endline (Pen.describe fold);
+  print_endline (Pen.describe field)
+;;
+
+let census_test () =
+  print_endline "\n*** Census test";
+  let dolly = Sheep.create "dolly" in
+  let shaun = Sheep.create "shaun" in
+  Sheep.sheer shaun;
+  let census = Census.of_flock [| dolly; shaun |] in
+  Printf.printf "counted %ld sheep\n" census.Census.count;
+  print_endline (Census.describe census);
+  print_endline
+    (Census.describe { census with Census.naked = 0l; names = [| "nobody" |] })
+;;
+
+let node_test () =
+  print_endline "\n*** Node test";
+  let root = Node.create "root" in
+  let left = Node.create "left" in
+  Node.add_child root left;
+  Node.add_child root (Node.create "right");
+  Node.add_child left (Node.create "leaf");
+  Printf.printf "root has %d children\n" (Array.length (Node.children root));
+  let rec print indent { Node.label; children } =
+    Printf.printf "%s%s\n" indent label;
+    Array.iter (print (indent ^ "  ")) children
+  in
+  print "" (Node.summary root)
+;;
+
+let samples_test () =
+  print_endline "\n*** Samples test";
+  let samples = Samples.of_array [| 1.5; 2.; 4.25 |] in
+  Samples.scale samples 2.;
+  Samples.to_array samples |> Array.iter (Printf.printf "%g\n");
+  let empty = Samples.of_array [||] in
+  Samples.scale empty 2.;
+  Printf.printf "empty: %d\n" (Array.length (Samples.to_array empty))
+;;
+
+let payload_test () =
+  print_endline "\n*** Payload test";
+  let payload = Payload.of_string "dolly" in
+  let view = Payload.view payload in
+  Printf.printf "char kind: %b\n" (Bigarray.Array1.kind view = Bigarray.char);
+  view.{0} <- 'D';
+  let copy = Bigarray.Array1.create Bigarray.char Bigarray.c_layout 5 in
+  Bigarray.Array1.blit view copy;
+  Printf.printf "rust: %s, copy: %c...%c\n" (Payload.to_string payload) copy.{0} copy.{4}
+;;
+
+let buffer_test () =
+  print_endline "\n*** Buffer test";
+  let sheep = Sheep.create "dolly" in
+  let buf = Bytes.make 8 '.' in
+  let len = Sheep.write_name sheep buf in
+  Printf.printf "written %ld bytes: %s\n" len (Bytes.to_string buf);
+  let short = Bytes.create 3 in
+  let len = Sheep.write_name sheep short in
+  Printf.printf "written %ld bytes: %s\n" len (Bytes.to_string short)
+;;
+
+let connection_test () =
+  print_endline "\n*** Connection test";
+  let conn = Connection.connect "localhost" in
+  Connection.send conn "hello";
+  print_endline (Connection.request conn "ping");
+  let closed = Connection.close conn in
+  (* [Connection.send closed "bye"] would not type check *)
+  Printf.printf "open: %b\n" (Connection.is_open closed)
+;;
+
+let background_test () =
+  print_endline "\n*** Background test";
+  Test_callback.count_in_background 3l (fun i ->
+    Printf.printf "worker counted sheep #%ld\n" i)
+;;
+
+let propagate_test () =
+  print_endline "\n*** Propagate test";
+  let sheep = Sheep.create "dolly" in
+  Printf.printf
+    "naked: %b\n"
+    (Test_callback.call_cb_propagate sheep (fun sheep -> Sheep.sheer sheep));
+  match Test_callback.call_cb_propagate sheep (fun _ -> raise Not_found) with
+  | _ -> print_endline "exception got lost"
+  | exception Not_found -> print_endline "Not_found propagated through Rust"
+;;
+
+let consuming_test () =
+  print_endline "\n*** Consuming test";
+  let wolf = Wolf.create "big bad wolf" in
+  let count_refs wolf =
+    (* Finalize the values left over from previous calls *)
+    Gc.full_major ();
+    Printf.printf "references inside callback: %ld\n" (Wolf.ref_count wolf);
+    wolf
+  in
+  (* [call_cb] keeps its own reference while the callback runs, while
+     [call_cb_consuming] moves it into the callback argument *)
+  ignore (Test_callback.call_cb wolf count_refs);
+  ignore (Test_callback.call_cb_consuming wolf count_refs);
+  (* Same within an option *)
+  ignore
+    (Test_callback.call_cb_consuming_option wolf (fun wolf ->
+       count_refs (Option.get wolf)));
+  Animal.talk wolf
+;;
+
+let lazy_test () =
+  print_endline "\n*** Lazy test";
+  let sheep = Sheep.create_lazy "shaun" in
+  print_endline "nothing is created yet";
+  Sheep.talk (Lazy.force sheep);
+  Sheep.talk (Lazy.force sheep)
+;;
+
+let shepherd_test () =
+  print_endline "\n*** Shepherd test";
+  let module Shepherd_impl = struct
+    let greet name = "hello, " ^ name
+    let whistle command = Printf.sprintf "whistles %S" command
+  end
+  in
+  let sheep = Sheep.create "dolly" in
+  Shepherd.tend (module Shepherd_impl) sheep
+;;
+
+let equal_test () =
+  print_endline "\n*** Equal test";
+  let dolly = Sheep.create "dolly" in
+  Printf.printf "dolly = dolly: %b\n" (Sheep.equal dolly (Sheep.create "dolly"));
+  Printf.printf "dolly = shaun: %b\n" (Sheep.equal dolly (Sheep.create "shaun"));
+  let wolf = Wolf.create "big bad wolf" in
+  Printf.printf "wolf = itself: %b\n" (Wolf.equal wolf wolf);
+  Printf.printf "wolf = its twin: %b\n" (Wolf.equal wolf (Wolf.create "big bad wolf"))
+;;
+
+let ordering_test () =
+  print_endline "\n*** Ordering test";
+  let module Sheep_set = Set.Make (Sheep) in
+  let flock =
+    [ "timmy"; "dolly"; "shaun"; "dolly" ] |> List.map Sheep.create |> Sheep_set.of_list
+  in
+  Gc.full_major ();
+  Sheep_set.elements flock |> List.map Sheep.name |> String.concat ", " |> print_endline
+;;
+
+let whereabouts_test () =
+  print_endline "\n*** Whereabouts test";
+  let rec follow n whereabouts =
+    print_endline (Whereabouts.describe whereabouts);
+    if n > 0 then follow (n - 1) (Whereabouts.wander whereabouts)
+  in
+  follow 3 `Barn;
+  match Whereabouts.wander (`Pasture "the hill") with
+  | `Coords (x, y) -> Printf.printf "wandered off to %ld, %ld\n" x y
+  | `Barn | `Pasture _ -> print_endline "stayed around"
+;;
+
+let downcast_test () =
+  print_endline "\n*** Downcast test";
+  let recover (animal : Animal.t) =
+    match Sheep.of_animal animal with
+    | Some sheep ->
+      Sheep.sheer sheep;
+      Printf.printf "naked: %b\n" (Sheep.is_naked sheep)
+    | None -> Printf.printf "%s is not a sheep\n" (Animal.name animal)
+  in
+  recover (Sheep.create "dolly" :> Animal.t);
+  recover (Wolf.create "big bad wolf" :> Animal.t)
+;;
+
+let config_test () =
+  print_endline "\n*** Config test";
+  print_endline (Config.describe (Config.create ~name:"farm" ()));
+  print_endline (Config.describe (Config.create ~name:"barn" ~port:8081l ~verbose:true ()))
+;;
+
+let stack_test () =
+  print_endline "\n*** Stack test";
+  let module Sheep_stack = Stubs.Stack (Sheep) in
+  let module Name_stack = Stubs.Stack (String) in
+  let sheep = Sheep_stack.create () in
+  Sheep_stack.push sheep (Sheep.create "dolly");
+  Sheep_stack.push sheep (Sheep.create "shaun");
+  let names = Name_stack.create () in
+  Name_stack.push names "timmy";
+  Printf.printf
+    "sheep: %ld, names: %ld\n"
+    (Sheep_stack.length sheep)
+    (Name_stack.length names);
+  let rec drain () =
+    match Sheep_stack.pop sheep with
+    | Some sheep ->
+      Sheep.talk sheep;
+      drain ()
+    | None -> print_endline "no more sheep"
+  in
+  drain ();
+  Option.iter print_endline (Name_stack.pop names)
+;;
+
+let hash_test () =
+  print_endline "\n*** Hash test";
+  let module Nodes =
+    Weak.Make (struct
+      type t = Node.t
+
+      let equal = ( == )
+      let hash = Hashtbl.hash
+    end)
+  in
+  let root = Node.create "root" in
+  Node.add_child root (Node.create "child");
+  let child = (Node.children root).(0) in
+  let child_again = (Node.children root).(0) in
+  Printf.printf
+    "same object, distinct blocks: %b, same hash: %b\n"
+    (child != child_again)
+    (Hashtbl.hash child = Hashtbl.hash child_again);
+  Printf.printf
+    "root and child hash differ: %b\n"
+    (Hashtbl.hash root <> Hashtbl.hash child);
+  let nodes = Nodes.create 16 in
+  Nodes.add nodes root;
+  Nodes.add nodes child;
+  Printf.printf "root in weak set: %b\n" (Nodes.mem nodes root)
+;;
+
+let or_construct_test () =
+  print_endline "\n*** Or construct test";
+  let dolly = Sheep.create "dolly" in
+  Printf.printf "name of a sheep: %s\n" (Sheep.name_of (`Obj dolly));
+  Printf.printf "name of a string: %s\n" (Sheep.name_of (`Repr "shaun"))
+;;
+
+let try_create_test () =
+  print_endline "\n*** Try create test";
+  let try_create name =
+    match Sheep.try_create name with
+    | sheep -> Printf.printf "created %s\n" (Sheep.name_of (`Obj sheep))
+    | exception Sheep.Invalid_name msg -> print_endline ("Invalid_name: " ^ msg)
+  in
+  try_create "dolly";
+  try_create "";
+  let try_create_result name =
+    match Sheep.try_create_result name with
+    | Ok sheep -> Printf.printf "created %s\n" (Sheep.name_of (`Obj sheep))
+    | Error msg -> print_endline ("Error: " ^ msg)
+  in
+  try_create_result "shaun";
+  try_create_result ""
+;;
+
+let coercion_failure_test () =
+  print_endline "\n*** Coercion failure test";
+  let sheep = Sheep.create_immutable "dolly" in
+  Ocaml_rs_smartptr.Registry.set_raise_on_coercion_failure true;
+  let sheer sheep =
+    match Sheep.sheer sheep with
+    | () -> print_endline "sheared"
+    | exception Ocaml_rs_smartptr.Registry.Coercion_error msg ->
+      Printf.printf
+        "Coercion_error: %b\n"
+        (String.starts_with ~prefix:"immutable value" msg)
+  in
+  sheer sheep;
+  (* The failed coercion doesn't leave anything locked *)
+  sheer sheep;
+  Printf.printf "naked: %b\n" (Sheep.is_naked sheep);
+  Ocaml_rs_smartptr.Registry.set_raise_on_coercion_failure false
+;;
+
+let accessors_test () =
+  print_endline "\n*** Accessors test";
+  let print_fleece fleece =
+    Printf.printf "%s fleece of %ld kg\n" (Fleece.color fleece) (Fleece.weight fleece)
+  in
+  let fleece = Fleece.create "white" 3l in
+  Fleece.set_weight fleece 4l;
+  print_fleece fleece;
+  let fleece = Fleece.create_immutable "black" 2l in
+  Ocaml_rs_smartptr.Registry.set_raise_on_coercion_failure true;
+  (match Fleece.set_weight fleece 5l with
+   | () -> print_endline "weight set"
+   | exception Ocaml_rs_smartptr.Registry.Coercion_error msg ->
+     Printf.printf
+       "Coercion_error: %b\n"
+       (String.starts_with ~prefix:"immutable value" msg));
+  Ocaml_rs_smartptr.Registry.set_raise_on_coercion_failure false;
+  print_fleece fleece
+;;
+
+let describe_test () =
+  print_endline "\n*** Describe test";
+  print_endline (Animal.describe (Sheep.create "dolly"));
+  print_endline (Animal.describe (Wolf.create "big bad wolf"));
+  print_endline (Animal.describe "shaun");
+  print_endline (Animal.describe 42);
+  let module Name_stack = Stubs.Stack (String) in
+  print_endline (Animal.describe (Name_stack.create ()))
+;;
+
 let main () =
   sheep_test ();
+  constructors_test ();
   wolf_test ();
-  random_animal_test ()
+  random_animal_test ();
+  find_animal_test ();
+  sort_animals_test ();
+  herd_test ();
+  feed_test ();
+  adder_test ();
+  flock_test ();
+  stream_test ();
+  pen_test ();
+  census_test ();
+  node_test ();
+  samples_test ();
+  payload_test ();
+  buffer_test ();
+  connection_test ();
+  background_test ();
+  propagate_test ();
+  consuming_test ();
+  lazy_test ();
+  shepherd_test ();
+  equal_test ();
+  hash_test ();
+  or_construct_test ();
+  try_create_test ();
+  coercion_failure_test ();
+  accessors_test ();
+  chorus_test ();
+  ordering_test ();
+  whereabouts_test ();
+  downcast_test ();
+  config_test ();
+  stack_test ();
+  describe_test ()
 ;;
 
+let () = at_exit Ocaml_rs_smartptr.Registry.shutdown
 let () = main ()
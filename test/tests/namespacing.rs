@@ -0,0 +1,69 @@
+//! Tags of types registered by different crates, computed with the plugins of
+//! `ocaml_rs_smartptr_test` and the registrations of this crate in the same
+//! registry, as `stubs_gen_main` does.
+
+use ocaml_rs_smartptr::registry::{self, MemSized};
+use ocaml_rs_smartptr::{register_trait, register_type};
+use ocaml_rs_smartptr_test::animals;
+use ocaml_rs_smartptr_test::stubs::{Animal, Sheep};
+
+pub struct Lamb(String);
+
+impl animals::Animal for Lamb {
+    fn new(name: String) -> Self {
+        Lamb(name)
+    }
+
+    fn name(&self) -> String {
+        self.0.clone()
+    }
+
+    fn noise(&self) -> String {
+        "meeeh!".to_string()
+    }
+}
+
+impl MemSized for Lamb {
+    fn mem_size(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+// Checks that every tag of `Sup` is a tag of `Sub`, i.e. that OCaml accepts
+// `DynBox<Sub>` where `DynBox<Sup>` is expected
+fn assert_subtype(sub: &[String], sup: &[String]) {
+    for tag in sup {
+        assert!(sub.contains(tag), "{:?} is missing {}", sub, tag);
+    }
+}
+
+#[test]
+fn test_tags_across_crates() {
+    registry::initialize_plugins();
+    // `MemSized` is registered by this crate rather than its own, and
+    // `AnimalProxy` by `ocaml_rs_smartptr_test`, but both are foreign traits
+    // and keep their absolute paths
+    register_trait!({
+        ty: ocaml_rs_smartptr::registry::MemSized,
+        marker_traits: [core::marker::Send],
+    });
+    register_type!({
+        ty: crate::Lamb,
+        marker_traits: [core::marker::Send],
+        object_safe_traits: [
+            ocaml_rs_smartptr::registry::MemSized,
+            ocaml_rs_smartptr_test::stubs::AnimalProxy
+        ],
+    });
+
+    let lamb = registry::namespaced_implementations::<Lamb>();
+    assert_eq!(lamb[0], "namespacing::Lamb");
+    let mem_sized = registry::namespaced_implementations::<dyn MemSized + Send>();
+    assert_eq!(mem_sized[0], "ocaml_rs_smartptr::registry::MemSized");
+    assert_subtype(&lamb, &mem_sized);
+
+    let animal = registry::namespaced_implementations::<Animal>();
+    assert_eq!(animal[0], "ocaml_rs_smartptr_test::stubs::AnimalProxy");
+    assert_subtype(&lamb, &animal);
+    assert_subtype(&registry::namespaced_implementations::<Sheep>(), &animal);
+}
@@ -0,0 +1,8 @@
+//! Checks the compile errors reported for misuses of the library, see the
+//! expected `.stderr` files next to the sources in `tests/ui`.
+
+#[test]
+fn test_ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+// `Rc` isn't `Send`, so a stub can't return it in a `DynBox`
+use ocaml_rs_smartptr::ptr::DynBox;
+use std::rc::Rc;
+
+pub fn counter_create(_value: i32) -> DynBox<Rc<i32>> {
+    unimplemented!()
+}
+
+fn main() {}
@@ -0,0 +1,33 @@
+//! Unique IDs of OCaml types must not depend on the crate computing them, so
+//! these are computed from a crate other than `ocaml-rs-smartptr` itself, and
+//! compared against the IDs computed by the default `UNIQUE_ID_KEY`. Only IDs
+//! of types without a `TypeId` in them are fixed across Rust versions.
+
+use ocaml_gen::OCamlDesc;
+use ocaml_rs_smartptr::bytes::OcamlBytesMut;
+use ocaml_rs_smartptr::lazy::Lazy;
+use ocaml_rs_smartptr::ptr::DynBox;
+
+struct Sheep;
+struct Wolf;
+
+// `stable_unique_id("OcamlBytesMut", &())`, as computed by the library crate
+const BYTES_ID: u128 = 0xe5fe700896bcdb71feb35a46d372a8a6;
+// `stable_unique_id("Lazy", &BYTES_ID)`, as computed by this crate
+const LAZY_BYTES_ID: u128 = 0xf33c62d4d89892e07a598ebee86d192e;
+
+#[test]
+fn test_unique_id_is_stable() {
+    assert_eq!(OcamlBytesMut::unique_id(), BYTES_ID);
+    assert_eq!(Lazy::<OcamlBytesMut>::unique_id(), LAZY_BYTES_ID);
+}
+
+#[test]
+fn test_dynbox_unique_id_is_distinct() {
+    assert_eq!(DynBox::<Sheep>::unique_id(), DynBox::<Sheep>::unique_id());
+    assert_ne!(DynBox::<Sheep>::unique_id(), DynBox::<Wolf>::unique_id());
+    assert_ne!(
+        Lazy::<DynBox<Sheep>>::unique_id(),
+        DynBox::<Sheep>::unique_id()
+    );
+}